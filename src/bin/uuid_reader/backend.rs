@@ -0,0 +1,141 @@
+// Where identity values come from. The report only talks to `IdentityBackend`, so tests can
+// drive it with a fake and each platform plugs in its own reader.
+
+use std::fmt;
+use std::path::Path;
+
+use uuid_spoofer_core::id_files::{DBUS_MACHINE_ID, DMI_PRODUCT_UUID, ETC_MACHINE_ID};
+use uuid_spoofer_core::identity::{
+    PropertyValue, BOARD_ID_KEY, IO_MAC_ADDRESS_KEY, IO_PLATFORM_SERIAL_NUMBER_KEY,
+    IO_PLATFORM_UUID_KEY, MODEL_KEY, TARGET_TYPE_KEY,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Source {
+    // A property of the IOPlatformExpertDevice registry entry.
+    PlatformProperty(&'static str),
+    // A property found from a network interface's registry entry or its parents.
+    InterfaceProperty {
+        interface: &'static str,
+        key: &'static str,
+    },
+    // gethostuuid(2), as 16 bytes.
+    HostUuid,
+    Sysctl(&'static str),
+    File(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::PlatformProperty(_) => write!(f, "IOPlatformExpertDevice"),
+            Source::InterfaceProperty { interface, .. } => write!(f, "IORegistry {}", interface),
+            Source::HostUuid => write!(f, "gethostuuid(2)"),
+            Source::Sysctl(name) => write!(f, "sysctl {}", name),
+            Source::File(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+pub trait IdentityBackend {
+    fn read(&self, source: &Source) -> Result<PropertyValue, String>;
+}
+
+// One identity value to report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    pub name: &'static str,
+    pub source: Source,
+    // Whether this is a rendering of the spoofed UUID, checked by `--expect`.
+    pub carries_uuid: bool,
+}
+
+const fn probe(name: &'static str, source: Source) -> Probe {
+    Probe {
+        name,
+        source,
+        carries_uuid: false,
+    }
+}
+
+const fn uuid_probe(name: &'static str, source: Source) -> Probe {
+    Probe {
+        name,
+        source,
+        carries_uuid: true,
+    }
+}
+
+// Everything the macOS hook can override.
+pub fn macos_probes() -> Vec<Probe> {
+    vec![
+        uuid_probe(
+            IO_PLATFORM_UUID_KEY,
+            Source::PlatformProperty(IO_PLATFORM_UUID_KEY),
+        ),
+        probe(
+            IO_PLATFORM_SERIAL_NUMBER_KEY,
+            Source::PlatformProperty(IO_PLATFORM_SERIAL_NUMBER_KEY),
+        ),
+        probe(BOARD_ID_KEY, Source::PlatformProperty(BOARD_ID_KEY)),
+        probe(MODEL_KEY, Source::PlatformProperty(MODEL_KEY)),
+        probe(TARGET_TYPE_KEY, Source::PlatformProperty(TARGET_TYPE_KEY)),
+        probe(
+            IO_MAC_ADDRESS_KEY,
+            Source::InterfaceProperty {
+                interface: "en0",
+                key: IO_MAC_ADDRESS_KEY,
+            },
+        ),
+        uuid_probe("gethostuuid", Source::HostUuid),
+        uuid_probe("kern.uuid", Source::Sysctl("kern.uuid")),
+    ]
+}
+
+// Everything the Linux preload library can override.
+pub fn linux_probes() -> Vec<Probe> {
+    vec![
+        uuid_probe("machine-id", Source::File(ETC_MACHINE_ID)),
+        uuid_probe("dbus machine-id", Source::File(DBUS_MACHINE_ID)),
+        uuid_probe("product_uuid", Source::File(DMI_PRODUCT_UUID)),
+    ]
+}
+
+pub fn probes() -> Vec<Probe> {
+    if cfg!(target_os = "macos") {
+        macos_probes()
+    } else {
+        linux_probes()
+    }
+}
+
+// Identity files hold a single line.
+pub fn read_id_file(path: &str) -> Result<PropertyValue, String> {
+    std::fs::read_to_string(Path::new(path))
+        .map(|contents| PropertyValue::String(contents.trim_end().to_string()))
+        .map_err(|e| e.to_string())
+}
+
+// Reads identity files only; everything else is a macOS API.
+#[cfg(not(target_os = "macos"))]
+pub struct FileBackend;
+
+#[cfg(not(target_os = "macos"))]
+impl IdentityBackend for FileBackend {
+    fn read(&self, source: &Source) -> Result<PropertyValue, String> {
+        match source {
+            Source::File(path) => read_id_file(path),
+            _ => Err("only available on macOS".to_string()),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub fn system() -> impl IdentityBackend {
+    crate::iokit::IOKitBackend::new()
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn system() -> impl IdentityBackend {
+    FileBackend
+}
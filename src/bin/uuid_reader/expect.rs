@@ -0,0 +1,203 @@
+// `--expect`: checks that every readable rendering of the platform UUID is the expected one.
+
+use crate::backend::Probe;
+use crate::report::Entry;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub passed: bool,
+    pub reason: String,
+}
+
+// machine-id, product_uuid and friends differ only in case and dashes.
+fn normalize(uuid: &str) -> String {
+    uuid.chars()
+        .filter(char::is_ascii_hexdigit)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+// Marks each UUID entry with whether it matches `expected`, and judges the whole run.
+pub fn check(entries: &mut [Entry], probes: &[Probe], expected: &str) -> Verdict {
+    let expected_normalized = normalize(expected);
+    let mut matched = Vec::new();
+    let mut mismatched = Vec::new();
+    let mut unreadable = Vec::new();
+    for (entry, probe) in entries.iter_mut().zip(probes) {
+        if !probe.carries_uuid {
+            continue;
+        }
+        match &entry.value {
+            Some(value) => {
+                let matches = normalize(value) == expected_normalized;
+                entry.matches = Some(matches);
+                if matches {
+                    matched.push(entry.name);
+                } else {
+                    mismatched.push(entry.name);
+                }
+            }
+            None => unreadable.push(entry.name),
+        }
+    }
+
+    let unreadable_note = if unreadable.is_empty() {
+        String::new()
+    } else {
+        format!(" ({} could not be read)", unreadable.join(", "))
+    };
+    let (passed, reason) = if matched.is_empty() && mismatched.is_empty() {
+        (false, "none of the UUID values could be read".to_string())
+    } else if mismatched.is_empty() {
+        (
+            true,
+            format!(
+                "all {} UUID values match {}{}",
+                matched.len(),
+                expected,
+                unreadable_note
+            ),
+        )
+    } else if matched.is_empty() {
+        (
+            false,
+            format!(
+                "no UUID value matches {}: the library was not loaded or its hooks were not installed{}",
+                expected, unreadable_note
+            ),
+        )
+    } else {
+        (
+            false,
+            format!(
+                "{} not spoofed although {} {}: those hooks were not installed{}",
+                mismatched.join(", "),
+                matched.join(", "),
+                if matched.len() == 1 { "is" } else { "are" },
+                unreadable_note
+            ),
+        )
+    };
+    Verdict { passed, reason }
+}
+
+// Fails a run whose values matched if any of the library's hooks is degraded: other
+// images' calls may still reach the real functions.
+pub fn with_degraded_hooks(verdict: Verdict, degraded: &[&str]) -> Verdict {
+    if !verdict.passed || degraded.is_empty() {
+        return verdict;
+    }
+    Verdict {
+        passed: false,
+        reason: format!(
+            "{}, but {} {} degraded: see the library's log",
+            verdict.reason,
+            degraded.join(", "),
+            if degraded.len() == 1 { "is" } else { "are" }
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{linux_probes, macos_probes};
+
+    const UUID: &str = "DEADBEEF-0123-4567-89AB-CDEF00112233";
+
+    fn entries(probes: &[Probe], values: &[Option<&str>]) -> Vec<Entry> {
+        probes
+            .iter()
+            .zip(values)
+            .map(|(probe, value)| Entry {
+                name: probe.name,
+                source: probe.source.to_string(),
+                value: value.map(str::to_string),
+                error: value.is_none().then(|| "not present".to_string()),
+                matches: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn all_renderings_of_the_uuid_match() {
+        let probes = linux_probes();
+        let mut entries = entries(
+            &probes,
+            &[
+                Some("deadbeef0123456789abcdef00112233"),
+                None,
+                Some("deadbeef-0123-4567-89ab-cdef00112233"),
+            ],
+        );
+        let verdict = check(&mut entries, &probes, UUID);
+        assert!(verdict.passed, "{:?}", verdict);
+        assert_eq!(
+            verdict.reason,
+            format!(
+                "all 2 UUID values match {} (dbus machine-id could not be read)",
+                UUID
+            )
+        );
+        assert_eq!(
+            entries.iter().map(|e| e.matches).collect::<Vec<_>>(),
+            [Some(true), None, Some(true)]
+        );
+    }
+
+    #[test]
+    fn nothing_spoofed_means_not_loaded() {
+        let probes = linux_probes();
+        let mut entries = entries(&probes, &[Some("0123"), Some("0123"), Some("4567")]);
+        let verdict = check(&mut entries, &probes, UUID);
+        assert!(!verdict.passed);
+        assert!(verdict.reason.contains("not loaded"), "{}", verdict.reason);
+    }
+
+    #[test]
+    fn partial_spoofing_names_the_missing_hooks() {
+        let probes = macos_probes();
+        let values: Vec<Option<&str>> = probes
+            .iter()
+            .map(|probe| match probe.name {
+                "IOPlatformUUID" | "gethostuuid" => Some(UUID),
+                "kern.uuid" => Some("00000000-0000-0000-0000-000000000000"),
+                _ => Some("C02XK0AAJG5J"),
+            })
+            .collect();
+        let mut entries = entries(&probes, &values);
+        let verdict = check(&mut entries, &probes, UUID);
+        assert!(!verdict.passed);
+        assert_eq!(
+            verdict.reason,
+            "kern.uuid not spoofed although IOPlatformUUID, gethostuuid are: those hooks were not installed"
+        );
+        // Non-UUID values are never judged.
+        assert_eq!(entries.iter().filter(|e| e.matches.is_some()).count(), 3);
+    }
+
+    #[test]
+    fn degraded_hooks_fail_a_matching_run() {
+        let probes = linux_probes();
+        let mut entries = entries(&probes, &[Some(UUID), Some(UUID), Some(UUID)]);
+        let verdict = check(&mut entries, &probes, UUID);
+        assert_eq!(with_degraded_hooks(verdict.clone(), &[]), verdict);
+
+        let degraded = with_degraded_hooks(verdict, &["gethostuuid", "sysctlbyname"]);
+        assert!(!degraded.passed);
+        assert_eq!(
+            degraded.reason,
+            format!(
+                "all 3 UUID values match {}, but gethostuuid, sysctlbyname are degraded: see the library's log",
+                UUID
+            )
+        );
+    }
+
+    #[test]
+    fn unreadable_values_fail() {
+        let probes = linux_probes();
+        let mut entries = entries(&probes, &[None, None, None]);
+        assert!(!check(&mut entries, &probes, UUID).passed);
+    }
+}
@@ -0,0 +1,27 @@
+// The injected library's own account of its hooks, asked through its C query API. A
+// degraded hook can spoof the values read here and still miss calls from other images.
+
+use std::ffi::CString;
+
+use libc::{c_char, c_int};
+use uuid_spoofer_core::hook_state::HookState;
+use uuid_spoofer_core::scan::IDENTITY_APIS;
+
+type FnHookState = extern "C" fn(function: *const c_char) -> c_int;
+
+// The hooks the library reports as degraded; none when it is not loaded.
+pub fn degraded() -> Vec<&'static str> {
+    let query = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"uuid_spoof_hook_state".as_ptr()) };
+    if query.is_null() {
+        return Vec::new();
+    }
+    let query: FnHookState = unsafe { std::mem::transmute(query) };
+    IDENTITY_APIS
+        .iter()
+        .filter(|api| api.hooked)
+        .map(|api| api.name)
+        .filter(|name| {
+            CString::new(*name).is_ok_and(|name| query(name.as_ptr()) == HookState::Degraded.code())
+        })
+        .collect()
+}
@@ -0,0 +1,299 @@
+// The macOS backend: reads identity values straight from IOKit, gethostuuid(2) and sysctl.
+// Run it under the injected library to see what a spoofed app sees.
+
+use core_foundation_sys::base::{
+    kCFAllocatorDefault, CFAllocatorRef, CFGetTypeID, CFRelease, CFTypeRef,
+};
+use core_foundation_sys::data::{CFDataGetBytePtr, CFDataGetLength, CFDataGetTypeID, CFDataRef};
+use core_foundation_sys::number::{
+    kCFNumberSInt64Type, CFNumberGetTypeID, CFNumberGetValue, CFNumberRef,
+};
+use core_foundation_sys::string::{
+    kCFStringEncodingUTF8, CFStringGetCStringPtr, CFStringGetTypeID, CFStringRef,
+};
+
+use libc::{c_char, c_int, c_void, size_t, timespec};
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+use uuid_spoofer_core::identity::PropertyValue;
+
+use crate::backend::{IdentityBackend, Source};
+
+// --- IOKit FFI types and functions ---
+type IOOptionBits = u32;
+type IORegistryEntryT = *mut c_void; // Or mach_port_t which is u32
+type IOMasterPortT = u32; // mach_port_t
+type IOServiceMatchingCFDictRef = CFTypeRef; // Actually CFDictionaryRef
+type IOIteratorT = u32; // io_iterator_t
+
+const KERN_SUCCESS: i32 = 0;
+const MACH_PORT_NULL: u32 = 0; // Or 0 as *mut c_void if using that for mach_port_t
+
+const K_IO_REGISTRY_ITERATE_RECURSIVELY: IOOptionBits = 0x1;
+const K_IO_REGISTRY_ITERATE_PARENTS: IOOptionBits = 0x2;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOMasterPort(bootstrapPort: IOMasterPortT, masterPort: *mut IOMasterPortT) -> i32; // kern_return_t
+
+    fn IOServiceMatching(name: *const c_char) -> IOServiceMatchingCFDictRef;
+    fn IOBSDNameMatching(
+        masterPort: IOMasterPortT,
+        options: IOOptionBits,
+        bsdName: *const c_char,
+    ) -> IOServiceMatchingCFDictRef;
+    fn IOServiceGetMatchingServices(
+        masterPort: IOMasterPortT,
+        matching: IOServiceMatchingCFDictRef,
+        existing: *mut IOIteratorT,
+    ) -> i32; // kern_return_t
+
+    fn IOIteratorNext(iterator: IOIteratorT) -> IORegistryEntryT; // io_object_t
+    fn IOObjectRelease(object: u32) -> i32; // kern_return_t for io_object_t
+
+    fn IORegistryEntryCreateCFProperty(
+        entry: IORegistryEntryT,
+        key: CFStringRef,
+        allocator: CFAllocatorRef,
+        options: IOOptionBits,
+    ) -> CFTypeRef;
+    fn IORegistryEntrySearchCFProperty(
+        entry: IORegistryEntryT,
+        plane: *const c_char,
+        key: CFStringRef,
+        allocator: CFAllocatorRef,
+        options: IOOptionBits,
+    ) -> CFTypeRef;
+}
+
+extern "C" {
+    fn gethostuuid(id: *mut u8, wait: *const timespec) -> c_int;
+    fn sysctlbyname(
+        name: *const c_char,
+        oldp: *mut c_void,
+        oldlenp: *mut size_t,
+        newp: *mut c_void,
+        newlen: size_t,
+    ) -> c_int;
+}
+
+// Helper function to create a CFStringRef from a Rust string literal.
+fn cfstring_from_rust_str(s: &str) -> Option<CFStringRef> {
+    let c_string = CString::new(s).ok()?;
+    unsafe {
+        Some(core_foundation_sys::string::CFStringCreateWithCString(
+            kCFAllocatorDefault,
+            c_string.as_ptr(),
+            kCFStringEncodingUTF8,
+        ))
+    }
+}
+
+fn cfstring_to_string(s: CFStringRef) -> Option<String> {
+    unsafe {
+        let c_str_ptr = CFStringGetCStringPtr(s, kCFStringEncodingUTF8);
+        if !c_str_ptr.is_null() {
+            return Some(CStr::from_ptr(c_str_ptr).to_string_lossy().into_owned());
+        }
+        let length = core_foundation_sys::string::CFStringGetLength(s);
+        let buffer_size = core_foundation_sys::string::CFStringGetMaximumSizeForEncoding(
+            length,
+            kCFStringEncodingUTF8,
+        ) + 1;
+        let mut buffer = vec![0u8; buffer_size as usize];
+        if core_foundation_sys::string::CFStringGetCString(
+            s,
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer_size,
+            kCFStringEncodingUTF8,
+        ) == 0
+        {
+            return None;
+        }
+        let nul_pos = buffer.iter().position(|&c| c == 0)?;
+        buffer.truncate(nul_pos);
+        Some(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+// Converts (and releases) a +1 property value.
+fn take_property(value: CFTypeRef) -> Result<PropertyValue, String> {
+    if value.is_null() {
+        return Err("not present".to_string());
+    }
+    let converted = unsafe {
+        let type_id = CFGetTypeID(value);
+        if type_id == CFStringGetTypeID() {
+            cfstring_to_string(value as CFStringRef)
+                .map(PropertyValue::String)
+                .ok_or_else(|| "string is not convertible to UTF-8".to_string())
+        } else if type_id == CFDataGetTypeID() {
+            let data = value as CFDataRef;
+            let length = CFDataGetLength(data) as usize;
+            let bytes = if length == 0 {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(CFDataGetBytePtr(data), length).to_vec()
+            };
+            Ok(PropertyValue::Data(bytes))
+        } else if type_id == CFNumberGetTypeID() {
+            let mut n: i64 = 0;
+            CFNumberGetValue(
+                value as CFNumberRef,
+                kCFNumberSInt64Type,
+                &mut n as *mut i64 as *mut c_void,
+            );
+            Ok(PropertyValue::Number(n))
+        } else {
+            Err(format!("unsupported CF type {}", type_id))
+        }
+    };
+    unsafe { CFRelease(value) };
+    converted
+}
+
+// The first registry entry matching `matching` (which is consumed).
+fn first_matching_service(
+    master_port: IOMasterPortT,
+    matching: IOServiceMatchingCFDictRef,
+    what: &str,
+) -> Result<IORegistryEntryT, String> {
+    unsafe {
+        if matching.is_null() {
+            return Err(format!("cannot create a matching dictionary for {}", what));
+        }
+        let mut iterator: IOIteratorT = 0;
+        // The dictionary is consumed by IOServiceGetMatchingServices, even on failure.
+        if IOServiceGetMatchingServices(master_port, matching, &mut iterator) != KERN_SUCCESS {
+            return Err(format!("cannot get matching services for {}", what));
+        }
+        let service = IOIteratorNext(iterator);
+        IOObjectRelease(iterator);
+        if service.is_null() {
+            return Err(format!("no {} in the I/O Registry", what));
+        }
+        Ok(service)
+    }
+}
+
+fn with_cfstring_key<T>(
+    key: &str,
+    read: impl FnOnce(CFStringRef) -> Result<T, String>,
+) -> Result<T, String> {
+    let key_cfstring =
+        cfstring_from_rust_str(key).ok_or_else(|| format!("invalid key '{}'", key))?;
+    let result = read(key_cfstring);
+    unsafe { CFRelease(key_cfstring as CFTypeRef) };
+    result
+}
+
+pub struct IOKitBackend {
+    master_port: IOMasterPortT,
+}
+
+impl IOKitBackend {
+    pub fn new() -> Self {
+        // MACH_PORT_NULL also means "the default master port" if the lookup fails.
+        let mut master_port: IOMasterPortT = MACH_PORT_NULL;
+        if unsafe { IOMasterPort(MACH_PORT_NULL, &mut master_port) } != KERN_SUCCESS {
+            master_port = MACH_PORT_NULL;
+        }
+        IOKitBackend { master_port }
+    }
+
+    fn platform_property(&self, key: &str) -> Result<PropertyValue, String> {
+        let device = first_matching_service(
+            self.master_port,
+            unsafe { IOServiceMatching(c"IOPlatformExpertDevice".as_ptr()) },
+            "IOPlatformExpertDevice",
+        )?;
+        let result = with_cfstring_key(key, |key| {
+            take_property(unsafe {
+                IORegistryEntryCreateCFProperty(device, key, kCFAllocatorDefault, 0)
+            })
+        });
+        unsafe { IOObjectRelease(device as u32) };
+        result
+    }
+
+    // IOMACAddress lives on the Ethernet controller that is the parent of the interface.
+    fn interface_property(&self, interface: &str, key: &str) -> Result<PropertyValue, String> {
+        let bsd_name = CString::new(interface).map_err(|e| e.to_string())?;
+        let service = first_matching_service(
+            self.master_port,
+            unsafe { IOBSDNameMatching(self.master_port, 0, bsd_name.as_ptr()) },
+            interface,
+        )?;
+        let result = with_cfstring_key(key, |key| {
+            take_property(unsafe {
+                IORegistryEntrySearchCFProperty(
+                    service,
+                    c"IOService".as_ptr(),
+                    key,
+                    kCFAllocatorDefault,
+                    K_IO_REGISTRY_ITERATE_RECURSIVELY | K_IO_REGISTRY_ITERATE_PARENTS,
+                )
+            })
+        });
+        unsafe { IOObjectRelease(service as u32) };
+        result
+    }
+
+    fn host_uuid(&self) -> Result<PropertyValue, String> {
+        let mut uuid = [0u8; 16];
+        let wait = timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        if unsafe { gethostuuid(uuid.as_mut_ptr(), &wait) } != 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        Ok(PropertyValue::Data(uuid.to_vec()))
+    }
+
+    fn sysctl_string(&self, name: &str) -> Result<PropertyValue, String> {
+        let c_name = CString::new(name).map_err(|e| e.to_string())?;
+        let mut size: size_t = 0;
+        unsafe {
+            if sysctlbyname(
+                c_name.as_ptr(),
+                ptr::null_mut(),
+                &mut size,
+                ptr::null_mut(),
+                0,
+            ) != 0
+            {
+                return Err(std::io::Error::last_os_error().to_string());
+            }
+            let mut buffer = vec![0u8; size];
+            if sysctlbyname(
+                c_name.as_ptr(),
+                buffer.as_mut_ptr() as *mut c_void,
+                &mut size,
+                ptr::null_mut(),
+                0,
+            ) != 0
+            {
+                return Err(std::io::Error::last_os_error().to_string());
+            }
+            buffer.truncate(size);
+            let end = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+            Ok(PropertyValue::String(
+                String::from_utf8_lossy(&buffer[..end]).into_owned(),
+            ))
+        }
+    }
+}
+
+impl IdentityBackend for IOKitBackend {
+    fn read(&self, source: &Source) -> Result<PropertyValue, String> {
+        match source {
+            Source::PlatformProperty(key) => self.platform_property(key),
+            Source::InterfaceProperty { interface, key } => self.interface_property(interface, key),
+            Source::HostUuid => self.host_uuid(),
+            Source::Sysctl(name) => self.sysctl_string(name),
+            Source::File(path) => crate::backend::read_id_file(path),
+        }
+    }
+}
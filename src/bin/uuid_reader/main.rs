@@ -0,0 +1,84 @@
+// Dumps every identity value the spoofer can override, as this process sees it. Run it
+// plainly and under the injected library and compare the two.
+
+mod backend;
+mod expect;
+mod hooks;
+#[cfg(target_os = "macos")]
+mod iokit;
+mod report;
+
+use std::process::ExitCode;
+
+use uuid_spoofer_core::config;
+
+const USAGE: &str = "\
+Usage: uuid_reader [--json] [--expect <UUID>]
+
+Prints the identity values the spoofer can override.
+  --json           print a JSON array of {name, source, value | error} objects
+  --expect <UUID>  check that every UUID value read is <UUID> and that none of the
+                   library's hooks is degraded; prints PASS or FAIL with the reason and
+                   exits with status 1 on failure
+";
+
+fn usage_error(message: &str) -> ExitCode {
+    eprint!("uuid_reader: {}\n\n{}", message, USAGE);
+    ExitCode::from(2)
+}
+
+fn main() -> ExitCode {
+    let mut json = false;
+    let mut expected = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--expect" => {
+                let Some(value) = args.next() else {
+                    return usage_error("--expect needs a value");
+                };
+                match config::validate_uuid(&value) {
+                    Ok(uuid) => expected = Some(uuid),
+                    Err(e) => return usage_error(&format!("invalid --expect '{}': {}", value, e)),
+                }
+            }
+            "-h" | "--help" => {
+                print!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => return usage_error(&format!("unknown option '{}'", arg)),
+        }
+    }
+
+    let probes = backend::probes();
+    let mut entries = report::collect(&backend::system(), &probes);
+    let verdict = expected
+        .as_deref()
+        .map(|uuid| expect::check(&mut entries, &probes, uuid))
+        .map(|verdict| expect::with_degraded_hooks(verdict, &hooks::degraded()));
+    if json {
+        print!("{}", report::json(&entries));
+    } else {
+        print!("{}", report::human(&entries));
+    }
+
+    match verdict {
+        Some(verdict) => {
+            // Keep stdout parseable in JSON mode.
+            let label = if verdict.passed { "PASS" } else { "FAIL" };
+            if json {
+                eprintln!("{}: {}", label, verdict.reason);
+            } else {
+                println!("{}: {}", label, verdict.reason);
+            }
+            if verdict.passed {
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        None if entries.iter().all(|entry| entry.value.is_none()) => ExitCode::FAILURE,
+        None => ExitCode::SUCCESS,
+    }
+}
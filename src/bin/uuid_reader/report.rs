@@ -0,0 +1,217 @@
+// Collects the probes into a report and renders it for people or for scripts.
+
+use serde::Serialize;
+
+use uuid_spoofer_core::identity::PropertyValue;
+use uuid_spoofer_core::uuid::uuid_from_bytes;
+
+use crate::backend::{IdentityBackend, Probe, Source};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub name: &'static str,
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Set by `--expect` on the UUID entries it could check.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matches: Option<bool>,
+}
+
+pub fn collect(backend: &impl IdentityBackend, probes: &[Probe]) -> Vec<Entry> {
+    probes
+        .iter()
+        .map(|probe| {
+            let (value, error) = match backend.read(&probe.source) {
+                Ok(value) => (Some(display_value(&probe.source, &value)), None),
+                Err(e) => (None, Some(e)),
+            };
+            Entry {
+                name: probe.name,
+                source: probe.source.to_string(),
+                value,
+                error,
+                matches: None,
+            }
+        })
+        .collect()
+}
+
+// Renders a value the way the tools that usually show it do.
+pub fn display_value(source: &Source, value: &PropertyValue) -> String {
+    match value {
+        PropertyValue::String(s) => s.clone(),
+        PropertyValue::Number(n) => n.to_string(),
+        PropertyValue::Data(bytes) => match (source, bytes.len()) {
+            (Source::HostUuid, 16) => uuid_from_bytes(bytes.as_slice().try_into().unwrap()),
+            (Source::InterfaceProperty { .. }, 6) => bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+            _ => printable_c_string(bytes).unwrap_or_else(|| {
+                let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                format!("<{}>", hex)
+            }),
+        },
+    }
+}
+
+// board-id, model and friends are NUL-terminated strings stored as data.
+fn printable_c_string(bytes: &[u8]) -> Option<String> {
+    let text = bytes.strip_suffix(&[0])?;
+    if text.is_empty() || !text.iter().all(|b| b.is_ascii_graphic() || *b == b' ') {
+        return None;
+    }
+    Some(String::from_utf8_lossy(text).into_owned())
+}
+
+pub fn human(entries: &[Entry]) -> String {
+    let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    let mut out = String::new();
+    for entry in entries {
+        let mut shown = match (&entry.value, &entry.error) {
+            (Some(value), _) => value.clone(),
+            (None, Some(error)) => format!("<unavailable: {}>", error),
+            (None, None) => String::new(),
+        };
+        match entry.matches {
+            Some(true) => shown.push_str("  [ok]"),
+            Some(false) => shown.push_str("  [MISMATCH]"),
+            None => {}
+        }
+        out.push_str(&format!(
+            "{:width$}  {}\n",
+            entry.name,
+            shown,
+            width = width
+        ));
+    }
+    out
+}
+
+pub fn json(entries: &[Entry]) -> String {
+    serde_json::to_string_pretty(entries).expect("entries always serialize") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{linux_probes, macos_probes};
+    use std::collections::HashMap;
+
+    struct FakeBackend(HashMap<Source, PropertyValue>);
+
+    impl IdentityBackend for FakeBackend {
+        fn read(&self, source: &Source) -> Result<PropertyValue, String> {
+            self.0
+                .get(source)
+                .cloned()
+                .ok_or_else(|| "not present".to_string())
+        }
+    }
+
+    fn fake_mac() -> FakeBackend {
+        let mut values = HashMap::new();
+        for probe in macos_probes() {
+            let value = match &probe.source {
+                Source::PlatformProperty("IOPlatformUUID") => {
+                    PropertyValue::String("12345678-9ABC-DEF0-1234-56789ABCDEF0".into())
+                }
+                Source::PlatformProperty("board-id") => {
+                    PropertyValue::Data(b"Mac-7BA5B2DFE22DDD8C\0".to_vec())
+                }
+                Source::PlatformProperty("target-type") => continue,
+                Source::InterfaceProperty { .. } => {
+                    PropertyValue::Data(vec![0x02, 0x1c, 0x42, 0, 0, 1])
+                }
+                Source::HostUuid => PropertyValue::Data((0..16).collect()),
+                _ => PropertyValue::String(format!("value of {}", probe.name)),
+            };
+            values.insert(probe.source, value);
+        }
+        FakeBackend(values)
+    }
+
+    fn value_of<'a>(entries: &'a [Entry], name: &str) -> &'a Entry {
+        entries.iter().find(|e| e.name == name).unwrap()
+    }
+
+    #[test]
+    fn formats_each_kind_of_value() {
+        let entries = collect(&fake_mac(), &macos_probes());
+        assert_eq!(entries.len(), macos_probes().len());
+        let value = |name| value_of(&entries, name).value.as_deref();
+        assert_eq!(
+            value("IOPlatformUUID"),
+            Some("12345678-9ABC-DEF0-1234-56789ABCDEF0")
+        );
+        assert_eq!(value("board-id"), Some("Mac-7BA5B2DFE22DDD8C"));
+        assert_eq!(value("IOMACAddress"), Some("02:1c:42:00:00:01"));
+        assert_eq!(
+            value("gethostuuid"),
+            Some("00010203-0405-0607-0809-0A0B0C0D0E0F")
+        );
+        assert_eq!(value("kern.uuid"), Some("value of kern.uuid"));
+        assert_eq!(
+            value_of(&entries, "target-type").error.as_deref(),
+            Some("not present")
+        );
+    }
+
+    #[test]
+    fn opaque_data_is_hex() {
+        let source = Source::PlatformProperty("blob");
+        assert_eq!(
+            display_value(&source, &PropertyValue::Data(vec![0xde, 0xad, 0])),
+            "<dead00>"
+        );
+        assert_eq!(
+            display_value(&source, &PropertyValue::Data(vec![0])),
+            "<00>"
+        );
+        assert_eq!(display_value(&source, &PropertyValue::Number(-3)), "-3");
+    }
+
+    #[test]
+    fn human_output_aligns_names() {
+        let entries = collect(&fake_mac(), &macos_probes()[..2]);
+        let lines: Vec<_> = human(&entries).lines().map(str::to_string).collect();
+        assert_eq!(
+            lines,
+            [
+                "IOPlatformUUID          12345678-9ABC-DEF0-1234-56789ABCDEF0",
+                "IOPlatformSerialNumber  value of IOPlatformSerialNumber",
+            ]
+        );
+        let missing = collect(&FakeBackend(HashMap::new()), &linux_probes()[..1]);
+        assert_eq!(human(&missing), "machine-id  <unavailable: not present>\n");
+
+        let mut checked = collect(&fake_mac(), &macos_probes()[..1]);
+        checked[0].matches = Some(false);
+        assert_eq!(
+            human(&checked),
+            "IOPlatformUUID  12345678-9ABC-DEF0-1234-56789ABCDEF0  [MISMATCH]\n"
+        );
+    }
+
+    #[test]
+    fn json_output_has_value_or_error() {
+        let mut values = HashMap::new();
+        values.insert(
+            Source::File("/etc/machine-id"),
+            PropertyValue::String("0123".into()),
+        );
+        let entries = collect(&FakeBackend(values), &linux_probes()[..2]);
+        let parsed: serde_json::Value = serde_json::from_str(&json(&entries)).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!([
+                {"name": "machine-id", "source": "/etc/machine-id", "value": "0123"},
+                {"name": "dbus machine-id", "source": "/var/lib/dbus/machine-id", "error": "not present"},
+            ])
+        );
+    }
+}
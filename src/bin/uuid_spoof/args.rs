@@ -0,0 +1,72 @@
+// A minimal argument cursor. The commands take a handful of flags each, which does not
+// justify an argument-parsing dependency.
+
+use std::collections::VecDeque;
+use std::ffi::OsString;
+
+pub struct Args {
+    remaining: VecDeque<OsString>,
+}
+
+impl Args {
+    pub fn from_env() -> Self {
+        Args::new(std::env::args_os().skip(1))
+    }
+
+    pub fn new(args: impl IntoIterator<Item = impl Into<OsString>>) -> Self {
+        Args {
+            remaining: args.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn next(&mut self) -> Option<OsString> {
+        self.remaining.pop_front()
+    }
+
+    // The next argument, lossily converted; flags and command names are always ASCII.
+    pub fn next_string(&mut self) -> Option<String> {
+        self.next().map(|arg| arg.to_string_lossy().into_owned())
+    }
+
+    pub fn peek(&self) -> Option<&OsString> {
+        self.remaining.front()
+    }
+
+    // The value following `flag`, which must be present.
+    pub fn value(&mut self, flag: &str) -> Result<String, String> {
+        let value = self
+            .next()
+            .ok_or_else(|| format!("{} needs a value", flag))?;
+        value
+            .into_string()
+            .map_err(|_| format!("the value of {} is not valid UTF-8", flag))
+    }
+
+    pub fn rest(self) -> Vec<OsString> {
+        self.remaining.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walks_flags_values_and_rest() {
+        let mut args = Args::new(["--uuid", "X", "prog", "-v"]);
+        assert_eq!(args.next_string().as_deref(), Some("--uuid"));
+        assert_eq!(args.value("--uuid"), Ok("X".to_string()));
+        assert_eq!(args.peek(), Some(&OsString::from("prog")));
+        assert_eq!(args.rest(), ["prog", "-v"]);
+    }
+
+    #[test]
+    fn missing_value_is_an_error() {
+        let mut args = Args::new(["--seed"]);
+        args.next();
+        assert_eq!(
+            args.value("--seed"),
+            Err("--seed needs a value".to_string())
+        );
+    }
+}
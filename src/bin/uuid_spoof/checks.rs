@@ -0,0 +1,364 @@
+// Pre-launch checks: will the loader honour the injected library for this target?
+//
+// dyld silently drops DYLD_INSERT_LIBRARIES for SIP-protected and restricted binaries and
+// for the hardened runtime (unless entitled), and aborts the launch when library validation
+// rejects the injected library. ld.so ignores LD_PRELOAD paths for setuid programs and has
+// nothing to preload into for static executables.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use uuid_spoofer_core::macho::{self, MachO};
+
+pub const ALLOW_DYLD_ENVIRONMENT: &str = "com.apple.security.cs.allow-dyld-environment-variables";
+pub const DISABLE_LIBRARY_VALIDATION: &str = "com.apple.security.cs.disable-library-validation";
+
+// Shebangs nest (a script's interpreter may itself be a script), but not deeply.
+const MAX_INTERPRETER_DEPTH: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    // The injection will be ignored or will stop the target from launching.
+    Blocking,
+    // Worth knowing, but the launch may still work.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    fn blocking(message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Blocking,
+            message: message.into(),
+        }
+    }
+
+    fn warning(message: impl Into<String>) -> Self {
+        Finding {
+            severity: Severity::Warning,
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    MacOS,
+    Linux,
+}
+
+impl Platform {
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Platform::MacOS
+        } else {
+            Platform::Linux
+        }
+    }
+}
+
+// Everything known to defeat the injection into the executable at `path`.
+pub fn check_target(path: &Path, platform: Platform) -> Vec<Finding> {
+    check_at_depth(path, platform, 0)
+}
+
+fn check_at_depth(path: &Path, platform: Platform, depth: usize) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let shown = path.display();
+
+    if platform == Platform::MacOS && sip_protected(path) {
+        findings.push(Finding::blocking(format!(
+            "{} is protected by System Integrity Protection; dyld ignores DYLD_INSERT_LIBRARIES for it",
+            shown
+        )));
+    }
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.permissions().mode() & 0o6000 != 0 => {
+            findings.push(Finding::blocking(format!(
+                "{} is setuid/setgid; the loader ignores injected libraries for it",
+                shown
+            )));
+        }
+        Ok(_) => {}
+        Err(e) => {
+            findings.push(Finding::warning(format!("cannot inspect {}: {}", shown, e)));
+            return findings;
+        }
+    }
+
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            findings.push(Finding::warning(format!("cannot read {}: {}", shown, e)));
+            return findings;
+        }
+    };
+    if let Some(interpreter) = shebang_interpreter(&data) {
+        if depth < MAX_INTERPRETER_DEPTH {
+            for mut finding in check_at_depth(Path::new(&interpreter), platform, depth + 1) {
+                finding.message = format!(
+                    "{} is a script run by {}: {}",
+                    shown, interpreter, finding.message
+                );
+                findings.push(finding);
+            }
+        }
+        return findings;
+    }
+    match platform {
+        Platform::MacOS => findings.extend(check_macho(&shown.to_string(), &data)),
+        Platform::Linux => findings.extend(check_elf(&shown.to_string(), &data)),
+    }
+    findings
+}
+
+// Paths covered by SIP. /usr/local is the one writable exception.
+pub fn sip_protected(path: &Path) -> bool {
+    if path.starts_with("/usr/local") {
+        return false;
+    }
+    ["/System", "/usr", "/bin", "/sbin"]
+        .iter()
+        .any(|root| path.starts_with(root))
+}
+
+// The interpreter named on a `#!` line, if `data` is a script.
+pub fn shebang_interpreter(data: &[u8]) -> Option<String> {
+    let line = data.strip_prefix(b"#!")?;
+    let line = &line[..line.iter().position(|&b| b == b'\n').unwrap_or(line.len())];
+    let line = String::from_utf8_lossy(line);
+    line.split_whitespace().next().map(str::to_string)
+}
+
+pub fn check_macho(name: &str, data: &[u8]) -> Vec<Finding> {
+    let slices = match macho::slices(data) {
+        Ok(slices) => slices,
+        Err(e) => return vec![Finding::warning(format!("{}: {}", name, e))],
+    };
+    let mut findings = Vec::new();
+    for slice in slices {
+        let arch = macho::cpu_name(slice.cputype);
+        let signature = match MachO::parse(slice.data).and_then(|image| image.code_signature()) {
+            Ok(Some(signature)) => signature,
+            Ok(None) => continue,
+            Err(e) => {
+                findings.push(Finding::warning(format!("{} ({}): {}", name, arch, e)));
+                continue;
+            }
+        };
+        if signature.restricted() {
+            findings.push(Finding::blocking(format!(
+                "{} ({}) is signed as restricted; dyld ignores DYLD_INSERT_LIBRARIES for it",
+                name, arch
+            )));
+        }
+        if signature.hardened_runtime() && !signature.has_entitlement(ALLOW_DYLD_ENVIRONMENT) {
+            findings.push(Finding::blocking(format!(
+                "{} ({}) uses the hardened runtime without {}; dyld ignores DYLD_INSERT_LIBRARIES for it",
+                name, arch, ALLOW_DYLD_ENVIRONMENT
+            )));
+        } else if (signature.hardened_runtime() || signature.library_validation())
+            && !signature.has_entitlement(DISABLE_LIBRARY_VALIDATION)
+        {
+            findings.push(Finding::blocking(format!(
+                "{} ({}) enforces library validation; it will refuse a library not signed by the same team",
+                name, arch
+            )));
+        }
+    }
+    findings
+}
+
+const PT_INTERP: u32 = 3;
+
+pub fn check_elf(name: &str, data: &[u8]) -> Vec<Finding> {
+    match elf_has_interpreter(data) {
+        Some(true) => Vec::new(),
+        Some(false) => vec![Finding::blocking(format!(
+            "{} is statically linked; LD_PRELOAD has no effect on it",
+            name
+        ))],
+        None => vec![Finding::warning(format!(
+            "{} is not a readable ELF executable",
+            name
+        ))],
+    }
+}
+
+// Whether an ELF file has a PT_INTERP segment, i.e. is started by the dynamic loader.
+fn elf_has_interpreter(data: &[u8]) -> Option<bool> {
+    if data.get(..4)? != b"\x7fELF" {
+        return None;
+    }
+    let is_64 = *data.get(4)? == 2;
+    let little = *data.get(5)? == 1;
+    let read = |offset: usize, len: usize| -> Option<u64> {
+        let bytes = data.get(offset..offset.checked_add(len)?)?;
+        let mut value = 0u64;
+        for i in 0..len {
+            let byte = if little { bytes[len - 1 - i] } else { bytes[i] };
+            value = value << 8 | byte as u64;
+        }
+        Some(value)
+    };
+    let (phoff, phentsize, phnum) = if is_64 {
+        (read(0x20, 8)?, read(0x36, 2)?, read(0x38, 2)?)
+    } else {
+        (read(0x1c, 4)?, read(0x2a, 2)?, read(0x2c, 2)?)
+    };
+    for i in 0..phnum {
+        let entry = phoff.checked_add(i.checked_mul(phentsize)?)? as usize;
+        if read(entry, 4)? as u32 == PT_INTERP {
+            return Some(true);
+        }
+    }
+    Some(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid_spoofer_core::macho::{CPU_TYPE_ARM64, CS_REQUIRE_LV, CS_RESTRICT, CS_RUNTIME};
+    use uuid_spoofer_core::testing;
+
+    // Little-endian 64-bit ELF header plus `types.len()` program headers of those types.
+    fn elf(types: &[u32]) -> Vec<u8> {
+        let mut data = vec![0u8; 64];
+        data[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        data[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+        data[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+        data[0x38..0x3a].copy_from_slice(&(types.len() as u16).to_le_bytes());
+        for &kind in types {
+            let mut header = vec![0u8; 56];
+            header[..4].copy_from_slice(&kind.to_le_bytes());
+            data.extend(header);
+        }
+        data
+    }
+
+    fn entitlements(keys: &[&str]) -> String {
+        let mut xml = String::from("<plist><dict>");
+        for key in keys {
+            xml.push_str(&format!("<key>{}</key><true/>", key));
+        }
+        xml + "</dict></plist>"
+    }
+
+    fn blocking(findings: &[Finding]) -> usize {
+        findings
+            .iter()
+            .filter(|f| f.severity == Severity::Blocking)
+            .count()
+    }
+
+    #[test]
+    fn sip_paths() {
+        assert!(sip_protected(Path::new("/usr/bin/true")));
+        assert!(sip_protected(Path::new(
+            "/System/Applications/TextEdit.app/Contents/MacOS/TextEdit"
+        )));
+        assert!(sip_protected(Path::new("/bin/sh")));
+        assert!(!sip_protected(Path::new("/usr/local/bin/tool")));
+        assert!(!sip_protected(Path::new(
+            "/Applications/App.app/Contents/MacOS/App"
+        )));
+        assert!(!sip_protected(Path::new("/usrlocal/tool")));
+    }
+
+    #[test]
+    fn shebangs() {
+        assert_eq!(
+            shebang_interpreter(b"#!/bin/sh\necho"),
+            Some("/bin/sh".into())
+        );
+        assert_eq!(
+            shebang_interpreter(b"#! /usr/bin/env python3 -u\n"),
+            Some("/usr/bin/env".into())
+        );
+        assert_eq!(shebang_interpreter(b"\x7fELF"), None);
+        assert_eq!(shebang_interpreter(b"#!"), None);
+    }
+
+    #[test]
+    fn unsigned_and_plain_signed_images_pass() {
+        let plain = testing::signed_macho(CPU_TYPE_ARM64, 0, None);
+        assert!(check_macho("app", &plain).is_empty());
+    }
+
+    #[test]
+    fn hardened_runtime_blocks_unless_entitled() {
+        let hardened = testing::signed_macho(CPU_TYPE_ARM64, CS_RUNTIME, None);
+        let findings = check_macho("app", &hardened);
+        assert_eq!(blocking(&findings), 1);
+        assert!(
+            findings[0].message.contains("hardened runtime"),
+            "{:?}",
+            findings
+        );
+
+        let dyld_only = entitlements(&[ALLOW_DYLD_ENVIRONMENT]);
+        let image = testing::signed_macho(CPU_TYPE_ARM64, CS_RUNTIME, Some(&dyld_only));
+        let findings = check_macho("app", &image);
+        assert_eq!(blocking(&findings), 1);
+        assert!(
+            findings[0].message.contains("library validation"),
+            "{:?}",
+            findings
+        );
+
+        let both = entitlements(&[ALLOW_DYLD_ENVIRONMENT, DISABLE_LIBRARY_VALIDATION]);
+        let image = testing::signed_macho(CPU_TYPE_ARM64, CS_RUNTIME, Some(&both));
+        assert!(check_macho("app", &image).is_empty());
+    }
+
+    #[test]
+    fn restricted_and_library_validated_images_block() {
+        let image = testing::signed_macho(CPU_TYPE_ARM64, CS_RESTRICT | CS_REQUIRE_LV, None);
+        assert_eq!(blocking(&check_macho("app", &image)), 2);
+    }
+
+    #[test]
+    fn garbage_is_a_warning() {
+        let findings = check_macho("app", b"not a binary");
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn static_elf_blocks() {
+        assert!(check_elf("prog", &elf(&[6, PT_INTERP, 1])).is_empty());
+        assert_eq!(blocking(&check_elf("prog", &elf(&[1, 1]))), 1);
+        // A program header table at the very end of the address space.
+        let mut wrapping = elf(&[1]);
+        wrapping[0x20..0x28].copy_from_slice(&(u64::MAX - 1).to_le_bytes());
+        assert_eq!(check_elf("prog", &wrapping)[0].severity, Severity::Warning);
+        assert_eq!(
+            check_elf("prog", b"#!/bin/sh")[0].severity,
+            Severity::Warning
+        );
+    }
+
+    #[test]
+    fn scripts_are_judged_by_their_interpreter() {
+        let dir = std::env::temp_dir().join(format!("uuid_spoof_checks_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("script");
+        fs::write(&script, "#!/bin/sh\necho hi\n").unwrap();
+
+        let findings = check_target(&script, Platform::MacOS);
+        assert!(
+            findings
+                .iter()
+                .any(|f| f.severity == Severity::Blocking && f.message.contains("run by /bin/sh")),
+            "{:?}",
+            findings
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+// Command-line front end for the spoofer: launches programs with the library injected,
+// inspects binaries before injecting into them and manages stored identity profiles.
+
+mod args;
+mod checks;
+mod patch;
+mod profile;
+mod run;
+mod scan;
+mod verify;
+
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: uuid_spoof <command> [options]
+
+Commands:
+  run [--uuid <UUID> | --seed <SEED> | --profile <NAME>] [--library <PATH>] [--force]
+      [--] <program> [args...]
+      Run <program> with the spoofer library injected (DYLD_INSERT_LIBRARIES on macOS,
+      LD_PRELOAD on Linux). Without --uuid, --seed or --profile the usual configuration
+      applies.
+      --profile  use the stored profile <NAME> (see profile below)
+      --library  the library to inject (default: $UUID_SPOOF_LIBRARY, then next to uuid_spoof)
+      --force    launch even if the target is known to ignore the injected library
+  verify [--uuid <UUID> | --seed <SEED> | --profile <NAME>] [--library <PATH>]
+      [--reader <PATH>] [[--] <program>]
+      Run uuid_reader --expect under the injected library and report PASS or FAIL with the
+      reason; with <program>, also check that it will load the library. Exits with
+      status 1 on failure.
+  scan [--json] <path>
+      List the Mach-O images in <path> (a binary or a directory such as an .app bundle, walked
+      recursively) with the identity APIs each imports, and flag hardened-runtime and
+      library-validation signatures that would keep the injected library out.
+      --json     print the report as a JSON array
+  patch [--weak] [--install-name <NAME>] <binary>
+  patch --remove [--install-name <NAME>] <binary>
+  patch --restore <binary>
+      Add a load command for the library to a Mach-O <binary> (thin or fat), so it loads the
+      library at launch without DYLD_INSERT_LIBRARIES, or remove it again. The code signature
+      is removed, as the change invalidates it; re-sign the binary before launching it. The
+      first patch saves the original as <binary>.uuid_spoof-backup, with a restore manifest,
+      and --restore puts it back.
+      --install-name  the path dyld loads the library from
+                      (default: @executable_path/../Frameworks/libuuid_spoofer.dylib)
+      --weak          launch even if the library is missing
+  profile new [--model <MODEL>] [--serial-format <FORMAT>] [--force] <name>
+  profile list
+  profile models
+  profile show [--app <ID>] <name>
+  profile rm <name>
+  profile rename <old> <new>
+  profile export <name> [<file>]
+  profile import [--force] <file> [<name>]
+      Manage the profile store ($UUID_SPOOF_PROFILE_DIR, default ~/.config/uuid-spoof/profiles),
+      one <name>.toml file per identity; the library uses the one $UUID_SPOOF_PROFILE names.
+      new generates a consistent UUID, serial number, board-id and target-type for <MODEL>
+      (default: Macmini8,1) from the model catalog, which models lists: the built-in one
+      extended by $UUID_SPOOF_CATALOG or ~/.config/uuid-spoof/models.toml, and MAC
+      addresses for en0 and every other interface.
+      show prints every value the hooks will return under the profile, for the
+      application <ID> if the profile has a seed, and warns about values that contradict
+      each other, as import does; export writes to standard output without <file>; import
+      reads standard input for a <file> of -, and names the profile after the file unless
+      <name> is given.
+      --serial-format  legacy11, legacy12 or random10, the serial number layout of Macs
+                       from before 2010, 2010-2020 and since 2021 (default: the one
+                       <MODEL> was launched with)
+      --force          replace an existing profile
+  help
+      Show this message.
+";
+
+// Exit status for command-line mistakes, as used by most Unix tools.
+const USAGE_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    let mut args = args::Args::from_env();
+    let result = match args.next_string().as_deref() {
+        Some("run") => run::run(args),
+        Some("verify") => verify::verify(args),
+        Some("scan") => scan::run_scan(args),
+        Some("patch") => patch::patch(args),
+        Some("profile") => profile::profile(args),
+        Some("help" | "-h" | "--help") => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Some(other) => Err(format!("unknown command '{}'", other)),
+        None => Err("no command given".to_string()),
+    };
+    match result {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("uuid_spoof: {}", message);
+            eprint!("\n{}", USAGE);
+            ExitCode::from(USAGE_ERROR)
+        }
+    }
+}
@@ -0,0 +1,376 @@
+// `uuid_spoof patch`: adds (or removes) a load command for the spoofer library to a Mach-O
+// binary, so it loads the library at launch without DYLD_INSERT_LIBRARIES.
+//
+// The first patch copies the untouched binary next to it and writes a restore manifest
+// listing what was changed; `--restore` puts the copy back. The byte-level work is in
+// `uuid_spoofer_core::load_dylib`, so this also works on Linux against a copied bundle.
+
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use serde::{Deserialize, Serialize};
+
+use uuid_spoofer_core::load_dylib;
+use uuid_spoofer_core::macho;
+
+use crate::args::Args;
+
+// Where the library is looked for when it is copied into the bundle's Frameworks.
+pub const DEFAULT_INSTALL_NAME: &str = "@executable_path/../Frameworks/libuuid_spoofer.dylib";
+
+const BACKUP_SUFFIX: &str = ".uuid_spoof-backup";
+const MANIFEST_SUFFIX: &str = ".uuid_spoof-restore.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Add,
+    Remove,
+    Restore,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PatchOptions {
+    pub action: Action,
+    pub install_name: String,
+    pub weak: bool,
+    pub binary: PathBuf,
+}
+
+pub fn parse(mut args: Args) -> Result<PatchOptions, String> {
+    let mut action = Action::Add;
+    let mut install_name = None;
+    let mut weak = false;
+    let mut binary = None;
+    while let Some(arg) = args.next_string() {
+        match arg.as_str() {
+            "--remove" if action == Action::Add => action = Action::Remove,
+            "--restore" if action == Action::Add => action = Action::Restore,
+            "--remove" | "--restore" => {
+                return Err("patch: give at most one of --remove and --restore".to_string())
+            }
+            "--weak" => weak = true,
+            "--install-name" => install_name = Some(args.value("--install-name")?),
+            flag if flag.starts_with('-') => {
+                return Err(format!("patch: unknown option '{}'", flag));
+            }
+            _ if binary.is_some() => return Err("patch: give one binary to patch".to_string()),
+            _ => binary = Some(PathBuf::from(arg)),
+        }
+    }
+    if action == Action::Restore && (weak || install_name.is_some()) {
+        return Err("patch: --restore takes no other options".to_string());
+    }
+    if action == Action::Remove && weak {
+        return Err("patch: --weak only applies when adding".to_string());
+    }
+    let install_name = install_name.unwrap_or_else(|| DEFAULT_INSTALL_NAME.to_string());
+    if install_name.is_empty() || install_name.contains('\0') {
+        return Err("patch: invalid --install-name".to_string());
+    }
+    Ok(PatchOptions {
+        action,
+        install_name,
+        weak,
+        binary: binary.ok_or("patch: no binary given")?,
+    })
+}
+
+// One patch applied to the binary.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub action: Action,
+    pub install_name: String,
+    pub weak: bool,
+    // Architectures whose code signature was removed.
+    pub stripped_signatures: Vec<String>,
+}
+
+// Written next to the binary by the first patch; says how to undo every patch since.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub binary: PathBuf,
+    pub backup: PathBuf,
+    pub changes: Vec<Change>,
+}
+
+fn with_suffix(binary: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(binary.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+pub fn backup_path(binary: &Path) -> PathBuf {
+    with_suffix(binary, BACKUP_SUFFIX)
+}
+
+pub fn manifest_path(binary: &Path) -> PathBuf {
+    with_suffix(binary, MANIFEST_SUFFIX)
+}
+
+pub fn read_manifest(binary: &Path) -> Result<Option<Manifest>, String> {
+    let path = manifest_path(binary);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("cannot read {}: {}", path.display(), e)),
+    };
+    serde_json::from_str(&text)
+        .map(Some)
+        .map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write_manifest(binary: &Path, manifest: &Manifest) -> Result<(), String> {
+    let path = manifest_path(binary);
+    let text = serde_json::to_string_pretty(manifest).expect("manifests always serialize") + "\n";
+    fs::write(&path, text).map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
+
+// Replaces `path` with `data` in one step, keeping its permissions, so an interrupted
+// patch never leaves half a binary behind.
+fn replace_file(path: &Path, data: &[u8], permissions: fs::Permissions) -> Result<(), String> {
+    let temporary = with_suffix(path, ".uuid_spoof-tmp");
+    fs::write(&temporary, data)
+        .and_then(|()| fs::set_permissions(&temporary, permissions))
+        .and_then(|()| fs::rename(&temporary, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temporary);
+            format!("cannot write {}: {}", path.display(), e)
+        })
+}
+
+// Patches (or restores) the binary and records the change; returns the updated manifest.
+pub fn patch_binary(options: &PatchOptions) -> Result<Manifest, String> {
+    let binary = &options.binary;
+    if options.action == Action::Restore {
+        return restore_binary(binary);
+    }
+    let data = fs::read(binary).map_err(|e| format!("cannot read {}: {}", binary.display(), e))?;
+    let permissions = fs::metadata(binary)
+        .map_err(|e| format!("cannot read {}: {}", binary.display(), e))?
+        .permissions();
+    let patched = if options.action == Action::Add {
+        load_dylib::add_dylib(&data, &options.install_name, options.weak)
+    } else {
+        load_dylib::remove_dylib(&data, &options.install_name)
+    }
+    .map_err(|e| format!("{}: {}", binary.display(), e))?;
+
+    let mut manifest = match read_manifest(binary)? {
+        Some(manifest) => manifest,
+        None => {
+            // Only the first patch backs up: that copy is the untouched binary. Absolute
+            // paths, so --restore works from any directory.
+            let binary = fs::canonicalize(binary)
+                .map_err(|e| format!("cannot resolve {}: {}", binary.display(), e))?;
+            let backup = backup_path(&binary);
+            fs::copy(&binary, &backup)
+                .map_err(|e| format!("cannot back up to {}: {}", backup.display(), e))?;
+            Manifest {
+                binary,
+                backup,
+                changes: Vec::new(),
+            }
+        }
+    };
+    manifest.changes.push(Change {
+        action: options.action,
+        install_name: options.install_name.clone(),
+        weak: options.weak,
+        stripped_signatures: patched
+            .slices
+            .iter()
+            .filter(|slice| slice.stripped_signature)
+            .map(|slice| macho::cpu_name(slice.cputype))
+            .collect(),
+    });
+    replace_file(binary, &patched.data, permissions)?;
+    write_manifest(binary, &manifest)?;
+    Ok(manifest)
+}
+
+// Puts the backup back and removes it and the manifest.
+pub fn restore_binary(binary: &Path) -> Result<Manifest, String> {
+    let manifest = read_manifest(binary)?.ok_or_else(|| {
+        format!(
+            "{} has no restore manifest; it was not patched by uuid_spoof",
+            binary.display()
+        )
+    })?;
+    let backup = &manifest.backup;
+    let data = fs::read(backup).map_err(|e| format!("cannot read {}: {}", backup.display(), e))?;
+    let permissions = fs::metadata(backup)
+        .map_err(|e| format!("cannot read {}: {}", backup.display(), e))?
+        .permissions();
+    replace_file(binary, &data, permissions)?;
+    for leftover in [backup.clone(), manifest_path(binary)] {
+        fs::remove_file(&leftover)
+            .map_err(|e| format!("cannot remove {}: {}", leftover.display(), e))?;
+    }
+    Ok(manifest)
+}
+
+pub fn patch(args: Args) -> Result<ExitCode, String> {
+    let options = parse(args)?;
+    let shown = options.binary.display();
+    let manifest = match patch_binary(&options) {
+        Ok(manifest) => manifest,
+        Err(message) => {
+            eprintln!("uuid_spoof: {}", message);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    if options.action == Action::Restore {
+        println!(
+            "restored {} from {} (undoing {} change(s))",
+            shown,
+            manifest.backup.display(),
+            manifest.changes.len()
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+    let change = manifest.changes.last().expect("a change was just recorded");
+    if options.action == Action::Add {
+        println!("{} now loads {}", shown, options.install_name);
+    } else {
+        println!("{} no longer loads {}", shown, options.install_name);
+    }
+    if !change.stripped_signatures.is_empty() {
+        println!(
+            "removed the now invalid code signature ({}); re-sign before launching, e.g. \
+             codesign --force --sign - {}",
+            change.stripped_signatures.join(", "),
+            shown
+        );
+    }
+    println!(
+        "the original is saved as {}; undo with: uuid_spoof patch --restore {}",
+        manifest.backup.display(),
+        shown
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use uuid_spoofer_core::load_dylib::dylibs;
+    use uuid_spoofer_core::macho::MachO;
+
+    const X86_64_DYLIB: &[u8] =
+        include_bytes!("../../../uuid_spoofer_core/tests/fixtures/x86_64_dylib");
+
+    fn parsed(args: &[&str]) -> Result<PatchOptions, String> {
+        parse(Args::new(args.iter().copied()))
+    }
+
+    fn loaded(path: &Path) -> Vec<String> {
+        let data = fs::read(path).unwrap();
+        dylibs(&MachO::parse(&data).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|dylib| dylib.name)
+            .collect()
+    }
+
+    #[test]
+    fn parses_actions_and_options() {
+        assert_eq!(
+            parsed(&["App"]),
+            Ok(PatchOptions {
+                action: Action::Add,
+                install_name: DEFAULT_INSTALL_NAME.to_string(),
+                weak: false,
+                binary: PathBuf::from("App"),
+            })
+        );
+        let options = parsed(&["--weak", "--install-name", "@rpath/libs.dylib", "App"]).unwrap();
+        assert!(options.weak);
+        assert_eq!(options.install_name, "@rpath/libs.dylib");
+        assert_eq!(parsed(&["--remove", "App"]).unwrap().action, Action::Remove);
+        assert_eq!(
+            parsed(&["--restore", "App"]).unwrap().action,
+            Action::Restore
+        );
+        assert!(parsed(&[]).is_err());
+        assert!(parsed(&["--remove", "--restore", "App"]).is_err());
+        assert!(parsed(&["--restore", "--weak", "App"]).is_err());
+        assert!(parsed(&["--remove", "--weak", "App"]).is_err());
+        assert!(parsed(&["--install-name", "", "App"]).is_err());
+        assert!(parsed(&["App", "Other"]).is_err());
+    }
+
+    #[test]
+    fn patches_and_restores_with_a_manifest() {
+        let dir = std::env::temp_dir().join(format!("uuid_spoof_patch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("App");
+        fs::write(&binary, X86_64_DYLIB).unwrap();
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut options = parsed(&[binary.to_str().unwrap()]).unwrap();
+        let manifest = patch_binary(&options).unwrap();
+        assert_eq!(manifest.backup, dir.join("App.uuid_spoof-backup"));
+        assert_eq!(fs::read(&manifest.backup).unwrap(), X86_64_DYLIB);
+        assert_eq!(loaded(&binary).last().unwrap(), DEFAULT_INSTALL_NAME);
+        assert_eq!(
+            fs::metadata(&binary).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+        // Patching twice is refused, and leaves everything as it was.
+        let error = patch_binary(&options).unwrap_err();
+        assert!(error.contains("already loaded"), "{}", error);
+
+        options.action = Action::Remove;
+        options.install_name = "/usr/lib/libSystem.B.dylib".to_string();
+        let manifest = patch_binary(&options).unwrap();
+        assert_eq!(
+            manifest.changes,
+            [
+                Change {
+                    action: Action::Add,
+                    install_name: DEFAULT_INSTALL_NAME.to_string(),
+                    weak: false,
+                    stripped_signatures: Vec::new(),
+                },
+                Change {
+                    action: Action::Remove,
+                    install_name: "/usr/lib/libSystem.B.dylib".to_string(),
+                    weak: false,
+                    stripped_signatures: Vec::new(),
+                },
+            ]
+        );
+        assert_eq!(read_manifest(&binary).unwrap(), Some(manifest));
+        // The backup is still the untouched binary.
+        assert_eq!(fs::read(backup_path(&binary)).unwrap(), X86_64_DYLIB);
+
+        options.action = Action::Restore;
+        let restored = patch_binary(&options).unwrap();
+        assert_eq!(restored.changes.len(), 2);
+        assert_eq!(fs::read(&binary).unwrap(), X86_64_DYLIB);
+        assert!(!backup_path(&binary).exists());
+        assert!(!manifest_path(&binary).exists());
+        assert!(restore_binary(&binary)
+            .unwrap_err()
+            .contains("no restore manifest"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failures_leave_no_backup() {
+        let dir =
+            std::env::temp_dir().join(format!("uuid_spoof_patch_fail_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("notes.txt");
+        fs::write(&binary, "not a binary").unwrap();
+        let options = parsed(&[binary.to_str().unwrap()]).unwrap();
+        assert!(patch_binary(&options).is_err());
+        assert!(!backup_path(&binary).exists());
+        assert_eq!(fs::read(&binary).unwrap(), b"not a binary");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
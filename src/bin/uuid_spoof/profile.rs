@@ -0,0 +1,756 @@
+// `uuid_spoof profile`: manages the profile store, the directory of named identities the
+// library picks from with UUID_SPOOF_PROFILE (see `uuid_spoofer_core::profile_store`).
+//
+// `show` resolves a profile the way the library does, environment and config file
+// included, and prints every value in the form each hook hands it out.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use uuid_spoofer_core::catalog::{self, Catalog};
+use uuid_spoofer_core::config::{self, Resolved, SpoofMode};
+use uuid_spoofer_core::generate::{self, MachineIdentity, DEFAULT_MODEL};
+use uuid_spoofer_core::id_files::{self, IdFile};
+use uuid_spoofer_core::identity::{
+    PropertyValue, IO_PLATFORM_SERIAL_NUMBER_KEY, IO_PLATFORM_UUID_KEY, MODEL_KEY,
+};
+use uuid_spoofer_core::profile_store::{self, ProfileStore};
+use uuid_spoofer_core::serial::SerialFormat;
+use uuid_spoofer_core::uuid::uuid_to_host_id;
+
+use crate::args::Args;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ProfileCommand {
+    New {
+        name: String,
+        model: String,
+        // The model's own format unless given.
+        serial_format: Option<SerialFormat>,
+        force: bool,
+    },
+    List,
+    // The model catalog.
+    Models,
+    Show {
+        name: String,
+        // The application whose derived UUID to show, for seeded profiles.
+        app: Option<String>,
+    },
+    Remove {
+        name: String,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+    // To `file`, or standard output.
+    Export {
+        name: String,
+        file: Option<PathBuf>,
+    },
+    // From `file` ("-" for standard input), named after it unless `name` is given.
+    Import {
+        file: PathBuf,
+        name: Option<String>,
+        force: bool,
+    },
+}
+
+pub fn parse(mut args: Args) -> Result<ProfileCommand, String> {
+    let action = args.next_string().ok_or("profile: no action given")?;
+    let context = format!("profile {}", action);
+    let mut model = None;
+    let mut serial_format = None;
+    let mut app = None;
+    let mut force = false;
+    let mut operands = Vec::new();
+    while let Some(arg) = args.next_string() {
+        match (action.as_str(), arg.as_str()) {
+            ("new", "--model") => model = Some(args.value("--model")?),
+            ("new", "--serial-format") => {
+                serial_format = Some(
+                    args.value("--serial-format")?
+                        .parse()
+                        .map_err(|e| format!("{}: {}", context, e))?,
+                );
+            }
+            ("show", "--app") => app = Some(args.value("--app")?),
+            ("new" | "import", "--force") => force = true,
+            // A lone "-" is standard input or output.
+            (_, flag) if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("{}: unknown option '{}'", context, flag));
+            }
+            _ => operands.push(arg),
+        }
+    }
+    let (required, optional) = match action.as_str() {
+        "list" | "models" => (0, 0),
+        "new" | "show" | "rm" => (1, 0),
+        "rename" => (2, 0),
+        "export" | "import" => (1, 1),
+        _ => return Err(format!("profile: unknown action '{}'", action)),
+    };
+    if operands.len() < required || operands.len() > required + optional {
+        return Err(format!("{}: wrong number of arguments", context));
+    }
+    let mut operands = operands.into_iter();
+    let mut operand = || operands.next();
+    Ok(match action.as_str() {
+        "new" => ProfileCommand::New {
+            name: operand().unwrap_or_default(),
+            model: model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            serial_format,
+            force,
+        },
+        "list" => ProfileCommand::List,
+        "models" => ProfileCommand::Models,
+        "show" => ProfileCommand::Show {
+            name: operand().unwrap_or_default(),
+            app,
+        },
+        "rm" => ProfileCommand::Remove {
+            name: operand().unwrap_or_default(),
+        },
+        "rename" => ProfileCommand::Rename {
+            from: operand().unwrap_or_default(),
+            to: operand().unwrap_or_default(),
+        },
+        "export" => ProfileCommand::Export {
+            name: operand().unwrap_or_default(),
+            file: operand().filter(|file| file != "-").map(PathBuf::from),
+        },
+        _ => {
+            let file = PathBuf::from(operand().unwrap_or_default());
+            let name = operand();
+            if name.is_none() && file == Path::new("-") {
+                return Err(
+                    "profile import: give a name when importing from standard input".to_string(),
+                );
+            }
+            ProfileCommand::Import { file, name, force }
+        }
+    })
+}
+
+// Creates profile `name` with a fresh identity for `model` from `catalog`, with serial
+// numbers in the model's own format unless `serial_format` is given.
+pub fn create(
+    store: &ProfileStore,
+    catalog: &Catalog,
+    name: &str,
+    model: &str,
+    serial_format: Option<SerialFormat>,
+    force: bool,
+    entropy: &[u8],
+) -> Result<(PathBuf, MachineIdentity), String> {
+    let model = catalog.model(model).ok_or_else(|| {
+        let known: Vec<_> = catalog
+            .models()
+            .iter()
+            .map(|model| model.identifier.as_str())
+            .collect();
+        format!(
+            "unknown model '{}'; known models: {}",
+            model,
+            known.join(", ")
+        )
+    })?;
+    let serial_format = serial_format.unwrap_or_else(|| model.serial_format());
+    if !model.supports(serial_format) {
+        return Err(format!(
+            "{} was only made with random10 serial numbers",
+            model.identifier
+        ));
+    }
+    let identity = MachineIdentity::generate(model, serial_format, entropy);
+    let path = store
+        .save(name, &identity.to_toml(), force)
+        .map_err(|e| e.to_string())?;
+    Ok((path, identity))
+}
+
+// One line per stored profile: its name, UUID (or that it derives one), model and serial.
+pub fn list(store: &ProfileStore) -> Result<String, String> {
+    let names = store.names().map_err(|e| e.to_string())?;
+    if names.is_empty() {
+        return Ok(format!("no profiles in {}\n", store.dir().display()));
+    }
+    let width = names.iter().map(String::len).max().unwrap_or(0).max(4);
+    let mut out = format!(
+        "{:<width$}  {:<36}  {:<15}  SERIAL\n",
+        "NAME", "UUID", "MODEL"
+    );
+    for name in &names {
+        match store.load(name) {
+            Ok(profile) => {
+                let uuid = match (&profile.uuid, &profile.seed) {
+                    (Some(uuid), _) => uuid.clone(),
+                    (None, Some(_)) => "(derived per application)".to_string(),
+                    (None, None) => "-".to_string(),
+                };
+                out += &format!(
+                    "{:<width$}  {:<36}  {:<15}  {}\n",
+                    name,
+                    uuid,
+                    profile.identity.text(MODEL_KEY).unwrap_or("-"),
+                    profile
+                        .identity
+                        .text(IO_PLATFORM_SERIAL_NUMBER_KEY)
+                        .unwrap_or("-"),
+                );
+            }
+            Err(e) => out += &format!("{:<width$}  invalid: {}\n", name, e),
+        }
+    }
+    Ok(out)
+}
+
+// The text of NUL-terminated string data, as board-id and model are stored.
+fn c_string(bytes: &[u8]) -> Option<&str> {
+    std::str::from_utf8(bytes.strip_suffix(&[0])?).ok()
+}
+
+fn describe_value(value: &PropertyValue) -> (&'static str, String) {
+    match value {
+        PropertyValue::String(s) => ("CFString", format!("{:?}", s)),
+        PropertyValue::Number(n) => ("CFNumber", n.to_string()),
+        PropertyValue::Data(bytes) => {
+            let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            match c_string(bytes) {
+                Some(text) => ("CFData", format!("<{}> ({:?})", hex, text)),
+                None => ("CFData", format!("<{}>", hex)),
+            }
+        }
+    }
+}
+
+// The catalog's models, one per line.
+pub fn models(catalog: &Catalog, extended_by: Option<&Path>) -> String {
+    let mut out = match extended_by {
+        Some(path) => format!("built-in models, extended by {}:\n", path.display()),
+        None => "built-in models:\n".to_string(),
+    };
+    out += &format!(
+        "{:<16}  {:<20}  {:<7}  {:<6}  {:<9}  SERIAL CODES\n",
+        "MODEL", "BOARD-ID", "TARGET", "ARCH", "YEARS"
+    );
+    for model in catalog.models() {
+        out += &format!(
+            "{:<16}  {:<20}  {:<7}  {:<6}  {:<9}  {}\n",
+            model.identifier,
+            model.board_id,
+            model.target_type.as_deref().unwrap_or("-"),
+            model.architecture,
+            format!("{}-{}", model.years.0, model.years.1),
+            match model.serial_codes.is_empty() {
+                true => "-".to_string(),
+                false => model.serial_codes.join(" "),
+            }
+        );
+    }
+    out
+}
+
+// What the hooks return under `resolved`, to the application `app_id` if the UUID is
+// derived per application, and what in it contradicts `catalog`.
+pub fn describe(resolved: &Resolved, catalog: &Catalog, app_id: Option<&str>) -> String {
+    let mut out = format!("UUID from {}\n", resolved.source);
+    let uuid = match (&resolved.mode, app_id) {
+        (SpoofMode::Fixed(uuid), _) => Some(uuid.clone()),
+        (SpoofMode::Derived { .. }, Some(app_id)) => {
+            out += &format!("derived for {}\n", app_id);
+            Some(resolved.uuid_for(app_id))
+        }
+        (SpoofMode::Derived { .. }, None) => {
+            out += "derived per application; give --app <ID> to see what one gets\n";
+            None
+        }
+    };
+    let mut table = resolved.identity.clone();
+    if let Some(uuid) = &uuid {
+        table.insert(IO_PLATFORM_UUID_KEY, PropertyValue::String(uuid.clone()));
+    }
+    out += "\nmacOS IORegistry properties:\n";
+    for (key, value) in table.iter() {
+        let (kind, shown) = describe_value(value);
+        out += &format!("  {:<24} {:<9} {}\n", key, kind, shown);
+    }
+    let inconsistencies = catalog.check(&table);
+    if !inconsistencies.is_empty() {
+        out += "  warning: these values give each other away:\n";
+        for inconsistency in inconsistencies {
+            out += &format!("    {}\n", inconsistency);
+        }
+    }
+    if let Some(uuid) = uuid {
+        out += &format!("  {:<34} {}\n", "gethostuuid, kern.uuid", uuid);
+        out += "\nLinux:\n";
+        for (path, file) in [
+            (id_files::ETC_MACHINE_ID, IdFile::MachineId),
+            (id_files::DMI_PRODUCT_UUID, IdFile::ProductUuid),
+        ] {
+            if let Ok(contents) = file.contents(&uuid) {
+                out += &format!("  {:<34} {}\n", path, contents.trim_end());
+            }
+        }
+        if let Ok(host_id) = uuid_to_host_id(&uuid) {
+            out += &format!("  {:<34} {:08x}\n", "gethostid", host_id);
+        }
+    }
+    let mac_addresses = &resolved.mac_addresses;
+    if !mac_addresses.is_empty() {
+        out += "\nMAC addresses (IOMACAddress, getifaddrs, SIOCGIFHWADDR):\n";
+        for (interface, address) in mac_addresses.iter() {
+            out += &format!("  {:<34} {}\n", interface, address);
+        }
+        if mac_addresses.generates_others() {
+            out += "  every other interface with an address gets a generated one\n";
+        }
+    }
+    out
+}
+
+fn read_import(file: &Path) -> Result<String, String> {
+    if file == Path::new("-") {
+        let mut text = String::new();
+        std::io::stdin()
+            .read_to_string(&mut text)
+            .map_err(|e| format!("cannot read standard input: {}", e))?;
+        return Ok(text);
+    }
+    std::fs::read_to_string(file).map_err(|e| format!("cannot read {}: {}", file.display(), e))
+}
+
+// The profile name an imported file gets by default: its name without the extension.
+fn import_name(file: &Path) -> Result<String, String> {
+    file.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| format!("cannot name a profile after {}", file.display()))
+}
+
+fn execute(store: &ProfileStore, command: ProfileCommand) -> Result<(), String> {
+    let error = |e: profile_store::StoreError| e.to_string();
+    let catalog = || Catalog::installed().map_err(|e| e.to_string());
+    match command {
+        ProfileCommand::New {
+            name,
+            model,
+            serial_format,
+            force,
+        } => {
+            let entropy =
+                generate::entropy().map_err(|e| format!("cannot read random bytes: {}", e))?;
+            let (path, identity) = create(
+                store,
+                catalog()?,
+                &name,
+                &model,
+                serial_format,
+                force,
+                &entropy,
+            )?;
+            println!(
+                "created profile {:?} in {}: a {} with serial {}, MAC {} and UUID {}",
+                name,
+                path.display(),
+                identity.model.identifier,
+                identity.serial,
+                identity.mac,
+                identity.uuid
+            );
+            println!(
+                "use it with: {}={} or uuid_spoof run --profile {} <program>",
+                config::PROFILE_ENV_VAR,
+                name,
+                name
+            );
+        }
+        ProfileCommand::List => print!("{}", list(store)?),
+        ProfileCommand::Models => {
+            print!("{}", models(catalog()?, catalog::user_path().as_deref()));
+        }
+        ProfileCommand::Show { name, app } => {
+            // As the library resolves it: a UUID or seed from the environment still wins.
+            let resolved = config::resolve_without_profile()
+                .map_err(|e| e.to_string())?
+                .with_stored_profile(store, &name)
+                .map_err(error)?;
+            print!("{}", describe(&resolved, catalog()?, app.as_deref()));
+        }
+        ProfileCommand::Remove { name } => {
+            store.remove(&name).map_err(error)?;
+            println!("removed profile {:?}", name);
+        }
+        ProfileCommand::Rename { from, to } => {
+            store.rename(&from, &to).map_err(error)?;
+            println!("renamed profile {:?} to {:?}", from, to);
+        }
+        ProfileCommand::Export { name, file } => {
+            let text = store.read(&name).map_err(error)?;
+            match file {
+                Some(file) => std::fs::write(&file, text)
+                    .map_err(|e| format!("cannot write {}: {}", file.display(), e))?,
+                None => std::io::stdout()
+                    .write_all(text.as_bytes())
+                    .map_err(|e| format!("cannot write the profile: {}", e))?,
+            }
+        }
+        ProfileCommand::Import { file, name, force } => {
+            let name = match name {
+                Some(name) => name,
+                None => import_name(&file)?,
+            };
+            let text = read_import(&file)?;
+            let path = store.save(&name, &text, force).map_err(error)?;
+            println!("imported profile {:?} into {}", name, path.display());
+            let profile = store.load(&name).map_err(error)?;
+            for inconsistency in catalog()?.check(&profile.identity) {
+                eprintln!("uuid_spoof: warning: {}: {}", name, inconsistency);
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn profile(args: Args) -> Result<ExitCode, String> {
+    let command = parse(args)?;
+    let result = ProfileStore::from_env()
+        .map_err(|e| e.to_string())
+        .and_then(|store| execute(&store, command));
+    match result {
+        Ok(()) => Ok(ExitCode::SUCCESS),
+        Err(message) => {
+            eprintln!("uuid_spoof: {}", message);
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid_spoofer_core::config::resolve_from;
+
+    fn parsed(args: &[&str]) -> Result<ProfileCommand, String> {
+        parse(Args::new(args.iter().copied()))
+    }
+
+    fn temp_store(name: &str) -> ProfileStore {
+        let dir = std::env::temp_dir().join(format!(
+            "uuid_spoof_profile_{}_{}",
+            std::process::id(),
+            name
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        ProfileStore::new(dir)
+    }
+
+    #[test]
+    fn parses_actions() {
+        assert_eq!(
+            parsed(&[
+                "new",
+                "acme",
+                "--model",
+                "MacPro7,1",
+                "--serial-format",
+                "random10",
+                "--force"
+            ]),
+            Ok(ProfileCommand::New {
+                name: "acme".into(),
+                model: "MacPro7,1".into(),
+                serial_format: Some(SerialFormat::Random10),
+                force: true
+            })
+        );
+        assert_eq!(
+            parsed(&["new", "acme"]),
+            Ok(ProfileCommand::New {
+                name: "acme".into(),
+                model: DEFAULT_MODEL.into(),
+                serial_format: None,
+                force: false
+            })
+        );
+        assert_eq!(parsed(&["list"]), Ok(ProfileCommand::List));
+        assert_eq!(parsed(&["models"]), Ok(ProfileCommand::Models));
+        assert_eq!(
+            parsed(&["show", "--app", "com.example.app", "acme"]),
+            Ok(ProfileCommand::Show {
+                name: "acme".into(),
+                app: Some("com.example.app".into())
+            })
+        );
+        assert_eq!(
+            parsed(&["rename", "a", "b"]),
+            Ok(ProfileCommand::Rename {
+                from: "a".into(),
+                to: "b".into()
+            })
+        );
+        assert_eq!(
+            parsed(&["export", "acme", "-"]),
+            Ok(ProfileCommand::Export {
+                name: "acme".into(),
+                file: None
+            })
+        );
+        assert_eq!(
+            parsed(&["import", "--force", "/tmp/acme.toml"]),
+            Ok(ProfileCommand::Import {
+                file: PathBuf::from("/tmp/acme.toml"),
+                name: None,
+                force: true
+            })
+        );
+        assert_eq!(
+            parsed(&["import", "-", "acme"]),
+            Ok(ProfileCommand::Import {
+                file: PathBuf::from("-"),
+                name: Some("acme".into()),
+                force: false
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for args in [
+            &[][..],
+            &["frobnicate"],
+            &["list", "extra"],
+            &["new"],
+            &["rm", "a", "b"],
+            &["rename", "a"],
+            &["show", "--force", "a"],
+            &["list", "--model", "x"],
+            &["import", "-"],
+            &["new", "--model"],
+            &["new", "--serial-format", "legacy13", "acme"],
+        ] {
+            assert!(parsed(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn new_profiles_are_coherent_and_listed() {
+        let store = temp_store("new");
+        let (path, identity) = create(
+            &store,
+            Catalog::builtin(),
+            "acme",
+            "MacBookPro15,1",
+            None,
+            false,
+            b"one",
+        )
+        .unwrap();
+        assert_eq!(path, store.path("acme"));
+        assert!(create(
+            &store,
+            Catalog::builtin(),
+            "acme",
+            "MacBookPro15,1",
+            None,
+            false,
+            b"two"
+        )
+        .is_err());
+        let err = create(
+            &store,
+            Catalog::builtin(),
+            "other",
+            "PowerBook1,1",
+            None,
+            false,
+            b"one",
+        )
+        .unwrap_err();
+        assert!(err.starts_with("unknown model 'PowerBook1,1'; known models: iMac19,1"));
+        let err = create(
+            &store,
+            Catalog::builtin(),
+            "other",
+            "Mac14,2",
+            Some(SerialFormat::Legacy12),
+            false,
+            b"one",
+        )
+        .unwrap_err();
+        assert_eq!(err, "Mac14,2 was only made with random10 serial numbers");
+        let (_, air) = create(
+            &store,
+            Catalog::builtin(),
+            "air",
+            "Mac14,2",
+            None,
+            false,
+            b"one",
+        )
+        .unwrap();
+        assert_eq!(air.serial_format, SerialFormat::Random10);
+        store.remove("air").unwrap();
+
+        let profile = store.load("acme").unwrap();
+        assert_eq!(profile.uuid.as_deref(), Some(identity.uuid.as_str()));
+        assert_eq!(
+            profile.identity.get("board-id"),
+            Some(&PropertyValue::Data(b"Mac-937A206F2EE63C01\0".to_vec()))
+        );
+        assert_eq!(profile.identity.get("IOMACAddress"), None);
+        assert_eq!(
+            profile
+                .mac_addresses
+                .for_interface(generate::PRIMARY_INTERFACE),
+            Some(identity.mac)
+        );
+        assert!(profile.mac_addresses.generates_others());
+
+        store.save("chat", "seed = \"secret\"\n", false).unwrap();
+        std::fs::write(store.path("broken"), "uuid = 3\n").unwrap();
+        let listing = list(&store).unwrap();
+        let lines: Vec<_> = listing.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", listing);
+        assert!(lines[0].starts_with("NAME  "), "{}", listing);
+        assert!(
+            lines[1].starts_with("acme  ")
+                && lines[1].contains(&identity.uuid)
+                && lines[1].contains("MacBookPro15,1")
+                && lines[1].ends_with(&identity.serial),
+            "{}",
+            listing
+        );
+        assert!(lines[2].contains("invalid: "), "{}", listing);
+        assert!(
+            lines[3].contains("(derived per application)"),
+            "{}",
+            listing
+        );
+        std::fs::remove_dir_all(store.dir()).unwrap();
+
+        assert!(list(&store).unwrap().starts_with("no profiles in "));
+    }
+
+    #[test]
+    fn describes_what_the_hooks_return() {
+        let store = temp_store("show");
+        store
+            .save(
+                "acme",
+                "uuid = \"DEADBEEF-0123-4567-89AB-CDEF00112233\"\n\
+                 [properties]\n\
+                 IOPlatformSerialNumber = \"C02XK0AAJYVX\"\n\
+                 IOMACAddress = \"0a:00:00:00:00:01\"\n\
+                 board-id = \"Mac-7BA5B2DFE22DDD8C\"\n\
+                 [mac_addresses]\n\
+                 en0 = \"0a:00:00:00:00:02\"\n",
+                false,
+            )
+            .unwrap();
+        let resolved = resolve_from(None, None, None)
+            .unwrap()
+            .with_stored_profile(&store, "acme")
+            .unwrap();
+        let shown = describe(&resolved, Catalog::builtin(), None);
+        for expected in [
+            format!("UUID from profile \"acme\" in {}", store.path("acme").display()),
+            "  IOMACAddress             CFData    <0a0000000001>".to_string(),
+            "  IOPlatformSerialNumber   CFString  \"C02XK0AAJYVX\"".to_string(),
+            "  IOPlatformUUID           CFString  \"DEADBEEF-0123-4567-89AB-CDEF00112233\""
+                .to_string(),
+            "  board-id                 CFData    <4d61632d3742413542324446453232444444384300> (\"Mac-7BA5B2DFE22DDD8C\")".to_string(),
+            "  /etc/machine-id                    deadbeef0123456789abcdef00112233".to_string(),
+            "  gethostid                          deadbeef".to_string(),
+            "MAC addresses (IOMACAddress, getifaddrs, SIOCGIFHWADDR):".to_string(),
+            "  en0                                0a:00:00:00:00:02".to_string(),
+            "  warning: these values give each other away:".to_string(),
+            "    model: not spoofed, though board-id is; the real model shows through"
+                .to_string(),
+        ] {
+            assert!(shown.lines().any(|line| line == expected), "{}\n{}", expected, shown);
+        }
+
+        store.save("chat", "seed = \"seed\"\n", false).unwrap();
+        let resolved = resolve_from(None, None, None)
+            .unwrap()
+            .with_stored_profile(&store, "chat")
+            .unwrap();
+        let shown = describe(&resolved, Catalog::builtin(), None);
+        assert!(shown.contains("give --app <ID>"), "{}", shown);
+        assert!(!shown.contains("IOPlatformUUID"), "{}", shown);
+        assert!(!shown.contains("MAC addresses"), "{}", shown);
+        let shown = describe(&resolved, Catalog::builtin(), Some("com.example.app"));
+        assert!(
+            shown.contains("\"04B38FDE-CB0C-82D8-92FF-34A5F82165AD\""),
+            "{}",
+            shown
+        );
+        std::fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        let store = temp_store("export");
+        create(
+            &store,
+            Catalog::builtin(),
+            "acme",
+            DEFAULT_MODEL,
+            Some(SerialFormat::Random10),
+            false,
+            b"entropy",
+        )
+        .unwrap();
+        let exported = store.dir().with_extension("acme-copy.toml");
+        execute(
+            &store,
+            ProfileCommand::Export {
+                name: "acme".into(),
+                file: Some(exported.clone()),
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            import_name(&exported).unwrap(),
+            exported.file_stem().unwrap().to_str().unwrap()
+        );
+        execute(
+            &store,
+            ProfileCommand::Import {
+                file: exported.clone(),
+                name: Some("copy".into()),
+                force: false,
+            },
+        )
+        .unwrap();
+        assert_eq!(store.read("copy").unwrap(), store.read("acme").unwrap());
+        assert!(execute(
+            &store,
+            ProfileCommand::Import {
+                file: exported.clone(),
+                name: Some("copy".into()),
+                force: false,
+            },
+        )
+        .is_err());
+
+        std::fs::write(&exported, "uuid = \"nope\"\n").unwrap();
+        let err = execute(
+            &store,
+            ProfileCommand::Import {
+                file: exported.clone(),
+                name: Some("bad".into()),
+                force: false,
+            },
+        )
+        .unwrap_err();
+        assert!(err.contains(":1: uuid: invalid UUID \"nope\""), "{}", err);
+        assert_eq!(store.names().unwrap(), ["acme", "copy"]);
+        std::fs::remove_file(exported).unwrap();
+        std::fs::remove_dir_all(store.dir()).unwrap();
+    }
+}
@@ -0,0 +1,415 @@
+// `uuid_spoof run`: exec a program with the spoofer library injected.
+//
+// The launcher replaces itself with the target (execve), so the target keeps our pid and
+// whoever started us sees its exit status and signals directly.
+
+use std::ffi::{OsStr, OsString};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use uuid_spoofer_core::{config, profile_store};
+
+use crate::args::Args;
+use crate::checks::{self, Finding, Platform, Severity};
+
+// Overrides where the library to inject is looked for.
+pub const LIBRARY_ENV_VAR: &str = "UUID_SPOOF_LIBRARY";
+
+// Shell conventions for "could not execute" and "not found".
+const EXIT_CANNOT_EXECUTE: u8 = 126;
+const EXIT_NOT_FOUND: u8 = 127;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identity {
+    Uuid(String),
+    Seed(String),
+    // A profile of the profile store, by name.
+    Profile(String),
+}
+
+// The flags shared by every command that injects the library.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Injection {
+    pub identity: Option<Identity>,
+    pub library: Option<PathBuf>,
+}
+
+impl Injection {
+    // Consumes `flag` and its value if it is one of ours; `command` prefixes errors.
+    pub fn parse_flag(
+        &mut self,
+        command: &str,
+        flag: &str,
+        args: &mut Args,
+    ) -> Result<bool, String> {
+        match flag {
+            "--uuid" | "--seed" | "--profile" if self.identity.is_some() => {
+                return Err(format!(
+                    "{}: give at most one of --uuid, --seed and --profile",
+                    command
+                ));
+            }
+            "--uuid" => {
+                let value = args.value("--uuid")?;
+                let uuid = config::validate_uuid(&value)
+                    .map_err(|e| format!("{}: invalid --uuid '{}': {}", command, value, e))?;
+                self.identity = Some(Identity::Uuid(uuid));
+            }
+            "--seed" => {
+                let seed = args.value("--seed")?;
+                if seed.is_empty() {
+                    return Err(format!("{}: --seed must not be empty", command));
+                }
+                self.identity = Some(Identity::Seed(seed));
+            }
+            "--profile" => {
+                let name = args.value("--profile")?;
+                profile_store::validate_name(&name).map_err(|e| format!("{}: {}", command, e))?;
+                self.identity = Some(Identity::Profile(name));
+            }
+            "--library" => self.library = Some(PathBuf::from(args.value("--library")?)),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn find_library(&self) -> Result<PathBuf, String> {
+        find_library(
+            self.library.as_deref(),
+            std::env::var_os(LIBRARY_ENV_VAR).as_deref(),
+            std::env::current_exe().ok().as_deref(),
+        )
+    }
+
+    // A command for `program` with `library` injected and the identity in its environment.
+    pub fn command(&self, program: &Path, library: &Path) -> Command {
+        let mut command = Command::new(program);
+        let preload_var = preload_env_var(Platform::current());
+        command.env(
+            preload_var,
+            prepend_library(std::env::var_os(preload_var).as_deref(), library),
+        );
+        match &self.identity {
+            Some(Identity::Uuid(uuid)) => {
+                command.env(config::UUID_ENV_VAR, uuid);
+            }
+            Some(Identity::Seed(seed)) => {
+                // A UUID from the environment would win over the seed.
+                command
+                    .env(config::SEED_ENV_VAR, seed)
+                    .env_remove(config::UUID_ENV_VAR);
+            }
+            Some(Identity::Profile(name)) => {
+                // As would either over the profile's own.
+                command
+                    .env(config::PROFILE_ENV_VAR, name)
+                    .env_remove(config::UUID_ENV_VAR)
+                    .env_remove(config::SEED_ENV_VAR);
+            }
+            None => {}
+        }
+        command
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct RunOptions {
+    pub injection: Injection,
+    pub force: bool,
+    pub program: OsString,
+    pub args: Vec<OsString>,
+}
+
+pub fn parse(mut args: Args) -> Result<RunOptions, String> {
+    let mut injection = Injection::default();
+    let mut force = false;
+    while let Some(arg) = args.peek() {
+        let flag = arg.to_string_lossy().into_owned();
+        if !flag.starts_with('-') {
+            break;
+        }
+        args.next();
+        if flag == "--" {
+            break;
+        }
+        if injection.parse_flag("run", &flag, &mut args)? {
+            continue;
+        }
+        match flag.as_str() {
+            "--force" => force = true,
+            _ => return Err(format!("run: unknown option '{}'", flag)),
+        }
+    }
+    let mut rest = args.rest().into_iter();
+    let program = rest
+        .next()
+        .ok_or_else(|| "run: no program given".to_string())?;
+    Ok(RunOptions {
+        injection,
+        force,
+        program,
+        args: rest.collect(),
+    })
+}
+
+// Prints `findings` and says whether they should stop the launch.
+pub fn report_findings(findings: &[Finding], force: bool) -> bool {
+    for finding in findings {
+        let label = match (finding.severity, force) {
+            (Severity::Blocking, false) => "error",
+            _ => "warning",
+        };
+        eprintln!("uuid_spoof: {}: {}", label, finding.message);
+    }
+    !force && findings.iter().any(|f| f.severity == Severity::Blocking)
+}
+
+pub fn run(args: Args) -> Result<ExitCode, String> {
+    let options = parse(args)?;
+    let library = match options.injection.find_library() {
+        Ok(library) => library,
+        Err(message) => {
+            eprintln!("uuid_spoof: {}", message);
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    let Some(program) = find_program(&options.program, std::env::var_os("PATH").as_deref()) else {
+        eprintln!(
+            "uuid_spoof: {}: command not found",
+            options.program.to_string_lossy()
+        );
+        return Ok(ExitCode::from(EXIT_NOT_FOUND));
+    };
+
+    let findings = checks::check_target(&program, Platform::current());
+    if report_findings(&findings, options.force) {
+        eprintln!("uuid_spoof: not launching; use --force to launch anyway");
+        return Ok(ExitCode::FAILURE);
+    }
+
+    let mut command = options.injection.command(&program, &library);
+    command.arg0(&options.program).args(&options.args);
+    // exec only returns on failure.
+    let error = command.exec();
+    eprintln!(
+        "uuid_spoof: cannot execute {}: {}",
+        program.display(),
+        error
+    );
+    Ok(ExitCode::from(EXIT_CANNOT_EXECUTE))
+}
+
+pub fn preload_env_var(platform: Platform) -> &'static str {
+    match platform {
+        Platform::MacOS => "DYLD_INSERT_LIBRARIES",
+        Platform::Linux => "LD_PRELOAD",
+    }
+}
+
+// `library` in front of any libraries already being injected, without repeating it.
+pub fn prepend_library(existing: Option<&OsStr>, library: &Path) -> OsString {
+    let mut value = OsString::from(library);
+    for entry in existing
+        .map(|existing| std::env::split_paths(existing).collect::<Vec<_>>())
+        .unwrap_or_default()
+    {
+        if !entry.as_os_str().is_empty() && entry != library {
+            value.push(":");
+            value.push(entry);
+        }
+    }
+    value
+}
+
+fn library_file_name() -> String {
+    format!(
+        "{}uuid_spoofer{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    )
+}
+
+// The library given on the command line, then in the environment, then installed next to
+// this executable (as in `target/<profile>/`) or in a sibling `lib/` directory.
+pub fn find_library(
+    explicit: Option<&Path>,
+    from_env: Option<&OsStr>,
+    current_exe: Option<&Path>,
+) -> Result<PathBuf, String> {
+    let given = explicit
+        .map(Path::to_path_buf)
+        .or_else(|| from_env.filter(|v| !v.is_empty()).map(PathBuf::from));
+    if let Some(library) = given {
+        // dyld resolves relative paths against the target's working directory, not ours.
+        return std::fs::canonicalize(&library)
+            .map_err(|e| format!("cannot use library {}: {}", library.display(), e));
+    }
+
+    let name = library_file_name();
+    let candidates: Vec<PathBuf> = current_exe
+        .and_then(Path::parent)
+        .map(|dir| vec![dir.join(&name), dir.join("../lib").join(&name)])
+        .unwrap_or_default();
+    candidates
+        .iter()
+        .find_map(|candidate| std::fs::canonicalize(candidate).ok())
+        .ok_or_else(|| {
+            format!(
+                "cannot find {} (looked in {}); build it with `cargo build --lib` or pass --library",
+                name,
+                candidates
+                    .iter()
+                    .map(|c| c.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })
+}
+
+// Resolves `program` the way execvp would: as a path if it contains a slash, otherwise
+// through the directories in PATH. `None` if there is no such file, which execvp
+// reports as ENOENT.
+pub fn find_program(program: &OsStr, path_var: Option<&OsStr>) -> Option<PathBuf> {
+    let program_path = Path::new(program);
+    if program.is_empty() {
+        return None;
+    }
+    if program_path.components().count() > 1 || program_path.is_absolute() {
+        return program_path.exists().then(|| program_path.to_path_buf());
+    }
+    std::env::split_paths(path_var?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(args: &[&str]) -> Result<RunOptions, String> {
+        parse(Args::new(args.iter().copied()))
+    }
+
+    #[test]
+    fn parses_options_and_program() {
+        let options = parsed(&[
+            "--uuid",
+            "12345678-9abc-def0-1234-56789abcdef0",
+            "--force",
+            "--",
+            "prog",
+            "--uuid",
+            "x",
+        ])
+        .unwrap();
+        assert_eq!(
+            options.injection.identity,
+            Some(Identity::Uuid(
+                "12345678-9ABC-DEF0-1234-56789ABCDEF0".into()
+            ))
+        );
+        assert!(options.force);
+        assert_eq!(options.program, "prog");
+        assert_eq!(options.args, ["--uuid", "x"]);
+
+        let options = parsed(&["--seed", "s", "--library", "/tmp/lib.so", "prog"]).unwrap();
+        assert_eq!(options.injection.identity, Some(Identity::Seed("s".into())));
+        assert_eq!(
+            options.injection.library,
+            Some(PathBuf::from("/tmp/lib.so"))
+        );
+        assert!(options.args.is_empty());
+
+        let options = parsed(&["--profile", "acme", "prog"]).unwrap();
+        assert_eq!(
+            options.injection.identity,
+            Some(Identity::Profile("acme".into()))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        for args in [
+            &["--uuid", "nope", "prog"][..],
+            &[
+                "--uuid",
+                "12345678-9ABC-DEF0-1234-56789ABCDEF0",
+                "--seed",
+                "s",
+                "prog",
+            ],
+            &["--seed", "", "prog"],
+            &["--seed", "s", "--profile", "acme", "prog"],
+            &["--profile", "../acme", "prog"],
+            &["--verbose", "prog"],
+            &["--force"],
+            &["--"],
+            &[],
+        ] {
+            assert!(parsed(args).is_err(), "{:?} was accepted", args);
+        }
+    }
+
+    #[test]
+    fn prepends_without_duplicates() {
+        let library = Path::new("/opt/lib/libuuid_spoofer.so");
+        assert_eq!(prepend_library(None, library), library.as_os_str());
+        assert_eq!(
+            prepend_library(
+                Some(OsStr::new("/a.so:/opt/lib/libuuid_spoofer.so:/b.so")),
+                library
+            ),
+            "/opt/lib/libuuid_spoofer.so:/a.so:/b.so"
+        );
+    }
+
+    #[test]
+    fn finds_library_next_to_exe_or_as_given() {
+        let dir = std::env::temp_dir().join(format!("uuid_spoof_run_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("bin")).unwrap();
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        let installed = dir.join("lib").join(library_file_name());
+        std::fs::write(&installed, b"").unwrap();
+        let exe = dir.join("bin").join("uuid_spoof");
+
+        let found = find_library(None, None, Some(&exe)).unwrap();
+        assert_eq!(found, installed.canonicalize().unwrap());
+        let found = find_library(None, Some(installed.as_os_str()), None).unwrap();
+        assert_eq!(found, installed.canonicalize().unwrap());
+        assert!(find_library(Some(&dir.join("missing.so")), None, Some(&exe)).is_err());
+        let error = find_library(None, None, Some(&dir.join("uuid_spoof"))).unwrap_err();
+        assert!(error.contains("--library"), "{}", error);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn finds_programs_like_execvp() {
+        assert_eq!(
+            find_program(OsStr::new("/bin/sh"), None),
+            Some(PathBuf::from("/bin/sh"))
+        );
+        // A path is not searched for, and has to exist.
+        assert_eq!(
+            find_program(OsStr::new("./uuid-spoof-no-such-program"), None),
+            None
+        );
+        let found = find_program(
+            OsStr::new("sh"),
+            Some(OsStr::new("/nonexistent:/bin:/usr/bin")),
+        );
+        assert!(found.is_some_and(|path| path.ends_with("sh")));
+        assert_eq!(
+            find_program(OsStr::new("sh"), Some(OsStr::new("/nonexistent"))),
+            None
+        );
+        assert_eq!(find_program(OsStr::new(""), Some(OsStr::new("/bin"))), None);
+    }
+}
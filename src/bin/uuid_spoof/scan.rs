@@ -0,0 +1,396 @@
+// `uuid_spoof scan`: lists which Mach-O images in a file or bundle import identity APIs,
+// and which code signatures would keep the injected library out. Pure file parsing, so it
+// also runs on Linux against a copied bundle.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use serde::Serialize;
+
+use uuid_spoofer_core::macho::{self, CodeSignature};
+use uuid_spoofer_core::scan::{self, SliceScan, MH_EXECUTE};
+
+use crate::args::Args;
+use crate::checks::{self, Severity, DISABLE_LIBRARY_VALIDATION};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScanOptions {
+    pub json: bool,
+    pub path: PathBuf,
+}
+
+pub fn parse(mut args: Args) -> Result<ScanOptions, String> {
+    let mut json = false;
+    let mut path = None;
+    while let Some(arg) = args.next_string() {
+        match arg.as_str() {
+            "--json" => json = true,
+            flag if flag.starts_with('-') => {
+                return Err(format!("scan: unknown option '{}'", flag));
+            }
+            _ if path.is_some() => return Err("scan: give one path to scan".to_string()),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+    Ok(ScanOptions {
+        json,
+        path: path.ok_or("scan: no path given")?,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Import {
+    pub name: &'static str,
+    pub hooked: bool,
+    pub reads: &'static str,
+}
+
+// One architecture of one Mach-O file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Image {
+    // Relative to the scanned directory, or as given for a single file.
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    pub identity_imports: Vec<Import>,
+    pub signed: bool,
+    pub hardened_runtime: bool,
+    // Enforced, i.e. not waived by the disable-library-validation entitlement.
+    pub library_validation: bool,
+    // Why the loader would refuse the injection, for executables.
+    pub blockers: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn enforces_library_validation(signature: &CodeSignature) -> bool {
+    (signature.hardened_runtime() || signature.library_validation())
+        && !signature.has_entitlement(DISABLE_LIBRARY_VALIDATION)
+}
+
+fn image(path: &str, slice: SliceScan, blockers: &[String]) -> Image {
+    let arch = macho::cpu_name(slice.cputype);
+    let signed = slice.signature.is_some();
+    let signature = slice.signature.unwrap_or_default();
+    Image {
+        path: path.to_string(),
+        // check_macho names the architecture in each message.
+        blockers: if slice.filetype == MH_EXECUTE {
+            blockers
+                .iter()
+                .filter(|message| message.contains(&format!("({})", arch)))
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        },
+        arch: Some(arch),
+        kind: Some(scan::filetype_name(slice.filetype)),
+        identity_imports: slice
+            .identity_imports
+            .iter()
+            .map(|api| Import {
+                name: api.name,
+                hooked: api.hooked,
+                reads: api.reads,
+            })
+            .collect(),
+        signed,
+        hardened_runtime: signature.hardened_runtime(),
+        library_validation: enforces_library_validation(&signature),
+        error: None,
+    }
+}
+
+fn unreadable(path: &str, error: String) -> Image {
+    Image {
+        path: path.to_string(),
+        arch: None,
+        kind: None,
+        identity_imports: Vec::new(),
+        signed: false,
+        hardened_runtime: false,
+        library_validation: false,
+        blockers: Vec::new(),
+        error: Some(error),
+    }
+}
+
+// The images of one file; a file that cannot be parsed is one entry with the error.
+pub fn scan_file(path: &str, data: &[u8]) -> Vec<Image> {
+    let slices = match scan::scan(data) {
+        Ok(slices) => slices,
+        Err(e) => return vec![unreadable(path, e.to_string())],
+    };
+    let blockers: Vec<String> = checks::check_macho(path, data)
+        .into_iter()
+        .filter(|finding| finding.severity == Severity::Blocking)
+        .map(|finding| finding.message)
+        .collect();
+    slices
+        .into_iter()
+        .map(|slice| image(path, slice, &blockers))
+        .collect()
+}
+
+// Whether the file starts with a Mach-O magic number, without reading all of it.
+fn looks_like_macho(path: &Path) -> bool {
+    let mut magic = [0u8; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .map(|()| macho::is_macho(&magic))
+        .unwrap_or(false)
+}
+
+// Regular files under `root`, in a stable order. Symlinks are not followed: a framework's
+// Versions/Current and top-level links would list the same binary several times.
+fn files(root: &Path) -> Result<Vec<PathBuf>, String> {
+    let metadata = fs::symlink_metadata(root)
+        .map_err(|e| format!("scan: cannot read {}: {}", root.display(), e))?;
+    if metadata.is_file() || (metadata.is_symlink() && root.is_file()) {
+        return Ok(vec![root.to_path_buf()]);
+    }
+    let mut found = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => pending.push(entry.path()),
+                Ok(kind) if kind.is_file() => found.push(entry.path()),
+                _ => {}
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+pub fn scan_path(root: &Path) -> Result<Vec<Image>, String> {
+    let mut images = Vec::new();
+    for file in files(root)? {
+        if !looks_like_macho(&file) {
+            continue;
+        }
+        let shown = match file.strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative.display().to_string(),
+            _ => file.display().to_string(),
+        };
+        match fs::read(&file) {
+            Ok(data) => images.extend(scan_file(&shown, &data)),
+            Err(e) => images.push(unreadable(&shown, e.to_string())),
+        }
+    }
+    Ok(images)
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+pub fn table(images: &[Image]) -> String {
+    let mut rows = vec![[
+        "PATH".to_string(),
+        "ARCH".to_string(),
+        "KIND".to_string(),
+        "HARDENED".to_string(),
+        "LIB-VAL".to_string(),
+        "IDENTITY IMPORTS".to_string(),
+    ]];
+    for image in images {
+        let imports = if let Some(error) = &image.error {
+            format!("<unreadable: {}>", error)
+        } else if image.identity_imports.is_empty() {
+            "-".to_string()
+        } else {
+            image
+                .identity_imports
+                .iter()
+                .map(|import| {
+                    if import.hooked {
+                        import.name.to_string()
+                    } else {
+                        format!("{} (not hooked)", import.name)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        rows.push([
+            image.path.clone(),
+            image.arch.clone().unwrap_or_else(|| "-".to_string()),
+            image.kind.clone().unwrap_or_else(|| "-".to_string()),
+            yes_no(image.hardened_runtime).to_string(),
+            yes_no(image.library_validation).to_string(),
+            imports,
+        ]);
+    }
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let mut out = String::new();
+    for row in &rows {
+        for (cell, width) in row.iter().zip(widths) {
+            out.push_str(&format!("{:width$}  ", cell, width = width));
+        }
+        out.push_str(&row[5]);
+        out.push('\n');
+    }
+    let blockers: Vec<&String> = images.iter().flat_map(|image| &image.blockers).collect();
+    if !blockers.is_empty() {
+        out.push('\n');
+        for blocker in blockers {
+            out.push_str(&format!("blocked: {}\n", blocker));
+        }
+    }
+    out
+}
+
+pub fn json(images: &[Image]) -> String {
+    serde_json::to_string_pretty(images).expect("images always serialize") + "\n"
+}
+
+pub fn run_scan(args: Args) -> Result<ExitCode, String> {
+    let options = parse(args)?;
+    let images = scan_path(&options.path)?;
+    if options.json {
+        print!("{}", json(&images));
+    } else if images.is_empty() {
+        println!("no Mach-O files under {}", options.path.display());
+    } else {
+        print!("{}", table(&images));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid_spoofer_core::macho::{CPU_TYPE_ARM64, CPU_TYPE_X86_64, CS_RUNTIME};
+    use uuid_spoofer_core::testing;
+
+    const X86_64_DYLIB: &[u8] =
+        include_bytes!("../../../uuid_spoofer_core/tests/fixtures/x86_64_dylib");
+    const ARM64_CHAINED_64_OFFSET: &[u8] =
+        include_bytes!("../../../uuid_spoofer_core/tests/fixtures/arm64_chained_64_offset");
+
+    fn parsed(args: &[&str]) -> Result<ScanOptions, String> {
+        parse(Args::new(args.iter().copied()))
+    }
+
+    #[test]
+    fn parses_the_path_and_format() {
+        assert_eq!(
+            parsed(&["--json", "/Applications/App.app"]),
+            Ok(ScanOptions {
+                json: true,
+                path: PathBuf::from("/Applications/App.app"),
+            })
+        );
+        assert!(parsed(&[]).is_err());
+        assert!(parsed(&["a", "b"]).is_err());
+        assert!(parsed(&["--table", "a"]).is_err());
+    }
+
+    #[test]
+    fn walks_a_bundle() {
+        let bundle = std::env::temp_dir()
+            .join(format!("uuid_spoof_scan_{}", std::process::id()))
+            .join("App.app");
+        let macos = bundle.join("Contents/MacOS");
+        let frameworks = bundle.join("Contents/Frameworks");
+        fs::create_dir_all(&macos).unwrap();
+        fs::create_dir_all(&frameworks).unwrap();
+        fs::write(
+            macos.join("App"),
+            testing::fat(&[
+                (
+                    CPU_TYPE_X86_64,
+                    &testing::signed_macho(CPU_TYPE_X86_64, 0, None),
+                ),
+                (
+                    CPU_TYPE_ARM64,
+                    &testing::signed_macho(CPU_TYPE_ARM64, CS_RUNTIME, None),
+                ),
+            ]),
+        )
+        .unwrap();
+        fs::write(frameworks.join("libid.dylib"), X86_64_DYLIB).unwrap();
+        fs::write(frameworks.join("helper"), ARM64_CHAINED_64_OFFSET).unwrap();
+        fs::write(bundle.join("Contents/Info.plist"), "<plist/>").unwrap();
+        fs::write(frameworks.join("broken.dylib"), b"\xfe\xed\xfa\xcf").unwrap();
+        std::os::unix::fs::symlink("libid.dylib", frameworks.join("libcurrent.dylib")).unwrap();
+
+        let images = scan_path(&bundle).unwrap();
+        fs::remove_dir_all(bundle.parent().unwrap()).unwrap();
+        let summary: Vec<(&str, Option<&str>, usize)> = images
+            .iter()
+            .map(|image| {
+                (
+                    image.path.as_str(),
+                    image.arch.as_deref(),
+                    image.identity_imports.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("Contents/Frameworks/broken.dylib", None, 0),
+                ("Contents/Frameworks/helper", Some("arm64"), 3),
+                ("Contents/Frameworks/libid.dylib", Some("x86_64"), 4),
+                ("Contents/MacOS/App", Some("x86_64"), 0),
+                ("Contents/MacOS/App", Some("arm64"), 0),
+            ]
+        );
+        assert!(images[0].error.is_some());
+
+        let app = &images[4];
+        assert!(app.signed && app.hardened_runtime && app.library_validation);
+        assert_eq!(app.blockers.len(), 1, "{:?}", app.blockers);
+        assert!(app.blockers[0].contains("hardened runtime"));
+        assert!(images[3].signed && !images[3].hardened_runtime);
+        assert!(images[3].blockers.is_empty());
+
+        let table = table(&images);
+        assert!(table.starts_with("PATH  "), "{}", table);
+        assert!(
+            table.contains("IORegistryEntryCreateCFProperty, IORegistryEntrySearchCFProperty"),
+            "{}",
+            table
+        );
+        assert!(table.contains("\nblocked: Contents/MacOS/App (arm64) uses the hardened runtime"));
+
+        let json: serde_json::Value = serde_json::from_str(&json(&images)).unwrap();
+        assert_eq!(json[2]["kind"], "dylib");
+        assert_eq!(json[2]["identity_imports"][2]["name"], "gethostuuid");
+        assert_eq!(json[2]["identity_imports"][2]["hooked"], true);
+        assert_eq!(json[4]["hardened_runtime"], true);
+        assert_eq!(json[0].get("arch"), None);
+    }
+
+    #[test]
+    fn scans_a_single_file() {
+        let images = scan_file("libid.dylib", X86_64_DYLIB);
+        assert_eq!(images.len(), 1);
+        assert!(!images[0].signed);
+        assert!(!images[0].library_validation);
+        assert!(images[0]
+            .identity_imports
+            .iter()
+            .all(|import| import.hooked));
+    }
+}
@@ -0,0 +1,210 @@
+// `uuid_spoof verify`: runs `uuid_reader --expect` under the injected library to prove the
+// spoof actually takes effect, and optionally checks that a target program will load it.
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use uuid_spoofer_core::profile_store::ProfileStore;
+use uuid_spoofer_core::profiles::Process;
+use uuid_spoofer_core::{config, derive};
+
+use crate::args::Args;
+use crate::checks::{self, Platform, Severity};
+use crate::run::{find_program, Identity, Injection};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct VerifyOptions {
+    pub injection: Injection,
+    pub reader: Option<PathBuf>,
+    // A program whose launch should also be checked.
+    pub target: Option<OsString>,
+}
+
+pub fn parse(mut args: Args) -> Result<VerifyOptions, String> {
+    let mut injection = Injection::default();
+    let mut reader = None;
+    while let Some(arg) = args.peek() {
+        let flag = arg.to_string_lossy().into_owned();
+        if !flag.starts_with('-') {
+            break;
+        }
+        args.next();
+        if flag == "--" {
+            break;
+        }
+        if injection.parse_flag("verify", &flag, &mut args)? {
+            continue;
+        }
+        match flag.as_str() {
+            "--reader" => reader = Some(PathBuf::from(args.value("--reader")?)),
+            _ => return Err(format!("verify: unknown option '{}'", flag)),
+        }
+    }
+    let mut rest = args.rest().into_iter();
+    let target = rest.next();
+    if rest.next().is_some() {
+        return Err("verify: give at most one program to check".to_string());
+    }
+    Ok(VerifyOptions {
+        injection,
+        reader,
+        target,
+    })
+}
+
+// The UUID the library should hand to a process whose app id is `app_id`, an unbundled
+// program started by this one.
+pub fn expected_uuid(identity: Option<&Identity>, app_id: &str) -> Result<String, String> {
+    match identity {
+        Some(Identity::Uuid(uuid)) => Ok(uuid.clone()),
+        Some(Identity::Seed(seed)) => Ok(derive::derive_uuid(seed, app_id)),
+        // The child gets no UUID or seed from the environment, only the config file.
+        Some(Identity::Profile(name)) => {
+            let resolved = config::resolve_from(None, None, config::config_path().as_deref())
+                .map_err(|e| {
+                    format!("the configuration is invalid, so nothing is spoofed: {}", e)
+                })?;
+            ProfileStore::from_env()
+                .and_then(|store| resolved.with_stored_profile(&store, name))
+                .map(|resolved| resolved.uuid_for(app_id))
+                .map_err(|e| format!("profile {:?} cannot be used: {}", name, e))
+        }
+        None => config::resolve()
+            .map(|resolved| {
+                let process = Process {
+                    exe: Some(PathBuf::from(app_id)),
+                    bundle_id: None,
+                    parent: std::env::current_exe().ok(),
+                };
+                resolved.for_process(&process).uuid_for(app_id)
+            })
+            .map_err(|e| format!("the configuration is invalid, so nothing is spoofed: {}", e)),
+    }
+}
+
+fn find_reader(explicit: Option<&Path>) -> Result<PathBuf, String> {
+    let reader = match explicit {
+        Some(reader) => reader.to_path_buf(),
+        None => std::env::current_exe()
+            .map_err(|e| format!("cannot locate uuid_reader: {}", e))?
+            .with_file_name(format!("uuid_reader{}", std::env::consts::EXE_SUFFIX)),
+    };
+    // Unbundled programs are identified by their canonical path, as the library sees it.
+    std::fs::canonicalize(&reader)
+        .map_err(|e| format!("cannot use reader {}: {}", reader.display(), e))
+}
+
+fn fail(reason: &str) -> ExitCode {
+    println!("FAIL: {}", reason);
+    ExitCode::FAILURE
+}
+
+pub fn verify(args: Args) -> Result<ExitCode, String> {
+    let options = parse(args)?;
+    let library = match options.injection.find_library() {
+        Ok(library) => library,
+        Err(message) => return Ok(fail(&message)),
+    };
+    let reader = match find_reader(options.reader.as_deref()) {
+        Ok(reader) => reader,
+        Err(message) => return Ok(fail(&message)),
+    };
+    let expected = match expected_uuid(
+        options.injection.identity.as_ref(),
+        &reader.to_string_lossy(),
+    ) {
+        Ok(expected) => expected,
+        Err(message) => return Ok(fail(&message)),
+    };
+
+    // The reader prints its own PASS/FAIL line and exits with 1 on a mismatch.
+    let status = options
+        .injection
+        .command(&reader, &library)
+        .arg("--expect")
+        .arg(&expected)
+        .status();
+    let reader_passed = match status {
+        Ok(status) if status.success() => true,
+        Ok(status) if status.code() == Some(1) => false,
+        Ok(status) => {
+            return Ok(fail(&format!(
+                "{} did not finish normally ({}); the injected library may have crashed it",
+                reader.display(),
+                status
+            )))
+        }
+        Err(e) => return Ok(fail(&format!("cannot run {}: {}", reader.display(), e))),
+    };
+
+    let Some(target) = &options.target else {
+        return Ok(if reader_passed {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    };
+    let Some(program) = find_program(target, std::env::var_os("PATH").as_deref()) else {
+        return Ok(fail(&format!(
+            "{}: command not found",
+            target.to_string_lossy()
+        )));
+    };
+    let findings = checks::check_target(&program, Platform::current());
+    for finding in findings.iter().filter(|f| f.severity == Severity::Warning) {
+        eprintln!("uuid_spoof: warning: {}", finding.message);
+    }
+    let blocking: Vec<_> = findings
+        .iter()
+        .filter(|f| f.severity == Severity::Blocking)
+        .map(|f| f.message.as_str())
+        .collect();
+    if !blocking.is_empty() {
+        return Ok(fail(&blocking.join("; ")));
+    }
+    if !reader_passed {
+        return Ok(ExitCode::FAILURE);
+    }
+    println!("PASS: {} will load the library", program.display());
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(args: &[&str]) -> Result<VerifyOptions, String> {
+        parse(Args::new(args.iter().copied()))
+    }
+
+    #[test]
+    fn parses_options_and_optional_target() {
+        let options = parsed(&["--seed", "s", "--reader", "/tmp/reader"]).unwrap();
+        assert_eq!(options.injection.identity, Some(Identity::Seed("s".into())));
+        assert_eq!(options.reader, Some(PathBuf::from("/tmp/reader")));
+        assert_eq!(options.target, None);
+
+        let options = parsed(&["--", "/Applications/App.app/Contents/MacOS/App"]).unwrap();
+        assert_eq!(
+            options.target,
+            Some(OsString::from("/Applications/App.app/Contents/MacOS/App"))
+        );
+        assert!(parsed(&["a", "b"]).is_err());
+        assert!(parsed(&["--force"]).is_err());
+        assert!(parsed(&["--uuid", "bad"]).is_err());
+    }
+
+    #[test]
+    fn expected_uuid_follows_the_identity() {
+        let uuid = "12345678-9ABC-DEF0-1234-56789ABCDEF0".to_string();
+        assert_eq!(
+            expected_uuid(Some(&Identity::Uuid(uuid.clone())), "/bin/reader"),
+            Ok(uuid)
+        );
+        assert_eq!(
+            expected_uuid(Some(&Identity::Seed("seed".into())), "com.example.app"),
+            Ok("04B38FDE-CB0C-82D8-92FF-34A5F82165AD".to_string())
+        );
+    }
+}
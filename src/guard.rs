@@ -0,0 +1,19 @@
+// Every hook runs its body through `guarded`, so a panic while spoofing is logged and the
+// call passed through to the original instead of unwinding into C, where it would abort
+// the host app (see `uuid_spoofer_core::hook_guard`).
+
+use uuid_spoofer_core::hook_guard;
+
+// Runs the hook for `function`: `hook` returns the spoofed result or `None` to pass the
+// call through, `original` calls the real function (`None` if it is unknown) and
+// `failed` is what the caller gets when neither produced a result.
+pub fn guarded<R>(
+    function: &str,
+    hook: impl FnOnce() -> Option<R>,
+    original: impl FnOnce() -> Option<R>,
+    failed: R,
+) -> R {
+    hook_guard::guarded(hook, original, failed, |failure| {
+        log!(Error, "{}: {}", function, failure)
+    })
+}
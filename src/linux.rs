@@ -0,0 +1,552 @@
+// Linux backend: loaded with LD_PRELOAD, it interposes the libc calls that open the
+// machine identity files (see `id_files`) and serves the spoofed UUID in their place,
+// along with `gethostid`. The profile's MAC addresses are written over the hardware
+// addresses `getifaddrs` lists and `SIOCGIFHWADDR` returns. The same hooks are also
+// written into the GOT of every loaded object (see `rebind`), for the objects whose
+// calls do not go through the global scope.
+//
+// The replacement content lives in a memfd, so once `open`/`fopen` hand out its
+// descriptor, `read`, `pread`, `mmap`, `lseek` and `fstat` all behave like a real file
+// without being interposed themselves.
+
+use ctor::ctor;
+use libc::{c_char, c_int, c_long, c_uint, c_ulong, c_void, mode_t, FILE};
+use std::ffi::{CStr, OsStr};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::OnceLock;
+
+use crate::guard::guarded;
+use crate::logging;
+use crate::original::{self, Original};
+use crate::rebind::{rebind_if_loaded, rebind_symbols, Rebinding};
+use uuid_spoofer_core::catalog::Catalog;
+use uuid_spoofer_core::config;
+use uuid_spoofer_core::id_files::IdFile;
+use uuid_spoofer_core::mac::MacAddresses;
+use uuid_spoofer_core::profiles::Process;
+use uuid_spoofer_core::uuid::uuid_to_host_id;
+
+// The UUID to serve, resolved once in `init`. `None` means the configuration was invalid
+// (or `init` has not run yet) and every call is passed through.
+static SPOOFED_UUID: OnceLock<Option<String>> = OnceLock::new();
+
+// The interfaces' addresses, resolved in `init` along with the UUID. Empty (or unset)
+// passes every hardware address through.
+static SPOOFED_MAC_ADDRESSES: OnceLock<MacAddresses> = OnceLock::new();
+
+// --- Original functions: the definitions our exports shadow (see `original`) ---
+type FnOpen = extern "C" fn(path: *const c_char, flags: c_int, ...) -> c_int;
+type FnOpenat = extern "C" fn(dirfd: c_int, path: *const c_char, flags: c_int, ...) -> c_int;
+type FnFopen = extern "C" fn(path: *const c_char, mode: *const c_char) -> *mut FILE;
+type FnGethostid = extern "C" fn() -> c_long;
+type FnGetifaddrs = extern "C" fn(ifap: *mut *mut libc::ifaddrs) -> c_int;
+type FnIoctl = extern "C" fn(fd: c_int, request: c_ulong, arg: *mut c_void) -> c_int;
+
+static ORIGINAL_OPEN: Original = Original::interposed(c"open");
+static ORIGINAL_OPEN64: Original = Original::interposed(c"open64");
+static ORIGINAL_OPENAT: Original = Original::interposed(c"openat");
+static ORIGINAL_OPENAT64: Original = Original::interposed(c"openat64");
+static ORIGINAL_OPEN_2: Original = Original::interposed(c"__open_2");
+static ORIGINAL_OPEN64_2: Original = Original::interposed(c"__open64_2");
+static ORIGINAL_OPENAT_2: Original = Original::interposed(c"__openat_2");
+static ORIGINAL_OPENAT64_2: Original = Original::interposed(c"__openat64_2");
+static ORIGINAL_FOPEN: Original = Original::interposed(c"fopen");
+static ORIGINAL_FOPEN64: Original = Original::interposed(c"fopen64");
+static ORIGINAL_GETHOSTID: Original = Original::interposed(c"gethostid");
+static ORIGINAL_GETIFADDRS: Original = Original::interposed(c"getifaddrs");
+static ORIGINAL_IOCTL: Original = Original::interposed(c"ioctl");
+
+static ORIGINALS: [&Original; 13] = [
+    &ORIGINAL_OPEN,
+    &ORIGINAL_OPEN64,
+    &ORIGINAL_OPENAT,
+    &ORIGINAL_OPENAT64,
+    &ORIGINAL_OPEN_2,
+    &ORIGINAL_OPEN64_2,
+    &ORIGINAL_OPENAT_2,
+    &ORIGINAL_OPENAT64_2,
+    &ORIGINAL_FOPEN,
+    &ORIGINAL_FOPEN64,
+    &ORIGINAL_GETHOSTID,
+    &ORIGINAL_GETIFADDRS,
+    &ORIGINAL_IOCTL,
+];
+
+fn set_errno(errno: c_int) {
+    unsafe { *libc::__errno_location() = errno };
+}
+
+// --- Spoofed file contents ---
+
+// What to serve instead of `path` (relative to `dirfd`), or `None` to pass through.
+fn spoofed_contents(dirfd: c_int, path: *const c_char) -> Option<String> {
+    let uuid = SPOOFED_UUID.get()?.as_deref()?;
+    if path.is_null() {
+        return None;
+    }
+    let path = Path::new(OsStr::from_bytes(
+        unsafe { CStr::from_ptr(path) }.to_bytes(),
+    ));
+    if !IdFile::may_match(path) {
+        return None;
+    }
+
+    let absolute = if path.is_absolute() {
+        path.to_path_buf()
+    } else if dirfd == libc::AT_FDCWD {
+        std::env::current_dir().ok()?.join(path)
+    } else {
+        PathBuf::from(format!("/proc/self/fd/{}", dirfd)).join(path)
+    };
+    // canonicalize() only stats and reads links, so it never re-enters these hooks.
+    let id_file = IdFile::for_path(&absolute).or_else(|| {
+        std::fs::canonicalize(&absolute)
+            .ok()
+            .and_then(|canonical| IdFile::for_path(&canonical))
+    });
+    let Some(id_file) = id_file else {
+        log!(
+            Trace,
+            "{}: not an identity file, passed through",
+            path.display()
+        );
+        return None;
+    };
+    log!(Debug, "{}: spoofed as {:?}", path.display(), id_file);
+    id_file.contents(uuid).ok()
+}
+
+// An anonymous in-memory file holding `contents`, positioned at the start.
+fn memfd_with(contents: &[u8], cloexec: bool) -> Option<c_int> {
+    let flags = if cloexec { libc::MFD_CLOEXEC } else { 0 };
+    let fd = unsafe { libc::memfd_create(c"uuid-spoof".as_ptr(), flags) };
+    if fd < 0 {
+        return None;
+    }
+    let mut written = 0;
+    while written < contents.len() {
+        let n = unsafe {
+            libc::write(
+                fd,
+                contents[written..].as_ptr() as *const c_void,
+                contents.len() - written,
+            )
+        };
+        if n <= 0 {
+            unsafe { libc::close(fd) };
+            return None;
+        }
+        written += n as usize;
+    }
+    unsafe { libc::lseek(fd, 0, libc::SEEK_SET) };
+    Some(fd)
+}
+
+// Read-only opens of an identity file get a memfd; anything else is the caller's business,
+// including O_PATH and O_DIRECTORY opens, which never read the contents.
+fn spoofed_fd(dirfd: c_int, path: *const c_char, flags: c_int) -> Option<c_int> {
+    if flags & libc::O_ACCMODE != libc::O_RDONLY
+        || flags & (libc::O_CREAT | libc::O_TRUNC | libc::O_PATH | libc::O_DIRECTORY) != 0
+    {
+        return None;
+    }
+    let contents = spoofed_contents(dirfd, path)?;
+    memfd_with(contents.as_bytes(), flags & libc::O_CLOEXEC != 0)
+}
+
+fn spoofed_file(path: *const c_char, mode: *const c_char) -> Option<*mut FILE> {
+    if mode.is_null() {
+        return None;
+    }
+    let mode = unsafe { CStr::from_ptr(mode) }.to_bytes();
+    if mode.first() != Some(&b'r') || mode.contains(&b'+') {
+        return None;
+    }
+    let contents = spoofed_contents(libc::AT_FDCWD, path)?;
+    let fd = memfd_with(contents.as_bytes(), mode.contains(&b'e'))?;
+    let file = unsafe { libc::fdopen(fd, c"r".as_ptr()) };
+    if file.is_null() {
+        unsafe { libc::close(fd) };
+        return None;
+    }
+    Some(file)
+}
+
+// --- Spoofed MAC addresses ---
+
+// An interface name as the kernel fills it in: NUL-terminated unless it uses the whole
+// buffer.
+fn interface_name(name: &[c_char]) -> Option<&str> {
+    let bytes = unsafe { std::slice::from_raw_parts(name.as_ptr() as *const u8, name.len()) };
+    let length = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    std::str::from_utf8(&bytes[..length]).ok()
+}
+
+// Rewrites the link-layer (AF_PACKET) addresses in a list `getifaddrs` returned. The list
+// is the caller's own allocation, so it is edited in place.
+fn spoof_interface_list(list: *mut libc::ifaddrs) {
+    let Some(mac_addresses) = SPOOFED_MAC_ADDRESSES.get().filter(|macs| !macs.is_empty()) else {
+        return;
+    };
+    let mut replaced = 0;
+    let mut entry = list;
+    while let Some(ifa) = unsafe { entry.as_ref() } {
+        entry = ifa.ifa_next;
+        if ifa.ifa_addr.is_null()
+            || ifa.ifa_name.is_null()
+            || unsafe { (*ifa.ifa_addr).sa_family } as c_int != libc::AF_PACKET
+        {
+            continue;
+        }
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) };
+        let Ok(name) = name.to_str() else {
+            continue;
+        };
+        let link = unsafe { &mut *(ifa.ifa_addr as *mut libc::sockaddr_ll) };
+        // Longer (InfiniBand) addresses do not fit sll_addr and are never replaced.
+        let length = (link.sll_halen as usize).min(link.sll_addr.len());
+        if mac_addresses.replace(name, &mut link.sll_addr[..length]) {
+            replaced += 1;
+        }
+    }
+    log!(Debug, "getifaddrs: spoofed {} hardware addresses", replaced);
+}
+
+// Rewrites the Ethernet address a successful SIOCGIFHWADDR left in `request`.
+fn spoof_hardware_address(request: *mut libc::ifreq) {
+    let Some(mac_addresses) = SPOOFED_MAC_ADDRESSES.get().filter(|macs| !macs.is_empty()) else {
+        return;
+    };
+    let Some(request) = (unsafe { request.as_mut() }) else {
+        return;
+    };
+    let Some(name) = interface_name(&request.ifr_name) else {
+        return;
+    };
+    // Both carry 6-byte addresses; loopback's is all zeros unless the profile gives one.
+    let address = unsafe { &mut request.ifr_ifru.ifru_hwaddr };
+    if address.sa_family != libc::ARPHRD_ETHER && address.sa_family != libc::ARPHRD_LOOPBACK {
+        return;
+    }
+    let bytes =
+        unsafe { std::slice::from_raw_parts_mut(address.sa_data.as_mut_ptr() as *mut u8, 6) };
+    if mac_addresses.replace(name, bytes) {
+        log!(Debug, "ioctl(SIOCGIFHWADDR, {}): spoofed", name);
+    } else {
+        log!(Trace, "ioctl(SIOCGIFHWADDR, {}): passed through", name);
+    }
+}
+
+// The definition behind `original`, or `None` with errno set to ENOSYS if there is none.
+fn next_address(original: &Original) -> Option<*mut c_void> {
+    let Some(address) = original.get() else {
+        set_errno(libc::ENOSYS);
+        return None;
+    };
+    Some(address.as_ptr())
+}
+
+// Objects opened with RTLD_DEEPBIND (and everything they pull in) bind to libc ahead of
+// this library. glibc cannot tell us when one is loaded, so every hook first rebinds what
+// dlopen has loaded since the last look; such an object's own calls are spoofed from the
+// first hooked call made after it is loaded.
+fn rebind_loaded_objects(function: &str) {
+    guarded(
+        function,
+        || {
+            rebind_if_loaded();
+            Some(())
+        },
+        || Some(()),
+        (),
+    );
+}
+
+fn call_open(original: &Original, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    rebind_loaded_objects(original.name());
+    guarded(
+        original.name(),
+        || spoofed_fd(libc::AT_FDCWD, path, flags),
+        || {
+            let address = next_address(original)?;
+            let original = unsafe { std::mem::transmute::<*mut c_void, FnOpen>(address) };
+            Some(original(path, flags, mode as c_uint))
+        },
+        -1,
+    )
+}
+
+fn call_openat(
+    original: &Original,
+    dirfd: c_int,
+    path: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+) -> c_int {
+    rebind_loaded_objects(original.name());
+    guarded(
+        original.name(),
+        || spoofed_fd(dirfd, path, flags),
+        || {
+            let address = next_address(original)?;
+            let original = unsafe { std::mem::transmute::<*mut c_void, FnOpenat>(address) };
+            Some(original(dirfd, path, flags, mode as c_uint))
+        },
+        -1,
+    )
+}
+
+fn call_fopen(original: &Original, path: *const c_char, mode: *const c_char) -> *mut FILE {
+    rebind_loaded_objects(original.name());
+    guarded(
+        original.name(),
+        || spoofed_file(path, mode),
+        || {
+            let address = next_address(original)?;
+            let original = unsafe { std::mem::transmute::<*mut c_void, FnFopen>(address) };
+            Some(original(path, mode))
+        },
+        ptr::null_mut(),
+    )
+}
+
+// --- Interposed functions ---
+// open and openat are variadic in C. Stable Rust cannot define variadic functions, but on
+// the supported ABIs the optional mode argument arrives exactly where a fixed third
+// (or fourth) parameter would, so declaring it explicitly is equivalent.
+
+#[no_mangle]
+pub extern "C" fn open(path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    call_open(&ORIGINAL_OPEN, path, flags, mode)
+}
+
+#[no_mangle]
+pub extern "C" fn open64(path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    call_open(&ORIGINAL_OPEN64, path, flags, mode)
+}
+
+#[no_mangle]
+pub extern "C" fn openat(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    call_openat(&ORIGINAL_OPENAT, dirfd, path, flags, mode)
+}
+
+#[no_mangle]
+pub extern "C" fn openat64(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    call_openat(&ORIGINAL_OPENAT64, dirfd, path, flags, mode)
+}
+
+// The checked variants `_FORTIFY_SOURCE` builds call when the flags are not a constant.
+// They take no mode, and glibc aborts in them if the flags would need one, so the mode
+// passed on is 0; the extra argument is ignored by the originals like any unused one.
+
+#[no_mangle]
+pub extern "C" fn __open_2(path: *const c_char, flags: c_int) -> c_int {
+    call_open(&ORIGINAL_OPEN_2, path, flags, 0)
+}
+
+#[no_mangle]
+pub extern "C" fn __open64_2(path: *const c_char, flags: c_int) -> c_int {
+    call_open(&ORIGINAL_OPEN64_2, path, flags, 0)
+}
+
+#[no_mangle]
+pub extern "C" fn __openat_2(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    call_openat(&ORIGINAL_OPENAT_2, dirfd, path, flags, 0)
+}
+
+#[no_mangle]
+pub extern "C" fn __openat64_2(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+    call_openat(&ORIGINAL_OPENAT64_2, dirfd, path, flags, 0)
+}
+
+#[no_mangle]
+pub extern "C" fn fopen(path: *const c_char, mode: *const c_char) -> *mut FILE {
+    call_fopen(&ORIGINAL_FOPEN, path, mode)
+}
+
+#[no_mangle]
+pub extern "C" fn fopen64(path: *const c_char, mode: *const c_char) -> *mut FILE {
+    call_fopen(&ORIGINAL_FOPEN64, path, mode)
+}
+
+#[no_mangle]
+pub extern "C" fn gethostid() -> c_long {
+    rebind_loaded_objects("gethostid");
+    guarded(
+        "gethostid",
+        || {
+            let uuid = SPOOFED_UUID.get()?.as_deref()?;
+            let host_id = uuid_to_host_id(uuid).ok()?;
+            log!(Debug, "gethostid: spoofed as {:08x}", host_id);
+            // glibc sign-extends the 32-bit ID.
+            Some(host_id as i32 as c_long)
+        },
+        || {
+            let address = next_address(&ORIGINAL_GETHOSTID)?;
+            let original = unsafe { std::mem::transmute::<*mut c_void, FnGethostid>(address) };
+            Some(original())
+        },
+        -1,
+    )
+}
+
+// The list comes from the original, and only its hardware addresses are rewritten.
+#[no_mangle]
+pub extern "C" fn getifaddrs(ifap: *mut *mut libc::ifaddrs) -> c_int {
+    rebind_loaded_objects("getifaddrs");
+    let result = guarded(
+        "getifaddrs",
+        || None,
+        || {
+            let address = next_address(&ORIGINAL_GETIFADDRS)?;
+            let original = unsafe { std::mem::transmute::<*mut c_void, FnGetifaddrs>(address) };
+            Some(original(ifap))
+        },
+        -1,
+    );
+    if result == 0 && !ifap.is_null() {
+        // If rewriting panics, the caller keeps the real addresses.
+        guarded(
+            "getifaddrs",
+            || {
+                spoof_interface_list(unsafe { *ifap });
+                Some(())
+            },
+            || Some(()),
+            (),
+        );
+    }
+    result
+}
+
+// ioctl is variadic as well; every request passes its argument where a fixed third
+// parameter would be. Only SIOCGIFHWADDR is looked at, after the original succeeds.
+#[no_mangle]
+pub extern "C" fn ioctl(fd: c_int, request: c_ulong, arg: *mut c_void) -> c_int {
+    rebind_loaded_objects("ioctl");
+    let result = guarded(
+        "ioctl",
+        || None,
+        || {
+            let address = next_address(&ORIGINAL_IOCTL)?;
+            let original = unsafe { std::mem::transmute::<*mut c_void, FnIoctl>(address) };
+            Some(original(fd, request, arg))
+        },
+        -1,
+    );
+    // The kernel only reads the low 32 bits of the request.
+    if result == 0 && request as u32 == libc::SIOCGIFHWADDR as u32 {
+        guarded(
+            "ioctl",
+            || {
+                spoof_hardware_address(arg as *mut libc::ifreq);
+                Some(())
+            },
+            || Some(()),
+            (),
+        );
+    }
+    result
+}
+
+// The hooks to write into GOT slots, with the original each one captures.
+fn rebindings() -> Vec<Rebinding> {
+    let hook = |original: &'static Original, replacement: *mut c_void| Rebinding {
+        original,
+        replacement,
+    };
+    vec![
+        hook(&ORIGINAL_OPEN, open as *mut c_void),
+        hook(&ORIGINAL_OPEN64, open64 as *mut c_void),
+        hook(&ORIGINAL_OPENAT, openat as *mut c_void),
+        hook(&ORIGINAL_OPENAT64, openat64 as *mut c_void),
+        hook(&ORIGINAL_OPEN_2, __open_2 as *mut c_void),
+        hook(&ORIGINAL_OPEN64_2, __open64_2 as *mut c_void),
+        hook(&ORIGINAL_OPENAT_2, __openat_2 as *mut c_void),
+        hook(&ORIGINAL_OPENAT64_2, __openat64_2 as *mut c_void),
+        hook(&ORIGINAL_FOPEN, fopen as *mut c_void),
+        hook(&ORIGINAL_FOPEN64, fopen64 as *mut c_void),
+        hook(&ORIGINAL_GETHOSTID, gethostid as *mut c_void),
+        hook(&ORIGINAL_GETIFADDRS, getifaddrs as *mut c_void),
+        hook(&ORIGINAL_IOCTL, ioctl as *mut c_void),
+    ]
+}
+
+// What the config file's rules can match on. The parent's executable may belong to
+// another user; then only its name, from /proc, is known.
+fn current_process() -> Process {
+    let parent = format!("/proc/{}", std::os::unix::process::parent_id());
+    let parent = std::fs::read_link(format!("{}/exe", parent))
+        .ok()
+        .or_else(|| {
+            std::fs::read_to_string(format!("{}/comm", parent))
+                .ok()
+                .map(|name| PathBuf::from(name.trim_end()))
+        });
+    Process {
+        exe: std::env::current_exe().ok(),
+        bundle_id: None,
+        parent,
+    }
+}
+
+// --- Library constructor ---
+#[ctor]
+fn init() {
+    let process = current_process();
+    // Unbundled Linux programs are identified by their executable path.
+    let app_id = process
+        .exe
+        .as_ref()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    logging::init();
+    // Loaded once here so a broken user file is reported at startup; profiles draw
+    // generated serial numbers from the same catalog and fall back to the built-in one.
+    if let Err(e) = Catalog::installed() {
+        log!(Error, "{}. Using the built-in model catalog.", e);
+    }
+    let spoofed_uuid = match config::resolve() {
+        Ok(resolved) => {
+            let resolved = resolved.for_process(&process);
+            if let Some(profile) = &resolved.profile {
+                log!(Info, "using profile {:?} for {}", profile, app_id);
+            }
+            if !resolved.mac_addresses.is_empty() {
+                log!(Info, "spoofing MAC addresses for {}", app_id);
+            }
+            let _ = SPOOFED_MAC_ADDRESSES.set(resolved.mac_addresses.clone());
+            let uuid = resolved.uuid_for(&app_id);
+            log!(
+                Info,
+                "serving {} to {} (from {})",
+                uuid,
+                app_id,
+                resolved.source
+            );
+            Some(uuid)
+        }
+        Err(e) => {
+            log!(Error, "{}. Identity files will not be spoofed.", e);
+            None
+        }
+    };
+    let _ = SPOOFED_UUID.set(spoofed_uuid);
+
+    original::register(&ORIGINALS);
+    match rebind_symbols(rebindings()) {
+        Some(patched) => log!(Info, "patched {} import slots", patched),
+        None => log!(
+            Error,
+            "import slots not patched; only calls that reach the exported hooks are spoofed"
+        ),
+    }
+    // The exports are installed by being preloaded; look up every original now, so a
+    // missing one shows up here rather than on first use.
+    for original in ORIGINALS {
+        original.get();
+    }
+    original::log_hook_states();
+}
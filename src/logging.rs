@@ -0,0 +1,115 @@
+// The hooks' logger. Configured once from the environment in the library constructor, then
+// safe to call from inside any hook: lines are formatted on the stack (no allocation) and
+// written with one write(2) (no lock, so no deadlock), and a per-thread flag drops any
+// message logged while the same thread is already logging.
+
+use std::cell::Cell;
+use std::ffi::CString;
+use std::fmt;
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+
+use libc::c_void;
+use uuid_spoofer_core::logging::{Level, LineBuffer, LOG_ENV_VAR, LOG_FILE_ENV_VAR};
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::DEFAULT as u8);
+static FD: AtomicI32 = AtomicI32::new(libc::STDERR_FILENO);
+
+thread_local! {
+    static LOGGING: Cell<bool> = const { Cell::new(false) };
+}
+
+// Logs a message at the given level, e.g. `log!(Debug, "hooked {}", name)`. The arguments
+// are only evaluated when the level is enabled.
+macro_rules! log {
+    ($level:ident, $($arg:tt)+) => {
+        if $crate::logging::enabled(uuid_spoofer_core::logging::Level::$level) {
+            $crate::logging::write(
+                uuid_spoofer_core::logging::Level::$level,
+                format_args!($($arg)+),
+            );
+        }
+    };
+}
+
+// Reads UUID_SPOOF_LOG and UUID_SPOOF_LOG_FILE. Call before installing any hook.
+pub fn init() {
+    // The file is opened before the level applies: on Linux the open goes through our own
+    // hook, whose first call is logged, and that line belongs in the file.
+    let file_error = open_log_file();
+
+    let level = std::env::var(LOG_ENV_VAR).ok();
+    match level.as_deref().map(Level::parse) {
+        Some(Some(level)) => LEVEL.store(level as u8, Ordering::Relaxed),
+        Some(None) => log!(
+            Error,
+            "invalid {} '{}'; expected off, error, info, debug or trace",
+            LOG_ENV_VAR,
+            level.unwrap_or_default()
+        ),
+        None => {}
+    }
+    if let Some(error) = file_error {
+        log!(Error, "{}", error);
+    }
+}
+
+// Points the log at UUID_SPOOF_LOG_FILE, if set. Returns why it could not be opened.
+fn open_log_file() -> Option<String> {
+    let path = std::env::var_os(LOG_FILE_ENV_VAR).filter(|path| !path.is_empty())?;
+    let c_path = CString::new(std::os::unix::ffi::OsStrExt::as_bytes(path.as_os_str())).ok()?;
+    let fd = unsafe {
+        libc::open(
+            c_path.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND | libc::O_CLOEXEC,
+            0o644 as libc::c_uint,
+        )
+    };
+    if fd < 0 {
+        return Some(format!(
+            "cannot open log file {}: {}; logging to stderr",
+            path.to_string_lossy(),
+            std::io::Error::last_os_error()
+        ));
+    }
+    FD.store(fd, Ordering::Relaxed);
+    None
+}
+
+pub fn enabled(level: Level) -> bool {
+    level != Level::Off && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+pub fn write(level: Level, message: fmt::Arguments<'_>) {
+    // Fails during thread teardown, when there is nowhere sensible to log to anyway.
+    let Ok(false) = LOGGING.try_with(|logging| logging.replace(true)) else {
+        return;
+    };
+    let line = LineBuffer::format(level, std::process::id(), message);
+    let bytes = line.as_bytes();
+    let fd = FD.load(Ordering::Relaxed);
+    // A short or failed write loses (part of) a log line, which is all we can do.
+    unsafe { libc::write(fd, bytes.as_ptr() as *const c_void, bytes.len()) };
+    let _ = LOGGING.try_with(|logging| logging.set(false));
+}
+
+// Displays the image containing an address (usually a hook's return address), so the log
+// says which framework or app asked.
+#[cfg(target_os = "macos")]
+pub struct Caller(pub *const c_void);
+
+#[cfg(target_os = "macos")]
+impl fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+        if self.0.is_null()
+            || unsafe { libc::dladdr(self.0, &mut info) } == 0
+            || info.dli_fname.is_null()
+        {
+            return write!(f, "{:p}", self.0);
+        }
+        let path = unsafe { std::ffi::CStr::from_ptr(info.dli_fname) }.to_bytes();
+        let name = path.rsplit(|&b| b == b'/').next().unwrap_or(path);
+        let offset = (self.0 as usize).wrapping_sub(info.dli_fbase as usize);
+        write!(f, "{}+{:#x}", String::from_utf8_lossy(name), offset)
+    }
+}
@@ -0,0 +1,1094 @@
+use core_foundation_sys::base::{
+    kCFAllocatorDefault, CFAllocatorRef, CFEqual, CFGetTypeID, CFHash, CFIndex, CFRelease,
+    CFTypeRef,
+};
+use core_foundation_sys::dictionary::{
+    CFDictionaryContainsKey, CFDictionaryCreateMutableCopy, CFDictionarySetValue,
+    CFMutableDictionaryRef,
+};
+use core_foundation_sys::string::{CFStringCreateWithCString, CFStringGetTypeID, CFStringRef};
+use ctor::ctor;
+use libc::{c_char, c_int, c_void, size_t, timespec};
+use std::cell::OnceCell;
+use std::ffi::{CStr, CString, OsString};
+use std::fmt;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::OnceLock;
+
+use crate::guard::guarded;
+use crate::logging::{self, Caller};
+use crate::original::{self, Original};
+use crate::rebind::{rebind_symbols, Rebinding};
+use uuid_spoofer_core::catalog::Catalog;
+use uuid_spoofer_core::config;
+use uuid_spoofer_core::hook_state::HookState;
+use uuid_spoofer_core::identity::{
+    is_platform_key, IdentityTable, PropertyValue, IO_MAC_ADDRESS_KEY, IO_PLATFORM_UUID_KEY,
+    MODEL_KEY, TARGET_TYPE_KEY,
+};
+use uuid_spoofer_core::key_matcher::{KeyMatcher, KeyObjects};
+use uuid_spoofer_core::mac::MacAddresses;
+use uuid_spoofer_core::profiles::Process;
+use uuid_spoofer_core::substitute::{substitute_properties, PropertyDictionary};
+use uuid_spoofer_core::uuid;
+use uuid_spoofer_core::value_cache::{RetainedObjects, ValueCache};
+
+// --- IOKit and CoreFoundation constants and types ---
+type IOOptionBits = u32;
+// io_registry_entry_t is an opaque pointer type in IOKit/
+// In C it's typedef mach_port_t io_object_t; typedef io_object_t io_registry_entry_t;
+// mach_port_t is a natural_t which is an unsigned int.
+// Using *mut c_void for simplicity as it's treated as an opaque handle here.
+type IORegistryEntryT = *mut c_void;
+type KernReturnT = i32;
+const KERN_SUCCESS: KernReturnT = 0;
+const PROC_PIDPATHINFO_MAXSIZE: usize = 4096;
+const KIO_REGISTRY_ITERATE_RECURSIVELY: IOOptionBits = 1;
+
+// <net/if_dl.h>: the link-level address getifaddrs lists for each interface.
+const AF_LINK: c_int = 18;
+
+#[repr(C)]
+struct SockaddrDl {
+    sdl_len: u8,
+    sdl_family: u8,
+    sdl_index: u16,
+    sdl_type: u8,
+    // The name's length, then the address's, in sdl_data.
+    sdl_nlen: u8,
+    sdl_alen: u8,
+    sdl_slen: u8,
+    sdl_data: [c_char; 12],
+}
+
+// The address the current hook will return to, i.e. inside whoever called it. Apple's ABIs
+// always maintain the frame pointer chain, so the saved return address sits right above
+// the saved frame pointer. Only valid when expanded directly in a hook's body.
+macro_rules! return_address {
+    () => {{
+        let frame: *const *const c_void;
+        #[cfg(target_arch = "aarch64")]
+        unsafe {
+            std::arch::asm!("mov {}, x29", out(reg) frame, options(nomem, nostack))
+        };
+        #[cfg(target_arch = "x86_64")]
+        unsafe {
+            std::arch::asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack))
+        };
+        unsafe { *frame.add(1) }
+    }};
+}
+
+// Every property this process sees spoofed, IOPlatformUUID included, resolved once in
+// `init` for the host app. `None` means the configuration was invalid and every call is
+// passed through to the original function.
+static SPOOFED_PROPERTIES: OnceLock<Option<IdentityTable>> = OnceLock::new();
+
+// The network interfaces' addresses, set along with SPOOFED_PROPERTIES.
+static SPOOFED_MAC_ADDRESSES: OnceLock<MacAddresses> = OnceLock::new();
+
+extern "C" {
+    fn CFRetain(cf: CFTypeRef) -> CFTypeRef;
+    // Address of the calling thread's errno.
+    fn __error() -> *mut c_int;
+    fn proc_pidpath(pid: c_int, buffer: *mut c_void, buffersize: u32) -> c_int;
+
+    // IORegistryEntryCreateCFProperty is part of IOKit.framework
+    // Its signature is:
+    // CFTypeRef IORegistryEntryCreateCFProperty(
+    //     io_registry_entry_t entry,
+    //     CFStringRef key,
+    //     CFAllocatorRef allocator,
+    //     IOOptionBits options
+    // );
+    //
+    // As are the recursive and whole-dictionary variants:
+    // CFTypeRef IORegistryEntrySearchCFProperty(
+    //     io_registry_entry_t entry,
+    //     const io_name_t plane,
+    //     CFStringRef key,
+    //     CFAllocatorRef allocator,
+    //     IOOptionBits options
+    // );
+    // kern_return_t IORegistryEntryCreateCFProperties(
+    //     io_registry_entry_t entry,
+    //     CFMutableDictionaryRef *properties,
+    //     CFAllocatorRef allocator,
+    //     IOOptionBits options
+    // );
+}
+
+// --- Original function pointers ---
+type FnIORegistryEntryCreateCFProperty = extern "C" fn(
+    entry: IORegistryEntryT,
+    key: CFStringRef,
+    allocator: CFAllocatorRef,
+    options: IOOptionBits,
+) -> CFTypeRef;
+
+type FnIORegistryEntrySearchCFProperty = extern "C" fn(
+    entry: IORegistryEntryT,
+    plane: *const c_char,
+    key: CFStringRef,
+    allocator: CFAllocatorRef,
+    options: IOOptionBits,
+) -> CFTypeRef;
+
+type FnIORegistryEntryCreateCFProperties = extern "C" fn(
+    entry: IORegistryEntryT,
+    properties: *mut CFMutableDictionaryRef,
+    allocator: CFAllocatorRef,
+    options: IOOptionBits,
+) -> KernReturnT;
+
+// int getifaddrs(struct ifaddrs **ifap);
+type FnGetifaddrs = extern "C" fn(ifap: *mut *mut libc::ifaddrs) -> c_int;
+
+// int gethostuuid(uuid_t id, const struct timespec *wait);
+type FnGethostuuid = extern "C" fn(id: *mut u8, wait: *const timespec) -> c_int;
+
+// int sysctlbyname(const char *name, void *oldp, size_t *oldlenp, void *newp, size_t newlen);
+type FnSysctlbyname = extern "C" fn(
+    name: *const c_char,
+    oldp: *mut c_void,
+    oldlenp: *mut size_t,
+    newp: *mut c_void,
+    newlen: size_t,
+) -> c_int;
+
+// The originals, captured by the rebinding engine when it first patches an import of
+// them, which may be when a later image is loaded (see `original`).
+static ORIGINAL_IOREGISTRYENTRYCREATECFPROPERTY: Original =
+    Original::new(c"IORegistryEntryCreateCFProperty");
+static ORIGINAL_IOREGISTRYENTRYSEARCHCFPROPERTY: Original =
+    Original::new(c"IORegistryEntrySearchCFProperty");
+static ORIGINAL_IOREGISTRYENTRYCREATECFPROPERTIES: Original =
+    Original::new(c"IORegistryEntryCreateCFProperties");
+static ORIGINAL_GETHOSTUUID: Original = Original::new(c"gethostuuid");
+static ORIGINAL_SYSCTLBYNAME: Original = Original::new(c"sysctlbyname");
+static ORIGINAL_GETIFADDRS: Original = Original::new(c"getifaddrs");
+
+static ORIGINALS: [&Original; 6] = [
+    &ORIGINAL_IOREGISTRYENTRYCREATECFPROPERTY,
+    &ORIGINAL_IOREGISTRYENTRYSEARCHCFPROPERTY,
+    &ORIGINAL_IOREGISTRYENTRYCREATECFPROPERTIES,
+    &ORIGINAL_GETHOSTUUID,
+    &ORIGINAL_SYSCTLBYNAME,
+    &ORIGINAL_GETIFADDRS,
+];
+
+// The function `original` holds, typed as `F`, or `None` with errno set to ENOSYS if
+// there is none.
+//
+// Safety: `F` must be the extern "C" fn type of the original.
+unsafe fn original_function<F: Copy>(original: &Original) -> Option<F> {
+    let Some(address) = original.get() else {
+        set_errno(libc::ENOSYS);
+        return None;
+    };
+    Some(std::mem::transmute_copy::<*mut c_void, F>(
+        &address.as_ptr(),
+    ))
+}
+
+// Copies a CFString into a Rust String, whatever its length.
+fn cfstring_to_string(s: CFStringRef) -> Option<String> {
+    use core_foundation_sys::string::{
+        kCFStringEncodingUTF8, CFStringGetLength, CFStringGetMaximumSizeForEncoding,
+    };
+
+    unsafe {
+        let length = CFStringGetLength(s);
+        let buffer_size = CFStringGetMaximumSizeForEncoding(length, kCFStringEncodingUTF8) + 1;
+        let mut buffer = vec![0u8; buffer_size as usize];
+        if core_foundation_sys::string::CFStringGetCString(
+            s,
+            buffer.as_mut_ptr() as *mut c_char,
+            buffer_size,
+            kCFStringEncodingUTF8,
+        ) == 0
+        {
+            return None;
+        }
+        let nul_pos = buffer.iter().position(|&c| c == 0)?;
+        buffer.truncate(nul_pos);
+        String::from_utf8(buffer).ok()
+    }
+}
+
+// The main bundle's identifier, if the host app has one.
+fn bundle_id() -> Option<String> {
+    use core_foundation_sys::bundle::{CFBundleGetIdentifier, CFBundleGetMainBundle};
+
+    let bundle_id = unsafe {
+        let bundle = CFBundleGetMainBundle();
+        if bundle.is_null() {
+            return None;
+        }
+        // Get rule: the identifier is owned by the bundle, no release needed.
+        CFBundleGetIdentifier(bundle)
+    };
+    if bundle_id.is_null() {
+        return None;
+    }
+    cfstring_to_string(bundle_id)
+}
+
+// The executable of the process that started this one, if it is still there.
+fn parent_executable() -> Option<PathBuf> {
+    let mut buffer = vec![0u8; PROC_PIDPATHINFO_MAXSIZE];
+    let length = unsafe {
+        proc_pidpath(
+            libc::getppid(),
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.len() as u32,
+        )
+    };
+    if length <= 0 {
+        return None;
+    }
+    buffer.truncate(length as usize);
+    Some(PathBuf::from(OsString::from_vec(buffer)))
+}
+
+// What the config file's rules can match on.
+fn current_process() -> Process {
+    Process {
+        exe: std::env::current_exe().ok(),
+        bundle_id: bundle_id(),
+        parent: parent_executable(),
+    }
+}
+
+// Identifies the host app for per-application derivation: the main bundle's identifier
+// when there is one, otherwise the executable path.
+fn app_id(process: &Process) -> String {
+    match (&process.bundle_id, &process.exe) {
+        (Some(id), _) => id.clone(),
+        (None, Some(exe)) => exe.to_string_lossy().into_owned(),
+        (None, None) => String::new(),
+    }
+}
+
+// A CFString key, as the key matcher sees it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct CFKey(CFStringRef);
+
+// The matcher's own keys are immutable CFStrings kept for the life of the process.
+unsafe impl Send for CFKey {}
+unsafe impl Sync for CFKey {}
+
+impl From<CFKey> for usize {
+    fn from(key: CFKey) -> usize {
+        key.0 as usize
+    }
+}
+
+struct CFKeyObjects;
+
+impl KeyObjects for CFKeyObjects {
+    type Key = CFKey;
+
+    fn create(&self, name: &str) -> Option<CFKey> {
+        cfstring_from_str(name).map(CFKey)
+    }
+
+    fn is_string(&self, key: CFKey) -> bool {
+        unsafe { CFGetTypeID(key.0 as CFTypeRef) == CFStringGetTypeID() }
+    }
+
+    fn hash(&self, key: CFKey) -> u64 {
+        unsafe { CFHash(key.0 as CFTypeRef) as u64 }
+    }
+
+    fn equal(&self, a: CFKey, b: CFKey) -> bool {
+        unsafe { CFEqual(a.0 as CFTypeRef, b.0 as CFTypeRef) != 0 }
+    }
+}
+
+// The keys of the spoofed property table, created once.
+static KEY_MATCHER: OnceLock<KeyMatcher<CFKey>> = OnceLock::new();
+
+// IOMACAddress is always among them, for the per-interface addresses.
+fn key_matcher(properties: &IdentityTable) -> &'static KeyMatcher<CFKey> {
+    KEY_MATCHER.get_or_init(|| {
+        let names = properties.iter().map(|(name, _)| name);
+        let mac = properties.get(IO_MAC_ADDRESS_KEY).is_none();
+        KeyMatcher::new(
+            &CFKeyObjects,
+            names.chain(mac.then_some(IO_MAC_ADDRESS_KEY)),
+        )
+    })
+}
+
+// A caller's key for the log, which may be any CF object. Converted on the stack, since
+// it is logged from inside the hooks; registry keys are far shorter than the buffer.
+struct KeyDescription(CFStringRef);
+
+impl fmt::Display for KeyDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use core_foundation_sys::string::{kCFStringEncodingUTF8, CFStringGetCString};
+
+        if !CFKeyObjects.is_string(CFKey(self.0)) {
+            return f.write_str("<not a string>");
+        }
+        let mut buffer = [0 as c_char; 256];
+        let converted = unsafe {
+            CFStringGetCString(
+                self.0,
+                buffer.as_mut_ptr(),
+                buffer.len() as CFIndex,
+                kCFStringEncodingUTF8,
+            )
+        } != 0;
+        let text = converted
+            .then(|| unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_str().ok())
+            .flatten();
+        f.write_str(text.unwrap_or("<unconvertible>"))
+    }
+}
+
+// The spoofed property table, or `None` when spoofing is disabled. Built in `init`,
+// before any slot is patched, since resolving it calls functions the hooks replace.
+fn spoofed_properties() -> Option<&'static IdentityTable> {
+    SPOOFED_PROPERTIES.get()?.as_ref()
+}
+
+// The table for this process: the profile the rules pick, with its UUID derived from
+// the host app's identity. Fixed UUIDs ignore the app id.
+fn resolve_properties(spoof_config: &config::Resolved, catalog: &Catalog) -> IdentityTable {
+    let process = current_process();
+    let app_id = app_id(&process);
+    let spoof_config = spoof_config.for_process(&process);
+    if let Some(profile) = &spoof_config.profile {
+        log!(Info, "using profile {:?} for {}", profile, app_id);
+    }
+    for inconsistency in catalog.check(&spoof_config.identity) {
+        log!(Info, "inconsistent identity: {}", inconsistency);
+    }
+    let _ = SPOOFED_MAC_ADDRESSES.set(spoof_config.mac_addresses.clone());
+    let mut table = spoof_config.identity.clone();
+    table.insert(
+        IO_PLATFORM_UUID_KEY,
+        PropertyValue::String(spoof_config.uuid_for(&app_id)),
+    );
+    table
+}
+
+// The spoofed IOPlatformUUID, which the non-IOKit hooks report in their own formats.
+fn spoofed_uuid() -> Option<&'static str> {
+    match spoofed_properties()?.get(IO_PLATFORM_UUID_KEY)? {
+        PropertyValue::String(uuid) => Some(uuid),
+        _ => None,
+    }
+}
+
+// The interfaces' addresses, or `None` when no interface is spoofed.
+fn spoofed_mac_addresses() -> Option<&'static MacAddresses> {
+    SPOOFED_MAC_ADDRESSES.get().filter(|macs| !macs.is_empty())
+}
+
+// Builds a new +1 CF object for a table value, as IORegistryEntryCreateCFProperty would.
+fn create_cf_property(value: &PropertyValue) -> CFTypeRef {
+    use core_foundation_sys::data::CFDataCreate;
+    use core_foundation_sys::number::{kCFNumberSInt64Type, CFNumberCreate};
+
+    match value {
+        PropertyValue::String(s) => match CString::new(s.as_str()) {
+            Ok(c_str) => unsafe {
+                CFStringCreateWithCString(
+                    kCFAllocatorDefault,
+                    c_str.as_ptr(),
+                    core_foundation_sys::string::kCFStringEncodingUTF8,
+                ) as CFTypeRef
+            },
+            Err(_) => ptr::null(),
+        },
+        PropertyValue::Data(bytes) => unsafe {
+            CFDataCreate(kCFAllocatorDefault, bytes.as_ptr(), bytes.len() as _) as CFTypeRef
+        },
+        PropertyValue::Number(n) => unsafe {
+            CFNumberCreate(
+                kCFAllocatorDefault,
+                kCFNumberSInt64Type,
+                n as *const i64 as *const c_void,
+            ) as CFTypeRef
+        },
+    }
+}
+
+// The values handed out by the IOKit hooks, each created on first use.
+static VALUE_CACHE: OnceLock<ValueCache> = OnceLock::new();
+
+fn value_cache(properties: &IdentityTable) -> &'static ValueCache {
+    VALUE_CACHE.get_or_init(|| ValueCache::new(properties))
+}
+
+// Spoofed values are immutable CF objects, so callers can share them; each gets its own
+// reference, as IOKit's create rule requires.
+struct CFValues;
+
+impl RetainedObjects for CFValues {
+    fn create(&self, value: &PropertyValue) -> Option<usize> {
+        Some(create_cf_property(value) as usize).filter(|&object| object != 0)
+    }
+
+    fn retain(&self, object: usize) {
+        unsafe { CFRetain(object as CFTypeRef) };
+    }
+
+    fn release(&self, object: usize) {
+        unsafe { CFRelease(object as CFTypeRef) };
+    }
+}
+
+// Wraps a CFMutableDictionary so the shared substitution logic can edit it, with the
+// cached values. The platform keys count as absent unless the dictionary is `entry`'s and
+// `entry` is the platform entry, which is only asked once.
+struct CFPropertyDictionary {
+    dictionary: CFMutableDictionaryRef,
+    values: &'static ValueCache,
+    entry: IORegistryEntryT,
+    platform: OnceCell<bool>,
+}
+
+impl PropertyDictionary for CFPropertyDictionary {
+    fn contains_key(&self, key: &str) -> bool {
+        if is_platform_key(key) && !*self.platform.get_or_init(|| is_platform_entry(self.entry)) {
+            return false;
+        }
+        let Some(cf_key) = cfstring_from_str(key) else {
+            return false;
+        };
+        let contains =
+            unsafe { CFDictionaryContainsKey(self.dictionary, cf_key as *const c_void) != 0 };
+        unsafe { CFRelease(cf_key as CFTypeRef) };
+        contains
+    }
+
+    fn set(&mut self, key: &str, _value: &PropertyValue) -> bool {
+        let Some(cf_key) = cfstring_from_str(key) else {
+            return false;
+        };
+        // The cache was built from the same table, so it holds `_value` already made.
+        let cf_value = self.values.get(&CFValues, key);
+        if let Some(cf_value) = cf_value {
+            // The dictionary retains both; drop our own references afterwards.
+            unsafe {
+                CFDictionarySetValue(
+                    self.dictionary,
+                    cf_key as *const c_void,
+                    cf_value as CFTypeRef,
+                );
+                CFRelease(cf_value as CFTypeRef);
+            }
+        }
+        unsafe { CFRelease(cf_key as CFTypeRef) };
+        cf_value.is_some()
+    }
+}
+
+fn cfstring_from_str(s: &str) -> Option<CFStringRef> {
+    let c_str = CString::new(s).ok()?;
+    let cf_string = unsafe {
+        CFStringCreateWithCString(
+            kCFAllocatorDefault,
+            c_str.as_ptr(),
+            core_foundation_sys::string::kCFStringEncodingUTF8,
+        )
+    };
+    (!cf_string.is_null()).then_some(cf_string)
+}
+
+// Whether `entry` is the IOPlatformExpertDevice, the one entry whose platform keys are
+// spoofed. IOKit is loaded by the time a registry hook runs, so IOObjectConformsTo is
+// looked up rather than linked.
+fn is_platform_entry(entry: IORegistryEntryT) -> bool {
+    type FnIOObjectConformsTo =
+        extern "C" fn(object: IORegistryEntryT, class_name: *const c_char) -> c_int;
+    static CONFORMS_TO: OnceLock<usize> = OnceLock::new();
+    let address = *CONFORMS_TO.get_or_init(|| unsafe {
+        libc::dlsym(libc::RTLD_DEFAULT, c"IOObjectConformsTo".as_ptr()) as usize
+    });
+    if address == 0 {
+        return false;
+    }
+    let conforms_to =
+        unsafe { std::mem::transmute::<*mut c_void, FnIOObjectConformsTo>(address as *mut c_void) };
+    conforms_to(entry, c"IOPlatformExpertDevice".as_ptr()) != 0
+}
+
+// The BSD name of the network interface `entry` is, or of the one below it when `entry`
+// is the controller, which is where IOMACAddress lives.
+fn interface_name(entry: IORegistryEntryT) -> Option<String> {
+    let search: FnIORegistryEntrySearchCFProperty =
+        unsafe { original_function(&ORIGINAL_IOREGISTRYENTRYSEARCHCFPROPERTY) }?;
+    let key = cfstring_from_str("BSD Name")?;
+    let name = search(
+        entry,
+        c"IOService".as_ptr(),
+        key,
+        unsafe { kCFAllocatorDefault },
+        KIO_REGISTRY_ITERATE_RECURSIVELY,
+    );
+    unsafe { CFRelease(key as CFTypeRef) };
+    if name.is_null() {
+        return None;
+    }
+    let text = CFKeyObjects
+        .is_string(CFKey(name as CFStringRef))
+        .then(|| cfstring_to_string(name as CFStringRef))
+        .flatten();
+    unsafe { CFRelease(name) };
+    text
+}
+
+// A new +1 CFData holding the address `entry`'s interface gets in place of `real`, its
+// IOMACAddress, with the interface's name; `None` if the interface is not spoofed.
+fn spoofed_mac_address(entry: IORegistryEntryT, real: CFTypeRef) -> Option<(String, CFTypeRef)> {
+    use core_foundation_sys::data::{CFDataGetBytePtr, CFDataGetLength, CFDataGetTypeID};
+
+    let mac_addresses = spoofed_mac_addresses()?;
+    if real.is_null() || unsafe { CFGetTypeID(real) != CFDataGetTypeID() } {
+        return None;
+    }
+    let mut bytes = unsafe {
+        let data = real as core_foundation_sys::data::CFDataRef;
+        std::slice::from_raw_parts(CFDataGetBytePtr(data), CFDataGetLength(data) as usize).to_vec()
+    };
+    let name = interface_name(entry)?;
+    if !mac_addresses.replace(&name, &mut bytes) {
+        return None;
+    }
+    let data = create_cf_property(&PropertyValue::Data(bytes));
+    (!data.is_null()).then_some((name, data))
+}
+
+// Returns a new +1 spoofed value for `key`, or `None` to fall through to the original.
+// IOMACAddress depends on the interface, so `real` fetches the value being replaced.
+// `function` and `caller` only feed the log.
+fn spoofed_property(
+    function: &str,
+    caller: *const c_void,
+    entry: IORegistryEntryT,
+    key: CFStringRef,
+    real: impl FnOnce() -> Option<CFTypeRef>,
+) -> Option<CFTypeRef> {
+    if key.is_null() {
+        return None;
+    }
+    let Some(properties) = spoofed_properties() else {
+        log!(
+            Trace,
+            "{} from {}: spoofing disabled, passed through",
+            function,
+            Caller(caller)
+        );
+        return None;
+    };
+
+    let Some(name) = key_matcher(properties).find(&CFKeyObjects, CFKey(key)) else {
+        log!(
+            Trace,
+            "{}({}) from {}: passed through",
+            function,
+            KeyDescription(key),
+            Caller(caller)
+        );
+        return None;
+    };
+    if is_platform_key(name) && !is_platform_entry(entry) {
+        log!(
+            Trace,
+            "{}({}) from {}: not the platform entry, passed through",
+            function,
+            name,
+            Caller(caller)
+        );
+        return None;
+    }
+    if name == IO_MAC_ADDRESS_KEY && spoofed_mac_addresses().is_some() {
+        let real = real().unwrap_or(ptr::null());
+        let spoofed = spoofed_mac_address(entry, real);
+        if !real.is_null() {
+            unsafe { CFRelease(real) };
+        }
+        if let Some((interface, value)) = spoofed {
+            log!(
+                Debug,
+                "{}({}) of {} from {}: spoofed",
+                function,
+                name,
+                interface,
+                Caller(caller)
+            );
+            return Some(value);
+        }
+    }
+    if properties.get(name).is_none() {
+        // Only IOMACAddress is matched without being in the table.
+        log!(
+            Trace,
+            "{}({}) from {}: passed through",
+            function,
+            name,
+            Caller(caller)
+        );
+        return None;
+    }
+    let Some(value) = value_cache(properties).get(&CFValues, name) else {
+        log!(
+            Error,
+            "{}({}) from {}: cannot create the spoofed value, passed through",
+            function,
+            name,
+            Caller(caller)
+        );
+        return None;
+    };
+    log!(
+        Debug,
+        "{}({}) from {}: spoofed",
+        function,
+        name,
+        Caller(caller)
+    );
+    Some(value as CFTypeRef)
+}
+
+// --- Replacement functions ---
+// What IOKit returns for a failed call when the original function is unknown.
+const KIO_RETURN_ERROR: KernReturnT = 0xe00002bc_u32 as KernReturnT;
+
+fn set_errno(errno: c_int) {
+    unsafe { *__error() = errno };
+}
+
+#[no_mangle]
+pub extern "C" fn replaced_IORegistryEntryCreateCFProperty(
+    entry: IORegistryEntryT,
+    key: CFStringRef,
+    allocator: CFAllocatorRef,
+    options: IOOptionBits,
+) -> CFTypeRef {
+    let caller = return_address!();
+    // Asked for the real value by the hook, and called instead of it if the hook fails.
+    let call_original = || {
+        let original: FnIORegistryEntryCreateCFProperty =
+            unsafe { original_function(&ORIGINAL_IOREGISTRYENTRYCREATECFPROPERTY) }?;
+        Some(original(entry, key, allocator, options))
+    };
+    guarded(
+        "IORegistryEntryCreateCFProperty",
+        || {
+            spoofed_property(
+                "IORegistryEntryCreateCFProperty",
+                caller,
+                entry,
+                key,
+                call_original,
+            )
+        },
+        call_original,
+        ptr::null(),
+    )
+}
+
+#[no_mangle]
+pub extern "C" fn replaced_IORegistryEntrySearchCFProperty(
+    entry: IORegistryEntryT,
+    plane: *const c_char,
+    key: CFStringRef,
+    allocator: CFAllocatorRef,
+    options: IOOptionBits,
+) -> CFTypeRef {
+    let caller = return_address!();
+    let call_original = || {
+        let original: FnIORegistryEntrySearchCFProperty =
+            unsafe { original_function(&ORIGINAL_IOREGISTRYENTRYSEARCHCFPROPERTY) }?;
+        Some(original(entry, plane, key, allocator, options))
+    };
+    guarded(
+        "IORegistryEntrySearchCFProperty",
+        || {
+            spoofed_property(
+                "IORegistryEntrySearchCFProperty",
+                caller,
+                entry,
+                key,
+                call_original,
+            )
+        },
+        call_original,
+        ptr::null(),
+    )
+}
+
+// Puts the spoofed address of `entry`'s interface into `dictionary`, if it has an
+// IOMACAddress and the interface is spoofed. Returns whether it did.
+fn spoof_dictionary_mac_address(
+    entry: IORegistryEntryT,
+    dictionary: CFMutableDictionaryRef,
+) -> bool {
+    use core_foundation_sys::dictionary::CFDictionaryGetValue;
+
+    let Some(key) = cfstring_from_str(IO_MAC_ADDRESS_KEY) else {
+        return false;
+    };
+    // The dictionary keeps its own reference to the real value.
+    let real = unsafe { CFDictionaryGetValue(dictionary, key as *const c_void) };
+    let spoofed = spoofed_mac_address(entry, real);
+    if let Some((_, value)) = spoofed {
+        unsafe {
+            CFDictionarySetValue(dictionary, key as *const c_void, value);
+            CFRelease(value);
+        }
+    }
+    unsafe { CFRelease(key as CFTypeRef) };
+    spoofed.is_some()
+}
+
+// Replaces the caller's property dictionary with a copy holding the spoofed values.
+fn spoof_property_dictionary(
+    caller: *const c_void,
+    entry: IORegistryEntryT,
+    properties: *mut CFMutableDictionaryRef,
+    allocator: CFAllocatorRef,
+) {
+    let Some(spoofed) = spoofed_properties() else {
+        return;
+    };
+    unsafe {
+        let original = *properties;
+        if original.is_null() {
+            return;
+        }
+        // Hand back a substituted copy and release the caller's +1 on the original.
+        let copy = CFDictionaryCreateMutableCopy(allocator, 0, original);
+        if copy.is_null() {
+            return;
+        }
+        let mut dictionary = CFPropertyDictionary {
+            dictionary: copy,
+            values: value_cache(spoofed),
+            entry,
+            platform: OnceCell::new(),
+        };
+        let mut replaced = substitute_properties(&mut dictionary, spoofed);
+        // The interface's own address wins over a table one.
+        if spoof_dictionary_mac_address(entry, copy) && spoofed.get(IO_MAC_ADDRESS_KEY).is_none() {
+            replaced += 1;
+        }
+        log!(
+            Debug,
+            "IORegistryEntryCreateCFProperties from {}: spoofed {} properties",
+            Caller(caller),
+            replaced
+        );
+        CFRelease(original as CFTypeRef);
+        *properties = copy;
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn replaced_IORegistryEntryCreateCFProperties(
+    entry: IORegistryEntryT,
+    properties: *mut CFMutableDictionaryRef,
+    allocator: CFAllocatorRef,
+    options: IOOptionBits,
+) -> KernReturnT {
+    let caller = return_address!();
+    // The spoofing edits what the original returns, so the original always runs first.
+    let result = guarded(
+        "IORegistryEntryCreateCFProperties",
+        || None,
+        || {
+            let original: FnIORegistryEntryCreateCFProperties =
+                unsafe { original_function(&ORIGINAL_IOREGISTRYENTRYCREATECFPROPERTIES) }?;
+            Some(original(entry, properties, allocator, options))
+        },
+        KIO_RETURN_ERROR,
+    );
+    if result != KERN_SUCCESS || properties.is_null() {
+        return result;
+    }
+    // If substitution panics, the caller keeps the original dictionary.
+    guarded(
+        "IORegistryEntryCreateCFProperties",
+        || {
+            spoof_property_dictionary(caller, entry, properties, allocator);
+            Some(result)
+        },
+        || Some(result),
+        result,
+    )
+}
+
+// Rewrites the link-level (AF_LINK) addresses in a list `getifaddrs` returned, in place.
+fn spoof_interface_list(caller: *const c_void, list: *mut libc::ifaddrs) {
+    let Some(mac_addresses) = spoofed_mac_addresses() else {
+        log!(Trace, "getifaddrs from {}: passed through", Caller(caller));
+        return;
+    };
+    let mut replaced = 0;
+    let mut entry = list;
+    while let Some(ifa) = unsafe { entry.as_ref() } {
+        entry = ifa.ifa_next;
+        if ifa.ifa_addr.is_null()
+            || ifa.ifa_name.is_null()
+            || unsafe { (*ifa.ifa_addr).sa_family } as c_int != AF_LINK
+        {
+            continue;
+        }
+        let Ok(name) = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_str() else {
+            continue;
+        };
+        // The address follows the name in sdl_data, which runs on past the struct for
+        // sdl_len bytes in all; only Ethernet-style 6-byte addresses are replaced.
+        let link = ifa.ifa_addr as *mut SockaddrDl;
+        let (length, name_length, address_length) =
+            unsafe { ((*link).sdl_len, (*link).sdl_nlen, (*link).sdl_alen) };
+        let offset = std::mem::offset_of!(SockaddrDl, sdl_data) + name_length as usize;
+        if address_length != 6 || offset + address_length as usize > length as usize {
+            continue;
+        }
+        let address = unsafe {
+            std::slice::from_raw_parts_mut((link as *mut u8).add(offset), address_length as usize)
+        };
+        if mac_addresses.replace(name, address) {
+            replaced += 1;
+        }
+    }
+    log!(
+        Debug,
+        "getifaddrs from {}: spoofed {} link-level addresses",
+        Caller(caller),
+        replaced
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn replaced_getifaddrs(ifap: *mut *mut libc::ifaddrs) -> c_int {
+    let caller = return_address!();
+    // The list comes from the original; only its addresses are rewritten.
+    let result = guarded(
+        "getifaddrs",
+        || None,
+        || {
+            let original: FnGetifaddrs = unsafe { original_function(&ORIGINAL_GETIFADDRS) }?;
+            Some(original(ifap))
+        },
+        -1,
+    );
+    if result == 0 && !ifap.is_null() {
+        // If rewriting panics, the caller keeps the real addresses.
+        guarded(
+            "getifaddrs",
+            || {
+                spoof_interface_list(caller, unsafe { *ifap });
+                Some(())
+            },
+            || Some(()),
+            (),
+        );
+    }
+    result
+}
+
+fn spoofed_gethostuuid(caller: *const c_void, id: *mut u8) -> Option<c_int> {
+    let bytes = spoofed_uuid()
+        .filter(|_| !id.is_null())
+        .and_then(|uuid| uuid::uuid_to_bytes(uuid).ok());
+    let Some(bytes) = bytes else {
+        log!(Debug, "gethostuuid from {}: passed through", Caller(caller));
+        return None;
+    };
+    log!(Debug, "gethostuuid from {}: spoofed", Caller(caller));
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), id, bytes.len()) };
+    Some(0)
+}
+
+#[no_mangle]
+pub extern "C" fn replaced_gethostuuid(id: *mut u8, wait: *const timespec) -> c_int {
+    let caller = return_address!();
+    guarded(
+        "gethostuuid",
+        || spoofed_gethostuuid(caller, id),
+        || {
+            let original: FnGethostuuid = unsafe { original_function(&ORIGINAL_GETHOSTUUID) }?;
+            Some(original(id, wait))
+        },
+        -1,
+    )
+}
+
+// Only reads of kern.uuid are answered here; writes and everything else go through.
+fn spoofed_sysctlbyname(
+    caller: *const c_void,
+    name: *const c_char,
+    oldp: *mut c_void,
+    oldlenp: *mut size_t,
+    newp: *mut c_void,
+) -> Option<c_int> {
+    if name.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(name) };
+    let c_string = Some(name)
+        .filter(|_| !oldlenp.is_null() && newp.is_null())
+        .and_then(spoofed_sysctl_value);
+    let Some(c_string) = c_string else {
+        log!(
+            Trace,
+            "sysctlbyname({}) from {}: passed through",
+            name.to_string_lossy(),
+            Caller(caller)
+        );
+        return None;
+    };
+    unsafe {
+        // A null buffer is a size query; a short one fails like the kernel does.
+        if !oldp.is_null() {
+            if *oldlenp < c_string.len() {
+                set_errno(libc::ENOMEM);
+                return Some(-1);
+            }
+            ptr::copy_nonoverlapping(c_string.as_ptr(), oldp as *mut u8, c_string.len());
+        }
+        *oldlenp = c_string.len();
+    }
+    log!(
+        Debug,
+        "sysctlbyname({}) from {}: spoofed",
+        name.to_string_lossy(),
+        Caller(caller)
+    );
+    Some(0)
+}
+
+// The NUL-terminated value sysctlbyname reports for `name`, or `None` to pass it through:
+// kern.uuid is the spoofed UUID, and hw.model and hw.target the table's `model` and
+// `target-type`. Only Apple silicon kernels have hw.target, so it is left alone where
+// the real one does not exist.
+fn spoofed_sysctl_value(name: &CStr) -> Option<Vec<u8>> {
+    let properties = spoofed_properties()?;
+    let text = match name.to_bytes() {
+        b"kern.uuid" => return uuid::uuid_to_c_string(spoofed_uuid()?).ok().map(Vec::from),
+        b"hw.model" => properties.text(MODEL_KEY)?,
+        b"hw.target" if sysctl_exists(name) => properties.text(TARGET_TYPE_KEY)?,
+        _ => return None,
+    };
+    CString::new(text).ok().map(CString::into_bytes_with_nul)
+}
+
+// Whether the real sysctl `name` exists, asked as a size query.
+fn sysctl_exists(name: &CStr) -> bool {
+    let Some(original) = (unsafe { original_function::<FnSysctlbyname>(&ORIGINAL_SYSCTLBYNAME) })
+    else {
+        return false;
+    };
+    let mut length: size_t = 0;
+    original(
+        name.as_ptr(),
+        ptr::null_mut(),
+        &mut length,
+        ptr::null_mut(),
+        0,
+    ) == 0
+}
+
+#[no_mangle]
+pub extern "C" fn replaced_sysctlbyname(
+    name: *const c_char,
+    oldp: *mut c_void,
+    oldlenp: *mut size_t,
+    newp: *mut c_void,
+    newlen: size_t,
+) -> c_int {
+    let caller = return_address!();
+    guarded(
+        "sysctlbyname",
+        || spoofed_sysctlbyname(caller, name, oldp, oldlenp, newp),
+        || {
+            let original: FnSysctlbyname = unsafe { original_function(&ORIGINAL_SYSCTLBYNAME) }?;
+            Some(original(name, oldp, oldlenp, newp, newlen))
+        },
+        -1,
+    )
+}
+
+// --- Dylib constructor ---
+#[ctor]
+fn init() {
+    logging::init();
+    let catalog = Catalog::installed().unwrap_or_else(|e| {
+        log!(Error, "{}. Using the built-in model catalog.", e);
+        Catalog::builtin()
+    });
+    let spoofed_properties = match config::resolve() {
+        Ok(resolved) => {
+            log!(
+                Info,
+                "spoofing {} identity properties (UUID from {})",
+                resolved.identity.len() + 1,
+                resolved.source
+            );
+            Some(resolve_properties(&resolved, catalog))
+        }
+        Err(e) => {
+            log!(Error, "{}. No properties will be spoofed.", e);
+            None
+        }
+    };
+    let _ = SPOOFED_PROPERTIES.set(spoofed_properties);
+
+    // The engine captures each original before pointing any slot at its hook, including
+    // in images loaded after init.
+    let hook = |original: &'static Original, replacement: *mut c_void| Rebinding {
+        original,
+        replacement,
+    };
+    let rebindings = vec![
+        hook(
+            &ORIGINAL_IOREGISTRYENTRYCREATECFPROPERTY,
+            replaced_IORegistryEntryCreateCFProperty as *mut c_void,
+        ),
+        hook(
+            &ORIGINAL_IOREGISTRYENTRYSEARCHCFPROPERTY,
+            replaced_IORegistryEntrySearchCFProperty as *mut c_void,
+        ),
+        hook(
+            &ORIGINAL_IOREGISTRYENTRYCREATECFPROPERTIES,
+            replaced_IORegistryEntryCreateCFProperties as *mut c_void,
+        ),
+        hook(&ORIGINAL_GETHOSTUUID, replaced_gethostuuid as *mut c_void),
+        hook(&ORIGINAL_SYSCTLBYNAME, replaced_sysctlbyname as *mut c_void),
+        hook(&ORIGINAL_GETIFADDRS, replaced_getifaddrs as *mut c_void),
+    ];
+
+    original::register(&ORIGINALS);
+    match rebind_symbols(rebindings) {
+        Some(patched) => log!(Info, "patched {} import slots", patched),
+        None => {
+            log!(
+                Error,
+                "import slots not patched; the hooks are not installed"
+            );
+            for original in ORIGINALS {
+                original.install_failed();
+            }
+        }
+    }
+    for original in ORIGINALS {
+        if original.state() == HookState::Uninstalled {
+            log!(
+                Info,
+                "{} is not imported by any loaded image yet; images loaded later are hooked as they load",
+                original.name()
+            );
+        }
+    }
+    original::log_hook_states();
+}
@@ -0,0 +1,185 @@
+// The original implementation behind each hook, and the hook's state (see
+// `uuid_spoofer_core::hook_state`). The rebinding engine captures the original from the
+// first import slot it patches, and an interposed export finds it with dlsym(RTLD_NEXT);
+// any other hook reached before its original was captured falls back to dlsym(RTLD_NEXT)
+// too, and runs degraded. Every state change is logged, and the current states can be
+// queried in-process with `hook_states` or, from C, `uuid_spoof_hook_state`.
+
+use libc::{c_char, c_int, c_void};
+use std::ffi::CStr;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::OnceLock;
+
+use uuid_spoofer_core::hook_state::{Event, HookState, HookStatus};
+
+pub struct Original {
+    name: &'static CStr,
+    address: AtomicPtr<c_void>,
+    status: HookStatus,
+    // The hook is an export that shadows the original, so the definition next in the
+    // lookup order is the original by design, not a fallback.
+    interposed: bool,
+}
+
+impl Original {
+    // The original of a hook that is only reached through patched import slots.
+    pub const fn new(name: &'static CStr) -> Self {
+        Original {
+            name,
+            address: AtomicPtr::new(ptr::null_mut()),
+            status: HookStatus::new(),
+            interposed: false,
+        }
+    }
+
+    // The original of a hook exported under the function's own name.
+    #[cfg(target_os = "linux")]
+    pub const fn interposed(name: &'static CStr) -> Self {
+        Original {
+            interposed: true,
+            ..Original::new(name)
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name.to_str().unwrap_or_default()
+    }
+
+    pub fn state(&self) -> HookState {
+        self.status.state()
+    }
+
+    // Stores the implementation found in an import slot, before that slot is pointed at
+    // the hook.
+    pub fn capture(&self, address: *mut c_void) {
+        self.address.store(address, Ordering::Release);
+        self.record(Event::Captured);
+    }
+
+    // Marks the hook degraded after installing the hooks failed. Interposed hooks do not
+    // depend on the install, so only the patch-only backend needs this.
+    #[cfg(target_os = "macos")]
+    pub fn install_failed(&self) {
+        self.record(Event::InstallFailed);
+    }
+
+    // Marks the hook degraded for good after one of its import slots was left unpatched.
+    #[cfg(target_os = "macos")]
+    pub fn bypassed(&self) {
+        self.record(Event::Bypassed);
+    }
+
+    // The original to call through: the captured one, or else whatever dlsym(RTLD_NEXT)
+    // finds, which degrades a hook that is not interposed. `None` if there is neither.
+    pub fn get(&self) -> Option<NonNull<c_void>> {
+        if let Some(address) = self.captured() {
+            return Some(address);
+        }
+        let address = self.next();
+        match address {
+            Some(address) if self.interposed => self.capture(address.as_ptr()),
+            Some(address) => {
+                // Not captured, so a later capture still wins and reinstalls the hook.
+                let _ = self.address.compare_exchange(
+                    ptr::null_mut(),
+                    address.as_ptr(),
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                );
+                self.record(Event::FellBack);
+            }
+            None => self.record(Event::Unresolved),
+        }
+        address
+    }
+
+    fn captured(&self) -> Option<NonNull<c_void>> {
+        NonNull::new(self.address.load(Ordering::Acquire))
+    }
+
+    fn next(&self) -> Option<NonNull<c_void>> {
+        NonNull::new(unsafe { libc::dlsym(libc::RTLD_NEXT, self.name.as_ptr()) })
+    }
+
+    fn record(&self, event: Event) {
+        let Some((from, to)) = self.status.record(event) else {
+            return;
+        };
+        match (to, event) {
+            (HookState::Installed, _) => {
+                log!(Debug, "{}: {} -> {}", self.name(), from, to)
+            }
+            (_, Event::FellBack) => log!(
+                Error,
+                "{}: {} -> {}: called before its original was captured; passing through to the one dlsym(RTLD_NEXT) found",
+                self.name(),
+                from,
+                to
+            ),
+            (_, Event::Unresolved) => log!(
+                Error,
+                "{}: {} -> {}: no original implementation found; calls that are not spoofed fail",
+                self.name(),
+                from,
+                to
+            ),
+            (_, Event::Bypassed) => log!(
+                Error,
+                "{}: {} -> {}: an import slot could not be patched; calls through it are not spoofed",
+                self.name(),
+                from,
+                to
+            ),
+            _ => log!(Error, "{}: {} -> {}", self.name(), from, to),
+        }
+    }
+}
+
+static HOOKS: OnceLock<&'static [&'static Original]> = OnceLock::new();
+
+// Makes the backend's hooks visible to the query API. Only the first call counts.
+pub fn register(hooks: &'static [&'static Original]) {
+    let _ = HOOKS.set(hooks);
+}
+
+// Every registered hook with its state, in registration order.
+pub fn hook_states() -> Vec<(&'static str, HookState)> {
+    HOOKS
+        .get()
+        .map(|hooks| {
+            hooks
+                .iter()
+                .map(|original| (original.name(), original.state()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Logs one line with the state of every hook.
+pub fn log_hook_states() {
+    let states = hook_states();
+    log!(
+        Info,
+        "hook states: {}",
+        states
+            .iter()
+            .map(|(name, state)| format!("{} {}", name, state))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+// The state of the hook for the C function `name`: 0 uninstalled, 1 installed,
+// 2 degraded, or -1 if there is no such hook.
+#[no_mangle]
+pub extern "C" fn uuid_spoof_hook_state(name: *const c_char) -> c_int {
+    if name.is_null() {
+        return -1;
+    }
+    let name = unsafe { CStr::from_ptr(name) };
+    HOOKS
+        .get()
+        .and_then(|hooks| hooks.iter().find(|original| original.name == name))
+        .map_or(-1, |original| original.state().code())
+}
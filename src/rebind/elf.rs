@@ -0,0 +1,270 @@
+// The ELF half of the rebinding engine: points the GOT slots of every loaded object that
+// `uuid_spoofer_core::elf` finds in its dynamic relocations at our hooks. Exporting a
+// function from the preloaded library already catches every call that the dynamic linker
+// resolves through the global scope; this catches the rest, from objects opened with
+// RTLD_DEEPBIND or in another link-map namespace, which bind to libc directly.
+//
+// glibc has no load callback for a preloaded library to register, so objects are listed
+// with dl_iterate_phdr at startup and again whenever `rebind_if_loaded` sees that dlopen
+// has loaded more since; the registry makes each pass patch only the objects it has not
+// seen. Wrapping dlopen instead would make this library its caller, and glibc would
+// search for a bare name with our RUNPATH rather than the real caller's.
+
+use std::ffi::CStr;
+use std::mem::{offset_of, size_of};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use libc::{c_int, c_void, dl_phdr_info, size_t};
+use uuid_spoofer_core::elf::{
+    self, Dynamic, ProgramHeader, PHDR_SIZE, PT_DYNAMIC, PT_GNU_RELRO, PT_LOAD,
+};
+
+use super::{engine, install, patched_slots, try_engine, Engine, Rebinding};
+
+#[cfg(target_arch = "x86_64")]
+const SLOT_KINDS: &[u32] = elf::X86_64_SLOT_KINDS;
+#[cfg(target_arch = "aarch64")]
+const SLOT_KINDS: &[u32] = elf::AARCH64_SLOT_KINDS;
+// Other architectures (and 32-bit objects) are left to LD_PRELOAD alone.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const SLOT_KINDS: &[u32] = &[];
+
+// Rebinds the given functions in every object loaded so far except this library itself.
+// Objects loaded later are rebound by `rebind_if_loaded`. Returns how many slots were
+// patched, or `None` if nothing was installed: only the first call installs anything.
+pub fn rebind_symbols(rebindings: Vec<Rebinding>) -> Option<usize> {
+    if !install(rebindings) {
+        return None;
+    }
+    rebind_new_images();
+    Some(patched_slots())
+}
+
+// glibc's count of objects loaded so far, as of the last pass.
+static LOADS_SEEN: AtomicU64 = AtomicU64::new(0);
+
+// Rebinds the objects loaded since the last pass and forgets those unloaded since.
+fn rebind_new_images() {
+    let mut engine = engine();
+    if let Some(engine) = engine.as_mut() {
+        rebind_pass(engine);
+    }
+}
+
+// Runs a pass if dlopen has loaded anything since the last one. Without a load this only
+// reads glibc's counter, so every hook can afford to call it. A pass already running
+// (on this thread, from a call it made, or on another) is not waited for.
+pub fn rebind_if_loaded() {
+    if loads() == LOADS_SEEN.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(mut engine) = try_engine() else {
+        return;
+    };
+    if let Some(engine) = engine.as_mut() {
+        rebind_pass(engine);
+    }
+}
+
+// The `dlpi_adds` counter, which glibc bumps for every object it loads. Only the first
+// listed object is looked at; the counter is the same in all of them.
+fn loads() -> u64 {
+    unsafe extern "C" fn first_listed(
+        info: *mut dl_phdr_info,
+        size: size_t,
+        data: *mut c_void,
+    ) -> c_int {
+        *(data as *mut u64) = adds(&*info, size);
+        1
+    }
+    let mut adds = 0u64;
+    unsafe { libc::dl_iterate_phdr(Some(first_listed), &mut adds as *mut u64 as *mut c_void) };
+    adds
+}
+
+// `dlpi_adds`, if `size` says this glibc fills it in (0 otherwise).
+fn adds(info: &dl_phdr_info, size: size_t) -> u64 {
+    if size < offset_of!(dl_phdr_info, dlpi_adds) + size_of::<u64>() {
+        return 0;
+    }
+    info.dlpi_adds
+}
+
+fn rebind_pass(engine: &mut Engine) {
+    let mut pass = Pass {
+        engine,
+        listed: Vec::new(),
+        loads: 0,
+    };
+    // The callback runs with the loader's lock held, so no object can be unloaded while
+    // its slots are patched. Nothing called from it takes that lock again.
+    unsafe { libc::dl_iterate_phdr(Some(image_listed), &mut pass as *mut Pass as *mut c_void) };
+    let Pass {
+        engine,
+        listed,
+        loads,
+    } = pass;
+    LOADS_SEEN.store(loads, Ordering::Relaxed);
+    let unloaded: Vec<u64> = engine
+        .registry
+        .images()
+        .iter()
+        .map(|image| image.header)
+        .filter(|base| !listed.contains(base))
+        .collect();
+    for base in unloaded {
+        if let Some(image) = engine.registry.remove_image(base) {
+            log!(Debug, "{}: unloaded", image.path);
+        }
+    }
+}
+
+struct Pass<'a> {
+    engine: &'a mut Engine,
+    // Load bases of the objects dl_iterate_phdr reported.
+    listed: Vec<u64>,
+    // `dlpi_adds` as this pass saw it.
+    loads: u64,
+}
+
+// The address ranges of an object's segments of the given type.
+fn ranges(base: u64, headers: &[ProgramHeader], kind: u32) -> Vec<Range<u64>> {
+    headers
+        .iter()
+        .filter(|header| header.kind == kind)
+        .map(|header| {
+            let start = base.wrapping_add(header.vaddr);
+            start..start.wrapping_add(header.memsz)
+        })
+        .collect()
+}
+
+unsafe extern "C" fn image_listed(
+    info: *mut dl_phdr_info,
+    size: size_t,
+    data: *mut c_void,
+) -> c_int {
+    let pass = &mut *(data as *mut Pass);
+    let info = &*info;
+    pass.loads = adds(info, size);
+    #[allow(clippy::unnecessary_cast)] // Elf32_Addr on 32-bit targets
+    let base = info.dlpi_addr as u64;
+    pass.listed.push(base);
+    let headers = if info.dlpi_phdr.is_null() {
+        Vec::new()
+    } else {
+        elf::program_headers(std::slice::from_raw_parts(
+            info.dlpi_phdr as *const u8,
+            info.dlpi_phnum as usize * PHDR_SIZE,
+        ))
+    };
+    let loaded = ranges(base, &headers, PT_LOAD);
+    let own_code = rebind_new_images as *const () as u64;
+    if loaded.iter().any(|range| range.contains(&own_code)) {
+        return 0;
+    }
+    // The main program's name is empty.
+    let name = match info.dlpi_name {
+        name if name.is_null() || *name == 0 => "the main program".to_string(),
+        name => CStr::from_ptr(name).to_string_lossy().into_owned(),
+    };
+    if !pass.engine.registry.add_image(base, &name) {
+        return 0;
+    }
+    match rebind_image(base, &headers, &loaded, pass.engine) {
+        Ok(0) => log!(Trace, "{}: nothing to rebind", name),
+        Ok(count) => log!(Debug, "{}: rebound {} slots", name, count),
+        Err(e) => log!(Debug, "{}: not rebound: {}", name, e),
+    }
+    0
+}
+
+unsafe fn rebind_image(
+    base: u64,
+    headers: &[ProgramHeader],
+    loaded: &[Range<u64>],
+    engine: &mut Engine,
+) -> Result<usize, &'static str> {
+    let Some(dynamic) = headers.iter().find(|header| header.kind == PT_DYNAMIC) else {
+        return Ok(0);
+    };
+    let mapped = |address: u64, size: u64| {
+        std::slice::from_raw_parts(address as usize as *const u8, size as usize)
+    };
+    let dynamic = Dynamic::parse(mapped(base.wrapping_add(dynamic.vaddr), dynamic.memsz));
+    if dynamic.tables.is_empty() {
+        return Ok(0);
+    }
+    let (Some(symtab), Some(strtab)) = (dynamic.symtab, dynamic.strtab) else {
+        return Err("relocations without a symbol table");
+    };
+    let relocations: Vec<elf::Relocation> = dynamic
+        .tables
+        .iter()
+        .flat_map(|table| {
+            let data = mapped(elf::relocated(table.address, base), table.size);
+            elf::relocations(data, table.rela)
+        })
+        .collect();
+    let symbols = mapped(
+        elf::relocated(symtab, base),
+        elf::symbol_table_size(&relocations) as u64,
+    );
+    let strings = mapped(elf::relocated(strtab, base), dynamic.strsz);
+    let names: Vec<&'static str> = engine.rebindings.iter().map(Rebinding::name).collect();
+    let slots = elf::import_slots(&relocations, SLOT_KINDS, symbols, strings, &names);
+
+    let relro = ranges(base, headers, PT_GNU_RELRO);
+    let page_size = libc::sysconf(libc::_SC_PAGESIZE) as u64;
+    let mut patched = 0;
+    for slot in slots {
+        let address = base.wrapping_add(slot.address);
+        let pointer = address as usize as *mut *mut c_void;
+        if !loaded.iter().any(|range| range.contains(&address)) {
+            log!(
+                Debug,
+                "skipped the {} slot at {:p}, outside the object's segments",
+                names[slot.name],
+                pointer
+            );
+            continue;
+        }
+        // With BIND_NOW the dynamic linker makes the GOT read-only once it is filled in.
+        let page = address & !(page_size - 1);
+        let read_only = relro.iter().any(|range| range.contains(&address));
+        if read_only
+            && libc::mprotect(
+                page as usize as *mut c_void,
+                page_size as usize,
+                libc::PROT_READ | libc::PROT_WRITE,
+            ) != 0
+        {
+            log!(
+                Error,
+                "cannot make the {} slot at {:p} writable",
+                names[slot.name],
+                pointer
+            );
+            continue;
+        }
+        // A lazily bound slot still points back into the object's own PLT, which is not
+        // an implementation of the function.
+        let current = *pointer;
+        let current = if loaded.iter().any(|range| range.contains(&(current as u64))) {
+            std::ptr::null_mut()
+        } else {
+            current
+        };
+        engine.patch_slot(base, slot.name, slot.address, pointer, current);
+        patched += 1;
+        if read_only {
+            libc::mprotect(
+                page as usize as *mut c_void,
+                page_size as usize,
+                libc::PROT_READ,
+            );
+        }
+    }
+    Ok(patched)
+}
@@ -0,0 +1,295 @@
+// The Mach-O half of the rebinding engine: points the imports of every image, loaded now or
+// later, at our hooks, by overwriting the symbol pointer slots that
+// `uuid_spoofer_core::rebind` finds in each image's load commands, and, for
+// images using chained fixups, the bind slots `uuid_spoofer_core::chained_fixups` finds in
+// the image's file. This is the job fishhook used to do for us; the parsing half lives in
+// core so it is tested on any host against fixture binaries.
+
+use std::ffi::CStr;
+
+use libc::{c_int, c_void};
+use uuid_spoofer_core::chained_fixups::ChainedFixups;
+use uuid_spoofer_core::macho::{self, MachO, MachOError};
+use uuid_spoofer_core::rebind::{ImageLayout, Slot, Tables};
+
+use super::{engine, install, patched_slots, Engine, Rebinding};
+
+const VM_PROT_READ: c_int = 0x1;
+const VM_PROT_WRITE: c_int = 0x2;
+const VM_PROT_COPY: c_int = 0x10;
+const KERN_SUCCESS: c_int = 0;
+
+extern "C" {
+    fn _dyld_register_func_for_add_image(func: extern "C" fn(header: *const u8, slide: isize));
+    fn _dyld_register_func_for_remove_image(func: extern "C" fn(header: *const u8, slide: isize));
+    static mach_task_self_: u32;
+    fn vm_protect(
+        target_task: u32,
+        address: usize,
+        size: usize,
+        set_maximum: c_int,
+        new_protection: c_int,
+    ) -> c_int;
+}
+
+// Rebinds the given functions in every image loaded so far except this library itself,
+// and in every image loaded from now on. Returns how many slots were patched so far, or
+// `None` if nothing was installed: only the first call installs anything.
+pub fn rebind_symbols(rebindings: Vec<Rebinding>) -> Option<usize> {
+    if !install(rebindings) {
+        return None;
+    }
+    // dyld calls image_added for every image already loaded before this returns, so the
+    // engine must not be locked here.
+    unsafe {
+        _dyld_register_func_for_add_image(image_added);
+        _dyld_register_func_for_remove_image(image_removed);
+    }
+    Some(patched_slots())
+}
+
+// The path of the image containing `address`, as dyld loaded it.
+fn image_path(address: *const c_void) -> Option<&'static CStr> {
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    if unsafe { libc::dladdr(address, &mut info) } == 0 || info.dli_fname.is_null() {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(info.dli_fname) })
+}
+
+extern "C" fn image_added(header: *const u8, slide: isize) {
+    let mut own_image = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    unsafe { libc::dladdr(image_added as *const c_void, &mut own_image) };
+    if header.is_null() || std::ptr::eq(header as *const c_void, own_image.dli_fbase) {
+        return;
+    }
+    let mut engine = engine();
+    let Some(engine) = engine.as_mut() else {
+        return;
+    };
+    let path = image_path(header as *const c_void);
+    let name = path.map(CStr::to_string_lossy).unwrap_or_default();
+    if !engine.registry.add_image(header as u64, &name) {
+        return;
+    }
+    match unsafe { rebind_image(header, slide, path, engine) } {
+        Ok(0) => log!(Trace, "{}: nothing to rebind", name),
+        Ok(count) => log!(Debug, "{}: rebound {} slots", name, count),
+        Err(e) => log!(Debug, "{}: not rebound: {}", name, e),
+    }
+}
+
+extern "C" fn image_removed(header: *const u8, _slide: isize) {
+    if let Some(image) = engine()
+        .as_mut()
+        .and_then(|engine| engine.registry.remove_image(header as u64))
+    {
+        log!(Debug, "{}: unloaded", image.path);
+    }
+}
+
+// The header and load commands of a loaded image, as a byte slice.
+unsafe fn load_commands<'a>(header: *const u8) -> Result<MachO<'a>, MachOError> {
+    // mach_header: magic, cputype, cpusubtype, filetype, ncmds, sizeofcmds, ...
+    let sizeofcmds = std::ptr::read_unaligned(header.add(20) as *const u32) as usize;
+    let header_size = match std::ptr::read_unaligned(header as *const u32) {
+        macho::MH_MAGIC_64 => 32,
+        _ => 28,
+    };
+    MachO::parse(std::slice::from_raw_parts(header, header_size + sizeofcmds))
+}
+
+// The file an image was loaded from, mapped read-only.
+struct MappedFile {
+    data: *mut c_void,
+    len: usize,
+}
+
+impl MappedFile {
+    fn open(path: &CStr) -> std::io::Result<Self> {
+        let fd = unsafe { libc::open(path.as_ptr(), libc::O_RDONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+        let mapped = if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+            Err(std::io::Error::last_os_error())
+        } else if stat.st_size <= 0 {
+            Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+        } else {
+            let len = stat.st_size as usize;
+            let data = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    len,
+                    libc::PROT_READ,
+                    libc::MAP_PRIVATE,
+                    fd,
+                    0,
+                )
+            };
+            if data == libc::MAP_FAILED {
+                Err(std::io::Error::last_os_error())
+            } else {
+                Ok(MappedFile { data, len })
+            }
+        };
+        unsafe { libc::close(fd) };
+        mapped
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.data as *const u8, self.len) }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.data, self.len) };
+    }
+}
+
+// Bind slots from the image's chained fixups. dyld has already replaced the chains in
+// memory with the bound pointers, so they are read from the file the image came from.
+fn chained_slots(
+    macho: &MachO<'_>,
+    layout: &ImageLayout,
+    path: Option<&CStr>,
+    names: &[&str],
+) -> Result<Vec<Slot>, String> {
+    let path = path.ok_or("image has no path")?;
+    let file = MappedFile::open(path).map_err(|e| format!("cannot map the file: {}", e))?;
+    // The capability bits (arm64e's pointer authentication ABI version) may differ.
+    let subtype = |cpusubtype: i32| cpusubtype & 0x00ff_ffff;
+    let slices = macho::slices(file.bytes()).map_err(|e| e.to_string())?;
+    let slice = slices
+        .iter()
+        .find(|slice| {
+            slice.cputype == macho.cputype && subtype(slice.cpusubtype) == subtype(macho.cpusubtype)
+        })
+        .ok_or("the file has no slice for the loaded architecture")?;
+    let fixups = ChainedFixups::parse(layout, slice.data).map_err(|e| e.to_string())?;
+    Ok(fixups
+        .map(|fixups| fixups.import_slots(names))
+        .unwrap_or_default())
+}
+
+unsafe fn rebind_image(
+    header: *const u8,
+    slide: isize,
+    path: Option<&CStr>,
+    engine: &mut Engine,
+) -> Result<usize, MachOError> {
+    let macho = load_commands(header)?;
+    let layout = ImageLayout::parse(&macho)?;
+    let names: Vec<&'static str> = engine.rebindings.iter().map(Rebinding::name).collect();
+    let mut slots = match layout.table_ranges() {
+        // The linkedit tables are mapped, not at their file offsets.
+        Some(ranges) => {
+            let mapped = |range: &std::ops::Range<u64>| -> Result<&[u8], MachOError> {
+                let address = layout
+                    .linkedit_address(range.start)
+                    .ok_or(MachOError::Malformed("symbol tables outside __LINKEDIT"))?;
+                Ok(std::slice::from_raw_parts(
+                    (address as isize).wrapping_add(slide) as *const u8,
+                    (range.end - range.start) as usize,
+                ))
+            };
+            let tables = Tables {
+                symbols: mapped(&ranges.symbols)?,
+                strings: mapped(&ranges.strings)?,
+                indirect: mapped(&ranges.indirect)?,
+            };
+            layout.import_slots(&tables, &names)
+        }
+        None => Vec::new(),
+    };
+    if layout.chained_fixups.is_some() {
+        match chained_slots(&macho, &layout, path, &names) {
+            // A __got slot can be both an indirect symbol and a chained bind.
+            Ok(chained) => {
+                for slot in chained {
+                    if !slots.iter().any(|known| known.address == slot.address) {
+                        slots.push(slot);
+                    }
+                }
+            }
+            Err(e) => log!(
+                Info,
+                "{}: chained fixups not rebound: {}",
+                path.map(CStr::to_string_lossy).unwrap_or_default(),
+                e
+            ),
+        }
+    }
+
+    // Where the image itself is mapped; __PAGEZERO, with no access at all, is not.
+    let mapped: Vec<std::ops::Range<u64>> = layout
+        .segments
+        .iter()
+        .filter(|segment| segment.initprot != 0)
+        .map(|segment| {
+            let start = (segment.vmaddr as i64).wrapping_add(slide as i64) as u64;
+            start..start.wrapping_add(segment.vmsize)
+        })
+        .collect();
+    let mut patched = 0;
+    for slot in slots {
+        let name = names[slot.name];
+        let pointer = (slot.address as isize).wrapping_add(slide) as *mut *mut c_void;
+        let original = engine.rebindings[slot.name].original;
+        if slot.authenticated {
+            // An arm64e signed pointer would need our replacement signed with the slot's
+            // key and discriminator, which this library cannot do, so it stays unhooked.
+            log!(
+                Info,
+                "left the signed {} slot at {:p} unpatched",
+                name,
+                pointer
+            );
+            original.bypassed();
+            continue;
+        }
+        let segment = &layout.segments[slot.segment];
+        let read_only = segment.read_only_after_binding();
+        // __DATA_CONST may be backed by the shared cache, hence the copy-on-write request.
+        if read_only
+            && vm_protect(
+                mach_task_self_,
+                pointer as usize,
+                std::mem::size_of::<*mut c_void>(),
+                0,
+                VM_PROT_READ | VM_PROT_WRITE | VM_PROT_COPY,
+            ) != KERN_SUCCESS
+        {
+            log!(
+                Error,
+                "cannot make the {} slot at {:p} writable",
+                name,
+                pointer
+            );
+            original.bypassed();
+            continue;
+        }
+        // A lazy __la_symbol_ptr slot not yet bound still points at the image's own stub
+        // helper, which is not an implementation of the function.
+        let current = *pointer;
+        let current = if mapped.iter().any(|range| range.contains(&(current as u64))) {
+            std::ptr::null_mut()
+        } else {
+            current
+        };
+        engine.patch_slot(header as u64, slot.name, slot.address, pointer, current);
+        patched += 1;
+        if read_only {
+            vm_protect(
+                mach_task_self_,
+                pointer as usize,
+                std::mem::size_of::<*mut c_void>(),
+                0,
+                VM_PROT_READ,
+            );
+        }
+    }
+    Ok(patched)
+}
@@ -0,0 +1,118 @@
+// Points the imports of every loaded image at our hooks by overwriting the pointer slots
+// the loader filled in. The platform halves find the slots: `macho` through dyld's image
+// callbacks and each image's load commands, `elf` through dl_iterate_phdr and each
+// object's dynamic relocations. Both take the same `Rebinding` list and share the
+// bookkeeping here, so a hook is declared the same way on either system.
+
+use std::sync::{Mutex, MutexGuard};
+
+use libc::c_void;
+use uuid_spoofer_core::image_registry::ImageRegistry;
+
+use crate::original::Original;
+
+#[cfg(target_os = "linux")]
+mod elf;
+#[cfg(target_os = "macos")]
+mod macho;
+
+#[cfg(target_os = "linux")]
+pub use elf::{rebind_if_loaded, rebind_symbols};
+#[cfg(target_os = "macos")]
+pub use macho::rebind_symbols;
+
+// A function to hook: every image's imports of it are pointed at `replacement`, and the
+// first original implementation found is captured into `original` (left alone until
+// some image imports the function), before any slot is pointed at the replacement.
+pub struct Rebinding {
+    pub original: &'static Original,
+    pub replacement: *mut c_void,
+}
+
+impl Rebinding {
+    pub fn name(&self) -> &'static str {
+        self.original.name()
+    }
+}
+
+struct Engine {
+    rebindings: Vec<Rebinding>,
+    registry: ImageRegistry,
+}
+
+// `original` is a static and `replacement` points at code; neither is tied to a thread.
+unsafe impl Send for Engine {}
+
+static ENGINE: Mutex<Option<Engine>> = Mutex::new(None);
+
+// Runs inside loader callbacks, where a panic would take the host process down.
+fn engine() -> MutexGuard<'static, Option<Engine>> {
+    ENGINE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// `engine`, unless another pass holds it.
+#[cfg(target_os = "linux")]
+fn try_engine() -> Option<MutexGuard<'static, Option<Engine>>> {
+    match ENGINE.try_lock() {
+        Ok(engine) => Some(engine),
+        Err(std::sync::TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+        Err(std::sync::TryLockError::WouldBlock) => None,
+    }
+}
+
+// Stores the rebindings for the platform half to apply. Only the first call installs
+// anything; later ones are logged and return false.
+fn install(rebindings: Vec<Rebinding>) -> bool {
+    let mut engine = engine();
+    if engine.is_some() {
+        log!(Error, "the rebinding engine is already installed");
+        return false;
+    }
+    let replacements = rebindings
+        .iter()
+        .map(|rebinding| rebinding.replacement as u64)
+        .collect();
+    *engine = Some(Engine {
+        rebindings,
+        registry: ImageRegistry::new(replacements),
+    });
+    true
+}
+
+fn patched_slots() -> usize {
+    engine()
+        .as_ref()
+        .map_or(0, |engine| engine.registry.patched_slots())
+}
+
+impl Engine {
+    // Points the writable slot at `pointer` (at unslid `address` in the image identified
+    // by `image`) at the replacement for rebinding `index`. `current` is what the slot
+    // holds, or null if that is not an implementation of the function.
+    unsafe fn patch_slot(
+        &mut self,
+        image: u64,
+        index: usize,
+        address: u64,
+        pointer: *mut *mut c_void,
+        current: *mut c_void,
+    ) {
+        let rebinding = &self.rebindings[index];
+        let original = self
+            .registry
+            .record_slot(image, index, address, current as u64);
+        if let Some(original) = original {
+            // Published first, so a call through the patched slot can always reach it.
+            rebinding.original.capture(original as *mut c_void);
+            log!(
+                Info,
+                "hooked {} (original at {:#x})",
+                rebinding.name(),
+                original
+            );
+        }
+        std::ptr::write_volatile(pointer, rebinding.replacement);
+    }
+}
@@ -0,0 +1,729 @@
+//! End-to-end tests of the Linux LD_PRELOAD backend: run real programs with the built
+//! library injected and check what they read from the identity files. They use the
+//! library `cargo build` left in the target directory, so build before testing.
+#![cfg(target_os = "linux")]
+
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::Command;
+use uuid_spoofer_core::mac::{MacAddress, MacAddresses};
+
+const UUID: &str = "DEADBEEF-0123-4567-89AB-CDEF00112233";
+const MACHINE_ID: &str = "deadbeef0123456789abcdef00112233\n";
+const PRODUCT_UUID: &str = "deadbeef-0123-4567-89ab-cdef00112233\n";
+
+// Names the child `child_main` runs, in the copy of this binary started under the
+// preload library.
+const CHILD_ENV_VAR: &str = "UUID_SPOOFER_PRELOAD_CHILD";
+
+// target/<profile>/libuuid_spoofer.so, next to the deps/ directory holding this test.
+// `cargo test` does not build cdylib targets, so run `cargo build` (`--release` for
+// release tests) first.
+fn preload_library() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let library = exe
+        .parent()
+        .and_then(|deps| deps.parent())
+        .unwrap()
+        .join("libuuid_spoofer.so");
+    assert!(
+        library.exists(),
+        "{} not built: run `cargo build` before `cargo test`",
+        library.display()
+    );
+    library
+}
+
+fn preloaded(program: impl Into<PathBuf>) -> Command {
+    let mut command = Command::new(program.into());
+    command
+        .env("LD_PRELOAD", preload_library())
+        .env("UUID_SPOOF_VALUE", UUID)
+        .env_remove("UUID_SPOOF_SEED")
+        .env_remove("UUID_SPOOF_PROFILE")
+        .env_remove("UUID_SPOOF_LOG")
+        .env_remove("UUID_SPOOF_LOG_FILE")
+        .env("UUID_SPOOF_CONFIG", "/nonexistent/uuid-spoof.toml");
+    command
+}
+
+// A copy of this test binary running `child` under the preload library.
+fn in_child(child: &str) -> Command {
+    let mut command = preloaded(std::env::current_exe().unwrap());
+    command
+        .args([
+            "child_main",
+            "--exact",
+            "--ignored",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(CHILD_ENV_VAR, child);
+    command
+}
+
+// Runs the child named by CHILD_ENV_VAR: the half of a test that has to run inside the
+// preloaded process.
+#[test]
+#[ignore = "started by the tests that need it, under the preload library"]
+fn child_main() {
+    let child = std::env::var(CHILD_ENV_VAR).unwrap_or_default();
+    match child.as_str() {
+        "deep_bound_reader" => deep_bound_reader(),
+        "reader" => reader(),
+        "flag_opener" => flag_opener(),
+        "fortified_reader" => fortified_reader(),
+        "hook_states" => hook_states(),
+        "interface_lister" => interface_lister(),
+        _ => panic!("${} names no child: {:?}", CHILD_ENV_VAR, child),
+    }
+}
+
+#[test]
+fn cat_sees_spoofed_files() {
+    let output = preloaded("cat")
+        .args([
+            "/etc/machine-id",
+            "/var/lib/dbus/machine-id",
+            "/sys/class/dmi/id/product_uuid",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!("{}{}{}", MACHINE_ID, MACHINE_ID, PRODUCT_UUID)
+    );
+}
+
+#[test]
+fn invalid_uuid_passes_through() {
+    let output = preloaded("cat")
+        .arg("/etc/machine-id")
+        .env("UUID_SPOOF_VALUE", "not-a-uuid")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("invalid UUID"), "{}", stderr);
+    assert_ne!(String::from_utf8_lossy(&output.stdout), MACHINE_ID);
+}
+
+#[test]
+fn debug_log_goes_to_the_log_file() {
+    let log = std::env::temp_dir().join(format!("uuid_spoofer_test_{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&log);
+    let output = preloaded("cat")
+        .arg("/etc/machine-id")
+        .env("UUID_SPOOF_LOG", "debug")
+        .env("UUID_SPOOF_LOG_FILE", &log)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+    let contents = std::fs::read_to_string(&log).unwrap();
+    std::fs::remove_file(&log).unwrap();
+    assert!(contents.contains("] Info: serving "), "{}", contents);
+    assert!(
+        contents.contains("] Debug: /etc/machine-id: spoofed as"),
+        "{}",
+        contents
+    );
+    // Trace lines stay out at debug level.
+    assert!(!contents.contains("] Trace: "), "{}", contents);
+}
+
+#[test]
+fn log_off_silences_errors() {
+    let output = preloaded("cat")
+        .arg("/etc/machine-id")
+        .env("UUID_SPOOF_VALUE", "not-a-uuid")
+        .env("UUID_SPOOF_LOG", "off")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn rules_pick_a_profile_per_program() {
+    let config = std::env::temp_dir().join(format!(
+        "uuid_spoofer_test_profiles_{}.toml",
+        std::process::id()
+    ));
+    std::fs::write(
+        &config,
+        r#"
+uuid = "11111111-1111-1111-1111-111111111111"
+
+[profiles.cat]
+uuid = "22222222-2222-2222-2222-222222222222"
+
+[[rules]]
+profile = "cat"
+exe = "cat"
+"#,
+    )
+    .unwrap();
+    let read = |program: &str| {
+        let output = preloaded(program)
+            .arg("/etc/machine-id")
+            .env_remove("UUID_SPOOF_VALUE")
+            .env("UUID_SPOOF_CONFIG", &config)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let cat = read("cat");
+    let head = read("head");
+    std::fs::remove_file(&config).unwrap();
+    assert_eq!(cat, "22222222222222222222222222222222\n");
+    assert_eq!(head, "11111111111111111111111111111111\n");
+}
+
+#[test]
+fn a_stored_profile_is_picked_by_name() {
+    let store =
+        std::env::temp_dir().join(format!("uuid_spoofer_test_store_{}", std::process::id()));
+    std::fs::create_dir_all(&store).unwrap();
+    std::fs::write(
+        store.join("acme.toml"),
+        "uuid = \"33333333-3333-3333-3333-333333333333\"\n",
+    )
+    .unwrap();
+    let read = |profile: &str| {
+        preloaded("cat")
+            .arg("/etc/machine-id")
+            .env_remove("UUID_SPOOF_VALUE")
+            .env("UUID_SPOOF_PROFILE", profile)
+            .env("UUID_SPOOF_PROFILE_DIR", &store)
+            .output()
+            .unwrap()
+    };
+    let acme = read("acme");
+    let missing = read("missing");
+    std::fs::remove_dir_all(&store).unwrap();
+    assert!(acme.status.success(), "{:?}", acme);
+    assert_eq!(
+        String::from_utf8(acme.stdout).unwrap(),
+        "33333333333333333333333333333333\n"
+    );
+    // An unknown profile is an error, and the real file is served.
+    assert!(
+        String::from_utf8_lossy(&missing.stderr).contains("no profile named \"missing\""),
+        "{:?}",
+        missing
+    );
+    assert_ne!(
+        String::from_utf8(missing.stdout).unwrap(),
+        "33333333333333333333333333333333\n"
+    );
+}
+
+#[test]
+fn hostid_sees_spoofed_host_id() {
+    let output = preloaded("hostid").output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "deadbeef\n");
+}
+
+#[test]
+fn deep_bound_libraries_see_spoofed_files() {
+    let output = in_child("deep_bound_reader")
+        .env("UUID_SPOOF_LOG", "debug")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}{}", stdout, stderr);
+    if stdout.contains("no libz") {
+        eprintln!("libz.so.1 is not installed; skipped");
+        return;
+    }
+    assert!(
+        stdout.contains(&format!("gzread: {}", MACHINE_ID)),
+        "{}",
+        stdout
+    );
+    assert!(stderr.contains("libz.so.1: rebound 1 slots"), "{}", stderr);
+    assert!(
+        stderr.contains("] Info: hooked open (original at "),
+        "{}",
+        stderr
+    );
+}
+
+// Reads /etc/machine-id through zlib opened with RTLD_DEEPBIND, which makes zlib's own
+// `open` bind to libc rather than the preloaded library. For
+// `deep_bound_libraries_see_spoofed_files`.
+fn deep_bound_reader() {
+    type GzOpen = extern "C" fn(*const libc::c_char, *const libc::c_char) -> *mut libc::c_void;
+    type GzRead = extern "C" fn(*mut libc::c_void, *mut libc::c_void, libc::c_uint) -> libc::c_int;
+    type GzClose = extern "C" fn(*mut libc::c_void) -> libc::c_int;
+
+    let zlib = unsafe {
+        libc::dlopen(
+            c"libz.so.1".as_ptr(),
+            libc::RTLD_NOW | libc::RTLD_LOCAL | libc::RTLD_DEEPBIND,
+        )
+    };
+    if zlib.is_null() {
+        print!("no libz");
+        return;
+    }
+    // zlib's slots are rebound by the first hooked call made after it is loaded.
+    unsafe { libc::gethostid() };
+    let mut buffer = [0u8; 64];
+    let contents = unsafe {
+        let symbol = |name: &std::ffi::CStr| {
+            let address = libc::dlsym(zlib, name.as_ptr());
+            assert!(!address.is_null(), "{:?}", name);
+            address
+        };
+        let gzopen = std::mem::transmute::<*mut libc::c_void, GzOpen>(symbol(c"gzopen"));
+        let gzread = std::mem::transmute::<*mut libc::c_void, GzRead>(symbol(c"gzread"));
+        let gzclose = std::mem::transmute::<*mut libc::c_void, GzClose>(symbol(c"gzclose"));
+        // gzread passes files that are not gzip-compressed through unchanged.
+        let file = gzopen(c"/etc/machine-id".as_ptr(), c"rb".as_ptr());
+        assert!(!file.is_null());
+        let n = gzread(
+            file,
+            buffer.as_mut_ptr() as *mut _,
+            buffer.len() as libc::c_uint,
+        );
+        gzclose(file);
+        String::from_utf8_lossy(&buffer[..n.max(0) as usize]).into_owned()
+    };
+    print!("gzread: {}", contents);
+}
+
+// glibc searches for a bare library name with the RUNPATH of the object calling dlopen,
+// so the preloaded library must not stand in between. Builds a library and a program
+// whose RUNPATH is the only way to find it; skipped without a C compiler.
+#[test]
+fn dlopen_searches_the_callers_runpath() {
+    let dir =
+        std::env::temp_dir().join(format!("uuid_spoofer_test_runpath_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("library.c"), "int uuid_spoofer_runpath_test;\n").unwrap();
+    std::fs::write(
+        dir.join("loader.c"),
+        "#include <dlfcn.h>\n\
+         #include <stdio.h>\n\
+         int main(void) {\n\
+         \x20   void *handle = dlopen(\"libuuid_spoofer_runpath.so\", RTLD_NOW);\n\
+         \x20   puts(handle ? \"loaded\" : dlerror());\n\
+         \x20   return handle == NULL;\n\
+         }\n",
+    )
+    .unwrap();
+    let cc = |args: &[&str]| Command::new("cc").current_dir(&dir).args(args).status();
+    let built = cc(&[
+        "-shared",
+        "-fPIC",
+        "-o",
+        "libuuid_spoofer_runpath.so",
+        "library.c",
+    ])
+    .and_then(|_| {
+        cc(&[
+            "-o",
+            "loader",
+            "loader.c",
+            &format!("-Wl,--enable-new-dtags,-rpath,{}", dir.display()),
+            "-ldl",
+        ])
+    });
+    if !built.is_ok_and(|status| status.success()) {
+        std::fs::remove_dir_all(&dir).unwrap();
+        eprintln!("no working C compiler; skipped");
+        return;
+    }
+
+    let output = preloaded(dir.join("loader")).output().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "loaded\n");
+}
+
+// `uuid_spoof run`, which finds the library next to itself in target/<profile>/.
+fn launched(program: &str) -> Command {
+    preload_library();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_uuid_spoof"));
+    command
+        .args(["run", "--uuid", UUID, "--", program])
+        .env_remove("LD_PRELOAD")
+        .env_remove("UUID_SPOOF_LIBRARY")
+        .env("UUID_SPOOF_CONFIG", "/nonexistent/uuid-spoof.toml");
+    command
+}
+
+#[test]
+fn launcher_injects_library_and_uuid() {
+    let output = launched("cat").arg("/etc/machine-id").output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), MACHINE_ID);
+}
+
+#[test]
+fn launcher_propagates_exit_status_and_signals() {
+    let status = launched("sh").args(["-c", "exit 7"]).status().unwrap();
+    assert_eq!(status.code(), Some(7));
+
+    let status = launched("sh")
+        .args(["-c", "kill -TERM $$"])
+        .status()
+        .unwrap();
+    assert_eq!(status.signal(), Some(libc::SIGTERM));
+}
+
+#[test]
+fn launcher_reports_missing_programs() {
+    for program in ["uuid-spoof-no-such-program", "./uuid-spoof-no-such-program"] {
+        let output = launched(program).output().unwrap();
+        assert_eq!(output.status.code(), Some(127), "{}", program);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("command not found"));
+    }
+}
+
+#[test]
+fn uuid_reader_reports_spoofed_values() {
+    let output = preloaded(env!("CARGO_BIN_EXE_uuid_reader"))
+        .arg("--json")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let entries: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+    let values: Vec<_> = entries
+        .iter()
+        .map(|entry| (entry["name"].as_str().unwrap(), entry["value"].as_str()))
+        .collect();
+    assert_eq!(
+        values,
+        [
+            ("machine-id", Some(MACHINE_ID.trim_end())),
+            ("dbus machine-id", Some(MACHINE_ID.trim_end())),
+            ("product_uuid", Some(PRODUCT_UUID.trim_end())),
+        ]
+    );
+}
+
+#[test]
+fn uuid_reader_expect_reports_mismatches() {
+    let output = preloaded(env!("CARGO_BIN_EXE_uuid_reader"))
+        .args(["--expect", "00000000-0000-0000-0000-000000000001"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("[MISMATCH]"), "{}", stdout);
+    assert!(stdout.contains("FAIL: no UUID value matches"), "{}", stdout);
+}
+
+fn verify(args: &[&str]) -> std::process::Output {
+    preload_library();
+    Command::new(env!("CARGO_BIN_EXE_uuid_spoof"))
+        .arg("verify")
+        .args(args)
+        .env_remove("LD_PRELOAD")
+        .env_remove("UUID_SPOOF_LIBRARY")
+        .env_remove("UUID_SPOOF_VALUE")
+        .env_remove("UUID_SPOOF_SEED")
+        .env("UUID_SPOOF_CONFIG", "/nonexistent/uuid-spoof.toml")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn verify_passes_when_the_spoof_works() {
+    for args in [&["--uuid", UUID][..], &["--seed", "secret"], &[]] {
+        let output = verify(args);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{:?}: {}", args, stdout);
+        assert!(
+            stdout.contains("PASS: all 3 UUID values match"),
+            "{}",
+            stdout
+        );
+    }
+
+    let output = verify(&["--uuid", UUID, "--", "cat"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("cat will load the library"), "{}", stdout);
+}
+
+#[test]
+fn verify_fails_when_the_library_is_not_the_spoofer() {
+    // Any other library loads fine but spoofs nothing.
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+    let libc = maps
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .find(|path| path.contains("/libc.so") || path.contains("/libc-"))
+        .unwrap();
+    let output = verify(&["--uuid", UUID, "--library", libc]);
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("the library was not loaded or its hooks were not installed"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn std_and_stdio_readers_see_spoofed_files() {
+    let output = in_child("reader").output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("std: {}", MACHINE_ID)),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(&format!("fopen: {}", PRODUCT_UUID)),
+        "{}",
+        stdout
+    );
+}
+
+// Reads the identity files through std (open64) and stdio (fopen). For
+// `std_and_stdio_readers_see_spoofed_files`.
+fn reader() {
+    print!(
+        "std: {}",
+        std::fs::read_to_string("/etc/machine-id").unwrap()
+    );
+
+    let mut buffer = [0u8; 64];
+    let contents = unsafe {
+        let file = libc::fopen(c"/sys/class/dmi/id/product_uuid".as_ptr(), c"r".as_ptr());
+        assert!(!file.is_null());
+        let n = libc::fread(buffer.as_mut_ptr() as *mut _, 1, buffer.len(), file);
+        libc::fclose(file);
+        String::from_utf8_lossy(&buffer[..n]).into_owned()
+    };
+    print!("fopen: {}", contents);
+}
+
+#[test]
+fn path_and_directory_opens_pass_through() {
+    let output = in_child("flag_opener").output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{}", stdout);
+    // The real file, not a memfd standing in for it.
+    assert!(stdout.contains("O_PATH: /etc/machine-id\n"), "{}", stdout);
+    assert!(
+        stdout.contains(&format!("O_DIRECTORY: {}\n", libc::ENOTDIR)),
+        "{}",
+        stdout
+    );
+}
+
+// Opens /etc/machine-id with O_PATH, printing what the descriptor refers to, and with
+// O_DIRECTORY, printing the errno. For `path_and_directory_opens_pass_through`.
+fn flag_opener() {
+    let path = c"/etc/machine-id".as_ptr();
+    let fd = unsafe { libc::open(path, libc::O_PATH | libc::O_CLOEXEC) };
+    assert!(fd >= 0);
+    let target = std::fs::read_link(format!("/proc/self/fd/{}", fd)).unwrap();
+    unsafe { libc::close(fd) };
+    println!("O_PATH: {}", target.display());
+
+    let fd = unsafe { libc::open(path, libc::O_RDONLY | libc::O_DIRECTORY) };
+    let errno = std::io::Error::last_os_error().raw_os_error().unwrap();
+    if fd >= 0 {
+        unsafe { libc::close(fd) };
+    }
+    println!("O_DIRECTORY: {}", if fd >= 0 { 0 } else { errno });
+}
+
+#[test]
+fn fortified_opens_see_spoofed_files() {
+    let output = in_child("fortified_reader").output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(output.status.success(), "{}", stdout);
+    for function in ["__open_2", "__open64_2", "__openat_2", "__openat64_2"] {
+        assert!(
+            stdout.contains(&format!("{}: {}", function, MACHINE_ID)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+// Reads /etc/machine-id through each of the `_FORTIFY_SOURCE` open variants, looked up
+// the way a fortified program binds them. For `fortified_opens_see_spoofed_files`.
+fn fortified_reader() {
+    type Open2 = extern "C" fn(*const libc::c_char, libc::c_int) -> libc::c_int;
+    type Openat2 = extern "C" fn(libc::c_int, *const libc::c_char, libc::c_int) -> libc::c_int;
+
+    let symbol = |name: &std::ffi::CStr| {
+        let address = unsafe { libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr()) };
+        assert!(!address.is_null(), "{:?}", name);
+        address
+    };
+    let read = |name: &std::ffi::CStr, fd: libc::c_int| {
+        assert!(fd >= 0, "{:?}", name);
+        let mut buffer = [0u8; 64];
+        let n = unsafe { libc::read(fd, buffer.as_mut_ptr() as *mut _, buffer.len()) };
+        unsafe { libc::close(fd) };
+        print!(
+            "{}: {}",
+            name.to_str().unwrap(),
+            String::from_utf8_lossy(&buffer[..n.max(0) as usize])
+        );
+    };
+    let path = c"/etc/machine-id".as_ptr();
+    for name in [c"__open_2", c"__open64_2"] {
+        let open = unsafe { std::mem::transmute::<*mut libc::c_void, Open2>(symbol(name)) };
+        read(name, open(path, libc::O_RDONLY));
+    }
+    for name in [c"__openat_2", c"__openat64_2"] {
+        let openat = unsafe { std::mem::transmute::<*mut libc::c_void, Openat2>(symbol(name)) };
+        read(name, openat(libc::AT_FDCWD, path, libc::O_RDONLY));
+    }
+}
+
+#[test]
+fn hook_states_can_be_queried() {
+    let output = in_child("hook_states")
+        .env("UUID_SPOOF_LOG", "info")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}{}", stdout, stderr);
+    assert!(
+        stdout.contains("open=1 gethostid=1 dlopen=-1 IORegistryEntryCreateCFProperty=-1"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stderr.contains("] Info: hook states: open installed, open64 installed, "),
+        "{}",
+        stderr
+    );
+}
+
+// Prints the state of a few hooks as `uuid_spoof_hook_state` reports them: 1 installed,
+// -1 no such hook. For `hook_states_can_be_queried`.
+fn hook_states() {
+    type HookState = extern "C" fn(*const libc::c_char) -> libc::c_int;
+    let address = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"uuid_spoof_hook_state".as_ptr()) };
+    assert!(!address.is_null());
+    let hook_state = unsafe { std::mem::transmute::<*mut libc::c_void, HookState>(address) };
+    let states: Vec<String> = [
+        c"open",
+        c"gethostid",
+        c"dlopen",
+        c"IORegistryEntryCreateCFProperty",
+    ]
+    .iter()
+    .map(|name| format!("{}={}", name.to_str().unwrap(), hook_state(name.as_ptr())))
+    .collect();
+    print!("{}", states.join(" "));
+}
+
+#[test]
+fn hardware_addresses_come_from_the_profile() {
+    let store = std::env::temp_dir().join(format!("uuid_spoofer_test_macs_{}", std::process::id()));
+    std::fs::create_dir_all(&store).unwrap();
+    std::fs::write(
+        store.join("nic.toml"),
+        "seed = \"nic secret\"\n\
+         [mac_addresses]\n\
+         lo = \"02:11:22:33:44:55\"\n\
+         \"*\" = \"generate\"\n",
+    )
+    .unwrap();
+    let output = in_child("interface_lister")
+        .env_remove("UUID_SPOOF_VALUE")
+        .env("UUID_SPOOF_PROFILE", "nic")
+        .env("UUID_SPOOF_PROFILE_DIR", &store)
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&store).unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}{}", stdout, stderr);
+
+    // The first line follows the test harness's "test child_main ... ".
+    let listed: Vec<(&str, &str, &str)> = stdout
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            match fields[fields.len().saturating_sub(3)..] {
+                [via @ ("getifaddrs" | "ioctl"), name, mac] => Some((via, name, mac)),
+                _ => None,
+            }
+        })
+        .collect();
+    assert!(
+        listed.contains(&("getifaddrs", "lo", "02:11:22:33:44:55")),
+        "{}",
+        stdout
+    );
+    assert!(
+        listed.contains(&("ioctl", "lo", "02:11:22:33:44:55")),
+        "{}",
+        stdout
+    );
+    // Every other interface with an address gets one generated for the profile.
+    for (_, name, mac) in listed.iter().filter(|(_, name, _)| *name != "lo") {
+        let generated = MacAddresses::generated(b"nic\0nic secret", name, None);
+        assert!(
+            *mac == "00:00:00:00:00:00" || *mac == generated.to_string(),
+            "{}",
+            stdout
+        );
+    }
+}
+
+// Prints every interface's hardware address as `getifaddrs` lists it and as
+// SIOCGIFHWADDR returns it. For `hardware_addresses_come_from_the_profile`.
+fn interface_lister() {
+    let format = |bytes: &[u8]| MacAddress(bytes.try_into().unwrap()).to_string();
+    let mut names = Vec::new();
+    unsafe {
+        let mut list = std::ptr::null_mut();
+        assert_eq!(libc::getifaddrs(&mut list), 0);
+        let mut entry = list;
+        while let Some(ifa) = entry.as_ref() {
+            entry = ifa.ifa_next;
+            if ifa.ifa_addr.is_null() || (*ifa.ifa_addr).sa_family as i32 != libc::AF_PACKET {
+                continue;
+            }
+            let link = &*(ifa.ifa_addr as *const libc::sockaddr_ll);
+            if link.sll_halen != 6 {
+                continue;
+            }
+            let name = std::ffi::CStr::from_ptr(ifa.ifa_name).to_owned();
+            println!(
+                "getifaddrs {} {}",
+                name.to_string_lossy(),
+                format(&link.sll_addr[..6])
+            );
+            names.push(name);
+        }
+        libc::freeifaddrs(list);
+
+        let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+        assert!(socket >= 0);
+        for name in names {
+            let mut request: libc::ifreq = std::mem::zeroed();
+            for (to, from) in request.ifr_name.iter_mut().zip(name.to_bytes()) {
+                *to = *from as libc::c_char;
+            }
+            if libc::ioctl(socket, libc::SIOCGIFHWADDR, &mut request) != 0 {
+                continue;
+            }
+            let data = &request.ifr_ifru.ifru_hwaddr.sa_data[..6];
+            let bytes: Vec<u8> = data.iter().map(|&b| b as u8).collect();
+            println!("ioctl {} {}", name.to_string_lossy(), format(&bytes));
+        }
+        libc::close(socket);
+    }
+}
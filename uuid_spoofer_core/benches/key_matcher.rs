@@ -0,0 +1,158 @@
+//! Per-call cost of deciding whether an IORegistry key is spoofed, against a mock of the
+//! CoreFoundation string objects, so it runs anywhere: the key matcher's paths next to
+//! the copy-into-a-buffer-and-look-up approach it replaced.
+//!
+//! `cargo bench -p uuid_spoofer_core --bench key_matcher [-- <iterations>]`
+
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::CStr;
+use std::hash::{Hash, Hasher};
+use std::hint::black_box;
+use std::time::Instant;
+
+use uuid_spoofer_core::identity::{IdentityTable, PropertyValue};
+use uuid_spoofer_core::key_matcher::{KeyMatcher, KeyObjects};
+
+// A heap-allocated immutable string, referred to by address like a CFStringRef.
+struct MockString {
+    text: String,
+}
+
+// Keys are leaked, so every address stays valid for the whole run.
+struct MockObjects;
+
+impl MockObjects {
+    fn key(text: &str) -> usize {
+        Box::into_raw(Box::new(MockString {
+            text: text.to_string(),
+        })) as usize
+    }
+
+    fn text(key: usize) -> &'static str {
+        unsafe { &(*(key as *const MockString)).text }
+    }
+
+    // CFStringGetCString: copies the string and a terminator, failing if they do not fit.
+    fn c_string(key: usize, buffer: &mut [u8]) -> bool {
+        let text = Self::text(key).as_bytes();
+        if text.len() >= buffer.len() {
+            return false;
+        }
+        buffer[..text.len()].copy_from_slice(text);
+        buffer[text.len()] = 0;
+        true
+    }
+}
+
+impl KeyObjects for MockObjects {
+    type Key = usize;
+
+    fn create(&self, name: &str) -> Option<usize> {
+        Some(Self::key(name))
+    }
+
+    fn is_string(&self, _key: usize) -> bool {
+        true
+    }
+
+    fn hash(&self, key: usize) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        Self::text(key).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn equal(&self, a: usize, b: usize) -> bool {
+        Self::text(a) == Self::text(b)
+    }
+}
+
+// What the hook did before the matcher: copy the key into a 256-byte buffer, make a
+// String of it and look that up in the table.
+fn copy_and_look_up(table: &IdentityTable, key: usize) -> bool {
+    let mut buffer = [0u8; 256];
+    if !MockObjects::c_string(key, &mut buffer) {
+        return false;
+    }
+    let name = CStr::from_bytes_until_nul(&buffer)
+        .unwrap()
+        .to_string_lossy();
+    table.get(&name).is_some()
+}
+
+fn time(label: &str, iterations: u32, mut call: impl FnMut() -> bool) {
+    // Warm up, and make sure the case does what its label says.
+    let expected = call();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        assert_eq!(black_box(call()), expected);
+    }
+    let per_call = start.elapsed().as_nanos() as f64 / iterations as f64;
+    println!(
+        "{:<46} {:>8.1} ns/call  ({})",
+        label,
+        per_call,
+        if expected {
+            "spoofed"
+        } else {
+            "passed through"
+        }
+    );
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; any number is the iteration count.
+    let iterations = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(2_000_000);
+
+    let mut table = IdentityTable::new();
+    for name in [
+        "IOPlatformUUID",
+        "IOPlatformSerialNumber",
+        "board-id",
+        "model",
+        "target-type",
+        "IOMACAddress",
+    ] {
+        table.insert(name, PropertyValue::String(String::new()));
+    }
+    let objects = MockObjects;
+    let matcher = KeyMatcher::new(&objects, table.iter().map(|(name, _)| name));
+
+    // A caller's CFSTR constant: a distinct object from the matcher's own.
+    let constant = MockObjects::key("IOPlatformUUID");
+    let unrelated = MockObjects::key("IOPlatformSerialNumberX");
+    let long = MockObjects::key(&"k".repeat(300));
+
+    println!("{} iterations per case", iterations);
+    time("before: copy + String + table lookup", iterations, || {
+        copy_and_look_up(&table, black_box(constant))
+    });
+    time("before: unrelated key", iterations, || {
+        copy_and_look_up(&table, black_box(unrelated))
+    });
+    // Two objects with the same contents, as from two images: each evicts the other from
+    // the matcher's memory, so every call takes the hash path.
+    let other_constant = MockObjects::key("IOPlatformUUID");
+    let mut flip = false;
+    time(
+        "matcher: caller constant, not seen before",
+        iterations,
+        || {
+            flip = !flip;
+            let key = if flip { constant } else { other_constant };
+            matcher.find(&objects, black_box(key)).is_some()
+        },
+    );
+    matcher.find(&objects, constant);
+    time("matcher: caller constant, seen before", iterations, || {
+        matcher.find(&objects, black_box(constant)).is_some()
+    });
+    time("matcher: unrelated key", iterations, || {
+        matcher.find(&objects, black_box(unrelated)).is_some()
+    });
+    time("matcher: 300-byte key", iterations, || {
+        matcher.find(&objects, black_box(long)).is_some()
+    });
+}
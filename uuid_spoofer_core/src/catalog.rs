@@ -0,0 +1,825 @@
+//! The catalog of Mac models identities are built from, and the checker that holds
+//! identities against it.
+//!
+//! A spoofed identity only holds up if its parts agree: the board-id and target-type are
+//! the ones the model ships with, the serial number ends in a code naming the model and
+//! is dated in a year it was made, and an Apple silicon board never claims to be an
+//! Intel model. The catalog records those facts per model, as data:
+//!
+//! ```toml
+//! version = 1
+//!
+//! [[models]]
+//! identifier = "Macmini9,1"
+//! board_id = "J274AP"
+//! target_type = "J274"          # optional for Intel models
+//! architecture = "arm64"        # or "x86_64"
+//! serial_codes = ["Q6NV"]       # may be empty for models made from 2021 on
+//! years = [2020, 2023]
+//! ```
+//!
+//! The built-in catalog is extended by the file at `UUID_SPOOF_CATALOG`, or by
+//! `models.toml` next to the default config file if there is one. Its entries replace
+//! built-in ones with the same identifier. Files must give the `version` this build
+//! reads, [`CATALOG_VERSION`], so that a newer format is refused rather than misread.
+
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use toml::Spanned;
+
+use crate::config::default_config_dir;
+use crate::identity::{
+    IdentityTable, PropertyValue, BOARD_ID_KEY, IO_PLATFORM_SERIAL_NUMBER_KEY, MODEL_KEY,
+    TARGET_TYPE_KEY,
+};
+use crate::profiles;
+use crate::serial::{self, Serial, SerialFormat};
+
+/// Environment variable naming a catalog file to extend the built-in one with.
+pub const CATALOG_ENV_VAR: &str = "UUID_SPOOF_CATALOG";
+/// The catalog format this build reads.
+pub const CATALOG_VERSION: i64 = 1;
+
+const BUILTIN: &str = include_str!("models.toml");
+const BUILTIN_PATH: &str = "<built-in catalog>";
+const FILE_NAME: &str = "models.toml";
+
+/// The processor family a model is built around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum Architecture {
+    #[serde(rename = "x86_64")]
+    X86_64,
+    #[serde(rename = "arm64")]
+    Arm64,
+}
+
+impl Architecture {
+    /// The architecture a board-id's shape gives away: Intel board-ids start with `Mac-`
+    /// and Apple silicon ones end in `AP`, as in `J274AP`.
+    pub fn of_board_id(board_id: &str) -> Option<Architecture> {
+        if board_id.starts_with("Mac-") {
+            Some(Architecture::X86_64)
+        } else if board_id.len() > 2 && board_id.ends_with("AP") {
+            Some(Architecture::Arm64)
+        } else {
+            None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Architecture::X86_64 => "x86_64",
+            Architecture::Arm64 => "arm64",
+        }
+    }
+
+    // For messages: "an Intel model".
+    fn description(self) -> &'static str {
+        match self {
+            Architecture::X86_64 => "an Intel",
+            Architecture::Arm64 => "an Apple silicon",
+        }
+    }
+}
+
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// What an identity needs to know about a Mac model.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Model {
+    /// The `model` property, e.g. `Macmini8,1`.
+    pub identifier: String,
+    pub board_id: String,
+    /// The `target-type` property, which Apple silicon and T2 Macs report.
+    pub target_type: Option<String>,
+    pub architecture: Architecture,
+    /// The four-character codes ending this model's legacy serial numbers.
+    pub serial_codes: Vec<String>,
+    /// The first and last year it was made.
+    pub years: (u16, u16),
+}
+
+impl Model {
+    /// Whether serial numbers in `format` can be made for this model: legacy ones need
+    /// its serial codes.
+    pub fn supports(&self, format: SerialFormat) -> bool {
+        format == SerialFormat::Random10 || !self.serial_codes.is_empty()
+    }
+
+    /// The serial number format the model was launched with.
+    pub fn serial_format(&self) -> SerialFormat {
+        SerialFormat::ALL
+            .into_iter()
+            .rev()
+            .find(|format| {
+                let (first, last) = format.years();
+                (first..=last).contains(&self.years.0)
+            })
+            .unwrap_or(SerialFormat::Legacy11)
+    }
+
+    // Why the entry cannot be used, as the field at fault and a reason.
+    fn validate(&self) -> Result<(), (&'static str, String)> {
+        let (letters, numbers) = self.identifier.split_at(
+            self.identifier
+                .find(|c: char| c.is_ascii_digit())
+                .unwrap_or(0),
+        );
+        let number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        let well_formed = letters.bytes().all(|b| b.is_ascii_alphabetic())
+            && !letters.is_empty()
+            && numbers
+                .split_once(',')
+                .is_some_and(|(major, minor)| number(major) && number(minor));
+        if !well_formed {
+            return Err((
+                "identifier",
+                format!(
+                    "{:?} is not a model identifier like Macmini8,1",
+                    self.identifier
+                ),
+            ));
+        }
+        if Architecture::of_board_id(&self.board_id) != Some(self.architecture) {
+            return Err((
+                "board_id",
+                format!(
+                    "{:?} is not {} board-id; those look like {}",
+                    self.board_id,
+                    self.architecture.description(),
+                    match self.architecture {
+                        Architecture::X86_64 => "Mac-7BA5B2DFE22DDD8C",
+                        Architecture::Arm64 => "J274AP",
+                    }
+                ),
+            ));
+        }
+        match &self.target_type {
+            Some(target_type) if target_type.is_empty() || target_type.contains('\0') => {
+                return Err((
+                    "target_type",
+                    format!("{:?} is not a target-type", target_type),
+                ));
+            }
+            None if self.architecture == Architecture::Arm64 => {
+                return Err((
+                    "target_type",
+                    "Apple silicon models need a target-type".to_string(),
+                ));
+            }
+            _ => {}
+        }
+        if self.years.0 > self.years.1 {
+            return Err(("years", "the first year is after the last".to_string()));
+        }
+        if let Some(code) = self
+            .serial_codes
+            .iter()
+            .find(|code| !serial::is_model_code(code))
+        {
+            return Err((
+                "serial_codes",
+                format!(
+                    "{:?} is not a serial code: use 4 digits and capitals other than I and O",
+                    code
+                ),
+            ));
+        }
+        if self.serial_codes.is_empty() && self.years.0 < SerialFormat::Random10.years().0 {
+            return Err((
+                "serial_codes",
+                format!(
+                    "give the codes its serial numbers end in, as it was made before {}",
+                    SerialFormat::Random10.years().0
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum CatalogError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        /// The line and dotted key the parser stopped at, when it says.
+        location: Option<(usize, String)>,
+        message: String,
+    },
+    /// The file gives no `version`, or one this build does not read.
+    Version { path: PathBuf, found: Option<i64> },
+    /// A model entry is malformed or contradicts itself.
+    Model {
+        path: PathBuf,
+        line: usize,
+        /// The dotted path of the offending key, e.g. `models[2].board_id`.
+        key: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for CatalogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CatalogError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            CatalogError::Parse {
+                path,
+                location: Some((line, key)),
+                message,
+            } if !key.is_empty() => {
+                write!(f, "{}:{}: {}: {}", path.display(), line, key, message)
+            }
+            CatalogError::Parse {
+                path,
+                location: Some((line, _)),
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            CatalogError::Parse {
+                path,
+                location: None,
+                message,
+            } => write!(f, "{}: {}", path.display(), message),
+            CatalogError::Version { path, found: None } => write!(
+                f,
+                "{}: no catalog version; add `version = {}`",
+                path.display(),
+                CATALOG_VERSION
+            ),
+            CatalogError::Version {
+                path,
+                found: Some(version),
+            } => write!(
+                f,
+                "{}: catalog version {} is not supported; this build reads version {}",
+                path.display(),
+                version,
+                CATALOG_VERSION
+            ),
+            CatalogError::Model {
+                path,
+                line,
+                key,
+                reason,
+            } => write!(f, "{}:{}: {}: {}", path.display(), line, key, reason),
+        }
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+/// Only the version, read first so that a newer format is reported as such.
+#[derive(Deserialize)]
+struct VersionFile {
+    version: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CatalogFile {
+    #[serde(rename = "version")]
+    _version: i64,
+    #[serde(default)]
+    models: Vec<Spanned<Model>>,
+}
+
+/// A property that gives a spoofed identity away by disagreeing with the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inconsistency {
+    pub key: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+/// A set of models, by identifier.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Catalog {
+    models: Vec<Model>,
+}
+
+impl Catalog {
+    /// The catalog shipped with this build.
+    pub fn builtin() -> &'static Catalog {
+        static CATALOG: OnceLock<Catalog> = OnceLock::new();
+        CATALOG.get_or_init(|| {
+            Catalog::parse(BUILTIN, Path::new(BUILTIN_PATH)).expect("the built-in catalog is valid")
+        })
+    }
+
+    /// The built-in catalog extended by the user's file, loaded once per process.
+    pub fn installed() -> Result<&'static Catalog, &'static CatalogError> {
+        static CATALOG: OnceLock<Result<Catalog, CatalogError>> = OnceLock::new();
+        CATALOG
+            .get_or_init(|| Catalog::load_from(user_path().as_deref()))
+            .as_ref()
+    }
+
+    /// The built-in catalog, extended by the file at `path` if one is given.
+    pub fn load_from(path: Option<&Path>) -> Result<Catalog, CatalogError> {
+        let mut catalog = Catalog::builtin().clone();
+        if let Some(path) = path {
+            let source = std::fs::read_to_string(path).map_err(|error| CatalogError::Io {
+                path: path.to_path_buf(),
+                error,
+            })?;
+            catalog.extend(Catalog::parse(&source, path)?);
+        }
+        Ok(catalog)
+    }
+
+    /// Reads and checks a catalog from `source`, the text of the file at `path`.
+    pub fn parse(source: &str, path: &Path) -> Result<Catalog, CatalogError> {
+        let parse_error = |e: toml::de::Error| CatalogError::Parse {
+            path: path.to_path_buf(),
+            location: e.span().map(|span| profiles::locate(source, span.start)),
+            message: e.message().to_string(),
+        };
+        let version = toml::from_str::<VersionFile>(source)
+            .map_err(parse_error)?
+            .version;
+        if version != Some(CATALOG_VERSION) {
+            return Err(CatalogError::Version {
+                path: path.to_path_buf(),
+                found: version,
+            });
+        }
+        let file: CatalogFile = toml::from_str(source).map_err(parse_error)?;
+
+        let mut catalog = Catalog::default();
+        for (index, entry) in file.models.into_iter().enumerate() {
+            let line = profiles::locate(source, entry.span().start).0;
+            let error = |field: &str, reason: String| CatalogError::Model {
+                path: path.to_path_buf(),
+                line,
+                key: format!("models[{}].{}", index, field),
+                reason,
+            };
+            let model = entry.into_inner();
+            model
+                .validate()
+                .map_err(|(field, reason)| error(field, reason))?;
+            if catalog.model(&model.identifier).is_some() {
+                return Err(error(
+                    "identifier",
+                    format!("{} is listed twice", model.identifier),
+                ));
+            }
+            catalog.models.push(model);
+        }
+        Ok(catalog)
+    }
+
+    /// Adds `other`'s models, replacing any with the same identifier.
+    pub fn extend(&mut self, other: Catalog) {
+        for model in other.models {
+            match self
+                .models
+                .iter_mut()
+                .find(|known| known.identifier == model.identifier)
+            {
+                Some(known) => *known = model,
+                None => self.models.push(model),
+            }
+        }
+    }
+
+    pub fn models(&self) -> &[Model] {
+        &self.models
+    }
+
+    /// The model with this `model` identifier, e.g. `MacBookPro15,1`.
+    pub fn model(&self, identifier: &str) -> Option<&Model> {
+        self.models
+            .iter()
+            .find(|model| model.identifier == identifier)
+    }
+
+    /// The ways `identity` contradicts itself, judged by the model it claims to be.
+    ///
+    /// A model the catalog does not know can only be reported as such, and an identity
+    /// that spoofs neither `model` nor `board-id` passes, as the real ones agree.
+    pub fn check(&self, identity: &IdentityTable) -> Vec<Inconsistency> {
+        let mut found = Vec::new();
+        let mut report = |key: &'static str, message: String| {
+            found.push(Inconsistency { key, message });
+        };
+        let board_id = identity.text(BOARD_ID_KEY);
+        let Some(identifier) = identity.text(MODEL_KEY) else {
+            if board_id.is_some() {
+                report(
+                    MODEL_KEY,
+                    "not spoofed, though board-id is; the real model shows through".to_string(),
+                );
+            }
+            return found;
+        };
+        let Some(model) = self.model(identifier) else {
+            report(
+                MODEL_KEY,
+                format!(
+                    "{:?} is not in the model catalog, so nothing can be checked against it",
+                    identifier
+                ),
+            );
+            return found;
+        };
+
+        match board_id {
+            None => report(
+                BOARD_ID_KEY,
+                format!(
+                    "not spoofed, though model is; set it to {:?}",
+                    model.board_id
+                ),
+            ),
+            Some(board_id) if board_id != model.board_id => {
+                let owner = self.models.iter().find(|m| m.board_id == board_id);
+                let architecture = owner
+                    .map(|owner| owner.architecture)
+                    .or_else(|| Architecture::of_board_id(board_id));
+                let message = match (architecture, owner) {
+                    (Some(architecture), _) if architecture != model.architecture => format!(
+                        "{:?} is {} board-id, but {} is {} model",
+                        board_id,
+                        architecture.description(),
+                        model.identifier,
+                        model.architecture.description()
+                    ),
+                    (_, Some(owner)) => format!(
+                        "{:?} belongs to {}, not {} ({:?})",
+                        board_id, owner.identifier, model.identifier, model.board_id
+                    ),
+                    _ => format!(
+                        "{:?} is not the board-id of {} ({:?})",
+                        board_id, model.identifier, model.board_id
+                    ),
+                };
+                report(BOARD_ID_KEY, message);
+            }
+            Some(_) => {}
+        }
+
+        match (identity.text(TARGET_TYPE_KEY), &model.target_type) {
+            (Some(found), Some(expected)) if found != expected => report(
+                TARGET_TYPE_KEY,
+                format!(
+                    "{:?} is not the target-type of {} ({:?})",
+                    found, model.identifier, expected
+                ),
+            ),
+            (Some(found), None) => report(
+                TARGET_TYPE_KEY,
+                format!("{:?} is given, but {} has none", found, model.identifier),
+            ),
+            (None, Some(expected)) if model.architecture == Architecture::Arm64 => report(
+                TARGET_TYPE_KEY,
+                format!("not spoofed; {} reports {:?}", model.identifier, expected),
+            ),
+            _ => {}
+        }
+
+        if let Some(value) = identity.get(IO_PLATFORM_SERIAL_NUMBER_KEY) {
+            if let Some(message) = check_serial(model, value) {
+                report(IO_PLATFORM_SERIAL_NUMBER_KEY, message);
+            }
+        }
+        found
+    }
+}
+
+// What gives the serial number away as not `model`'s, if anything.
+fn check_serial(model: &Model, value: &PropertyValue) -> Option<String> {
+    let PropertyValue::String(text) = value else {
+        return Some("not a string, as IOKit reports it".to_string());
+    };
+    let serial = match Serial::parse(text) {
+        Ok(serial) => serial,
+        Err(e) => return Some(format!("{:?} is not an Apple serial number: {}", text, e)),
+    };
+    let (first, last) = model.years;
+    let (format_first, format_last) = serial.format().years();
+    if last < format_first || first > format_last {
+        return Some(format!(
+            "{:?} is a {} serial number, which {} (made {}-{}) never had",
+            text,
+            serial.format(),
+            model.identifier,
+            first,
+            last
+        ));
+    }
+    if let Some(code) = serial.model_code() {
+        if !model.serial_codes.iter().any(|known| known.ends_with(code)) {
+            return Some(format!(
+                "{:?} ends in {}, which is not a code of {} ({})",
+                text,
+                code,
+                model.identifier,
+                model.serial_codes.join(", ")
+            ));
+        }
+    }
+    if !(first..=last).any(|year| serial.made_in(year)) {
+        return Some(format!(
+            "{:?} is dated outside the years {} was made ({}-{})",
+            text, model.identifier, first, last
+        ));
+    }
+    None
+}
+
+/// The catalog file [`Catalog::installed`] extends the built-in one with:
+/// `UUID_SPOOF_CATALOG`, or the default one if it exists.
+pub fn user_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CATALOG_ENV_VAR).filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    default_config_dir()
+        .map(|dir| dir.join(FILE_NAME))
+        .filter(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::{MachineIdentity, DEFAULT_MODEL};
+    use crate::identity::value_from_str;
+
+    fn parsed(source: &str) -> Result<Catalog, CatalogError> {
+        Catalog::parse(source, Path::new("models.toml"))
+    }
+
+    fn table(properties: &[(&str, &str)]) -> IdentityTable {
+        let mut table = IdentityTable::new();
+        for (key, value) in properties {
+            table.insert(*key, value_from_str(key, value).unwrap());
+        }
+        table
+    }
+
+    fn messages(identity: &IdentityTable) -> Vec<String> {
+        Catalog::builtin()
+            .check(identity)
+            .iter()
+            .map(Inconsistency::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn the_builtin_catalog_is_valid() {
+        let catalog = Catalog::builtin();
+        let model = catalog.model(DEFAULT_MODEL).unwrap();
+        assert_eq!(model.board_id, "Mac-7BA5B2DFE22DDD8C");
+        assert_eq!(model.architecture, Architecture::X86_64);
+        assert_eq!(model.serial_format(), SerialFormat::Legacy12);
+        let model = catalog.model("Mac14,2").unwrap();
+        assert_eq!(model.architecture, Architecture::Arm64);
+        assert_eq!(model.serial_format(), SerialFormat::Random10);
+        assert!(!model.supports(SerialFormat::Legacy12));
+        assert!(catalog.model("MacBookPro99,9").is_none());
+    }
+
+    #[test]
+    fn generated_identities_are_consistent() {
+        for model in Catalog::builtin().models() {
+            for format in SerialFormat::ALL {
+                let (first, last) = format.years();
+                if !model.supports(format) || model.years.1 < first || model.years.0 > last {
+                    continue;
+                }
+                for seed in 0..50u32 {
+                    let identity = MachineIdentity::generate(model, format, &seed.to_le_bytes());
+                    let text = identity.to_toml();
+                    let file: toml::Table = toml::from_str(&text).unwrap();
+                    let properties =
+                        IdentityTable::from_toml(file["properties"].as_table().unwrap()).unwrap();
+                    let found = Catalog::builtin().check(&properties);
+                    assert!(found.is_empty(), "{:?} in\n{}", found, text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_identities_are_reported() {
+        // An Apple silicon board under an Intel model, the request's example.
+        assert_eq!(
+            messages(&table(&[
+                (MODEL_KEY, "Macmini8,1"),
+                (BOARD_ID_KEY, "J274AP"),
+                (TARGET_TYPE_KEY, "J174"),
+            ])),
+            ["board-id: \"J274AP\" is an Apple silicon board-id, but Macmini8,1 is an Intel model"]
+        );
+        assert_eq!(
+            messages(&table(&[
+                (MODEL_KEY, "MacBookPro15,1"),
+                (BOARD_ID_KEY, "Mac-7BA5B2DFE22DDD8C"),
+                (TARGET_TYPE_KEY, "J274"),
+                (IO_PLATFORM_SERIAL_NUMBER_KEY, "C02J74RZJYVX"),
+            ])),
+            [
+                "board-id: \"Mac-7BA5B2DFE22DDD8C\" belongs to Macmini8,1, not MacBookPro15,1 \
+                 (\"Mac-937A206F2EE63C01\")",
+                "target-type: \"J274\" is not the target-type of MacBookPro15,1 (\"J680\")",
+                "IOPlatformSerialNumber: \"C02J74RZJYVX\" ends in JYVX, which is not a code of \
+                 MacBookPro15,1 (MD6M, MD6N)",
+            ]
+        );
+        assert_eq!(
+            messages(&table(&[
+                (MODEL_KEY, "Macmini9,1"),
+                (BOARD_ID_KEY, "Mac-F42C88C8"),
+                (IO_PLATFORM_SERIAL_NUMBER_KEY, "C02P74RZQ6NV"),
+            ])),
+            [
+                "board-id: \"Mac-F42C88C8\" is an Intel board-id, but Macmini9,1 is an Apple \
+                 silicon model",
+                "target-type: not spoofed; Macmini9,1 reports \"J274\"",
+                "IOPlatformSerialNumber: \"C02P74RZQ6NV\" is dated outside the years \
+                 Macmini9,1 was made (2020-2023)",
+            ]
+        );
+        assert_eq!(
+            messages(&table(&[
+                (MODEL_KEY, "Mac14,2"),
+                (BOARD_ID_KEY, "J413AP"),
+                (TARGET_TYPE_KEY, "J413"),
+                (IO_PLATFORM_SERIAL_NUMBER_KEY, "C02J74RZJYVX"),
+            ])),
+            [
+                "IOPlatformSerialNumber: \"C02J74RZJYVX\" is a legacy12 serial number, which \
+              Mac14,2 (made 2022-2024) never had"
+            ]
+        );
+        assert_eq!(
+            messages(&table(&[(MODEL_KEY, "Macmini8,1")])),
+            ["board-id: not spoofed, though model is; set it to \"Mac-7BA5B2DFE22DDD8C\""]
+        );
+        assert_eq!(
+            messages(&table(&[(BOARD_ID_KEY, "Mac-7BA5B2DFE22DDD8C")])),
+            ["model: not spoofed, though board-id is; the real model shows through"]
+        );
+        assert_eq!(
+            messages(&table(&[(MODEL_KEY, "PowerBook1,1")])),
+            [
+                "model: \"PowerBook1,1\" is not in the model catalog, so nothing can be checked \
+              against it"
+            ]
+        );
+        assert!(messages(&table(&[(IO_PLATFORM_SERIAL_NUMBER_KEY, "C02J74RZJYVX")])).is_empty());
+    }
+
+    #[test]
+    fn user_files_extend_and_override() {
+        let mut catalog = Catalog::builtin().clone();
+        catalog.extend(
+            parsed(
+                "version = 1\n\
+                 [[models]]\n\
+                 identifier = \"Macmini8,1\"\n\
+                 board_id = \"Mac-7BA5B2DFE22DDD8C\"\n\
+                 architecture = \"x86_64\"\n\
+                 serial_codes = [\"JYVX\"]\n\
+                 years = [2018, 2019]\n\
+                 [[models]]\n\
+                 identifier = \"Mac15,3\"\n\
+                 board_id = \"J504AP\"\n\
+                 target_type = \"J504\"\n\
+                 architecture = \"arm64\"\n\
+                 serial_codes = []\n\
+                 years = [2023, 2024]\n",
+            )
+            .unwrap(),
+        );
+        let builtin = Catalog::builtin().models().len();
+        assert_eq!(catalog.models().len(), builtin + 1);
+        assert_eq!(catalog.model("Macmini8,1").unwrap().years, (2018, 2019));
+        assert_eq!(catalog.model("Mac15,3").unwrap().board_id, "J504AP");
+
+        let path = std::env::temp_dir().join(format!("uuid_spoof_catalog_{}", std::process::id()));
+        std::fs::write(&path, "version = 1\n").unwrap();
+        assert_eq!(
+            &Catalog::load_from(Some(&path)).unwrap(),
+            Catalog::builtin()
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            Catalog::load_from(Some(&path)),
+            Err(CatalogError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn invalid_catalogs_are_rejected_with_their_location() {
+        let entry = |fields: &str| {
+            format!(
+                "version = 1\n\n[[models]]\nidentifier = \"Macmini8,1\"\n\
+                 board_id = \"Mac-7BA5B2DFE22DDD8C\"\narchitecture = \"x86_64\"\n\
+                 serial_codes = [\"JYVX\"]\nyears = [2018, 2020]\n\n[[models]]\n{}",
+                fields
+            )
+        };
+        let second = |fields: &str| {
+            entry(&format!(
+                "architecture = \"arm64\"\nyears = [2020, 2021]\n{}",
+                fields
+            ))
+        };
+        for (source, error) in [
+            (
+                "[[models]]\n".to_string(),
+                "models.toml: no catalog version; add `version = 1`",
+            ),
+            (
+                "version = 2\n".to_string(),
+                "models.toml: catalog version 2 is not supported; this build reads version 1",
+            ),
+            (
+                second(
+                    "identifier = \"Macmini9,1\"\nboard_id = \"Mac-AA95B1DDAB278B95\"\n\
+                     target_type = \"J274\"\nserial_codes = [\"Q6NV\"]\n",
+                ),
+                "models.toml:10: models[1].board_id: \"Mac-AA95B1DDAB278B95\" is not an \
+                 Apple silicon board-id; those look like J274AP",
+            ),
+            (
+                second(
+                    "identifier = \"Macmini9,1\"\nboard_id = \"J274AP\"\n\
+                     serial_codes = [\"Q6NV\"]\n",
+                ),
+                "models.toml:10: models[1].target_type: Apple silicon models need a \
+                 target-type",
+            ),
+            (
+                second(
+                    "identifier = \"Macmini9,1\"\nboard_id = \"J274AP\"\n\
+                     target_type = \"J274\"\nserial_codes = []\n",
+                ),
+                "models.toml:10: models[1].serial_codes: give the codes its serial numbers \
+                 end in, as it was made before 2021",
+            ),
+            (
+                second(
+                    "identifier = \"Macmini9,1\"\nboard_id = \"J274AP\"\n\
+                     target_type = \"J274\"\nserial_codes = [\"Q6NO\"]\n",
+                ),
+                "models.toml:10: models[1].serial_codes: \"Q6NO\" is not a serial code: use 4 \
+                 digits and capitals other than I and O",
+            ),
+            (
+                second(
+                    "identifier = \"Mac mini\"\nboard_id = \"J274AP\"\n\
+                     target_type = \"J274\"\nserial_codes = [\"Q6NV\"]\n",
+                ),
+                "models.toml:10: models[1].identifier: \"Mac mini\" is not a model identifier \
+                 like Macmini8,1",
+            ),
+            (
+                entry(
+                    "identifier = \"Macmini8,1\"\nboard_id = \"Mac-7BA5B2DFE22DDD8C\"\n\
+                     architecture = \"x86_64\"\nserial_codes = [\"JYVX\"]\nyears = [2020, 2018]\n",
+                ),
+                "models.toml:10: models[1].years: the first year is after the last",
+            ),
+            (
+                entry(
+                    "identifier = \"Macmini8,1\"\nboard_id = \"Mac-7BA5B2DFE22DDD8C\"\n\
+                     architecture = \"x86_64\"\nserial_codes = [\"JYVX\"]\nyears = [2018, 2020]\n",
+                ),
+                "models.toml:10: models[1].identifier: Macmini8,1 is listed twice",
+            ),
+        ] {
+            assert_eq!(
+                parsed(&source).unwrap_err().to_string(),
+                error,
+                "{}",
+                source
+            );
+        }
+        assert_eq!(
+            parsed(&entry(
+                "identifier = \"Macmini9,1\"\nboard_id = \"J274AP\"\narchitecture = \"ppc\"\n",
+            ))
+            .unwrap_err()
+            .to_string(),
+            "models.toml:13: models[1].architecture: unknown variant `ppc`, expected `x86_64` \
+             or `arm64`"
+        );
+    }
+}
@@ -0,0 +1,422 @@
+//! Finds import slots in images that use chained fixups (`LC_DYLD_CHAINED_FIXUPS`, the
+//! default for binaries targeting macOS 12 and later) instead of classic binding.
+//!
+//! Such images have no lazy pointers to walk: every pointer that dyld must fix up holds
+//! an encoded fixup in the file, linked to the next one on the same page. The chains only
+//! exist in the file (dyld overwrites them while loading), so this works on the file's
+//! bytes, and the library maps the slots it finds onto the loaded image by its slide.
+
+use crate::macho::{bytes_at, u32_at, u64_at, MachOError};
+use crate::rebind::{ImageLayout, Slot};
+
+pub const DYLD_CHAINED_PTR_ARM64E: u16 = 1;
+pub const DYLD_CHAINED_PTR_64: u16 = 2;
+pub const DYLD_CHAINED_PTR_64_OFFSET: u16 = 6;
+
+pub const DYLD_CHAINED_PTR_START_NONE: u16 = 0xffff;
+pub const DYLD_CHAINED_PTR_START_MULTI: u16 = 0x8000;
+
+pub const DYLD_CHAINED_IMPORT: u32 = 1;
+pub const DYLD_CHAINED_IMPORT_ADDEND: u32 = 2;
+pub const DYLD_CHAINED_IMPORT_ADDEND64: u32 = 3;
+
+/// An entry of the imports table, which bind fixups refer to by index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// As in the symbol table, with the leading underscore.
+    pub name: String,
+    /// 1-based index into the `LC_LOAD_DYLIB`s, or a negative special ordinal.
+    pub lib_ordinal: i32,
+    pub weak: bool,
+    pub addend: i64,
+}
+
+/// A pointer that dyld binds to `imports[import]` (plus the addends).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bind {
+    pub import: usize,
+    pub segment: usize,
+    /// Unslid address of the pointer.
+    pub address: u64,
+    /// The pointer's own addend, on top of the import's.
+    pub addend: i64,
+    pub authenticated: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainedFixups {
+    pub imports: Vec<Import>,
+    pub binds: Vec<Bind>,
+}
+
+// One decoded chain entry.
+struct Fixup {
+    bind: Option<(usize, i64)>,
+    authenticated: bool,
+    // Distance to the next fixup in `stride` units, 0 at the end of the chain.
+    next: u64,
+}
+
+fn decode(format: u16, raw: u64) -> Result<Fixup, MachOError> {
+    let bits = |shift: u32, width: u32| (raw >> shift) & ((1 << width) - 1);
+    match format {
+        DYLD_CHAINED_PTR_64 | DYLD_CHAINED_PTR_64_OFFSET => Ok(Fixup {
+            bind: (bits(63, 1) == 1).then(|| (bits(0, 24) as usize, bits(24, 8) as i64)),
+            authenticated: false,
+            next: bits(51, 12),
+        }),
+        DYLD_CHAINED_PTR_ARM64E => {
+            let authenticated = bits(63, 1) == 1;
+            // Authenticated binds have no addend; plain ones have a signed 19-bit one.
+            let addend = if authenticated {
+                0
+            } else {
+                ((bits(32, 19) << 45) as i64) >> 45
+            };
+            Ok(Fixup {
+                bind: (bits(62, 1) == 1).then(|| (bits(0, 16) as usize, addend)),
+                authenticated,
+                next: bits(51, 11),
+            })
+        }
+        _ => Err(MachOError::Malformed("unsupported chained pointer format")),
+    }
+}
+
+fn stride(format: u16) -> u64 {
+    match format {
+        DYLD_CHAINED_PTR_ARM64E => 8,
+        _ => 4,
+    }
+}
+
+fn c_string(data: &[u8], offset: usize) -> Result<String, MachOError> {
+    let rest = data
+        .get(offset..)
+        .ok_or(MachOError::Truncated { offset, len: 1 })?;
+    let end = rest
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(MachOError::Malformed("unterminated chained import name"))?;
+    Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+}
+
+impl ChainedFixups {
+    /// Parses the fixups of the image in `data` (a whole thin file or fat slice), or
+    /// returns `None` if it does not use chained fixups.
+    pub fn parse(layout: &ImageLayout, data: &[u8]) -> Result<Option<Self>, MachOError> {
+        let Some((dataoff, datasize)) = layout.chained_fixups else {
+            return Ok(None);
+        };
+        let blob = bytes_at(data, dataoff as usize, datasize as usize)?;
+        let le = |offset: usize| u32_at(blob, offset, false);
+        let u16_at = |offset: usize| {
+            bytes_at(blob, offset, 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        };
+        // dyld_chained_fixups_header
+        let starts_offset = le(4)? as usize;
+        let imports_offset = le(8)? as usize;
+        let symbols_offset = le(12)? as usize;
+        let imports_count = le(16)? as usize;
+        let imports_format = le(20)?;
+        if le(24)? != 0 {
+            return Err(MachOError::Malformed("compressed chained import names"));
+        }
+        let symbols = blob.get(symbols_offset..).unwrap_or_default();
+
+        let mut imports = Vec::with_capacity(imports_count.min(blob.len()));
+        for i in 0..imports_count {
+            let (lib_ordinal, weak, name_offset, addend) = match imports_format {
+                DYLD_CHAINED_IMPORT | DYLD_CHAINED_IMPORT_ADDEND => {
+                    let size = if imports_format == DYLD_CHAINED_IMPORT {
+                        4
+                    } else {
+                        8
+                    };
+                    let at = imports_offset + i * size;
+                    let raw = le(at)?;
+                    let addend = if imports_format == DYLD_CHAINED_IMPORT {
+                        0
+                    } else {
+                        le(at + 4)? as i32 as i64
+                    };
+                    (raw as u8 as i8 as i32, raw >> 8 & 1 == 1, raw >> 9, addend)
+                }
+                DYLD_CHAINED_IMPORT_ADDEND64 => {
+                    let at = imports_offset + i * 16;
+                    let raw = u64_at(blob, at, false)?;
+                    let addend = u64_at(blob, at + 8, false)? as i64;
+                    (
+                        raw as u16 as i16 as i32,
+                        raw >> 16 & 1 == 1,
+                        (raw >> 32) as u32,
+                        addend,
+                    )
+                }
+                _ => return Err(MachOError::Malformed("unsupported chained imports format")),
+            };
+            imports.push(Import {
+                name: c_string(symbols, name_offset as usize)?,
+                lib_ordinal,
+                weak,
+                addend,
+            });
+        }
+
+        let mut binds = Vec::new();
+        // dyld_chained_starts_in_image: seg_count, then an offset per segment (0 if the
+        // segment has no fixups) to its dyld_chained_starts_in_segment.
+        let seg_count = le(starts_offset)? as usize;
+        for index in 0..seg_count {
+            let seg_info = le(starts_offset + 4 + index * 4)? as usize;
+            if seg_info == 0 {
+                continue;
+            }
+            let starts = starts_offset + seg_info;
+            let page_size = u16_at(starts + 4)?;
+            let format = u16_at(starts + 6)?;
+            let page_count = u16_at(starts + 20)?;
+            let segment = layout.segments.get(index).ok_or(MachOError::Malformed(
+                "chained fixups for a missing segment",
+            ))?;
+            for page in 0..page_count as usize {
+                let start = u16_at(starts + 22 + page * 2)?;
+                if start == DYLD_CHAINED_PTR_START_NONE {
+                    continue;
+                }
+                if start & DYLD_CHAINED_PTR_START_MULTI != 0 {
+                    // Only used by the 32-bit formats.
+                    return Err(MachOError::Malformed("unsupported chained pointer format"));
+                }
+                let mut offset = (page * page_size as usize + start as usize) as u64;
+                loop {
+                    if offset >= segment.filesize {
+                        return Err(MachOError::Malformed("fixup chain leaves its segment"));
+                    }
+                    let raw = u64_at(data, (segment.fileoff + offset) as usize, false)?;
+                    let fixup = decode(format, raw)?;
+                    if let Some((import, addend)) = fixup.bind {
+                        if import >= imports.len() {
+                            return Err(MachOError::Malformed("bind to a missing import"));
+                        }
+                        binds.push(Bind {
+                            import,
+                            segment: index,
+                            address: segment.vmaddr + offset,
+                            addend,
+                            authenticated: fixup.authenticated,
+                        });
+                    }
+                    if fixup.next == 0 {
+                        break;
+                    }
+                    offset += fixup.next * stride(format);
+                }
+            }
+        }
+        Ok(Some(ChainedFixups { imports, binds }))
+    }
+
+    /// Every bind to one of `names` (C names, without the leading underscore), in chain
+    /// order. Binds with an addend point into a symbol rather than at it, so they are left
+    /// alone.
+    pub fn import_slots(&self, names: &[&str]) -> Vec<Slot> {
+        self.binds
+            .iter()
+            .filter_map(|bind| {
+                let import = &self.imports[bind.import];
+                if import.addend + bind.addend != 0 {
+                    return None;
+                }
+                let name = names
+                    .iter()
+                    .position(|name| import.name.strip_prefix('_') == Some(*name))?;
+                Some(Slot {
+                    name,
+                    segment: bind.segment,
+                    address: bind.address,
+                    authenticated: bind.authenticated,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::MachO;
+
+    // See the .yaml files next to them for how they are laid out.
+    const X86_64_CHAINED_64: &[u8] = include_bytes!("../tests/fixtures/x86_64_chained_64");
+    const ARM64_CHAINED_64_OFFSET: &[u8] =
+        include_bytes!("../tests/fixtures/arm64_chained_64_offset");
+    const ARM64E_CHAINED: &[u8] = include_bytes!("../tests/fixtures/arm64e_chained");
+
+    fn parsed(data: &[u8]) -> (ImageLayout, ChainedFixups) {
+        let layout = ImageLayout::parse(&MachO::parse(data).unwrap()).unwrap();
+        let fixups = ChainedFixups::parse(&layout, data).unwrap().unwrap();
+        (layout, fixups)
+    }
+
+    // (import name, segment name, address, authenticated) for each bind.
+    fn binds(data: &[u8]) -> Vec<(String, String, u64, bool)> {
+        let (layout, fixups) = parsed(data);
+        fixups
+            .binds
+            .iter()
+            .map(|bind| {
+                (
+                    fixups.imports[bind.import].name.clone(),
+                    layout.segments[bind.segment].name.clone(),
+                    bind.address,
+                    bind.authenticated,
+                )
+            })
+            .collect()
+    }
+
+    fn owned(expected: &[(&str, &str, u64, bool)]) -> Vec<(String, String, u64, bool)> {
+        expected
+            .iter()
+            .map(|&(name, segment, address, auth)| {
+                (name.to_string(), segment.to_string(), address, auth)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn walks_chained_ptr_64() {
+        let (_, fixups) = parsed(X86_64_CHAINED_64);
+        assert_eq!(
+            fixups.imports[3],
+            Import {
+                name: "_free".into(),
+                lib_ordinal: 1,
+                weak: true,
+                addend: 0,
+            }
+        );
+        // __DATA's second page has no fixups; its third starts a new chain.
+        assert_eq!(
+            binds(X86_64_CHAINED_64),
+            owned(&[
+                ("_gethostuuid", "__DATA_CONST", 0x1000, false),
+                (
+                    "_IORegistryEntryCreateCFProperty",
+                    "__DATA_CONST",
+                    0x1008,
+                    false
+                ),
+                ("_sysctlbyname", "__DATA_CONST", 0x1010, false),
+                ("_IORegistryEntryCreateCFProperty", "__DATA", 0x2008, false),
+                ("_free", "__DATA", 0x2010, false),
+                ("_gethostuuid", "__DATA", 0x4000, false),
+            ])
+        );
+        assert_eq!(fixups.binds[4].addend, 16);
+    }
+
+    #[test]
+    fn walks_chained_ptr_64_offset() {
+        let (_, fixups) = parsed(ARM64_CHAINED_64_OFFSET);
+        assert_eq!(fixups.imports[3].name, "_kIOMasterPortDefault");
+        assert_eq!(fixups.imports[3].addend, 8);
+        assert_eq!(fixups.imports[2].lib_ordinal, 2);
+        assert_eq!(
+            binds(ARM64_CHAINED_64_OFFSET),
+            owned(&[
+                ("_gethostuuid", "__DATA_CONST", 0x1_0000_4000, false),
+                ("_sysctlbyname", "__DATA_CONST", 0x1_0000_4008, false),
+                (
+                    "_IORegistryEntrySearchCFProperty",
+                    "__DATA",
+                    0x1_0000_8008,
+                    false
+                ),
+                ("_kIOMasterPortDefault", "__DATA", 0x1_0000_8010, false),
+            ])
+        );
+    }
+
+    #[test]
+    fn walks_chained_ptr_arm64e() {
+        // Rebases, authenticated or not, are skipped over.
+        assert_eq!(
+            binds(ARM64E_CHAINED),
+            owned(&[
+                (
+                    "_IORegistryEntryCreateCFProperty",
+                    "__AUTH_CONST",
+                    0x4000,
+                    true
+                ),
+                (
+                    "_IORegistryEntryCreateCFProperties",
+                    "__AUTH_CONST",
+                    0x4008,
+                    true
+                ),
+                ("_gethostuuid", "__DATA", 0x8008, false),
+                (
+                    "_IORegistryEntryCreateCFProperties",
+                    "__DATA",
+                    0x8018,
+                    false
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn reports_slots_by_name() {
+        let names = ["gethostuuid", "IORegistryEntryCreateCFProperty", "free"];
+        let (_, fixups) = parsed(X86_64_CHAINED_64);
+        let slots: Vec<(usize, u64)> = fixups
+            .import_slots(&names)
+            .iter()
+            .map(|slot| (slot.name, slot.address))
+            .collect();
+        // The bind to `free + 16` is not a slot holding `free`.
+        assert_eq!(slots, [(0, 0x1000), (1, 0x1008), (1, 0x2008), (0, 0x4000)]);
+
+        let (_, fixups) = parsed(ARM64_CHAINED_64_OFFSET);
+        assert!(fixups.import_slots(&["kIOMasterPortDefault"]).is_empty());
+
+        let (_, fixups) = parsed(ARM64E_CHAINED);
+        let slots = fixups.import_slots(&["IORegistryEntryCreateCFProperty"]);
+        assert_eq!(slots.len(), 1);
+        assert!(slots[0].authenticated);
+    }
+
+    #[test]
+    fn classic_images_have_no_chained_fixups() {
+        let data = include_bytes!("../tests/fixtures/x86_64_dylib");
+        let layout = ImageLayout::parse(&MachO::parse(data).unwrap()).unwrap();
+        assert_eq!(ChainedFixups::parse(&layout, data), Ok(None));
+    }
+
+    #[test]
+    fn rejects_broken_chains() {
+        let (mut layout, _) = parsed(X86_64_CHAINED_64);
+        // Shrink __DATA so its first chain runs off the end.
+        layout.segments[2].filesize = 0x10;
+        assert_eq!(
+            ChainedFixups::parse(&layout, X86_64_CHAINED_64),
+            Err(MachOError::Malformed("fixup chain leaves its segment"))
+        );
+
+        let (layout, _) = parsed(X86_64_CHAINED_64);
+        let mut data = X86_64_CHAINED_64.to_vec();
+        // Point the first __got bind at import 0x123456.
+        data[0x1000..0x1003].copy_from_slice(&[0x56, 0x34, 0x12]);
+        assert_eq!(
+            ChainedFixups::parse(&layout, &data),
+            Err(MachOError::Malformed("bind to a missing import"))
+        );
+        assert!(matches!(
+            ChainedFixups::parse(&layout, &data[..0x5010]),
+            Err(MachOError::Truncated { .. })
+        ));
+    }
+}
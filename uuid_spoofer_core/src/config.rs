@@ -0,0 +1,839 @@
+//! Resolution of the spoofed UUID at load time.
+//!
+//! The value is taken from the first of these that is present:
+//! 1. the `UUID_SPOOF_VALUE` or `UUID_SPOOF_SEED` environment variable,
+//! 2. the `uuid` or `seed` key of the config file (`UUID_SPOOF_CONFIG`, or
+//!    `$XDG_CONFIG_HOME/uuid-spoof/config.toml`, or `~/.config/uuid-spoof/config.toml`),
+//! 3. the built-in default.
+//!
+//! A fixed `uuid` wins over a `seed` given at the same level. A seed switches to
+//! per-application derivation (see [`crate::derive`]).
+//!
+//! Other IORegistry properties (serial number, board-id, ...) come from the config file's
+//! `[properties]` table regardless of where the UUID came from.
+//!
+//! The file can also define named profiles and rules choosing one per process (see
+//! [`crate::profiles`]); [`Resolved::for_process`] applies them. A selected profile's
+//! `uuid` or `seed` replaces the file's own, and its properties are laid over the file's.
+//!
+//! `UUID_SPOOF_PROFILE` names a profile of the profile store (see [`crate::profile_store`])
+//! to apply the same way to every process, in place of the rules.
+//!
+//! Nothing in here touches CoreFoundation, so it builds and is tested on any platform.
+
+use crate::identity::{IdentityTable, PropertyError};
+use crate::mac::MacAddresses;
+use crate::profile_store::{ProfileStore, StoreError};
+use crate::profiles::{self, Process, Profile, ProfileError, ProfileFile, Profiles, RuleFile};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+/// Used when neither the environment nor a config file provides a value.
+pub const DEFAULT_SPOOFED_UUID: &str = "DEADBEEF-DEAD-BEEF-DEAD-BEEFDEADBEEF";
+
+/// Environment variable holding the UUID to spoof.
+pub const UUID_ENV_VAR: &str = "UUID_SPOOF_VALUE";
+
+/// Environment variable holding the secret seed for per-application derivation.
+pub const SEED_ENV_VAR: &str = "UUID_SPOOF_SEED";
+
+/// Environment variable overriding the config file location.
+pub const CONFIG_PATH_ENV_VAR: &str = "UUID_SPOOF_CONFIG";
+
+/// Environment variable naming the profile store profile every process gets.
+pub const PROFILE_ENV_VAR: &str = "UUID_SPOOF_PROFILE";
+
+/// Where the spoofed value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// Named environment variable.
+    Env(&'static str),
+    ConfigFile(PathBuf),
+    /// A profile of the config file picked by one of its rules, or a profile store file
+    /// named by `UUID_SPOOF_PROFILE`.
+    Profile {
+        path: PathBuf,
+        name: String,
+    },
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Env(var) => write!(f, "${}", var),
+            Source::ConfigFile(path) => write!(f, "{}", path.display()),
+            Source::Profile { path, name } => {
+                write!(f, "profile {:?} in {}", name, path.display())
+            }
+            Source::Default => write!(f, "built-in default"),
+        }
+    }
+}
+
+/// How the spoofed UUID is produced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpoofMode {
+    /// The same validated UUID for every process, uppercased as IOKit reports it.
+    Fixed(String),
+    /// A UUID derived per application from this secret seed.
+    Derived { seed: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolved {
+    pub mode: SpoofMode,
+    pub source: Source,
+    /// Replacements for other IORegistry properties, from the config file's `[properties]`.
+    pub identity: IdentityTable,
+    /// The network interfaces' MAC addresses, which only profiles give.
+    pub mac_addresses: MacAddresses,
+    /// The config file's profiles and rules.
+    pub profiles: Profiles,
+    /// The profile applied: the one `UUID_SPOOF_PROFILE` names, or the one a rule picked
+    /// in [`Resolved::for_process`].
+    pub profile: Option<String>,
+}
+
+impl Resolved {
+    /// The settings for `process`: these, with the profile the first matching rule picks
+    /// applied. A profile already applied from the store is kept, and the rules ignored.
+    pub fn for_process(&self, process: &Process) -> Resolved {
+        if self.profile.is_some() {
+            return self.clone();
+        }
+        match self.profiles.select(process) {
+            Some((_, profile)) => self.with_profile(profile, &self.profiles.path),
+            None => self.clone(),
+        }
+    }
+
+    /// These settings with `profile`, read from `path`, applied: its properties are laid
+    /// over these, its MAC addresses replace these, and its `uuid` or `seed` replaces any
+    /// but the environment's.
+    pub fn with_profile(&self, profile: &Profile, path: &Path) -> Resolved {
+        let mut resolved = self.clone();
+        resolved.profile = Some(profile.name.clone());
+        for (key, value) in profile.identity.iter() {
+            resolved.identity.insert(key, value.clone());
+        }
+        resolved.mac_addresses = profile.mac_addresses.clone();
+        if matches!(self.source, Source::Env(_)) {
+            return resolved;
+        }
+        let source = Source::Profile {
+            path: path.to_path_buf(),
+            name: profile.name.clone(),
+        };
+        if let Some(uuid) = &profile.uuid {
+            resolved.mode = SpoofMode::Fixed(uuid.clone());
+            resolved.source = source;
+        } else if let Some(seed) = &profile.seed {
+            resolved.mode = SpoofMode::Derived { seed: seed.clone() };
+            resolved.source = source;
+        }
+        resolved
+    }
+
+    /// These settings with profile `name` of `store` applied, as by
+    /// [`Resolved::with_profile`].
+    pub fn with_stored_profile(
+        &self,
+        store: &ProfileStore,
+        name: &str,
+    ) -> Result<Resolved, StoreError> {
+        let profile = store.load(name)?;
+        Ok(self.with_profile(&profile, &store.path(name)))
+    }
+
+    /// The UUID the application identified by `app_id` should see.
+    pub fn uuid_for(&self, app_id: &str) -> String {
+        match &self.mode {
+            SpoofMode::Fixed(uuid) => uuid.clone(),
+            SpoofMode::Derived { seed } => crate::derive::derive_uuid(seed, app_id),
+        }
+    }
+}
+
+/// Why a candidate string is not an 8-4-4-4-12 hex UUID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UuidFormatError {
+    Length(usize),
+    MissingHyphen(usize),
+    InvalidChar { index: usize, found: char },
+}
+
+impl fmt::Display for UuidFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UuidFormatError::Length(len) => write!(f, "expected 36 characters, got {}", len),
+            UuidFormatError::MissingHyphen(index) => {
+                write!(f, "expected '-' at position {}", index)
+            }
+            UuidFormatError::InvalidChar { index, found } => {
+                write!(f, "invalid character {:?} at position {}", found, index)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    InvalidUuid {
+        source: Source,
+        value: String,
+        reason: UuidFormatError,
+    },
+    EmptySeed {
+        source: Source,
+    },
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        /// The line and dotted key the parser stopped at, when it says.
+        location: Option<(usize, String)>,
+        message: String,
+    },
+    Property {
+        path: PathBuf,
+        error: PropertyError,
+    },
+    Profile {
+        path: PathBuf,
+        error: ProfileError,
+    },
+    /// The profile `UUID_SPOOF_PROFILE` names could not be loaded.
+    Store(StoreError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidUuid {
+                source,
+                value,
+                reason,
+            } => write!(f, "invalid UUID {:?} from {}: {}", value, source, reason),
+            ConfigError::EmptySeed { source } => write!(f, "empty seed from {}", source),
+            ConfigError::Read { path, error } => {
+                write!(f, "failed to read {}: {}", path.display(), error)
+            }
+            ConfigError::Parse {
+                path,
+                location: Some((line, key)),
+                message,
+            } if !key.is_empty() => write!(
+                f,
+                "failed to parse {}:{}: {}: {}",
+                path.display(),
+                line,
+                key,
+                message
+            ),
+            ConfigError::Parse {
+                path,
+                location: Some((line, _)),
+                message,
+            } => write!(
+                f,
+                "failed to parse {}:{}: {}",
+                path.display(),
+                line,
+                message
+            ),
+            ConfigError::Parse {
+                path,
+                location: None,
+                message,
+            } => write!(f, "failed to parse {}: {}", path.display(), message),
+            ConfigError::Property { path, error } => write!(f, "{}: {}", path.display(), error),
+            ConfigError::Profile { path, error } => write!(
+                f,
+                "{}:{}: {}: {}",
+                path.display(),
+                error.line,
+                error.key,
+                error.reason
+            ),
+            ConfigError::Store(error) => write!(f, "${}: {}", PROFILE_ENV_VAR, error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    uuid: Option<String>,
+    seed: Option<String>,
+    properties: Option<toml::Table>,
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileFile>,
+    #[serde(default)]
+    rules: Vec<Spanned<RuleFile>>,
+}
+
+/// Checks `candidate` is an 8-4-4-4-12 hex UUID and returns it uppercased.
+pub fn validate_uuid(candidate: &str) -> Result<String, UuidFormatError> {
+    let len = candidate.chars().count();
+    if len != 36 {
+        return Err(UuidFormatError::Length(len));
+    }
+    for (index, c) in candidate.chars().enumerate() {
+        if matches!(index, 8 | 13 | 18 | 23) {
+            if c != '-' {
+                return Err(UuidFormatError::MissingHyphen(index));
+            }
+        } else if !c.is_ascii_hexdigit() {
+            return Err(UuidFormatError::InvalidChar { index, found: c });
+        }
+    }
+    Ok(candidate.to_ascii_uppercase())
+}
+
+/// `$XDG_CONFIG_HOME/uuid-spoof`, or `~/.config/uuid-spoof`.
+pub fn default_config_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("uuid-spoof"));
+    }
+    std::env::var_os("HOME")
+        .filter(|h| !h.is_empty())
+        .map(|home| PathBuf::from(home).join(".config/uuid-spoof"))
+}
+
+/// The config file consulted when `UUID_SPOOF_CONFIG` is not set.
+pub fn default_config_path() -> Option<PathBuf> {
+    default_config_dir().map(|dir| dir.join("config.toml"))
+}
+
+/// The config file to read: `UUID_SPOOF_CONFIG`, or the default.
+pub fn config_path() -> Option<PathBuf> {
+    std::env::var_os(CONFIG_PATH_ENV_VAR)
+        .map(PathBuf::from)
+        .or_else(default_config_path)
+}
+
+/// Resolves the spoof mode from the process environment and the default config location.
+pub fn resolve() -> Result<Resolved, ConfigError> {
+    let resolved = resolve_without_profile()?;
+    let env_profile = std::env::var(PROFILE_ENV_VAR).ok();
+    match env_profile
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        Some(name) => ProfileStore::from_env()
+            .and_then(|store| resolved.with_stored_profile(&store, name))
+            .map_err(ConfigError::Store),
+        None => Ok(resolved),
+    }
+}
+
+/// Like [`resolve`], but ignoring `UUID_SPOOF_PROFILE`.
+pub fn resolve_without_profile() -> Result<Resolved, ConfigError> {
+    let env_uuid = std::env::var(UUID_ENV_VAR).ok();
+    let env_seed = std::env::var(SEED_ENV_VAR).ok();
+    resolve_from(
+        env_uuid.as_deref(),
+        env_seed.as_deref(),
+        config_path().as_deref(),
+    )
+}
+
+/// Resolves the spoof mode from explicit inputs. Blank environment values count as unset.
+/// A missing config file is not an error; an unreadable or malformed one is.
+pub fn resolve_from(
+    env_uuid: Option<&str>,
+    env_seed: Option<&str>,
+    config_path: Option<&Path>,
+) -> Result<Resolved, ConfigError> {
+    let mut profiles = Profiles::default();
+    let config = match config_path {
+        Some(path) => read_config(path)?.map(|(config, file_profiles)| {
+            profiles = file_profiles;
+            (path, config)
+        }),
+        None => None,
+    };
+    let identity = match &config {
+        Some((
+            path,
+            ConfigFile {
+                properties: Some(properties),
+                ..
+            },
+        )) => IdentityTable::from_toml(properties).map_err(|error| ConfigError::Property {
+            path: path.to_path_buf(),
+            error,
+        })?,
+        _ => IdentityTable::new(),
+    };
+
+    let (mode, source) = if let Some(value) = env_uuid.map(str::trim).filter(|v| !v.is_empty()) {
+        validated(value, Source::Env(UUID_ENV_VAR))?
+    } else if let Some(seed) = env_seed.filter(|s| !s.trim().is_empty()) {
+        seeded(seed, Source::Env(SEED_ENV_VAR))?
+    } else {
+        match config {
+            Some((
+                path,
+                ConfigFile {
+                    uuid: Some(value), ..
+                },
+            )) => validated(value.trim(), Source::ConfigFile(path.to_path_buf()))?,
+            Some((
+                path,
+                ConfigFile {
+                    seed: Some(seed), ..
+                },
+            )) => seeded(&seed, Source::ConfigFile(path.to_path_buf()))?,
+            _ => validated(DEFAULT_SPOOFED_UUID, Source::Default)?,
+        }
+    };
+
+    Ok(Resolved {
+        mode,
+        source,
+        identity,
+        profiles,
+        mac_addresses: MacAddresses::new(),
+        profile: None,
+    })
+}
+
+fn seeded(seed: &str, source: Source) -> Result<(SpoofMode, Source), ConfigError> {
+    if seed.is_empty() {
+        return Err(ConfigError::EmptySeed { source });
+    }
+    let mode = SpoofMode::Derived {
+        seed: seed.to_string(),
+    };
+    Ok((mode, source))
+}
+
+fn validated(value: &str, source: Source) -> Result<(SpoofMode, Source), ConfigError> {
+    match validate_uuid(value) {
+        Ok(value) => Ok((SpoofMode::Fixed(value), source)),
+        Err(reason) => Err(ConfigError::InvalidUuid {
+            source,
+            value: value.to_string(),
+            reason,
+        }),
+    }
+}
+
+fn read_config(path: &Path) -> Result<Option<(ConfigFile, Profiles)>, ConfigError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => {
+            return Err(ConfigError::Read {
+                path: path.to_path_buf(),
+                error,
+            })
+        }
+    };
+    let mut config: ConfigFile = toml::from_str(&contents).map_err(|e| ConfigError::Parse {
+        path: path.to_path_buf(),
+        location: e.span().map(|span| profiles::locate(&contents, span.start)),
+        message: e.message().to_string(),
+    })?;
+    let profiles = Profiles::from_file(
+        path,
+        std::mem::take(&mut config.profiles),
+        std::mem::take(&mut config.rules),
+        &contents,
+    )
+    .map_err(|error| ConfigError::Profile {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    Ok(Some((config, profiles)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_temp_config(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "uuid_spoofer_config_{}_{}.toml",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn validate_accepts_and_uppercases() {
+        assert_eq!(
+            validate_uuid("12345678-9abc-def0-1234-56789abcdef0").unwrap(),
+            "12345678-9ABC-DEF0-1234-56789ABCDEF0"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_malformed() {
+        assert_eq!(validate_uuid("1234"), Err(UuidFormatError::Length(4)));
+        assert_eq!(
+            validate_uuid("123456789-abc-def0-1234-56789abcdef0"),
+            Err(UuidFormatError::MissingHyphen(8))
+        );
+        assert_eq!(
+            validate_uuid("12345678-9abc-def0-1234-56789abcdefg"),
+            Err(UuidFormatError::InvalidChar {
+                index: 35,
+                found: 'g'
+            })
+        );
+        assert_eq!(
+            validate_uuid("12345678-9abc-def0-1234-56789abcdéf"),
+            Err(UuidFormatError::Length(35))
+        );
+    }
+
+    fn fixed(uuid: &str) -> SpoofMode {
+        SpoofMode::Fixed(uuid.to_string())
+    }
+
+    #[test]
+    fn env_takes_precedence_over_file() {
+        let path = write_temp_config(
+            "env_wins",
+            "uuid = \"11111111-1111-1111-1111-111111111111\"",
+        );
+        let resolved = resolve_from(
+            Some("22222222-2222-2222-2222-222222222222"),
+            None,
+            Some(&path),
+        )
+        .unwrap();
+        assert_eq!(resolved.mode, fixed("22222222-2222-2222-2222-222222222222"));
+        assert_eq!(resolved.source, Source::Env(UUID_ENV_VAR));
+
+        let resolved = resolve_from(None, Some("secret"), Some(&path)).unwrap();
+        assert_eq!(
+            resolved.mode,
+            SpoofMode::Derived {
+                seed: "secret".to_string()
+            }
+        );
+        assert_eq!(resolved.source, Source::Env(SEED_ENV_VAR));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn uuid_wins_over_seed_at_same_level() {
+        let resolved = resolve_from(
+            Some("22222222-2222-2222-2222-222222222222"),
+            Some("secret"),
+            None,
+        )
+        .unwrap();
+        assert_eq!(resolved.mode, fixed("22222222-2222-2222-2222-222222222222"));
+
+        let path = write_temp_config(
+            "uuid_and_seed",
+            "seed = \"secret\"\nuuid = \"11111111-1111-1111-1111-111111111111\"",
+        );
+        let resolved = resolve_from(None, None, Some(&path)).unwrap();
+        assert_eq!(resolved.mode, fixed("11111111-1111-1111-1111-111111111111"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_used_when_env_absent_or_blank() {
+        let path = write_temp_config("file", "uuid = \"aaaaaaaa-bbbb-cccc-dddd-eeeeeeeeeeee\"\n");
+        for env in [None, Some(""), Some("  ")] {
+            let resolved = resolve_from(env, env, Some(&path)).unwrap();
+            assert_eq!(resolved.mode, fixed("AAAAAAAA-BBBB-CCCC-DDDD-EEEEEEEEEEEE"));
+            assert_eq!(resolved.source, Source::ConfigFile(path.clone()));
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn seed_from_file_derives_per_app() {
+        let path = write_temp_config("seed", "seed = \"secret\"\n");
+        let resolved = resolve_from(None, None, Some(&path)).unwrap();
+        assert_eq!(
+            resolved.uuid_for("com.example.app"),
+            crate::derive::derive_uuid("secret", "com.example.app")
+        );
+        assert_ne!(
+            resolved.uuid_for("com.example.app"),
+            resolved.uuid_for("com.example.other")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn properties_apply_whatever_the_uuid_source() {
+        let path = write_temp_config(
+            "properties",
+            "[properties]\nIOPlatformSerialNumber = \"C02ABC\"\nboard-id = \"Mac-1234\"\n",
+        );
+        for env in [None, Some("22222222-2222-2222-2222-222222222222")] {
+            let resolved = resolve_from(env, None, Some(&path)).unwrap();
+            assert_eq!(resolved.identity.len(), 2);
+            assert_eq!(
+                resolved.identity.get("IOPlatformSerialNumber"),
+                Some(&crate::identity::PropertyValue::String("C02ABC".into()))
+            );
+        }
+        std::fs::remove_file(path).unwrap();
+
+        let path = write_temp_config("bad_property", "[properties]\nIOMACAddress = \"nope\"\n");
+        let err = resolve_from(None, None, Some(&path)).unwrap_err();
+        assert!(matches!(err, ConfigError::Property { .. }));
+        assert!(err.to_string().contains("IOMACAddress"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_default() {
+        let missing = std::env::temp_dir().join("uuid_spoofer_config_does_not_exist.toml");
+        let resolved = resolve_from(None, None, Some(&missing)).unwrap();
+        assert_eq!(resolved.mode, fixed(DEFAULT_SPOOFED_UUID));
+        assert_eq!(resolved.source, Source::Default);
+        assert_eq!(resolved.uuid_for("anything"), DEFAULT_SPOOFED_UUID);
+
+        let path = write_temp_config("no_uuid_key", "# nothing here\n");
+        assert_eq!(
+            resolve_from(None, None, Some(&path)).unwrap().source,
+            Source::Default
+        );
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            resolve_from(None, None, None).unwrap().source,
+            Source::Default
+        );
+    }
+
+    #[test]
+    fn invalid_values_are_errors() {
+        let err = resolve_from(Some("not-a-uuid"), None, None).unwrap_err();
+        assert!(matches!(
+            err,
+            ConfigError::InvalidUuid {
+                source: Source::Env(UUID_ENV_VAR),
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("$UUID_SPOOF_VALUE"));
+
+        let path = write_temp_config("bad_uuid", "uuid = \"DEADBEEF\"");
+        let err = resolve_from(None, None, Some(&path)).unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidUuid { .. }));
+        std::fs::remove_file(path).unwrap();
+
+        let path = write_temp_config("empty_seed", "seed = \"\"");
+        let err = resolve_from(None, None, Some(&path)).unwrap_err();
+        assert!(matches!(err, ConfigError::EmptySeed { .. }));
+        std::fs::remove_file(path).unwrap();
+
+        let path = write_temp_config("bad_toml", "uuid = ");
+        let err = resolve_from(None, None, Some(&path)).unwrap_err();
+        assert!(matches!(err, ConfigError::Parse { .. }));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    const PROFILES: &str = r#"
+uuid = "11111111-1111-1111-1111-111111111111"
+[properties]
+IOPlatformSerialNumber = "C02TOP"
+board-id = "Mac-1234"
+
+[profiles.work]
+uuid = "22222222-2222-2222-2222-222222222222"
+[profiles.work.properties]
+IOPlatformSerialNumber = "C02WORK"
+[profiles.work.mac_addresses]
+en0 = "02:00:00:00:00:01"
+
+[profiles.chat]
+seed = "chat secret"
+
+[[rules]]
+profile = "work"
+exe = "/Applications/**"
+
+[[rules]]
+profile = "chat"
+bundle_id = "com.tinyspeck.*"
+"#;
+
+    fn app(exe: &str, bundle_id: Option<&str>) -> Process {
+        Process {
+            exe: Some(PathBuf::from(exe)),
+            bundle_id: bundle_id.map(str::to_string),
+            parent: None,
+        }
+    }
+
+    #[test]
+    fn profiles_apply_per_process() {
+        let path = write_temp_config("profiles", PROFILES);
+        let resolved = resolve_from(None, None, Some(&path)).unwrap();
+        assert_eq!(resolved.profile, None);
+
+        let work = resolved.for_process(&app("/Applications/Notes.app/Contents/MacOS/Notes", None));
+        assert_eq!(work.profile.as_deref(), Some("work"));
+        assert_eq!(work.mode, fixed("22222222-2222-2222-2222-222222222222"));
+        assert_eq!(
+            work.source.to_string(),
+            format!("profile \"work\" in {}", path.display())
+        );
+        // The profile's properties are laid over the top-level ones.
+        assert_eq!(
+            work.identity.get("IOPlatformSerialNumber"),
+            Some(&crate::identity::PropertyValue::String("C02WORK".into()))
+        );
+        assert!(work.identity.get("board-id").is_some());
+        assert_eq!(
+            work.mac_addresses.for_interface("en0").unwrap().to_string(),
+            "02:00:00:00:00:01"
+        );
+        assert!(resolved.mac_addresses.is_empty());
+
+        let chat = resolved.for_process(&app("/opt/slack", Some("com.tinyspeck.slackmacgap")));
+        assert_eq!(
+            chat.uuid_for("com.tinyspeck.slackmacgap"),
+            crate::derive::derive_uuid("chat secret", "com.tinyspeck.slackmacgap")
+        );
+
+        let other = resolved.for_process(&app("/usr/bin/true", None));
+        assert_eq!(other, resolved);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn the_environment_wins_over_a_profile() {
+        let path = write_temp_config("profiles_env", PROFILES);
+        let resolved = resolve_from(
+            Some("33333333-3333-3333-3333-333333333333"),
+            None,
+            Some(&path),
+        )
+        .unwrap()
+        .for_process(&app("/Applications/Notes.app/Contents/MacOS/Notes", None));
+        assert_eq!(resolved.mode, fixed("33333333-3333-3333-3333-333333333333"));
+        assert_eq!(resolved.source, Source::Env(UUID_ENV_VAR));
+        // Its properties still apply.
+        assert_eq!(resolved.profile.as_deref(), Some("work"));
+        assert_eq!(
+            resolved.identity.get("IOPlatformSerialNumber"),
+            Some(&crate::identity::PropertyValue::String("C02WORK".into()))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_stored_profile_replaces_the_rules() {
+        let path = write_temp_config("stored_profile", PROFILES);
+        let store = ProfileStore::new(
+            std::env::temp_dir().join(format!("uuid_spoofer_config_{}_store", std::process::id())),
+        );
+        store
+            .save(
+                "acme",
+                "uuid = \"44444444-4444-4444-4444-444444444444\"\n[properties]\nboard-id = \"Mac-ACME\"\n",
+                true,
+            )
+            .unwrap();
+        let resolved = resolve_from(None, None, Some(&path))
+            .unwrap()
+            .with_stored_profile(&store, "acme")
+            .unwrap();
+        assert_eq!(resolved.profile.as_deref(), Some("acme"));
+        assert_eq!(resolved.mode, fixed("44444444-4444-4444-4444-444444444444"));
+        assert_eq!(
+            resolved.source.to_string(),
+            format!("profile \"acme\" in {}", store.path("acme").display())
+        );
+        // The file's properties fill in what the profile leaves out.
+        assert_eq!(
+            resolved.identity.get("IOPlatformSerialNumber"),
+            Some(&crate::identity::PropertyValue::String("C02TOP".into()))
+        );
+        // No rule overrides the profile that was asked for.
+        let notes =
+            resolved.for_process(&app("/Applications/Notes.app/Contents/MacOS/Notes", None));
+        assert_eq!(notes, resolved);
+
+        let resolved = resolve_from(Some("33333333-3333-3333-3333-333333333333"), None, None)
+            .unwrap()
+            .with_stored_profile(&store, "acme")
+            .unwrap();
+        assert_eq!(resolved.mode, fixed("33333333-3333-3333-3333-333333333333"));
+        assert!(resolved.identity.get("board-id").is_some());
+
+        let err = ConfigError::Store(store.load("missing").unwrap_err());
+        assert!(
+            err.to_string()
+                .starts_with("$UUID_SPOOF_PROFILE: no profile named \"missing\""),
+            "{}",
+            err
+        );
+        std::fs::remove_dir_all(store.dir()).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn errors_name_the_file_line_and_key() {
+        let path = write_temp_config(
+            "bad_rule",
+            "[profiles.work]\n\n[[rules]]\nprofile = \"wrok\"\nexe = \"*\"\n",
+        );
+        let err = resolve_from(None, None, Some(&path)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}:4: rules[0].profile: no profile named \"wrok\"",
+                path.display()
+            )
+        );
+        std::fs::remove_file(path).unwrap();
+
+        let path = write_temp_config(
+            "bad_profile_key",
+            "uuid = \"11111111-1111-1111-1111-111111111111\"\n[profiles.work]\nuid = \"x\"\n",
+        );
+        let err = resolve_from(None, None, Some(&path)).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with(&format!(
+                "failed to parse {}:3: profiles.work.uid: unknown field `uid`",
+                path.display()
+            )),
+            "{}",
+            message
+        );
+        std::fs::remove_file(path).unwrap();
+
+        let path = write_temp_config(
+            "bad_top_level_key",
+            "uid = \"11111111-1111-1111-1111-111111111111\"\n",
+        );
+        let err = resolve_from(None, None, Some(&path)).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with(&format!(
+                "failed to parse {}:1: uid: unknown field `uid`",
+                path.display()
+            )),
+            "{}",
+            message
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
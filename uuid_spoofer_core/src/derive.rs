@@ -0,0 +1,78 @@
+//! Deterministic per-application UUIDs.
+//!
+//! Given a secret seed, every application gets its own stable UUID: the same app sees the
+//! same value on every launch, while two apps cannot correlate their values without the seed.
+//! The UUID is the first 16 bytes of `HMAC-SHA256(seed, "IOPlatformUUID\0" || app_id)`,
+//! stamped as an RFC 9562 version 8 (custom) UUID.
+
+use crate::uuid::uuid_from_bytes;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+const UUID_DOMAIN: &[u8] = b"IOPlatformUUID\0";
+
+/// Derives the UUID `app_id` sees under `seed`, formatted as uppercase 8-4-4-4-12 hex.
+///
+/// `app_id` is whatever identifies the host app, normally its bundle identifier and
+/// otherwise its executable path.
+pub fn derive_uuid(seed: &str, app_id: &str) -> String {
+    uuid_from_bytes(&derive_uuid_bytes(seed, app_id))
+}
+
+/// Raw 16-byte form of [`derive_uuid`].
+pub fn derive_uuid_bytes(seed: &str, app_id: &str) -> [u8; 16] {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(seed.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(UUID_DOMAIN);
+    mac.update(app_id.as_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    bytes[6] = (bytes[6] & 0x0f) | 0x80; // version 8
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 9562 variant
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::validate_uuid;
+
+    #[test]
+    fn stable_across_calls() {
+        assert_eq!(
+            derive_uuid("seed", "com.example.app"),
+            derive_uuid("seed", "com.example.app")
+        );
+    }
+
+    #[test]
+    fn known_value() {
+        // HMAC-SHA256("seed", "IOPlatformUUID\0com.example.app"), truncated and stamped;
+        // cross-checked against Python's hmac module.
+        assert_eq!(
+            derive_uuid("seed", "com.example.app"),
+            "04B38FDE-CB0C-82D8-92FF-34A5F82165AD"
+        );
+    }
+
+    #[test]
+    fn distinct_per_app_and_seed() {
+        let a = derive_uuid("seed", "com.example.a");
+        let b = derive_uuid("seed", "com.example.b");
+        let c = derive_uuid("other seed", "com.example.a");
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn output_is_valid_v8_uuid() {
+        for app in ["", "/usr/bin/true", "com.example.app", "ünïcode"] {
+            let uuid = derive_uuid("seed", app);
+            assert_eq!(validate_uuid(&uuid).unwrap(), uuid);
+            assert_eq!(&uuid[14..15], "8");
+            assert!(matches!(&uuid[19..20], "8" | "9" | "A" | "B"));
+        }
+    }
+}
@@ -0,0 +1,379 @@
+//! Finds the GOT slots through which a loaded ELF object calls imported functions, so the
+//! Linux library can point them at its hooks: the ELF counterpart of `rebind`.
+//!
+//! `LD_PRELOAD` interposes on every lookup that goes through the global scope, but an
+//! object opened with `RTLD_DEEPBIND` binds to its own dependencies first and so to libc
+//! directly. Its `DT_JMPREL` (PLT) and `DT_RELA`/`DT_REL` (GOT) relocations still name
+//! the symbol behind each slot, so the slots can be patched after the fact. Only 64-bit
+//! little-endian objects (x86_64, aarch64) are handled. The tables are read from a
+//! loaded object's memory, so everything here works on byte slices and addresses.
+
+use crate::macho::{u32_at, u64_at};
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_GNU_RELRO: u32 = 0x6474_e552;
+
+pub const DT_NULL: u64 = 0;
+pub const DT_PLTRELSZ: u64 = 2;
+pub const DT_STRTAB: u64 = 5;
+pub const DT_SYMTAB: u64 = 6;
+pub const DT_RELA: u64 = 7;
+pub const DT_RELASZ: u64 = 8;
+pub const DT_STRSZ: u64 = 10;
+pub const DT_REL: u64 = 17;
+pub const DT_RELSZ: u64 = 18;
+pub const DT_PLTREL: u64 = 20;
+pub const DT_JMPREL: u64 = 23;
+
+pub const R_X86_64_GLOB_DAT: u32 = 6;
+pub const R_X86_64_JUMP_SLOT: u32 = 7;
+pub const R_AARCH64_GLOB_DAT: u32 = 1025;
+pub const R_AARCH64_JUMP_SLOT: u32 = 1026;
+
+/// Relocation types that fill a pointer-sized slot with a symbol's address, per machine.
+pub const X86_64_SLOT_KINDS: &[u32] = &[R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT];
+pub const AARCH64_SLOT_KINDS: &[u32] = &[R_AARCH64_GLOB_DAT, R_AARCH64_JUMP_SLOT];
+
+pub const PHDR_SIZE: usize = 56;
+pub const DYN_SIZE: usize = 16;
+pub const SYM_SIZE: usize = 24;
+const RELA_SIZE: usize = 24;
+const REL_SIZE: usize = 16;
+
+/// An `Elf64_Phdr`, as far as rebinding cares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    pub kind: u32,
+    pub vaddr: u64,
+    pub memsz: u64,
+}
+
+/// Parses an array of program headers; a short last entry is ignored.
+pub fn program_headers(data: &[u8]) -> Vec<ProgramHeader> {
+    data.chunks_exact(PHDR_SIZE)
+        .map(|phdr| ProgramHeader {
+            kind: u32_at(phdr, 0, false).unwrap(),
+            vaddr: u64_at(phdr, 16, false).unwrap(),
+            memsz: u64_at(phdr, 40, false).unwrap(),
+        })
+        .collect()
+}
+
+/// A relocation table: its (unrelocated) address and size, and whether entries have
+/// addends (`Elf64_Rela`) or not (`Elf64_Rel`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RelocationTable {
+    pub address: u64,
+    pub size: u64,
+    pub rela: bool,
+}
+
+/// What rebinding needs from the dynamic section.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dynamic {
+    pub symtab: Option<u64>,
+    pub strtab: Option<u64>,
+    pub strsz: u64,
+    pub tables: Vec<RelocationTable>,
+}
+
+impl Dynamic {
+    /// Parses `Elf64_Dyn` entries up to `DT_NULL` (or the end of `data`).
+    pub fn parse(data: &[u8]) -> Self {
+        let mut dynamic = Dynamic::default();
+        let (mut jmprel, mut pltrelsz, mut pltrel) = (None, 0, DT_RELA);
+        let (mut rela, mut relasz, mut rel, mut relsz) = (None, 0, None, 0);
+        for entry in data.chunks_exact(DYN_SIZE) {
+            let tag = u64_at(entry, 0, false).unwrap();
+            let value = u64_at(entry, 8, false).unwrap();
+            match tag {
+                DT_NULL => break,
+                DT_SYMTAB => dynamic.symtab = Some(value),
+                DT_STRTAB => dynamic.strtab = Some(value),
+                DT_STRSZ => dynamic.strsz = value,
+                DT_JMPREL => jmprel = Some(value),
+                DT_PLTRELSZ => pltrelsz = value,
+                DT_PLTREL => pltrel = value,
+                DT_RELA => rela = Some(value),
+                DT_RELASZ => relasz = value,
+                DT_REL => rel = Some(value),
+                DT_RELSZ => relsz = value,
+                _ => {}
+            }
+        }
+        for (address, size, is_rela) in [
+            (jmprel, pltrelsz, pltrel == DT_RELA),
+            (rela, relasz, true),
+            (rel, relsz, false),
+        ] {
+            if let Some(address) = address.filter(|_| size > 0) {
+                dynamic.tables.push(RelocationTable {
+                    address,
+                    size,
+                    rela: is_rela,
+                });
+            }
+        }
+        dynamic
+    }
+}
+
+/// The run-time address of a dynamic section pointer. glibc rewrites most of them to
+/// absolute addresses when it loads an object, but not all (nor in every object, the
+/// vDSO's being read-only), so values below the load base are taken as unrelocated.
+pub fn relocated(value: u64, base: u64) -> u64 {
+    if value < base {
+        value.wrapping_add(base)
+    } else {
+        value
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    /// Unrelocated address of the slot.
+    pub offset: u64,
+    pub symbol: u32,
+    pub kind: u32,
+}
+
+/// Parses a relocation table.
+pub fn relocations(data: &[u8], rela: bool) -> Vec<Relocation> {
+    let size = if rela { RELA_SIZE } else { REL_SIZE };
+    data.chunks_exact(size)
+        .map(|entry| {
+            let info = u64_at(entry, 8, false).unwrap();
+            Relocation {
+                offset: u64_at(entry, 0, false).unwrap(),
+                symbol: (info >> 32) as u32,
+                kind: info as u32,
+            }
+        })
+        .collect()
+}
+
+/// How many bytes of the symbol table `relocations` refer to. The dynamic section does
+/// not give the table's size, so this is as much of it as may be read.
+pub fn symbol_table_size(relocations: &[Relocation]) -> usize {
+    relocations
+        .iter()
+        .map(|relocation| (relocation.symbol as usize + 1) * SYM_SIZE)
+        .max()
+        .unwrap_or(0)
+}
+
+/// A GOT slot to overwrite: the pointer at `address` (unrelocated) holds `names[name]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GotSlot {
+    pub name: usize,
+    pub address: u64,
+}
+
+fn symbol_name<'a>(symbols: &[u8], strings: &'a [u8], symbol: u32) -> Option<&'a [u8]> {
+    let strx = u32_at(symbols, symbol as usize * SYM_SIZE, false).ok()? as usize;
+    let rest = strings.get(strx..)?;
+    let end = rest.iter().position(|&b| b == 0)?;
+    Some(&rest[..end])
+}
+
+/// Every slot that a relocation of one of `slot_kinds` binds to one of `names`, in table
+/// order. Relocations whose symbol is out of range are skipped. ELF symbol names have no
+/// leading underscore; versions (`open@GLIBC_2.2.5`) live elsewhere, so any version of
+/// the symbol matches.
+pub fn import_slots(
+    relocations: &[Relocation],
+    slot_kinds: &[u32],
+    symbols: &[u8],
+    strings: &[u8],
+    names: &[&str],
+) -> Vec<GotSlot> {
+    relocations
+        .iter()
+        .filter(|relocation| relocation.symbol != 0 && slot_kinds.contains(&relocation.kind))
+        .filter_map(|relocation| {
+            let symbol = symbol_name(symbols, strings, relocation.symbol)?;
+            let name = names.iter().position(|name| name.as_bytes() == symbol)?;
+            Some(GotSlot {
+                name,
+                address: relocation.offset,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: &[&str] = &["open", "openat", "gethostid"];
+
+    fn dyn_entries(entries: &[(u64, u64)]) -> Vec<u8> {
+        entries
+            .iter()
+            .flat_map(|&(tag, value)| [tag.to_le_bytes(), value.to_le_bytes()].concat())
+            .collect()
+    }
+
+    fn rela(entries: &[(u64, u32, u32)]) -> Vec<u8> {
+        entries
+            .iter()
+            .flat_map(|&(offset, symbol, kind)| {
+                let info = (symbol as u64) << 32 | kind as u64;
+                [offset.to_le_bytes(), info.to_le_bytes(), 0u64.to_le_bytes()].concat()
+            })
+            .collect()
+    }
+
+    // A symbol table and string table holding `names` as symbols 1.. (0 is the null symbol).
+    fn symbols(names: &[&str]) -> (Vec<u8>, Vec<u8>) {
+        let mut symbols = vec![0u8; SYM_SIZE];
+        let mut strings = vec![0u8];
+        for name in names {
+            let mut symbol = vec![0u8; SYM_SIZE];
+            symbol[..4].copy_from_slice(&(strings.len() as u32).to_le_bytes());
+            symbols.extend(symbol);
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+        }
+        (symbols, strings)
+    }
+
+    #[test]
+    fn parses_program_headers() {
+        let mut data = vec![0u8; PHDR_SIZE * 2 + 10];
+        data[..4].copy_from_slice(&PT_DYNAMIC.to_le_bytes());
+        data[16..24].copy_from_slice(&0x3de0u64.to_le_bytes());
+        data[40..48].copy_from_slice(&0x1f0u64.to_le_bytes());
+        data[PHDR_SIZE..PHDR_SIZE + 4].copy_from_slice(&PT_GNU_RELRO.to_le_bytes());
+        assert_eq!(
+            program_headers(&data),
+            [
+                ProgramHeader {
+                    kind: PT_DYNAMIC,
+                    vaddr: 0x3de0,
+                    memsz: 0x1f0,
+                },
+                ProgramHeader {
+                    kind: PT_GNU_RELRO,
+                    vaddr: 0,
+                    memsz: 0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_the_dynamic_section() {
+        let data = dyn_entries(&[
+            (DT_STRTAB, 0x4a8),
+            (DT_SYMTAB, 0x3d8),
+            (DT_STRSZ, 0x90),
+            (DT_PLTRELSZ, 48),
+            (DT_PLTREL, DT_RELA),
+            (DT_JMPREL, 0x6e0),
+            (DT_RELA, 0x5d8),
+            (DT_RELASZ, 264),
+            // An empty REL table is no table.
+            (DT_REL, 0x700),
+            (DT_NULL, 0),
+            (DT_RELSZ, 16),
+        ]);
+        assert_eq!(
+            Dynamic::parse(&data),
+            Dynamic {
+                symtab: Some(0x3d8),
+                strtab: Some(0x4a8),
+                strsz: 0x90,
+                tables: vec![
+                    RelocationTable {
+                        address: 0x6e0,
+                        size: 48,
+                        rela: true,
+                    },
+                    RelocationTable {
+                        address: 0x5d8,
+                        size: 264,
+                        rela: true,
+                    },
+                ],
+            }
+        );
+        let rel_plt = Dynamic::parse(&dyn_entries(&[
+            (DT_JMPREL, 0x100),
+            (DT_PLTRELSZ, 32),
+            (DT_PLTREL, DT_REL),
+        ]));
+        assert!(!rel_plt.tables[0].rela);
+    }
+
+    #[test]
+    fn relocates_unrelocated_pointers_only() {
+        let base = 0x7f00_0000_0000;
+        assert_eq!(relocated(0x4a8, base), base + 0x4a8);
+        assert_eq!(relocated(base + 0x4a8, base), base + 0x4a8);
+        // The main executable of a non-PIE program is loaded at 0.
+        assert_eq!(relocated(0x40_04a8, 0), 0x40_04a8);
+    }
+
+    #[test]
+    fn finds_plt_and_got_slots_by_name() {
+        let (symbols, strings) = symbols(&["read", "open", "gethostid", "openat64"]);
+        let table = rela(&[
+            (0x4018, 1, R_X86_64_JUMP_SLOT),
+            (0x4020, 2, R_X86_64_JUMP_SLOT),
+            (0x3ff0, 3, R_X86_64_GLOB_DAT),
+            // A relative relocation has no symbol; a copy relocation is not a slot.
+            (0x3e00, 0, 8),
+            (0x4040, 2, 5),
+            (0x4028, 4, R_X86_64_JUMP_SLOT),
+            // Out of range of the symbol table.
+            (0x4030, 9, R_X86_64_JUMP_SLOT),
+        ]);
+        let relocations = relocations(&table, true);
+        assert_eq!(relocations.len(), 7);
+        assert_eq!(symbol_table_size(&relocations), 10 * SYM_SIZE);
+        assert_eq!(
+            import_slots(&relocations, X86_64_SLOT_KINDS, &symbols, &strings, NAMES),
+            [
+                GotSlot {
+                    name: 0,
+                    address: 0x4020,
+                },
+                GotSlot {
+                    name: 2,
+                    address: 0x3ff0,
+                },
+            ]
+        );
+        // The same table read as aarch64 has no slots of those types.
+        assert!(
+            import_slots(&relocations, AARCH64_SLOT_KINDS, &symbols, &strings, NAMES).is_empty()
+        );
+    }
+
+    #[test]
+    fn parses_rel_entries() {
+        let mut table = Vec::new();
+        for (offset, info) in [(0x2000u64, 1u64 << 32 | 7), (0x2008, 2 << 32 | 6)] {
+            table.extend_from_slice(&offset.to_le_bytes());
+            table.extend_from_slice(&info.to_le_bytes());
+        }
+        assert_eq!(
+            relocations(&table, false),
+            [
+                Relocation {
+                    offset: 0x2000,
+                    symbol: 1,
+                    kind: 7,
+                },
+                Relocation {
+                    offset: 0x2008,
+                    symbol: 2,
+                    kind: 6,
+                },
+            ]
+        );
+        assert_eq!(symbol_table_size(&[]), 0);
+    }
+}
@@ -0,0 +1,185 @@
+//! Fresh machine identities for new profiles.
+//!
+//! An identity only holds up if its parts agree with each other, so the fixed parts
+//! (board-id, target-type and the serial's model code and date) come from the model's
+//! entry in the [`crate::catalog`]. The random parts (UUID, the primary interface's MAC
+//! address and the rest of the serial) are expanded from caller-supplied entropy, so generation is repeatable in
+//! tests.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::catalog::Model;
+use crate::identity::{BOARD_ID_KEY, IO_PLATFORM_SERIAL_NUMBER_KEY, MODEL_KEY, TARGET_TYPE_KEY};
+use crate::mac::{MacAddress, GENERATE, OTHER_INTERFACES};
+use crate::serial::{self, SerialFormat};
+use crate::uuid::uuid_from_bytes;
+
+/// The model used when none is asked for.
+pub const DEFAULT_MODEL: &str = "Macmini8,1";
+
+/// The interface a generated identity gives its own address; the others get generated
+/// ones as they are seen.
+pub const PRIMARY_INTERFACE: &str = "en0";
+
+/// A generated identity for one machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MachineIdentity {
+    pub model: Model,
+    /// A random (version 4) UUID, uppercased.
+    pub uuid: String,
+    pub serial: String,
+    pub serial_format: SerialFormat,
+    /// The primary interface's address: locally administered, unicast.
+    pub mac: MacAddress,
+}
+
+impl MachineIdentity {
+    /// Generates an identity for `model` with a serial number in `serial_format`, drawing
+    /// every random choice from `entropy`. The model must support the format (see
+    /// [`Model::supports`]).
+    pub fn generate(model: &Model, serial_format: SerialFormat, entropy: &[u8]) -> Self {
+        let mut uuid = [0u8; 16];
+        uuid.copy_from_slice(&expand(entropy, "uuid")[..16]);
+        uuid[6] = (uuid[6] & 0x0f) | 0x40; // version 4
+        uuid[8] = (uuid[8] & 0x3f) | 0x80; // RFC 9562 variant
+
+        MachineIdentity {
+            model: model.clone(),
+            uuid: uuid_from_bytes(&uuid),
+            serial: serial::generate(serial_format, model, entropy),
+            serial_format,
+            mac: MacAddress::generate(entropy, "mac", None),
+        }
+    }
+
+    /// The identity as a profile store file.
+    pub fn to_toml(&self) -> String {
+        let quoted = |value: &str| toml::Value::String(value.to_string()).to_string();
+        let target_type = match &self.model.target_type {
+            Some(target_type) => format!("{} = {}\n", TARGET_TYPE_KEY, quoted(target_type)),
+            None => String::new(),
+        };
+        format!(
+            "# A {}, generated by `uuid_spoof profile new`.\n\
+             uuid = {}\n\
+             serial_format = {}\n\
+             \n\
+             [properties]\n\
+             {} = {}\n\
+             {} = {}\n\
+             {} = {}\n\
+             {}\
+             \n\
+             [mac_addresses]\n\
+             {} = {}\n\
+             {} = {}\n",
+            self.model.identifier,
+            quoted(&self.uuid),
+            quoted(self.serial_format.name()),
+            IO_PLATFORM_SERIAL_NUMBER_KEY,
+            quoted(&self.serial),
+            BOARD_ID_KEY,
+            quoted(&self.model.board_id),
+            MODEL_KEY,
+            quoted(&self.model.identifier),
+            target_type,
+            PRIMARY_INTERFACE,
+            quoted(&self.mac.to_string()),
+            quoted(OTHER_INTERFACES),
+            quoted(GENERATE),
+        )
+    }
+}
+
+/// 32 bytes from the system's random source.
+pub fn entropy() -> std::io::Result<[u8; 32]> {
+    use std::io::Read;
+    let mut bytes = [0u8; 32];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Independent random bytes for each part of the identity.
+pub(crate) fn expand(entropy: &[u8], label: &str) -> [u8; 32] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(entropy).expect("HMAC accepts keys of any size");
+    mac.update(label.as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+    use crate::config::validate_uuid;
+    use crate::serial::Serial;
+
+    fn model(identifier: &str) -> &'static Model {
+        Catalog::builtin().model(identifier).unwrap()
+    }
+
+    fn generated(identifier: &str, entropy: &[u8]) -> MachineIdentity {
+        MachineIdentity::generate(model(identifier), SerialFormat::Legacy12, entropy)
+    }
+
+    #[test]
+    fn the_same_entropy_gives_the_same_identity() {
+        assert_eq!(
+            generated("Macmini8,1", b"entropy"),
+            generated("Macmini8,1", b"entropy")
+        );
+        let a = generated("Macmini8,1", b"a");
+        let b = generated("Macmini8,1", b"b");
+        assert_ne!(a.uuid, b.uuid);
+        assert_ne!(a.mac, b.mac);
+    }
+
+    #[test]
+    fn parts_are_well_formed() {
+        for seed in 0..200u32 {
+            for format in SerialFormat::ALL {
+                let model = model("MacBookPro15,1");
+                let identity = MachineIdentity::generate(model, format, &seed.to_le_bytes());
+                assert_eq!(validate_uuid(&identity.uuid).unwrap(), identity.uuid);
+                assert_eq!(&identity.uuid[14..15], "4");
+                assert!(identity.mac.is_locally_administered(), "{}", identity.mac);
+                assert!(!identity.mac.is_multicast(), "{}", identity.mac);
+                let serial = Serial::parse_as(&identity.serial, format).unwrap();
+                assert!(
+                    (model.years.0..=model.years.1).any(|year| serial.made_in(year)),
+                    "{}",
+                    serial
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn renders_a_profile_file() {
+        let identity = generated("Macmini8,1", b"entropy");
+        let text = identity.to_toml();
+        let table: toml::Table = toml::from_str(&text).unwrap();
+        assert_eq!(table["uuid"].as_str(), Some(identity.uuid.as_str()));
+        assert_eq!(table["serial_format"].as_str(), Some("legacy12"));
+        let properties = table["properties"].as_table().unwrap();
+        assert_eq!(
+            properties[IO_PLATFORM_SERIAL_NUMBER_KEY].as_str(),
+            Some(identity.serial.as_str())
+        );
+        assert_eq!(
+            properties[BOARD_ID_KEY].as_str(),
+            Some("Mac-7BA5B2DFE22DDD8C")
+        );
+        assert_eq!(properties[MODEL_KEY].as_str(), Some("Macmini8,1"));
+        assert_eq!(properties[TARGET_TYPE_KEY].as_str(), Some("J174"));
+        let mac_addresses = table["mac_addresses"].as_table().unwrap();
+        assert_eq!(
+            mac_addresses[PRIMARY_INTERFACE].as_str(),
+            Some(identity.mac.to_string().as_str())
+        );
+        assert_eq!(mac_addresses[OTHER_INTERFACES].as_str(), Some(GENERATE));
+
+        let identity = generated("iMac19,1", b"entropy");
+        assert!(!identity.to_toml().contains(TARGET_TYPE_KEY));
+    }
+}
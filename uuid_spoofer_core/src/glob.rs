@@ -0,0 +1,247 @@
+//! Shell-style wildcard patterns, for the executable paths and bundle identifiers in
+//! profile rules.
+//!
+//! `?` matches one character and `*` any run of characters, neither crossing a `/`; `**`
+//! matches anything, slashes included. `[abc]`, `[a-z]` and `[!abc]` match one character
+//! from (or not from) a set. Everything else, including `.`, matches itself.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    One,
+    Star,
+    DoubleStar,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    // Whether this single-character token matches `c`.
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Token::Literal(literal) => *literal == c,
+            Token::One => c != '/',
+            Token::Class { negated, ranges } => {
+                c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }
+            Token::Star | Token::DoubleStar => false,
+        }
+    }
+}
+
+/// Why a pattern could not be compiled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GlobError {
+    /// A `[` with no closing `]`, at this character position.
+    UnclosedClass(usize),
+    /// A range like `[z-a]` whose ends are reversed.
+    ReversedRange(char, char),
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobError::UnclosedClass(index) => {
+                write!(f, "'[' at position {} is never closed", index)
+            }
+            GlobError::ReversedRange(lo, hi) => write!(f, "range {}-{} is reversed", lo, hi),
+        }
+    }
+}
+
+impl std::error::Error for GlobError {}
+
+/// A compiled pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Self, GlobError> {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '*' if chars.get(i + 1) == Some(&'*') => {
+                    tokens.push(Token::DoubleStar);
+                    i += 2;
+                    // Any further stars add nothing.
+                    while chars.get(i) == Some(&'*') {
+                        i += 1;
+                    }
+                    continue;
+                }
+                '*' => tokens.push(Token::Star),
+                '?' => tokens.push(Token::One),
+                '[' => {
+                    let (token, end) = class(&chars, i)?;
+                    tokens.push(token);
+                    i = end;
+                }
+                c => tokens.push(Token::Literal(c)),
+            }
+            i += 1;
+        }
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            tokens,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether the pattern contains a `/`, i.e. is meant for a whole path rather than a
+    /// file name.
+    pub fn has_slash(&self) -> bool {
+        self.pattern.contains('/')
+    }
+
+    /// Whether `text` matches the whole pattern.
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        // matched[j]: the tokens so far match text[..j]. One row per token, so the cost
+        // is bounded by pattern length times text length whatever the stars.
+        let mut matched = vec![false; text.len() + 1];
+        matched[0] = true;
+        for token in &self.tokens {
+            let mut next = vec![false; text.len() + 1];
+            match token {
+                Token::Star | Token::DoubleStar => {
+                    let crosses_slash = *token == Token::DoubleStar;
+                    for j in 0..=text.len() {
+                        next[j] = matched[j]
+                            || (j > 0 && next[j - 1] && (crosses_slash || text[j - 1] != '/'));
+                    }
+                }
+                _ => {
+                    for j in 1..=text.len() {
+                        next[j] = matched[j - 1] && token.matches(text[j - 1]);
+                    }
+                }
+            }
+            matched = next;
+        }
+        matched[text.len()]
+    }
+}
+
+impl fmt::Display for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+// Parses the set starting at `chars[start] == '['`; returns it and the index of its `]`.
+fn class(chars: &[char], start: usize) -> Result<(Token, usize), GlobError> {
+    let mut i = start + 1;
+    let negated = chars.get(i) == Some(&'!');
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    // A `]` straight after the opening bracket is a member, as in the shell.
+    let first = i;
+    loop {
+        let Some(&c) = chars.get(i) else {
+            return Err(GlobError::UnclosedClass(start));
+        };
+        if c == ']' && i > first {
+            break;
+        }
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&hi| hi != ']') {
+            let hi = chars[i + 2];
+            if hi < c {
+                return Err(GlobError::ReversedRange(c, hi));
+            }
+            ranges.push((c, hi));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+    Ok((Token::Class { negated, ranges }, i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, text: &str) -> bool {
+        Glob::new(pattern).unwrap().matches(text)
+    }
+
+    #[test]
+    fn literals_and_single_characters() {
+        assert!(matches("com.example.app", "com.example.app"));
+        assert!(!matches("com.example.app", "com.example.ap"));
+        assert!(!matches("com.example.app", "comXexample.app"));
+        assert!(matches("Slack?", "Slack2"));
+        assert!(!matches("Slack?", "Slack"));
+        assert!(matches("", ""));
+        assert!(!matches("", "x"));
+    }
+
+    #[test]
+    fn stars_stay_within_a_path_component() {
+        assert!(matches("com.tinyspeck.*", "com.tinyspeck.slackmacgap"));
+        assert!(matches("*", ""));
+        assert!(matches(
+            "/Applications/*.app/Contents/MacOS/*",
+            "/Applications/Slack.app/Contents/MacOS/Slack"
+        ));
+        assert!(!matches(
+            "/Applications/*",
+            "/Applications/Slack.app/Contents/MacOS/Slack"
+        ));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn double_stars_cross_slashes() {
+        assert!(matches(
+            "/Applications/**",
+            "/Applications/Slack.app/Contents/MacOS/Slack"
+        ));
+        assert!(matches("**/bin/*", "/usr/local/bin/node"));
+        assert!(!matches("**/bin/*", "/usr/local/bin/x/node"));
+        assert!(matches("/opt/***", "/opt/a/b"));
+    }
+
+    #[test]
+    fn sets() {
+        assert!(matches("v[0-9].[0-9]", "v1.2"));
+        assert!(!matches("v[0-9]", "vx"));
+        assert!(matches("[!.]*", "Slack"));
+        assert!(!matches("[!.]*", ".hidden"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(!matches("[/]", "/"));
+    }
+
+    #[test]
+    fn many_stars_stay_fast() {
+        let pattern = "*a".repeat(20) + "b";
+        assert!(!matches(&pattern, &"a".repeat(200)));
+    }
+
+    #[test]
+    fn malformed_patterns() {
+        assert_eq!(Glob::new("ab[cd"), Err(GlobError::UnclosedClass(2)));
+        assert_eq!(Glob::new("[!]"), Err(GlobError::UnclosedClass(0)));
+        assert_eq!(Glob::new("[z-a]"), Err(GlobError::ReversedRange('z', 'a')));
+        assert_eq!(
+            Glob::new("x[").unwrap_err().to_string(),
+            "'[' at position 1 is never closed"
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Keeps panics inside the hooks. A hook is called from C, where unwinding is undefined
+//! (Rust aborts the process instead), so a bug in spoofing must never take the host app
+//! down: a panicking hook is reported and the call goes to the original function, as if
+//! the hook had not been installed.
+//!
+//! The logic is platform-neutral; each hook supplies its spoofing body, the call to the
+//! original and a value that reads as an ordinary failure to its caller.
+
+use std::any::Any;
+use std::fmt;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Why a guarded call did not return the hook's answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// The spoofing body panicked; the original was called instead.
+    Hook(String),
+    /// The original function panicked (or unwound through a Rust frame in it).
+    Original(String),
+    /// The original function is not known, so nothing could be called.
+    NoOriginal,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Hook(message) => write!(
+                f,
+                "the hook panicked ({}); passed through to the original",
+                message
+            ),
+            Failure::Original(message) => write!(f, "the original function panicked ({})", message),
+            Failure::NoOriginal => write!(f, "the original function is unknown"),
+        }
+    }
+}
+
+/// The text of a panic payload, as passed to `panic!`.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+/// Runs a hook without letting a panic escape.
+///
+/// `hook` returns the spoofed result, or `None` to pass the call through. `original`
+/// calls the real function, or returns `None` if it is unknown. If either panics or the
+/// original is unknown, `report` is told why, and `failed` is returned when there is no
+/// result at all.
+pub fn guarded<R>(
+    hook: impl FnOnce() -> Option<R>,
+    original: impl FnOnce() -> Option<R>,
+    failed: R,
+    mut report: impl FnMut(&Failure),
+) -> R {
+    match catch_unwind(AssertUnwindSafe(hook)) {
+        Ok(Some(result)) => return result,
+        Ok(None) => {}
+        Err(payload) => report(&Failure::Hook(panic_message(&*payload))),
+    }
+    match catch_unwind(AssertUnwindSafe(original)) {
+        Ok(Some(result)) => result,
+        Ok(None) => {
+            report(&Failure::NoOriginal);
+            failed
+        }
+        Err(payload) => {
+            report(&Failure::Original(panic_message(&*payload)));
+            failed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    // A mock hook around a mock original, recording what happened.
+    #[derive(Default)]
+    struct Calls {
+        originals: Cell<usize>,
+        failures: RefCell<Vec<Failure>>,
+    }
+
+    impl Calls {
+        fn run(
+            &self,
+            hook: impl FnOnce() -> Option<i32>,
+            original: impl FnOnce() -> Option<i32>,
+        ) -> i32 {
+            guarded(
+                hook,
+                || {
+                    self.originals.set(self.originals.get() + 1);
+                    original()
+                },
+                -1,
+                |failure| self.failures.borrow_mut().push(failure.clone()),
+            )
+        }
+    }
+
+    #[test]
+    fn spoofed_results_skip_the_original() {
+        let calls = Calls::default();
+        assert_eq!(calls.run(|| Some(7), || Some(1)), 7);
+        assert_eq!(calls.originals.get(), 0);
+        assert!(calls.failures.borrow().is_empty());
+    }
+
+    #[test]
+    fn passed_through_calls_reach_the_original() {
+        let calls = Calls::default();
+        assert_eq!(calls.run(|| None, || Some(1)), 1);
+        assert_eq!(calls.originals.get(), 1);
+        assert!(calls.failures.borrow().is_empty());
+    }
+
+    #[test]
+    fn a_panicking_hook_falls_back_to_the_original() {
+        let calls = Calls::default();
+        let result = calls.run(
+            || Some("DEADBEEF".parse::<i32>().expect("malformed value")),
+            || Some(1),
+        );
+        assert_eq!(result, 1);
+        assert!(
+            matches!(
+                &calls.failures.borrow()[..],
+                [Failure::Hook(message)] if message.starts_with("malformed value: ")
+            ),
+            "{:?}",
+            calls.failures
+        );
+
+        // Formatted payloads too.
+        let calls = Calls::default();
+        assert_eq!(calls.run(|| panic!("key {} too long", 300), || Some(1)), 1);
+        assert_eq!(
+            calls.failures.borrow()[0].to_string(),
+            "the hook panicked (key 300 too long); passed through to the original"
+        );
+    }
+
+    #[test]
+    fn a_missing_or_panicking_original_returns_the_failure_value() {
+        let calls = Calls::default();
+        assert_eq!(calls.run(|| None, || None), -1);
+        assert_eq!(*calls.failures.borrow(), [Failure::NoOriginal]);
+
+        let calls = Calls::default();
+        let result = calls.run(|| panic!("hook"), || std::panic::panic_any(42u8));
+        assert_eq!(result, -1);
+        assert_eq!(
+            *calls.failures.borrow(),
+            [
+                Failure::Hook("hook".to_string()),
+                Failure::Original("non-string panic payload".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn hooks_keep_working_after_a_panic() {
+        let calls = Calls::default();
+        let panics = Cell::new(true);
+        let hook = || {
+            if panics.replace(false) {
+                panic!("first call");
+            }
+            Some(7)
+        };
+        assert_eq!(calls.run(hook, || Some(1)), 1);
+        assert_eq!(calls.run(hook, || Some(1)), 7);
+        assert_eq!(calls.failures.borrow().len(), 1);
+    }
+}
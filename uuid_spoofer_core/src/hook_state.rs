@@ -0,0 +1,228 @@
+//! Where each hook stands. A hook starts out uninstalled, becomes installed once its
+//! original implementation is known (captured from a patched import slot, or found next
+//! in the lookup order for an interposed export), and is degraded when it has to run
+//! without one: it was reached before any original was captured, or installing the hooks
+//! failed. A degraded hook still spoofs, and passes everything else to whatever
+//! `dlsym(RTLD_NEXT)` finds, failing the call if that is nothing. A hook is also degraded
+//! for good once one of its import slots could not be patched, since calls through that
+//! slot never reach it.
+//!
+//! The state lives in atomics, since hooks read it from any thread.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookState {
+    /// Not patched in anywhere yet, and never called.
+    Uninstalled,
+    /// The original is known; calls are spoofed or passed through to it.
+    Installed,
+    /// Running without a captured original.
+    Degraded,
+}
+
+impl HookState {
+    /// The number the C query API reports (`uuid_spoof_hook_state`).
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    fn from_code(code: u8) -> Self {
+        match code {
+            0 => HookState::Uninstalled,
+            1 => HookState::Installed,
+            _ => HookState::Degraded,
+        }
+    }
+
+    /// The state after `event`.
+    pub fn after(self, event: Event) -> Self {
+        match (self, event) {
+            // However the hook ran before, a captured original is the real thing.
+            (_, Event::Captured) => HookState::Installed,
+            // An original does not help calls that never reach the hook.
+            (_, Event::Bypassed) => HookState::Degraded,
+            // A hook with an original keeps it; nothing below makes it worse.
+            (HookState::Installed, _) => HookState::Installed,
+            (_, Event::FellBack | Event::Unresolved | Event::InstallFailed) => HookState::Degraded,
+        }
+    }
+}
+
+impl fmt::Display for HookState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HookState::Uninstalled => "uninstalled",
+            HookState::Installed => "installed",
+            HookState::Degraded => "degraded",
+        })
+    }
+}
+
+/// What can happen to a hook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Its original implementation was found: the rebinding engine took it from an import
+    /// slot it patched, or the loader has it next after our export.
+    Captured,
+    /// It was called before any original was captured, and `dlsym(RTLD_NEXT)` found one.
+    FellBack,
+    /// No original could be found at all.
+    Unresolved,
+    /// Installing the hooks failed.
+    InstallFailed,
+    /// An import slot was left pointing at the original, e.g. an arm64e signed pointer,
+    /// so calls through it skip the hook.
+    Bypassed,
+}
+
+/// The state of one hook, shared between threads.
+pub struct HookStatus {
+    state: AtomicU8,
+    // Set by `Event::Bypassed`; a later capture does not reinstall the hook.
+    bypassed: AtomicBool,
+}
+
+impl HookStatus {
+    pub const fn new() -> Self {
+        HookStatus {
+            state: AtomicU8::new(HookState::Uninstalled as u8),
+            bypassed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn state(&self) -> HookState {
+        HookState::from_code(self.state.load(Ordering::Acquire))
+    }
+
+    /// Applies `event`, returning the old and new state if it changed anything, so each
+    /// transition is reported once however many threads race through it.
+    pub fn record(&self, event: Event) -> Option<(HookState, HookState)> {
+        if event == Event::Bypassed {
+            self.bypassed.store(true, Ordering::Release);
+        }
+        let mut current = self.state.load(Ordering::Acquire);
+        loop {
+            let from = HookState::from_code(current);
+            let to = if self.bypassed.load(Ordering::Acquire) {
+                HookState::Degraded
+            } else {
+                from.after(event)
+            };
+            if to == from {
+                return None;
+            }
+            match self.state.compare_exchange_weak(
+                current,
+                to as u8,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Some((from, to)),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl Default for HookStatus {
+    fn default() -> Self {
+        HookStatus::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use HookState::*;
+
+    #[test]
+    fn capturing_the_original_installs() {
+        let status = HookStatus::new();
+        assert_eq!(status.state(), Uninstalled);
+        assert_eq!(
+            status.record(Event::Captured),
+            Some((Uninstalled, Installed))
+        );
+        // Later images hand over the same original; nothing changes.
+        assert_eq!(status.record(Event::Captured), None);
+        assert_eq!(status.state(), Installed);
+    }
+
+    #[test]
+    fn running_without_an_original_degrades() {
+        for event in [Event::FellBack, Event::Unresolved, Event::InstallFailed] {
+            let status = HookStatus::new();
+            assert_eq!(
+                status.record(event),
+                Some((Uninstalled, Degraded)),
+                "{:?}",
+                event
+            );
+            assert_eq!(status.record(Event::FellBack), None);
+            assert_eq!(status.state(), Degraded);
+        }
+    }
+
+    #[test]
+    fn a_later_capture_recovers_a_degraded_hook() {
+        let status = HookStatus::new();
+        status.record(Event::InstallFailed);
+        assert_eq!(status.record(Event::Captured), Some((Degraded, Installed)));
+    }
+
+    #[test]
+    fn installed_hooks_stay_installed() {
+        let status = HookStatus::new();
+        status.record(Event::Captured);
+        for event in [Event::FellBack, Event::Unresolved, Event::InstallFailed] {
+            assert_eq!(status.record(event), None, "{:?}", event);
+        }
+        assert_eq!(status.state(), Installed);
+    }
+
+    #[test]
+    fn an_unpatched_slot_degrades_for_good() {
+        let status = HookStatus::new();
+        status.record(Event::Captured);
+        assert_eq!(status.record(Event::Bypassed), Some((Installed, Degraded)));
+        assert_eq!(status.record(Event::Bypassed), None);
+        // Other images' slots still hand over the original.
+        assert_eq!(status.record(Event::Captured), None);
+        assert_eq!(status.state(), Degraded);
+    }
+
+    #[test]
+    fn codes_and_names() {
+        for (state, code, name) in [
+            (Uninstalled, 0, "uninstalled"),
+            (Installed, 1, "installed"),
+            (Degraded, 2, "degraded"),
+        ] {
+            assert_eq!(state.code(), code);
+            assert_eq!(HookState::from_code(code as u8), state);
+            assert_eq!(state.to_string(), name);
+        }
+    }
+
+    #[test]
+    fn racing_threads_report_a_transition_once() {
+        let status = Arc::new(HookStatus::new());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let status = status.clone();
+                std::thread::spawn(move || {
+                    (0..1_000)
+                        .filter(|_| status.record(Event::FellBack).is_some())
+                        .count()
+                })
+            })
+            .collect();
+        let reported: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+        assert_eq!(reported, 1);
+        assert_eq!(status.state(), Degraded);
+    }
+}
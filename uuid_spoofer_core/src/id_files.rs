@@ -0,0 +1,99 @@
+//! The Linux files that expose the machine's identity, and what the preload library
+//! serves in their place.
+//!
+//! Matching is purely lexical here; the hook also tries the canonicalised path so that
+//! symlinks such as `/sys/class/dmi/id` resolve to a known entry.
+
+use crate::config::UuidFormatError;
+use crate::uuid::uuid_to_machine_id;
+use std::path::Path;
+
+/// systemd's machine ID.
+pub const ETC_MACHINE_ID: &str = "/etc/machine-id";
+/// D-Bus' copy of the machine ID, usually a symlink to `/etc/machine-id`.
+pub const DBUS_MACHINE_ID: &str = "/var/lib/dbus/machine-id";
+/// SMBIOS system UUID.
+pub const DMI_PRODUCT_UUID: &str = "/sys/class/dmi/id/product_uuid";
+/// Where `/sys/class/dmi/id` actually points.
+pub const DMI_DEVICE_PRODUCT_UUID: &str = "/sys/devices/virtual/dmi/id/product_uuid";
+
+/// Which identity an intercepted path stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdFile {
+    /// 32 lowercase hex digits and a newline.
+    MachineId,
+    /// Lowercase dashed UUID and a newline, as the kernel prints it.
+    ProductUuid,
+}
+
+impl IdFile {
+    /// The identity file at exactly `path`, if any.
+    pub fn for_path(path: &Path) -> Option<IdFile> {
+        match path.to_str()? {
+            ETC_MACHINE_ID | DBUS_MACHINE_ID => Some(IdFile::MachineId),
+            DMI_PRODUCT_UUID | DMI_DEVICE_PRODUCT_UUID => Some(IdFile::ProductUuid),
+            _ => None,
+        }
+    }
+
+    /// Cheap pre-filter so the hook only canonicalises paths that could match.
+    pub fn may_match(path: &Path) -> bool {
+        matches!(
+            path.file_name().and_then(|name| name.to_str()),
+            Some("machine-id" | "product_uuid")
+        )
+    }
+
+    /// File contents presenting `uuid` in this file's format.
+    pub fn contents(self, uuid: &str) -> Result<String, UuidFormatError> {
+        match self {
+            IdFile::MachineId => Ok(format!("{}\n", uuid_to_machine_id(uuid)?)),
+            IdFile::ProductUuid => Ok(format!(
+                "{}\n",
+                crate::config::validate_uuid(uuid)?.to_ascii_lowercase()
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "DEADBEEF-0123-4567-89AB-CDEF00112233";
+
+    #[test]
+    fn known_paths() {
+        for (path, expected) in [
+            (ETC_MACHINE_ID, Some(IdFile::MachineId)),
+            (DBUS_MACHINE_ID, Some(IdFile::MachineId)),
+            (DMI_PRODUCT_UUID, Some(IdFile::ProductUuid)),
+            (DMI_DEVICE_PRODUCT_UUID, Some(IdFile::ProductUuid)),
+            ("/etc/hostname", None),
+            ("/tmp/machine-id", None),
+            ("machine-id", None),
+        ] {
+            assert_eq!(IdFile::for_path(Path::new(path)), expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn prefilter_is_by_file_name() {
+        assert!(IdFile::may_match(Path::new("/etc/./machine-id")));
+        assert!(IdFile::may_match(Path::new("product_uuid")));
+        assert!(!IdFile::may_match(Path::new("/etc/passwd")));
+    }
+
+    #[test]
+    fn contents() {
+        assert_eq!(
+            IdFile::MachineId.contents(UUID).unwrap(),
+            "deadbeef0123456789abcdef00112233\n"
+        );
+        assert_eq!(
+            IdFile::ProductUuid.contents(UUID).unwrap(),
+            "deadbeef-0123-4567-89ab-cdef00112233\n"
+        );
+        assert!(IdFile::MachineId.contents("nope").is_err());
+    }
+}
@@ -0,0 +1,287 @@
+//! The table of IORegistry properties the hook replaces, beyond IOPlatformUUID.
+//!
+//! Values are kept as plain Rust data and only turned into CoreFoundation objects by the
+//! hook itself, so building and querying the table works on any platform.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::mac::MacAddress;
+
+// Properties of the IOPlatformExpertDevice entry (the root of the IOService plane).
+// Other entries have keys of the same names, such as a PCI device's `model`, so these
+// are only replaced when read from that entry.
+pub const IO_PLATFORM_UUID_KEY: &str = "IOPlatformUUID";
+pub const IO_PLATFORM_SERIAL_NUMBER_KEY: &str = "IOPlatformSerialNumber";
+pub const BOARD_ID_KEY: &str = "board-id";
+pub const MODEL_KEY: &str = "model";
+pub const TARGET_TYPE_KEY: &str = "target-type";
+
+/// The keys above, which belong to the IOPlatformExpertDevice entry.
+pub const PLATFORM_KEYS: [&str; 5] = [
+    IO_PLATFORM_UUID_KEY,
+    IO_PLATFORM_SERIAL_NUMBER_KEY,
+    BOARD_ID_KEY,
+    MODEL_KEY,
+    TARGET_TYPE_KEY,
+];
+
+// A property of each network controller entry, replaced per interface (see `mac`).
+pub const IO_MAC_ADDRESS_KEY: &str = "IOMACAddress";
+
+/// Whether `key` is one of [`PLATFORM_KEYS`].
+pub fn is_platform_key(key: &str) -> bool {
+    PLATFORM_KEYS.contains(&key)
+}
+
+/// A replacement value, typed the way IOKit returns it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PropertyValue {
+    /// Returned as a CFString.
+    String(String),
+    /// Returned as CFData.
+    Data(Vec<u8>),
+    /// Returned as a 64-bit CFNumber.
+    Number(i64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PropertyError {
+    pub key: String,
+    pub reason: String,
+}
+
+impl fmt::Display for PropertyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "property {:?}: {}", self.key, self.reason)
+    }
+}
+
+impl std::error::Error for PropertyError {}
+
+/// Maps IORegistry property keys to the values the hook returns for them.
+///
+/// The [`PLATFORM_KEYS`] are replaced on the IOPlatformExpertDevice entry only,
+/// `IOMACAddress` on network controllers, and any other key wherever it is read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IdentityTable {
+    entries: BTreeMap<String, PropertyValue>,
+}
+
+impl IdentityTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces the value for `key`, returning the previous one.
+    pub fn insert(
+        &mut self,
+        key: impl Into<String>,
+        value: PropertyValue,
+    ) -> Option<PropertyValue> {
+        self.entries.insert(key.into(), value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&PropertyValue> {
+        self.entries.get(key)
+    }
+
+    /// The value for `key` as text: a string, or the C string in NUL-terminated data
+    /// such as `model` and `board-id`.
+    pub fn text(&self, key: &str) -> Option<&str> {
+        match self.entries.get(key)? {
+            PropertyValue::String(s) => Some(s),
+            PropertyValue::Data(bytes) => std::str::from_utf8(bytes.strip_suffix(&[0])?).ok(),
+            PropertyValue::Number(_) => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &PropertyValue)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Builds a table from a TOML `[properties]` table, with values converted by
+    /// [`value_from_toml`].
+    pub fn from_toml(table: &toml::Table) -> Result<Self, PropertyError> {
+        let mut identity = Self::new();
+        for (key, value) in table {
+            identity.insert(key.clone(), value_from_toml(key, value)?);
+        }
+        Ok(identity)
+    }
+}
+
+/// Converts one TOML value for `key`.
+///
+/// Strings go through [`value_from_str`], so well-known keys get IOKit's encoding.
+/// Integers become numbers and arrays of bytes become data.
+pub fn value_from_toml(key: &str, value: &toml::Value) -> Result<PropertyValue, PropertyError> {
+    let error = |reason: &str| PropertyError {
+        key: key.to_string(),
+        reason: reason.to_string(),
+    };
+    match value {
+        toml::Value::String(s) => value_from_str(key, s),
+        toml::Value::Integer(n) => Ok(PropertyValue::Number(*n)),
+        toml::Value::Array(items) => Ok(PropertyValue::Data(
+            items
+                .iter()
+                .map(|item| {
+                    item.as_integer()
+                        .and_then(|n| u8::try_from(n).ok())
+                        .ok_or_else(|| error("data arrays must contain integers 0-255"))
+                })
+                .collect::<Result<_, _>>()?,
+        )),
+        _ => Err(error("expected a string, integer or array of bytes")),
+    }
+}
+
+/// Encodes `value` the way IOKit reports `key`.
+///
+/// `board-id`, `model` and `target-type` are NUL-terminated C strings in CFData,
+/// `IOMACAddress` is six raw bytes parsed from `aa:bb:cc:dd:ee:ff`, and everything
+/// else (including `IOPlatformSerialNumber`) is a CFString.
+pub fn value_from_str(key: &str, value: &str) -> Result<PropertyValue, PropertyError> {
+    match key {
+        BOARD_ID_KEY | MODEL_KEY | TARGET_TYPE_KEY => {
+            if value.contains('\0') {
+                return Err(PropertyError {
+                    key: key.to_string(),
+                    reason: "value must not contain NUL".to_string(),
+                });
+            }
+            let mut bytes = value.as_bytes().to_vec();
+            bytes.push(0);
+            Ok(PropertyValue::Data(bytes))
+        }
+        IO_MAC_ADDRESS_KEY => MacAddress::parse(value)
+            .map(|mac| PropertyValue::Data(mac.0.to_vec()))
+            .ok_or_else(|| PropertyError {
+                key: key.to_string(),
+                reason: format!("{:?} is not a MAC address like aa:bb:cc:dd:ee:ff", value),
+            }),
+        _ => Ok(PropertyValue::String(value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_and_lookup() {
+        let mut table = IdentityTable::new();
+        assert!(table.is_empty());
+        table.insert(
+            IO_PLATFORM_SERIAL_NUMBER_KEY,
+            PropertyValue::String("C02ABC".into()),
+        );
+        assert_eq!(
+            table.insert(
+                IO_PLATFORM_SERIAL_NUMBER_KEY,
+                PropertyValue::String("C02XYZ".into())
+            ),
+            Some(PropertyValue::String("C02ABC".into()))
+        );
+        assert_eq!(
+            table.get(IO_PLATFORM_SERIAL_NUMBER_KEY),
+            Some(&PropertyValue::String("C02XYZ".into()))
+        );
+        assert_eq!(table.get("board-id"), None);
+        assert_eq!(table.len(), 1);
+
+        table.insert(MODEL_KEY, value_from_str(MODEL_KEY, "Macmini8,1").unwrap());
+        table.insert("raw", PropertyValue::Data(vec![1, 2]));
+        table.insert("count", PropertyValue::Number(3));
+        assert_eq!(table.text(MODEL_KEY), Some("Macmini8,1"));
+        assert_eq!(table.text(IO_PLATFORM_SERIAL_NUMBER_KEY), Some("C02XYZ"));
+        assert_eq!(table.text("raw"), None);
+        assert_eq!(table.text("count"), None);
+    }
+
+    #[test]
+    fn well_known_keys_use_iokit_encoding() {
+        assert_eq!(
+            value_from_str(BOARD_ID_KEY, "Mac-1234").unwrap(),
+            PropertyValue::Data(b"Mac-1234\0".to_vec())
+        );
+        assert_eq!(
+            value_from_str(MODEL_KEY, "MacBookPro18,3").unwrap(),
+            PropertyValue::Data(b"MacBookPro18,3\0".to_vec())
+        );
+        assert_eq!(
+            value_from_str(IO_MAC_ADDRESS_KEY, "02:00:5E:10:00:0a").unwrap(),
+            PropertyValue::Data(vec![0x02, 0x00, 0x5e, 0x10, 0x00, 0x0a])
+        );
+        assert_eq!(
+            value_from_str(IO_PLATFORM_SERIAL_NUMBER_KEY, "C02ABC").unwrap(),
+            PropertyValue::String("C02ABC".into())
+        );
+    }
+
+    #[test]
+    fn platform_keys() {
+        assert!(is_platform_key(IO_PLATFORM_UUID_KEY));
+        assert!(is_platform_key(MODEL_KEY));
+        assert!(!is_platform_key(IO_MAC_ADDRESS_KEY));
+        assert!(!is_platform_key("Model"));
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        for mac in [
+            "02:00:5e:10:00",
+            "02:00:5e:10:00:0a:ff",
+            "0g:00:5e:10:00:0a",
+            "2:0:5e:10:00:0a",
+        ] {
+            assert!(value_from_str(IO_MAC_ADDRESS_KEY, mac).is_err(), "{}", mac);
+        }
+        assert!(value_from_str(MODEL_KEY, "a\0b").is_err());
+    }
+
+    #[test]
+    fn from_toml_table() {
+        let table: toml::Table = toml::from_str(
+            r#"
+            IOPlatformSerialNumber = "C02ABC"
+            board-id = "Mac-1234"
+            IOMACAddress = "02:00:00:00:00:01"
+            vendor-id = 4203
+            custom-blob = [1, 2, 255]
+            "#,
+        )
+        .unwrap();
+        let identity = IdentityTable::from_toml(&table).unwrap();
+        assert_eq!(identity.len(), 5);
+        assert_eq!(
+            identity.get("vendor-id"),
+            Some(&PropertyValue::Number(4203))
+        );
+        assert_eq!(
+            identity.get("custom-blob"),
+            Some(&PropertyValue::Data(vec![1, 2, 255]))
+        );
+        assert_eq!(
+            identity.get(BOARD_ID_KEY),
+            Some(&PropertyValue::Data(b"Mac-1234\0".to_vec()))
+        );
+
+        let bad: toml::Table = toml::from_str("custom-blob = [1, 256]").unwrap();
+        assert_eq!(
+            IdentityTable::from_toml(&bad).unwrap_err().key,
+            "custom-blob"
+        );
+        let bad: toml::Table = toml::from_str("flag = true").unwrap();
+        assert!(IdentityTable::from_toml(&bad).is_err());
+    }
+}
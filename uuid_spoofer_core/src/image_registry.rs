@@ -0,0 +1,220 @@
+//! Bookkeeping for the rebinding engine: which loaded images have had their imports
+//! patched, what each patched slot held before, and the first original implementation
+//! found for each hooked function.
+//!
+//! The loader reports images as they come and go (and reports the already-loaded ones
+//! again when the callback is registered); this keeps patching each image exactly once
+//! and forgets images when they are unloaded, so a later image at the same address is
+//! patched again. Addresses are plain integers so it can be driven by fake events.
+
+/// One overwritten pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchedSlot {
+    /// Index of the hooked function, as given to `ImageRegistry::new`.
+    pub rebinding: usize,
+    pub address: u64,
+    /// What the slot held, or `None` if it already pointed at the replacement.
+    pub original: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchedImage {
+    /// Address of the image's Mach-O header (or ELF base), which identifies it.
+    pub header: u64,
+    pub path: String,
+    pub slots: Vec<PatchedSlot>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageRegistry {
+    replacements: Vec<u64>,
+    originals: Vec<Option<u64>>,
+    images: Vec<PatchedImage>,
+}
+
+impl ImageRegistry {
+    /// `replacements[i]` is the hook installed for function `i`.
+    pub fn new(replacements: Vec<u64>) -> Self {
+        ImageRegistry {
+            originals: vec![None; replacements.len()],
+            replacements,
+            images: Vec::new(),
+        }
+    }
+
+    /// Starts tracking a newly reported image. Returns `false` if it is already tracked,
+    /// in which case it must not be patched again.
+    pub fn add_image(&mut self, header: u64, path: &str) -> bool {
+        if self.image(header).is_some() {
+            return false;
+        }
+        self.images.push(PatchedImage {
+            header,
+            path: path.to_string(),
+            slots: Vec::new(),
+        });
+        true
+    }
+
+    /// Records that the slot at `address` in image `header` held `current` before being
+    /// pointed at the replacement for function `rebinding`. Returns `current` if it is
+    /// the first original found for that function, which the caller should publish
+    /// before overwriting the slot.
+    pub fn record_slot(
+        &mut self,
+        header: u64,
+        rebinding: usize,
+        address: u64,
+        current: u64,
+    ) -> Option<u64> {
+        let replacement = *self.replacements.get(rebinding)?;
+        // A null slot is an unresolved weak import, not an implementation.
+        let original = (current != replacement && current != 0).then_some(current);
+        let image = self
+            .images
+            .iter_mut()
+            .find(|image| image.header == header)?;
+        image.slots.push(PatchedSlot {
+            rebinding,
+            address,
+            original,
+        });
+        let known = &mut self.originals[rebinding];
+        if known.is_some() || original.is_none() {
+            return None;
+        }
+        *known = original;
+        original
+    }
+
+    /// Forgets an unloaded image, returning what was patched in it.
+    pub fn remove_image(&mut self, header: u64) -> Option<PatchedImage> {
+        let index = self
+            .images
+            .iter()
+            .position(|image| image.header == header)?;
+        Some(self.images.remove(index))
+    }
+
+    pub fn image(&self, header: u64) -> Option<&PatchedImage> {
+        self.images.iter().find(|image| image.header == header)
+    }
+
+    /// Tracked images, in the order they were reported.
+    pub fn images(&self) -> &[PatchedImage] {
+        &self.images
+    }
+
+    /// The first original implementation found for function `rebinding`.
+    pub fn original(&self, rebinding: usize) -> Option<u64> {
+        self.originals.get(rebinding).copied().flatten()
+    }
+
+    pub fn patched_slots(&self) -> usize {
+        self.images.iter().map(|image| image.slots.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GETHOSTUUID_HOOK: u64 = 0x7000;
+    const SYSCTLBYNAME_HOOK: u64 = 0x7100;
+
+    fn registry() -> ImageRegistry {
+        ImageRegistry::new(vec![GETHOSTUUID_HOOK, SYSCTLBYNAME_HOOK])
+    }
+
+    #[test]
+    fn patches_each_image_once() {
+        let mut registry = registry();
+        assert!(registry.add_image(0x1000, "/usr/bin/app"));
+        assert_eq!(
+            registry.record_slot(0x1000, 0, 0x1100, 0xa000),
+            Some(0xa000)
+        );
+        assert_eq!(
+            registry.record_slot(0x1000, 1, 0x1108, 0xb000),
+            Some(0xb000)
+        );
+        // Registering the callback reports the loaded images again.
+        assert!(!registry.add_image(0x1000, "/usr/bin/app"));
+        assert!(registry.add_image(0x2000, "/Library/Plugin.bundle/Plugin"));
+        assert_eq!(registry.record_slot(0x2000, 0, 0x2100, 0xa000), None);
+
+        assert_eq!(registry.patched_slots(), 3);
+        assert_eq!(
+            registry
+                .images()
+                .iter()
+                .map(|image| image.path.as_str())
+                .collect::<Vec<_>>(),
+            ["/usr/bin/app", "/Library/Plugin.bundle/Plugin"]
+        );
+        assert_eq!(
+            registry.image(0x2000).unwrap().slots,
+            [PatchedSlot {
+                rebinding: 0,
+                address: 0x2100,
+                original: Some(0xa000),
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_the_first_original_per_function() {
+        let mut registry = registry();
+        registry.add_image(0x1000, "/usr/lib/a.dylib");
+        // An unresolved weak import and an already patched slot are not originals.
+        assert_eq!(registry.record_slot(0x1000, 0, 0x1100, 0), None);
+        assert_eq!(
+            registry.record_slot(0x1000, 0, 0x1108, GETHOSTUUID_HOOK),
+            None
+        );
+        assert_eq!(registry.original(0), None);
+        assert_eq!(
+            registry.record_slot(0x1000, 0, 0x1110, 0xa000),
+            Some(0xa000)
+        );
+        // An image bound to an interposed implementation keeps its own original.
+        registry.add_image(0x2000, "/usr/lib/b.dylib");
+        assert_eq!(registry.record_slot(0x2000, 0, 0x2100, 0xc000), None);
+        assert_eq!(registry.original(0), Some(0xa000));
+        assert_eq!(registry.original(1), None);
+        assert_eq!(registry.original(7), None);
+        assert_eq!(
+            registry.image(0x2000).unwrap().slots[0].original,
+            Some(0xc000)
+        );
+        assert_eq!(registry.image(0x1000).unwrap().slots[1].original, None);
+    }
+
+    #[test]
+    fn forgets_unloaded_images() {
+        let mut registry = registry();
+        registry.add_image(0x1000, "/usr/lib/a.dylib");
+        registry.record_slot(0x1000, 1, 0x1100, 0xb000);
+        registry.add_image(0x5000, "/tmp/plugin.dylib");
+        registry.record_slot(0x5000, 1, 0x5100, 0xb000);
+
+        let removed = registry.remove_image(0x5000).unwrap();
+        assert_eq!(removed.path, "/tmp/plugin.dylib");
+        assert_eq!(removed.slots.len(), 1);
+        assert_eq!(registry.remove_image(0x5000), None);
+        assert_eq!(registry.patched_slots(), 1);
+        // Whatever is loaded at the same address next is patched afresh.
+        assert!(registry.add_image(0x5000, "/tmp/other.dylib"));
+        assert_eq!(registry.original(1), Some(0xb000));
+    }
+
+    #[test]
+    fn ignores_slots_of_unknown_images_and_functions() {
+        let mut registry = registry();
+        assert_eq!(registry.record_slot(0x1000, 0, 0x1100, 0xa000), None);
+        registry.add_image(0x1000, "/usr/lib/a.dylib");
+        assert_eq!(registry.record_slot(0x1000, 9, 0x1100, 0xa000), None);
+        assert_eq!(registry.patched_slots(), 0);
+        assert_eq!(registry.original(0), None);
+    }
+}
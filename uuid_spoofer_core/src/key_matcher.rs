@@ -0,0 +1,241 @@
+//! Decides which spoofed property an IORegistry key asks for, without copying the key.
+//!
+//! The key objects for every property in the identity table are created once, with their
+//! hashes. A lookup first compares the caller's key by identity (callers mostly pass
+//! `CFSTR` constants, which are the same object call after call once seen), then by hash,
+//! and only compares contents when the hashes agree. Nothing is copied or converted, so a
+//! key of any length works and a key that is not a string simply matches nothing.
+//!
+//! The hook supplies CoreFoundation through [`KeyObjects`]; tests and the benchmark use
+//! a mock.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The operations matching needs on the host's key objects.
+pub trait KeyObjects {
+    /// A reference to a key object: its address, as far as the matcher is concerned, with
+    /// 0 for none.
+    type Key: Copy + Eq + Into<usize>;
+
+    /// A new key object holding `name`, kept for the life of the matcher.
+    fn create(&self, name: &str) -> Option<Self::Key>;
+
+    /// Whether `key` is a string at all; only strings are compared further.
+    fn is_string(&self, key: Self::Key) -> bool;
+
+    /// The content hash, equal for equal strings (`CFHash`).
+    fn hash(&self, key: Self::Key) -> u64;
+
+    /// Content equality (`CFEqual`).
+    fn equal(&self, a: Self::Key, b: Self::Key) -> bool;
+}
+
+struct Entry<K> {
+    name: String,
+    key: K,
+    hash: u64,
+    // The last caller key object found equal to `key`, or 0. Only compared by identity,
+    // never dereferenced, so a stale address costs a content comparison at worst.
+    seen: AtomicUsize,
+}
+
+/// Matches caller keys against a fixed set of property names.
+pub struct KeyMatcher<K> {
+    entries: Vec<Entry<K>>,
+}
+
+impl<K: Copy + Eq + Into<usize>> KeyMatcher<K> {
+    /// Creates the key objects for `names`. Names whose object cannot be created are left
+    /// out and never match.
+    pub fn new<'a, O>(objects: &O, names: impl IntoIterator<Item = &'a str>) -> Self
+    where
+        O: KeyObjects<Key = K>,
+    {
+        let entries = names
+            .into_iter()
+            .filter_map(|name| {
+                let key = objects.create(name)?;
+                Some(Entry {
+                    name: name.to_string(),
+                    key,
+                    hash: objects.hash(key),
+                    seen: AtomicUsize::new(0),
+                })
+            })
+            .collect();
+        KeyMatcher { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The name of the property `key` asks for, or `None` if it is not one of them.
+    pub fn find<O>(&self, objects: &O, key: K) -> Option<&str>
+    where
+        O: KeyObjects<Key = K>,
+    {
+        let address: usize = key.into();
+        if address == 0 {
+            return None;
+        }
+        if let Some(entry) = self
+            .entries
+            .iter()
+            .find(|entry| entry.key == key || entry.seen.load(Ordering::Relaxed) == address)
+        {
+            // A stale `seen` may now be a different object at the same address.
+            if entry.key == key || objects.equal(entry.key, key) {
+                return Some(&entry.name);
+            }
+        }
+        if !objects.is_string(key) {
+            return None;
+        }
+        let hash = objects.hash(key);
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.hash == hash && objects.equal(entry.key, key))?;
+        entry.seen.store(address, Ordering::Relaxed);
+        Some(&entry.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+
+    // Key objects are indices into a list of strings (or non-strings), plus one so that
+    // 0 is never a key. Counts calls to see which path a lookup took.
+    #[derive(Default)]
+    struct MockObjects {
+        objects: RefCell<Vec<Option<String>>>,
+        hashes: Cell<usize>,
+        comparisons: Cell<usize>,
+    }
+
+    impl MockObjects {
+        fn string(&self, text: &str) -> usize {
+            self.objects.borrow_mut().push(Some(text.to_string()));
+            self.objects.borrow().len()
+        }
+
+        fn number(&self) -> usize {
+            self.objects.borrow_mut().push(None);
+            self.objects.borrow().len()
+        }
+
+        fn text(&self, key: usize) -> Option<String> {
+            self.objects.borrow()[key - 1].clone()
+        }
+
+        fn counts(&self) -> (usize, usize) {
+            (self.hashes.take(), self.comparisons.take())
+        }
+    }
+
+    impl KeyObjects for MockObjects {
+        type Key = usize;
+
+        fn create(&self, name: &str) -> Option<usize> {
+            (!name.is_empty()).then(|| self.string(name))
+        }
+
+        fn is_string(&self, key: usize) -> bool {
+            self.text(key).is_some()
+        }
+
+        fn hash(&self, key: usize) -> u64 {
+            self.hashes.set(self.hashes.get() + 1);
+            // Collides for strings of equal length, to exercise the equality check.
+            self.text(key).unwrap().len() as u64
+        }
+
+        fn equal(&self, a: usize, b: usize) -> bool {
+            self.comparisons.set(self.comparisons.get() + 1);
+            self.text(a).is_some() && self.text(a) == self.text(b)
+        }
+    }
+
+    const NAMES: [&str; 3] = ["IOPlatformUUID", "board-id", "model"];
+
+    #[test]
+    fn matches_by_content() {
+        let objects = MockObjects::default();
+        let matcher = KeyMatcher::new(&objects, NAMES);
+        assert_eq!(matcher.len(), 3);
+        let key = objects.string("board-id");
+        assert_eq!(matcher.find(&objects, key), Some("board-id"));
+        let other = objects.string("IOPlatformSerialNumber");
+        assert_eq!(matcher.find(&objects, other), None);
+        // Same length as "board-id", so the hashes collide; the contents decide.
+        let collision = objects.string("board-ix");
+        assert_eq!(matcher.find(&objects, collision), None);
+    }
+
+    #[test]
+    fn known_objects_skip_hashing() {
+        let objects = MockObjects::default();
+        let matcher = KeyMatcher::new(&objects, NAMES);
+        objects.counts();
+        // The matcher's own key objects match by identity.
+        assert_eq!(matcher.find(&objects, 1), Some("IOPlatformUUID"));
+        assert_eq!(objects.counts(), (0, 0));
+
+        let key = objects.string("model");
+        assert_eq!(matcher.find(&objects, key), Some("model"));
+        assert_eq!(objects.counts(), (1, 1));
+        // Seen before: one confirming comparison, no hash.
+        for _ in 0..3 {
+            assert_eq!(matcher.find(&objects, key), Some("model"));
+        }
+        assert_eq!(objects.counts(), (0, 3));
+    }
+
+    #[test]
+    fn a_reused_address_is_compared_again() {
+        let objects = MockObjects::default();
+        let matcher = KeyMatcher::new(&objects, NAMES);
+        let key = objects.string("model");
+        assert_eq!(matcher.find(&objects, key), Some("model"));
+        // The caller's key was freed and something else now lives at its address.
+        objects.objects.borrow_mut()[key - 1] = Some("mode1".to_string());
+        assert_eq!(matcher.find(&objects, key), None);
+    }
+
+    #[test]
+    fn long_keys_match() {
+        let long = "k".repeat(4096);
+        let objects = MockObjects::default();
+        let matcher = KeyMatcher::new(&objects, [long.as_str(), "model"]);
+        let key = objects.string(&long);
+        assert_eq!(matcher.find(&objects, key), Some(long.as_str()));
+        let longer = objects.string(&format!("{}k", long));
+        assert_eq!(matcher.find(&objects, longer), None);
+    }
+
+    #[test]
+    fn non_string_keys_match_nothing() {
+        let objects = MockObjects::default();
+        let matcher = KeyMatcher::new(&objects, NAMES);
+        let number = objects.number();
+        assert_eq!(matcher.find(&objects, number), None);
+        // Rejected before hashing, which CFHash would do differently for a non-string.
+        assert_eq!(objects.counts().0, 3);
+    }
+
+    #[test]
+    fn uncreatable_names_are_left_out() {
+        let objects = MockObjects::default();
+        let matcher = KeyMatcher::new(&objects, ["", "model"]);
+        assert_eq!(matcher.len(), 1);
+        assert!(KeyMatcher::new(&objects, []).is_empty());
+        assert_eq!(matcher.find(&objects, 0), None);
+    }
+}
@@ -0,0 +1,31 @@
+//! Platform-neutral parts of the UUID spoofer: configuration, UUID derivation and
+//! formatting, the identity property table and binary parsing. Shared by the injected
+//! library and the `uuid_spoof`/`uuid_reader` tools, and tested on any host.
+
+pub mod catalog;
+pub mod chained_fixups;
+pub mod config;
+pub mod derive;
+pub mod elf;
+pub mod generate;
+pub mod glob;
+pub mod hook_guard;
+pub mod hook_state;
+pub mod id_files;
+pub mod identity;
+pub mod image_registry;
+pub mod key_matcher;
+pub mod load_dylib;
+pub mod logging;
+pub mod mac;
+pub mod macho;
+pub mod profile_store;
+pub mod profiles;
+pub mod rebind;
+pub mod scan;
+pub mod serial;
+pub mod substitute;
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
+pub mod uuid;
+pub mod value_cache;
@@ -0,0 +1,527 @@
+//! Adds or removes an `LC_LOAD_DYLIB` for the spoofer library in a Mach-O file, so the
+//! target loads it at launch without `DYLD_INSERT_LIBRARIES` (what `insert_dylib` does).
+//!
+//! The new command goes into the zero padding between the load commands and the first
+//! section; the file never grows, so nothing after the header moves. Changing the load
+//! commands invalidates the code signature, so `LC_CODE_SIGNATURE` is removed and the
+//! signature itself cut off the end of `__LINKEDIT`. Fat files are patched slice by
+//! slice, each staying at its offset.
+
+use std::fmt;
+
+use crate::macho::{
+    self, bytes_at, u32_at, MachO, MachOError, FAT_MAGIC, FAT_MAGIC_64, LC_CODE_SIGNATURE,
+};
+use crate::rebind::{LC_SEGMENT, LC_SEGMENT_64, LINKEDIT};
+
+pub const LC_REQ_DYLD: u32 = 0x8000_0000;
+pub const LC_LOAD_DYLIB: u32 = 0xc;
+pub const LC_LOAD_WEAK_DYLIB: u32 = 0x18 | LC_REQ_DYLD;
+
+// sizeof(struct dylib_command): cmd, cmdsize, name offset, timestamp, current and
+// compatibility versions. The name follows.
+const DYLIB_COMMAND_SIZE: usize = 24;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    MachO(MachOError),
+    AlreadyLoaded(String),
+    NotLoaded(String),
+    /// The padding after the load commands is too small for the new one.
+    NoRoom {
+        cputype: i32,
+        needed: usize,
+        available: usize,
+    },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::MachO(e) => e.fmt(f),
+            PatchError::AlreadyLoaded(path) => write!(f, "{} is already loaded", path),
+            PatchError::NotLoaded(path) => write!(f, "{} is not loaded", path),
+            PatchError::NoRoom {
+                cputype,
+                needed,
+                available,
+            } => write!(
+                f,
+                "no room for the load command in the {} slice: it needs {} bytes but only {} \
+                 are free after the load commands (use a shorter library path, or relink the \
+                 target with -headerpad_max_install_names)",
+                macho::cpu_name(*cputype),
+                needed,
+                available
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+impl From<MachOError> for PatchError {
+    fn from(e: MachOError) -> Self {
+        PatchError::MachO(e)
+    }
+}
+
+/// What patching did to one architecture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlicePatch {
+    pub cputype: i32,
+    pub stripped_signature: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patched {
+    pub data: Vec<u8>,
+    pub slices: Vec<SlicePatch>,
+}
+
+/// A loaded library: its load command type and install name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dylib {
+    pub cmd: u32,
+    /// Offset of the load command within the slice.
+    pub offset: usize,
+    pub name: String,
+}
+
+fn put_u32(data: &mut [u8], offset: usize, value: u32, big_endian: bool) {
+    let bytes = if big_endian {
+        value.to_be_bytes()
+    } else {
+        value.to_le_bytes()
+    };
+    data[offset..offset + 4].copy_from_slice(&bytes);
+}
+
+fn put_u64(data: &mut [u8], offset: usize, value: u64, big_endian: bool) {
+    let bytes = if big_endian {
+        value.to_be_bytes()
+    } else {
+        value.to_le_bytes()
+    };
+    data[offset..offset + 8].copy_from_slice(&bytes);
+}
+
+/// The libraries an image loads, in load command order.
+pub fn dylibs(macho: &MachO<'_>) -> Result<Vec<Dylib>, MachOError> {
+    let mut dylibs = Vec::new();
+    for command in macho.load_commands()? {
+        if command.cmd != LC_LOAD_DYLIB && command.cmd != LC_LOAD_WEAK_DYLIB {
+            continue;
+        }
+        let name_offset = macho.u32_at(command.offset + 8)? as usize;
+        let name = command
+            .data
+            .get(name_offset..)
+            .ok_or(MachOError::Malformed("dylib name offset"))?;
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        dylibs.push(Dylib {
+            cmd: command.cmd,
+            offset: command.offset,
+            name: String::from_utf8_lossy(&name[..end]).into_owned(),
+        });
+    }
+    Ok(dylibs)
+}
+
+// Where the first section's (or segment's) contents start: the end of the room available
+// to load commands.
+fn first_content_offset(macho: &MachO<'_>) -> Result<usize, MachOError> {
+    let (word, section_size) = if macho.is_64 { (8, 80) } else { (4, 68) };
+    let mut first = macho.data().len();
+    for command in macho.load_commands()? {
+        if command.cmd != LC_SEGMENT_64 && command.cmd != LC_SEGMENT {
+            continue;
+        }
+        let at = command.offset;
+        let (fileoff, filesize) = if macho.is_64 {
+            (macho.u64_at(at + 40)?, macho.u64_at(at + 48)?)
+        } else {
+            (macho.u32_at(at + 32)? as u64, macho.u32_at(at + 36)? as u64)
+        };
+        // __TEXT maps the header itself, at file offset 0.
+        if fileoff > 0 && filesize > 0 {
+            first = first.min(fileoff as usize);
+        }
+        let nsects = macho.u32_at(at + 24 + 4 * word + 8)? as usize;
+        let sections = at + 24 + 4 * word + 16;
+        for i in 0..nsects {
+            // sectname[16], segname[16], addr, size, offset...
+            let offset = macho.u32_at(sections + i * section_size + 32 + 2 * word)? as usize;
+            // Zero-fill sections have no file contents.
+            if offset > 0 {
+                first = first.min(offset);
+            }
+        }
+    }
+    Ok(first)
+}
+
+// Removes the load command at `offset`, moving the later ones up and zeroing the freed
+// bytes at the end.
+fn remove_command(data: &mut [u8], offset: usize) -> Result<(), MachOError> {
+    let macho = MachO::parse(data)?;
+    let size = macho.u32_at(offset + 4)?;
+    let end = macho.header_size() + macho.sizeofcmds as usize;
+    let (ncmds, sizeofcmds, big_endian) = (macho.ncmds, macho.sizeofcmds, macho.big_endian);
+    let size_bytes = size as usize;
+    data.copy_within(offset + size_bytes..end, offset);
+    data[end - size_bytes..end].fill(0);
+    put_u32(data, 16, ncmds - 1, big_endian);
+    put_u32(data, 20, sizeofcmds - size, big_endian);
+    Ok(())
+}
+
+// Appends `command` to the load commands, in the padding after them.
+fn insert_command(data: &mut [u8], command: &[u8]) -> Result<(), PatchError> {
+    let macho = MachO::parse(data)?;
+    let end = macho.header_size() + macho.sizeofcmds as usize;
+    let limit = first_content_offset(&macho)?.max(end);
+    let (ncmds, sizeofcmds, big_endian) = (macho.ncmds, macho.sizeofcmds, macho.big_endian);
+    // Anything non-zero there belongs to someone else.
+    let available = data[end..limit]
+        .iter()
+        .position(|&b| b != 0)
+        .unwrap_or(limit - end);
+    if command.len() > available {
+        return Err(PatchError::NoRoom {
+            cputype: macho.cputype,
+            needed: command.len(),
+            available,
+        });
+    }
+    data[end..end + command.len()].copy_from_slice(command);
+    put_u32(data, 16, ncmds + 1, big_endian);
+    put_u32(data, 20, sizeofcmds + command.len() as u32, big_endian);
+    Ok(())
+}
+
+// Removes LC_CODE_SIGNATURE and the signature it points to. Returns whether there was one.
+fn strip_code_signature(data: &mut Vec<u8>) -> Result<bool, MachOError> {
+    let macho = MachO::parse(data)?;
+    let commands = macho.load_commands()?;
+    let Some(signature) = commands.iter().find(|c| c.cmd == LC_CODE_SIGNATURE) else {
+        return Ok(false);
+    };
+    let command_offset = signature.offset;
+    let dataoff = macho.u32_at(command_offset + 8)? as u64;
+    let datasize = macho.u32_at(command_offset + 12)? as u64;
+    let signature_end = dataoff + datasize;
+    // The signature is the last thing in __LINKEDIT, which ends the file.
+    let mut linkedit_filesize = None;
+    for command in &commands {
+        if (command.cmd == LC_SEGMENT_64 || command.cmd == LC_SEGMENT)
+            && bytes_at(command.data, 8, LINKEDIT.len())? == LINKEDIT.as_bytes()
+        {
+            let at = command.offset;
+            let (fileoff, filesize) = if macho.is_64 {
+                (macho.u64_at(at + 40)?, macho.u64_at(at + 48)?)
+            } else {
+                (macho.u32_at(at + 32)? as u64, macho.u32_at(at + 36)? as u64)
+            };
+            if fileoff + filesize == signature_end && dataoff >= fileoff {
+                linkedit_filesize = Some((at, dataoff - fileoff));
+            }
+        }
+    }
+    let (is_64, big_endian) = (macho.is_64, macho.big_endian);
+    bytes_at(data, dataoff as usize, datasize as usize)?;
+    remove_command(data, command_offset)?;
+    if let Some((at, filesize)) = linkedit_filesize {
+        if is_64 {
+            put_u64(data, at + 48, filesize, big_endian);
+        } else {
+            put_u32(data, at + 36, filesize as u32, big_endian);
+        }
+    }
+    if signature_end as usize == data.len() {
+        data.truncate(dataoff as usize);
+    } else {
+        data[dataoff as usize..signature_end as usize].fill(0);
+    }
+    Ok(true)
+}
+
+fn dylib_command(cmd: u32, path: &str, is_64: bool, big_endian: bool) -> Vec<u8> {
+    let align = if is_64 { 8 } else { 4 };
+    let size = (DYLIB_COMMAND_SIZE + path.len() + 1).div_ceil(align) * align;
+    let mut command = vec![0u8; size];
+    put_u32(&mut command, 0, cmd, big_endian);
+    put_u32(&mut command, 4, size as u32, big_endian);
+    put_u32(&mut command, 8, DYLIB_COMMAND_SIZE as u32, big_endian);
+    // Timestamp 2, as ld writes; versions 0.0.0, which any library satisfies.
+    put_u32(&mut command, 12, 2, big_endian);
+    command[DYLIB_COMMAND_SIZE..DYLIB_COMMAND_SIZE + path.len()].copy_from_slice(path.as_bytes());
+    command
+}
+
+// Applies `edit` to every slice of a thin or fat file, after stripping its signature.
+// Slices never grow, so each keeps its offset; the fat header gets the new sizes.
+fn patch_slices(
+    data: &[u8],
+    edit: impl Fn(&mut Vec<u8>) -> Result<(), PatchError>,
+) -> Result<Patched, PatchError> {
+    let magic = u32_at(data, 0, true)?;
+    let slices = macho::slices(data)?;
+    let mut out = data.to_vec();
+    let mut patches = Vec::with_capacity(slices.len());
+    let mut old_end = 0;
+    let mut new_end = 0;
+    for (index, slice) in slices.iter().enumerate() {
+        let mut image = slice.data.to_vec();
+        let stripped_signature = strip_code_signature(&mut image)?;
+        edit(&mut image)?;
+        patches.push(SlicePatch {
+            cputype: slice.cputype,
+            stripped_signature,
+        });
+        if magic != FAT_MAGIC && magic != FAT_MAGIC_64 {
+            return Ok(Patched {
+                data: image,
+                slices: patches,
+            });
+        }
+        let at = slice.offset;
+        out[at..at + image.len()].copy_from_slice(&image);
+        out[at + image.len()..at + slice.data.len()].fill(0);
+        // fat_arch(_64): cputype, cpusubtype, offset, size, align (all big-endian).
+        if magic == FAT_MAGIC_64 {
+            put_u64(&mut out, 8 + index * 32 + 16, image.len() as u64, true);
+        } else {
+            put_u32(&mut out, 8 + index * 20 + 12, image.len() as u32, true);
+        }
+        old_end = old_end.max(at + slice.data.len());
+        new_end = new_end.max(at + image.len());
+    }
+    // Only cut the file short if nothing follows the last slice.
+    if old_end == data.len() {
+        out.truncate(new_end);
+    }
+    Ok(Patched {
+        data: out,
+        slices: patches,
+    })
+}
+
+/// Adds a load command for the library at `path` (an install name such as
+/// `@executable_path/../Frameworks/libuuid_spoofer.dylib`) to every slice. A weak one lets
+/// the target launch without the library.
+pub fn add_dylib(data: &[u8], path: &str, weak: bool) -> Result<Patched, PatchError> {
+    let cmd = if weak {
+        LC_LOAD_WEAK_DYLIB
+    } else {
+        LC_LOAD_DYLIB
+    };
+    patch_slices(data, |image| {
+        let macho = MachO::parse(image)?;
+        if dylibs(&macho)?.iter().any(|dylib| dylib.name == path) {
+            return Err(PatchError::AlreadyLoaded(path.to_string()));
+        }
+        let command = dylib_command(cmd, path, macho.is_64, macho.big_endian);
+        insert_command(image, &command)
+    })
+}
+
+/// Removes every load command for the library at `path` from every slice.
+pub fn remove_dylib(data: &[u8], path: &str) -> Result<Patched, PatchError> {
+    patch_slices(data, |image| {
+        let mut removed = false;
+        // Each removal moves the later commands, so look again after it.
+        while let Some(dylib) = dylibs(&MachO::parse(image)?)?
+            .into_iter()
+            .find(|dylib| dylib.name == path)
+        {
+            remove_command(image, dylib.offset)?;
+            removed = true;
+        }
+        if removed {
+            Ok(())
+        } else {
+            Err(PatchError::NotLoaded(path.to_string()))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::{CPU_TYPE_ARM64, CPU_TYPE_X86_64, CS_RUNTIME};
+    use crate::scan;
+    use crate::testing::{fat, superblob};
+
+    // See the .yaml files next to them for how they are laid out. The dylib's load
+    // commands end at 0x368 and its __text starts at 0xff0.
+    const X86_64_DYLIB: &[u8] = include_bytes!("../tests/fixtures/x86_64_dylib");
+    const ARM64_EXECUTABLE: &[u8] = include_bytes!("../tests/fixtures/arm64_executable");
+
+    const LIBRARY: &str = "@executable_path/../Frameworks/libuuid_spoofer.dylib";
+
+    fn names(data: &[u8]) -> Vec<(u32, String)> {
+        dylibs(&MachO::parse(data).unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|dylib| (dylib.cmd, dylib.name))
+            .collect()
+    }
+
+    // The fixture with a code signature appended to __LINKEDIT, as codesign lays it out.
+    fn signed(image: &[u8]) -> Vec<u8> {
+        let mut data = image.to_vec();
+        let blob = superblob(CS_RUNTIME, None);
+        let mut command = Vec::new();
+        for field in [LC_CODE_SIGNATURE, 16, data.len() as u32, blob.len() as u32] {
+            command.extend_from_slice(&field.to_le_bytes());
+        }
+        insert_command(&mut data, &command).unwrap();
+        let macho = MachO::parse(&data).unwrap();
+        let linkedit = macho
+            .load_commands()
+            .unwrap()
+            .into_iter()
+            .find(|c| {
+                c.cmd == LC_SEGMENT_64 && c.data[8..8 + LINKEDIT.len()] == *LINKEDIT.as_bytes()
+            })
+            .unwrap()
+            .offset;
+        let filesize = macho.u64_at(linkedit + 48).unwrap() + blob.len() as u64;
+        put_u64(&mut data, linkedit + 48, filesize, false);
+        data.extend_from_slice(&blob);
+        data
+    }
+
+    #[test]
+    fn adds_a_load_command_in_the_padding() {
+        let patched = add_dylib(X86_64_DYLIB, LIBRARY, false).unwrap();
+        assert_eq!(
+            patched.slices,
+            [SlicePatch {
+                cputype: CPU_TYPE_X86_64,
+                stripped_signature: false,
+            }]
+        );
+        let data = &patched.data;
+        assert_eq!(data.len(), X86_64_DYLIB.len());
+        // Only the header and the padding change.
+        assert_eq!(data[0xff0..], X86_64_DYLIB[0xff0..]);
+        let macho = MachO::parse(data).unwrap();
+        assert_eq!(macho.ncmds, 10);
+        // 24 + 53 + NUL, rounded up to 8.
+        assert_eq!(macho.sizeofcmds, 840 + 80);
+        assert_eq!(
+            names(data).last(),
+            Some(&(LC_LOAD_DYLIB, LIBRARY.to_string()))
+        );
+        // Still a valid image for everything else that reads it.
+        assert_eq!(scan::scan(data).unwrap()[0].identity_imports.len(), 4);
+
+        let weak = add_dylib(X86_64_DYLIB, LIBRARY, true).unwrap();
+        assert_eq!(names(&weak.data).last().unwrap().0, LC_LOAD_WEAK_DYLIB);
+        assert_eq!(
+            add_dylib(data, LIBRARY, true),
+            Err(PatchError::AlreadyLoaded(LIBRARY.to_string()))
+        );
+    }
+
+    #[test]
+    fn removing_undoes_adding() {
+        let added = add_dylib(X86_64_DYLIB, LIBRARY, false).unwrap();
+        let removed = remove_dylib(&added.data, LIBRARY).unwrap();
+        assert_eq!(removed.data, X86_64_DYLIB);
+        assert_eq!(
+            remove_dylib(X86_64_DYLIB, LIBRARY),
+            Err(PatchError::NotLoaded(LIBRARY.to_string()))
+        );
+        // Commands after the removed one move up.
+        let iokit = "/System/Library/Frameworks/IOKit.framework/Versions/A/IOKit";
+        let without = remove_dylib(&added.data, iokit).unwrap();
+        assert_eq!(
+            names(&without.data),
+            [
+                (LC_LOAD_DYLIB, "/usr/lib/libSystem.B.dylib".to_string()),
+                (LC_LOAD_DYLIB, LIBRARY.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_exhausted_padding() {
+        let long = format!("/{}", "x".repeat(3300));
+        assert_eq!(
+            add_dylib(X86_64_DYLIB, &long, false),
+            Err(PatchError::NoRoom {
+                cputype: CPU_TYPE_X86_64,
+                needed: 3328,
+                available: 0xff0 - 0x368,
+            })
+        );
+        // Padding that is not zero is not free.
+        let mut data = X86_64_DYLIB.to_vec();
+        data[0x388] = 1;
+        let error = add_dylib(&data, LIBRARY, false).unwrap_err();
+        assert_eq!(
+            error,
+            PatchError::NoRoom {
+                cputype: CPU_TYPE_X86_64,
+                needed: 80,
+                available: 0x20,
+            }
+        );
+        assert!(error.to_string().contains("-headerpad_max_install_names"));
+    }
+
+    #[test]
+    fn strips_the_code_signature() {
+        let data = signed(X86_64_DYLIB);
+        assert!(MachO::parse(&data)
+            .unwrap()
+            .code_signature()
+            .unwrap()
+            .is_some());
+        let patched = add_dylib(&data, LIBRARY, false).unwrap();
+        assert!(patched.slices[0].stripped_signature);
+        // The signature and its command are gone, and __LINKEDIT ends where it did.
+        assert_eq!(
+            patched.data,
+            add_dylib(X86_64_DYLIB, LIBRARY, false).unwrap().data
+        );
+        assert!(!remove_dylib(&patched.data, LIBRARY).unwrap().slices[0].stripped_signature);
+    }
+
+    #[test]
+    fn patches_every_fat_slice() {
+        let file = fat(&[
+            (CPU_TYPE_X86_64, &signed(X86_64_DYLIB)),
+            (CPU_TYPE_ARM64, ARM64_EXECUTABLE),
+        ]);
+        let patched = add_dylib(&file, LIBRARY, false).unwrap();
+        assert_eq!(
+            patched
+                .slices
+                .iter()
+                .map(|slice| slice.stripped_signature)
+                .collect::<Vec<_>>(),
+            [true, false]
+        );
+        let slices = macho::slices(&patched.data).unwrap();
+        assert_eq!(slices[0].data.len(), X86_64_DYLIB.len());
+        assert_eq!(slices[1].offset, macho::slices(&file).unwrap()[1].offset);
+        for slice in &slices {
+            assert_eq!(
+                names(slice.data).last(),
+                Some(&(LC_LOAD_DYLIB, LIBRARY.to_string()))
+            );
+        }
+        let restored = remove_dylib(&patched.data, LIBRARY).unwrap();
+        assert_eq!(
+            restored.data,
+            fat(&[
+                (CPU_TYPE_X86_64, X86_64_DYLIB),
+                (CPU_TYPE_ARM64, ARM64_EXECUTABLE),
+            ])
+        );
+    }
+}
@@ -0,0 +1,165 @@
+//! Log levels and line formatting for the injected library's logger.
+//!
+//! Lines are formatted into a fixed-size buffer so that logging from inside a hook never
+//! allocates; the library writes each finished line with a single `write(2)`.
+
+use std::fmt;
+
+/// Selects the verbosity: `off`, `error` (the default), `info`, `debug` or `trace`.
+pub const LOG_ENV_VAR: &str = "UUID_SPOOF_LOG";
+/// Appends log lines to this file instead of stderr, for apps whose stderr goes nowhere.
+pub const LOG_FILE_ENV_VAR: &str = "UUID_SPOOF_LOG_FILE";
+
+/// Longest line written, newline included; longer messages are cut short with "...".
+pub const MAX_LINE_LEN: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Off = 0,
+    Error = 1,
+    Info = 2,
+    Debug = 3,
+    Trace = 4,
+}
+
+impl Level {
+    pub const DEFAULT: Level = Level::Error;
+
+    pub fn parse(value: &str) -> Option<Level> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" | "0" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Level::Off => "Off",
+            Level::Error => "Error",
+            Level::Info => "Info",
+            Level::Debug => "Debug",
+            Level::Trace => "Trace",
+        }
+    }
+}
+
+/// A log line under construction, on the stack.
+pub struct LineBuffer {
+    bytes: [u8; MAX_LINE_LEN],
+    len: usize,
+    truncated: bool,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        LineBuffer {
+            bytes: [0; MAX_LINE_LEN],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// `[uuid_spoofer <pid>] <Level>: <message>\n`, truncated to `MAX_LINE_LEN`.
+    pub fn format(level: Level, pid: u32, message: fmt::Arguments<'_>) -> Self {
+        let mut line = LineBuffer::new();
+        // Writing into a LineBuffer never fails; overflow just truncates.
+        let _ = fmt::write(
+            &mut line,
+            format_args!("[uuid_spoofer {}] {}: {}", pid, level.label(), message),
+        );
+        line.finish();
+        line
+    }
+
+    // Terminates the line, marking it if the message did not fit.
+    fn finish(&mut self) {
+        const MARK: &[u8] = b"...\n";
+        if self.truncated {
+            self.len = self.len.min(MAX_LINE_LEN - MARK.len());
+            // Back off to the start of a UTF-8 sequence.
+            while self.len > 0 && self.bytes[self.len] & 0xc0 == 0x80 {
+                self.len -= 1;
+            }
+            self.bytes[self.len..self.len + MARK.len()].copy_from_slice(MARK);
+            self.len += MARK.len();
+        } else {
+            self.bytes[self.len] = b'\n';
+            self.len += 1;
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        LineBuffer::new()
+    }
+}
+
+impl fmt::Write for LineBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Keep one byte for the newline.
+        let room = MAX_LINE_LEN - 1 - self.len;
+        let take = if s.len() <= room {
+            s.len()
+        } else {
+            self.truncated = true;
+            let mut end = room;
+            while !s.is_char_boundary(end) {
+                end -= 1;
+            }
+            end
+        };
+        self.bytes[self.len..self.len + take].copy_from_slice(&s.as_bytes()[..take]);
+        self.len += take;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_levels() {
+        assert_eq!(Level::parse("off"), Some(Level::Off));
+        assert_eq!(Level::parse(" Debug\n"), Some(Level::Debug));
+        assert_eq!(Level::parse("TRACE"), Some(Level::Trace));
+        assert_eq!(Level::parse("verbose"), None);
+        assert!(Level::Trace > Level::Info && Level::Error > Level::Off);
+    }
+
+    #[test]
+    fn formats_a_line() {
+        let line = LineBuffer::format(Level::Info, 42, format_args!("hooked {}", "sysctlbyname"));
+        assert_eq!(
+            line.as_bytes(),
+            b"[uuid_spoofer 42] Info: hooked sysctlbyname\n"
+        );
+    }
+
+    #[test]
+    fn truncates_long_lines_on_char_boundaries() {
+        let long = "é".repeat(MAX_LINE_LEN);
+        let line = LineBuffer::format(Level::Debug, 1, format_args!("{}", long));
+        let bytes = line.as_bytes();
+        assert!(bytes.len() <= MAX_LINE_LEN);
+        assert!(bytes.ends_with(b"...\n"));
+        assert!(std::str::from_utf8(bytes).is_ok());
+
+        let exact = "x".repeat(MAX_LINE_LEN - 1);
+        let mut line = LineBuffer::new();
+        fmt::Write::write_str(&mut line, &exact).unwrap();
+        line.finish();
+        assert_eq!(line.as_bytes().len(), MAX_LINE_LEN);
+        assert!(!line.truncated);
+    }
+}
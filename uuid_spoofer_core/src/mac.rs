@@ -0,0 +1,333 @@
+//! Network interface MAC addresses: parsing, formatting and generation, and the table of
+//! per-interface addresses a profile gives.
+//!
+//! Generated addresses are unicast and, unless an OUI (a vendor's 3-byte prefix) is
+//! chosen, locally administered, so they never collide with a real vendor's range. Like
+//! the rest of a generated identity they are expanded from caller-supplied entropy.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::generate::expand;
+
+/// The most bytes an interface name may have (`IFNAMSIZ` less the terminating NUL).
+pub const MAX_INTERFACE_NAME: usize = 15;
+
+/// The `[mac_addresses]` key that stands for every interface not listed.
+pub const OTHER_INTERFACES: &str = "*";
+
+/// The `[mac_addresses]` value asking for a generated address.
+pub const GENERATE: &str = "generate";
+
+/// A 6-byte Ethernet address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacAddress(pub [u8; 6]);
+
+impl MacAddress {
+    /// Parses `aa:bb:cc:dd:ee:ff` (or with `-`), in either case.
+    pub fn parse(text: &str) -> Option<Self> {
+        parse_hex_bytes(text).map(MacAddress)
+    }
+
+    /// An address drawn from `entropy` and `label`: the chosen OUI followed by three
+    /// random bytes, or six random bytes made unicast and locally administered.
+    pub fn generate(entropy: &[u8], label: &str, oui: Option<Oui>) -> Self {
+        let mut bytes = [0u8; 6];
+        bytes.copy_from_slice(&expand(entropy, label)[..6]);
+        match oui {
+            Some(oui) => bytes[..3].copy_from_slice(&oui.0),
+            None => bytes[0] = (bytes[0] & 0xfc) | 0x02,
+        }
+        MacAddress(bytes)
+    }
+
+    /// Whether this is a group address; an interface's own address never is.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    /// Whether the address was assigned locally rather than by the vendor.
+    pub fn is_locally_administered(&self) -> bool {
+        self.0[0] & 0x02 != 0
+    }
+
+    /// All zeros, as loopback and other interfaces without hardware report.
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 6]
+    }
+}
+
+impl fmt::Display for MacAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a, b, c, d, e, g
+        )
+    }
+}
+
+impl FromStr for MacAddress {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        MacAddress::parse(text)
+            .ok_or_else(|| format!("{:?} is not a MAC address like aa:bb:cc:dd:ee:ff", text))
+    }
+}
+
+/// An organizationally unique identifier: the vendor prefix of an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Oui(pub [u8; 3]);
+
+impl fmt::Display for Oui {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [a, b, c] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}", a, b, c)
+    }
+}
+
+impl FromStr for Oui {
+    type Err = String;
+
+    /// Parses `aa:bb:cc` (or with `-`). A multicast prefix cannot start an interface's
+    /// address and is refused.
+    fn from_str(text: &str) -> Result<Self, String> {
+        let bytes = parse_hex_bytes::<3>(text)
+            .ok_or_else(|| format!("{:?} is not an OUI like aa:bb:cc", text))?;
+        if bytes[0] & 0x01 != 0 {
+            return Err(format!("{} is a multicast prefix", Oui(bytes)));
+        }
+        Ok(Oui(bytes))
+    }
+}
+
+fn parse_hex_bytes<const N: usize>(text: &str) -> Option<[u8; N]> {
+    let mut bytes = [0u8; N];
+    let mut parts = text.split([':', '-']);
+    for byte in bytes.iter_mut() {
+        let part = parts.next()?;
+        // from_str_radix would take a sign, as in "+a".
+        if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    parts.next().is_none().then_some(bytes)
+}
+
+/// Whether `name` can name a network interface.
+pub fn is_interface_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_INTERFACE_NAME
+        && !name.contains(|c: char| c == '/' || c.is_whitespace() || c.is_control())
+}
+
+// Addresses for the interfaces the table does not list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Generator {
+    entropy: Vec<u8>,
+    oui: Option<Oui>,
+}
+
+/// The addresses a profile gives its network interfaces: fixed ones by name and,
+/// optionally, generated ones for every other interface that has an address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MacAddresses {
+    fixed: BTreeMap<String, MacAddress>,
+    others: Option<Generator>,
+}
+
+impl MacAddresses {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether no interface is spoofed at all.
+    pub fn is_empty(&self) -> bool {
+        self.fixed.is_empty() && self.others.is_none()
+    }
+
+    pub fn insert(&mut self, interface: impl Into<String>, address: MacAddress) {
+        self.fixed.insert(interface.into(), address);
+    }
+
+    /// Gives every interface without a fixed address one generated from `entropy`, which
+    /// stays the same for as long as the entropy does.
+    pub fn generate_others(&mut self, entropy: &[u8], oui: Option<Oui>) {
+        self.others = Some(Generator {
+            entropy: entropy.to_vec(),
+            oui,
+        });
+    }
+
+    /// Whether interfaces without a fixed address get a generated one.
+    pub fn generates_others(&self) -> bool {
+        self.others.is_some()
+    }
+
+    /// The address generated for `interface` from `entropy`.
+    pub fn generated(entropy: &[u8], interface: &str, oui: Option<Oui>) -> MacAddress {
+        MacAddress::generate(entropy, &format!("mac\0{}", interface), oui)
+    }
+
+    /// The address `interface` gets, if it is spoofed.
+    pub fn for_interface(&self, interface: &str) -> Option<MacAddress> {
+        if let Some(address) = self.fixed.get(interface) {
+            return Some(*address);
+        }
+        let others = self.others.as_ref()?;
+        Some(Self::generated(&others.entropy, interface, others.oui))
+    }
+
+    /// Overwrites `address`, the hardware address `interface` reports, with its spoofed
+    /// one and returns whether it did. Only 6-byte addresses are replaced, and generated
+    /// ones never replace an all-zero address, so loopback interfaces keep theirs.
+    pub fn replace(&self, interface: &str, address: &mut [u8]) -> bool {
+        let Ok(current) = <[u8; 6]>::try_from(&*address) else {
+            return false;
+        };
+        let spoofed = match self.fixed.get(interface) {
+            Some(fixed) => *fixed,
+            None if MacAddress(current).is_zero() => return false,
+            None => match self.for_interface(interface) {
+                Some(generated) => generated,
+                None => return false,
+            },
+        };
+        address.copy_from_slice(&spoofed.0);
+        true
+    }
+
+    /// The fixed addresses, by interface name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, MacAddress)> {
+        self.fixed
+            .iter()
+            .map(|(interface, address)| (interface.as_str(), *address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_formats_addresses() {
+        let address = MacAddress::parse("0A:1b:2c:3D:4e:5F").unwrap();
+        assert_eq!(address, MacAddress([0x0a, 0x1b, 0x2c, 0x3d, 0x4e, 0x5f]));
+        assert_eq!(address.to_string(), "0a:1b:2c:3d:4e:5f");
+        assert_eq!(MacAddress::parse("0a-1b-2c-3d-4e-5f"), Some(address));
+        for bad in [
+            "",
+            "0a:1b:2c:3d:4e",
+            "0a:1b:2c:3d:4e:5f:60",
+            "a:1b:2c:3d:4e:5f",
+            "0a:1b:2c:3d:4e:zz",
+            "0a1b2c3d4e5f",
+            "+a:bb:cc:dd:ee:ff",
+            "0a:1b:2c:3d:4e:+f",
+        ] {
+            assert_eq!(MacAddress::parse(bad), None, "{:?}", bad);
+        }
+        assert_eq!(
+            "nope".parse::<MacAddress>().unwrap_err(),
+            "\"nope\" is not a MAC address like aa:bb:cc:dd:ee:ff"
+        );
+        assert!(MacAddress([0x01, 0, 0x5e, 0, 0, 1]).is_multicast());
+        assert!(MacAddress([0x02, 0, 0, 0, 0, 1]).is_locally_administered());
+        assert!(!MacAddress([0xa4, 0x83, 0xe7, 0, 0, 1]).is_locally_administered());
+    }
+
+    #[test]
+    fn parses_ouis() {
+        assert_eq!("A4:83:e7".parse(), Ok(Oui([0xa4, 0x83, 0xe7])));
+        assert_eq!("a4-83-e7".parse::<Oui>().unwrap().to_string(), "a4:83:e7");
+        assert_eq!(
+            "a4:83".parse::<Oui>(),
+            Err("\"a4:83\" is not an OUI like aa:bb:cc".to_string())
+        );
+        assert_eq!(
+            "+2:00:00".parse::<Oui>(),
+            Err("\"+2:00:00\" is not an OUI like aa:bb:cc".to_string())
+        );
+        assert_eq!(
+            "01:00:5e".parse::<Oui>(),
+            Err("01:00:5e is a multicast prefix".to_string())
+        );
+    }
+
+    #[test]
+    fn generated_addresses_are_unicast_and_repeatable() {
+        let oui = Oui([0xa4, 0x83, 0xe7]);
+        for seed in 0..500u32 {
+            let entropy = seed.to_le_bytes();
+            let local = MacAddress::generate(&entropy, "mac", None);
+            assert!(!local.is_multicast(), "{}", local);
+            assert!(local.is_locally_administered(), "{}", local);
+            assert_eq!(local, MacAddress::generate(&entropy, "mac", None));
+
+            let vendor = MacAddress::generate(&entropy, "mac", Some(oui));
+            assert_eq!(vendor.0[..3], oui.0);
+            // The same random part, under the vendor's prefix.
+            assert_eq!(vendor.0[3..], local.0[3..]);
+        }
+        assert_ne!(
+            MacAddress::generate(b"entropy", "a", None),
+            MacAddress::generate(b"entropy", "b", None)
+        );
+    }
+
+    #[test]
+    fn interface_names_are_checked() {
+        for good in ["en0", "eth0", "enp0s31f6", "wlp2s0", "a23456789012345"] {
+            assert!(is_interface_name(good), "{:?}", good);
+        }
+        for bad in ["", "a234567890123456", "en 0", "en/0", "en\t"] {
+            assert!(!is_interface_name(bad), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn fixed_addresses_win_over_generated_ones() {
+        let fixed = MacAddress([0x02, 0, 0, 0, 0, 1]);
+        let mut addresses = MacAddresses::new();
+        assert!(addresses.is_empty());
+        addresses.insert("en0", fixed);
+        assert!(!addresses.is_empty() && !addresses.generates_others());
+        assert_eq!(addresses.for_interface("en0"), Some(fixed));
+        assert_eq!(addresses.for_interface("en1"), None);
+
+        addresses.generate_others(b"entropy", None);
+        assert_eq!(addresses.for_interface("en0"), Some(fixed));
+        let en1 = addresses.for_interface("en1").unwrap();
+        assert_eq!(en1, MacAddresses::generated(b"entropy", "en1", None));
+        assert_ne!(en1, addresses.for_interface("en2").unwrap());
+        assert_eq!(addresses.iter().collect::<Vec<_>>(), [("en0", fixed)]);
+    }
+
+    #[test]
+    fn replace_leaves_zero_and_odd_sized_addresses_alone() {
+        let mut addresses = MacAddresses::new();
+        addresses.insert("lo", MacAddress([0x02, 0, 0, 0, 0, 9]));
+        addresses.generate_others(b"entropy", Some(Oui([0xa4, 0x83, 0xe7])));
+
+        let mut real = [0x3c, 0x22, 0xfb, 0x11, 0x22, 0x33];
+        assert!(addresses.replace("en0", &mut real));
+        assert_eq!(MacAddress(real), addresses.for_interface("en0").unwrap());
+        assert_eq!(real[..3], [0xa4, 0x83, 0xe7]);
+
+        // A generated address never replaces a missing one; a fixed one does.
+        let mut zero = [0u8; 6];
+        assert!(!addresses.replace("lo0", &mut zero));
+        assert_eq!(zero, [0; 6]);
+        assert!(addresses.replace("lo", &mut zero));
+        assert_eq!(zero, [0x02, 0, 0, 0, 0, 9]);
+
+        let mut infiniband = [0xffu8; 20];
+        assert!(!addresses.replace("ib0", &mut infiniband));
+        assert_eq!(infiniband, [0xff; 20]);
+        assert!(!MacAddresses::new().replace("en0", &mut [1, 2, 3, 4, 5, 6]));
+    }
+}
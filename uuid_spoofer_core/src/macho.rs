@@ -0,0 +1,422 @@
+//! A small, allocation-light Mach-O reader for what the tools need to know about a
+//! binary before injecting into it: its architectures, load commands and code signature.
+//!
+//! Everything works on byte slices, so fat and thin binaries can be inspected (and the
+//! parser tested) on any host.
+
+use std::fmt;
+
+pub const MH_MAGIC: u32 = 0xfeed_face;
+pub const MH_MAGIC_64: u32 = 0xfeed_facf;
+pub const MH_CIGAM: u32 = 0xcefa_edfe;
+pub const MH_CIGAM_64: u32 = 0xcffa_edfe;
+pub const FAT_MAGIC: u32 = 0xcafe_babe;
+pub const FAT_MAGIC_64: u32 = 0xcafe_babf;
+
+pub const CPU_TYPE_X86_64: i32 = 0x0100_0007;
+pub const CPU_TYPE_ARM64: i32 = 0x0100_000c;
+
+pub const LC_CODE_SIGNATURE: u32 = 0x1d;
+
+/// Code signing flags (from the CodeDirectory) that matter for injection.
+pub const CS_ADHOC: u32 = 0x0000_0002;
+pub const CS_RESTRICT: u32 = 0x0000_0800;
+pub const CS_REQUIRE_LV: u32 = 0x0000_2000;
+pub const CS_RUNTIME: u32 = 0x0001_0000;
+
+pub(crate) const CSMAGIC_EMBEDDED_SIGNATURE: u32 = 0xfade_0cc0;
+pub(crate) const CSMAGIC_CODEDIRECTORY: u32 = 0xfade_0c02;
+pub(crate) const CSMAGIC_EMBEDDED_ENTITLEMENTS: u32 = 0xfade_7171;
+pub(crate) const CSSLOT_CODEDIRECTORY: u32 = 0;
+pub(crate) const CSSLOT_ENTITLEMENTS: u32 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MachOError {
+    /// Needed `len` bytes at `offset` but the buffer ends first.
+    Truncated {
+        offset: usize,
+        len: usize,
+    },
+    BadMagic(u32),
+    Malformed(&'static str),
+}
+
+impl fmt::Display for MachOError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachOError::Truncated { offset, len } => {
+                write!(f, "truncated: need {} bytes at offset {:#x}", len, offset)
+            }
+            MachOError::BadMagic(magic) => write!(f, "not a Mach-O file (magic {:#010x})", magic),
+            MachOError::Malformed(what) => write!(f, "malformed Mach-O: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for MachOError {}
+
+pub(crate) fn bytes_at(data: &[u8], offset: usize, len: usize) -> Result<&[u8], MachOError> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(MachOError::Truncated { offset, len })
+}
+
+pub(crate) fn u32_at(data: &[u8], offset: usize, big_endian: bool) -> Result<u32, MachOError> {
+    let bytes: [u8; 4] = bytes_at(data, offset, 4)?.try_into().unwrap();
+    Ok(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+pub(crate) fn u64_at(data: &[u8], offset: usize, big_endian: bool) -> Result<u64, MachOError> {
+    let bytes: [u8; 8] = bytes_at(data, offset, 8)?.try_into().unwrap();
+    Ok(if big_endian {
+        u64::from_be_bytes(bytes)
+    } else {
+        u64::from_le_bytes(bytes)
+    })
+}
+
+/// Whether `data` starts like a thin or fat Mach-O file.
+pub fn is_macho(data: &[u8]) -> bool {
+    matches!(
+        u32_at(data, 0, true),
+        Ok(FAT_MAGIC | FAT_MAGIC_64 | MH_CIGAM | MH_CIGAM_64 | MH_MAGIC | MH_MAGIC_64)
+    )
+}
+
+/// One architecture of a (possibly fat) file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slice<'a> {
+    pub cputype: i32,
+    pub cpusubtype: i32,
+    /// Offset of the slice within the whole file.
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+/// Splits a file into its architectures. A thin file is a single slice at offset 0.
+pub fn slices(data: &[u8]) -> Result<Vec<Slice<'_>>, MachOError> {
+    let magic = u32_at(data, 0, true)?;
+    if magic != FAT_MAGIC && magic != FAT_MAGIC_64 {
+        let macho = MachO::parse(data)?;
+        return Ok(vec![Slice {
+            cputype: macho.cputype,
+            cpusubtype: macho.cpusubtype,
+            offset: 0,
+            data,
+        }]);
+    }
+
+    let is_64 = magic == FAT_MAGIC_64;
+    let nfat_arch = u32_at(data, 4, true)? as usize;
+    let arch_size = if is_64 { 32 } else { 20 };
+    let mut slices = Vec::with_capacity(nfat_arch.min(16));
+    for i in 0..nfat_arch {
+        let arch = 8 + i * arch_size;
+        let cputype = u32_at(data, arch, true)? as i32;
+        let cpusubtype = u32_at(data, arch + 4, true)? as i32;
+        let (offset, size) = if is_64 {
+            (
+                u64_at(data, arch + 8, true)?,
+                u64_at(data, arch + 16, true)?,
+            )
+        } else {
+            (
+                u32_at(data, arch + 8, true)? as u64,
+                u32_at(data, arch + 12, true)? as u64,
+            )
+        };
+        let (offset, size) = (offset as usize, size as usize);
+        slices.push(Slice {
+            cputype,
+            cpusubtype,
+            offset,
+            data: bytes_at(data, offset, size)?,
+        });
+    }
+    Ok(slices)
+}
+
+/// Human-readable architecture name, as `lipo` prints it.
+pub fn cpu_name(cputype: i32) -> String {
+    match cputype {
+        CPU_TYPE_X86_64 => "x86_64".to_string(),
+        CPU_TYPE_ARM64 => "arm64".to_string(),
+        7 => "i386".to_string(),
+        12 => "arm".to_string(),
+        other => format!("cputype {:#x}", other),
+    }
+}
+
+/// A load command: its type and the full command bytes, header included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadCommand<'a> {
+    pub cmd: u32,
+    /// Offset of the command within the slice.
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+/// The header of a thin Mach-O image.
+#[derive(Debug, Clone, Copy)]
+pub struct MachO<'a> {
+    data: &'a [u8],
+    pub is_64: bool,
+    pub big_endian: bool,
+    pub cputype: i32,
+    pub cpusubtype: i32,
+    pub filetype: u32,
+    pub ncmds: u32,
+    pub sizeofcmds: u32,
+    pub flags: u32,
+}
+
+impl<'a> MachO<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, MachOError> {
+        let (is_64, big_endian) = match u32_at(data, 0, false)? {
+            MH_MAGIC => (false, false),
+            MH_MAGIC_64 => (true, false),
+            MH_CIGAM => (false, true),
+            MH_CIGAM_64 => (true, true),
+            magic => return Err(MachOError::BadMagic(magic)),
+        };
+        let field = |index: usize| u32_at(data, 4 + index * 4, big_endian);
+        let macho = MachO {
+            data,
+            is_64,
+            big_endian,
+            cputype: field(0)? as i32,
+            cpusubtype: field(1)? as i32,
+            filetype: field(2)?,
+            ncmds: field(3)?,
+            sizeofcmds: field(4)?,
+            flags: field(5)?,
+        };
+        bytes_at(data, macho.header_size(), macho.sizeofcmds as usize)?;
+        Ok(macho)
+    }
+
+    /// The bytes of this image.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Size of the mach_header(_64) preceding the load commands.
+    pub fn header_size(&self) -> usize {
+        if self.is_64 {
+            32
+        } else {
+            28
+        }
+    }
+
+    pub fn u32_at(&self, offset: usize) -> Result<u32, MachOError> {
+        u32_at(self.data, offset, self.big_endian)
+    }
+
+    pub fn u64_at(&self, offset: usize) -> Result<u64, MachOError> {
+        u64_at(self.data, offset, self.big_endian)
+    }
+
+    pub fn load_commands(&self) -> Result<Vec<LoadCommand<'a>>, MachOError> {
+        let mut commands = Vec::with_capacity(self.ncmds as usize);
+        let mut offset = self.header_size();
+        let end = offset + self.sizeofcmds as usize;
+        for _ in 0..self.ncmds {
+            let cmd = self.u32_at(offset)?;
+            let cmdsize = self.u32_at(offset + 4)? as usize;
+            if cmdsize < 8 || offset + cmdsize > end {
+                return Err(MachOError::Malformed("load command size"));
+            }
+            commands.push(LoadCommand {
+                cmd,
+                offset,
+                data: bytes_at(self.data, offset, cmdsize)?,
+            });
+            offset += cmdsize;
+        }
+        // Anything between the last command and sizeofcmds would be invisible to dyld's
+        // view of the header and to ours alike; a header that disagrees is not trusted.
+        if offset != end {
+            return Err(MachOError::Malformed("sizeofcmds"));
+        }
+        Ok(commands)
+    }
+
+    /// The embedded code signature, if the image has one.
+    pub fn code_signature(&self) -> Result<Option<CodeSignature>, MachOError> {
+        let Some(command) = self
+            .load_commands()?
+            .into_iter()
+            .find(|command| command.cmd == LC_CODE_SIGNATURE)
+        else {
+            return Ok(None);
+        };
+        let dataoff = self.u32_at(command.offset + 8)? as usize;
+        let datasize = self.u32_at(command.offset + 12)? as usize;
+        CodeSignature::parse(bytes_at(self.data, dataoff, datasize)?).map(Some)
+    }
+}
+
+/// What the embedded signature says about how the binary may be injected into.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CodeSignature {
+    /// CodeDirectory flags (`CS_*`).
+    pub flags: u32,
+    /// The XML entitlements plist, if any.
+    pub entitlements: Option<String>,
+}
+
+impl CodeSignature {
+    /// Parses an embedded signature SuperBlob (all fields big-endian).
+    pub fn parse(blob: &[u8]) -> Result<Self, MachOError> {
+        if u32_at(blob, 0, true)? != CSMAGIC_EMBEDDED_SIGNATURE {
+            return Err(MachOError::Malformed("code signature magic"));
+        }
+        let count = u32_at(blob, 8, true)? as usize;
+        let mut signature = CodeSignature::default();
+        for i in 0..count {
+            let slot = u32_at(blob, 12 + i * 8, true)?;
+            let offset = u32_at(blob, 16 + i * 8, true)? as usize;
+            let magic = u32_at(blob, offset, true)?;
+            let length = u32_at(blob, offset + 4, true)? as usize;
+            match (slot, magic) {
+                (CSSLOT_CODEDIRECTORY, CSMAGIC_CODEDIRECTORY) => {
+                    signature.flags = u32_at(blob, offset + 12, true)?;
+                }
+                (CSSLOT_ENTITLEMENTS, CSMAGIC_EMBEDDED_ENTITLEMENTS) => {
+                    let xml = bytes_at(blob, offset + 8, length.saturating_sub(8))?;
+                    signature.entitlements = Some(String::from_utf8_lossy(xml).into_owned());
+                }
+                _ => {}
+            }
+        }
+        Ok(signature)
+    }
+
+    pub fn hardened_runtime(&self) -> bool {
+        self.flags & CS_RUNTIME != 0
+    }
+
+    pub fn library_validation(&self) -> bool {
+        self.flags & CS_REQUIRE_LV != 0
+    }
+
+    pub fn restricted(&self) -> bool {
+        self.flags & CS_RESTRICT != 0
+    }
+
+    /// Whether the boolean entitlement `key` is present and true.
+    pub fn has_entitlement(&self, key: &str) -> bool {
+        let Some(xml) = &self.entitlements else {
+            return false;
+        };
+        let needle = format!("<key>{}</key>", key);
+        xml.find(&needle)
+            .map(|at| xml[at + needle.len()..].trim_start().starts_with("<true/>"))
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::*;
+
+    const ENTITLEMENTS: &str = "<?xml version=\"1.0\"?><plist><dict>\
+        <key>com.apple.security.cs.disable-library-validation</key>\n\t<true/>\
+        <key>com.apple.security.cs.allow-jit</key><false/></dict></plist>";
+
+    #[test]
+    fn thin_image_is_one_slice() {
+        let image = signed_macho(CPU_TYPE_ARM64, 0, None);
+        assert!(is_macho(&image));
+        let slices = slices(&image).unwrap();
+        assert_eq!(slices.len(), 1);
+        assert_eq!(slices[0].cputype, CPU_TYPE_ARM64);
+        assert_eq!(slices[0].offset, 0);
+    }
+
+    #[test]
+    fn fat_file_splits_into_slices() {
+        let arm = signed_macho(CPU_TYPE_ARM64, CS_RUNTIME, None);
+        let intel = signed_macho(CPU_TYPE_X86_64, 0, None);
+        let file = fat(&[(CPU_TYPE_X86_64, &intel), (CPU_TYPE_ARM64, &arm)]);
+        assert!(is_macho(&file));
+        let slices = slices(&file).unwrap();
+        assert_eq!(
+            slices
+                .iter()
+                .map(|s| cpu_name(s.cputype))
+                .collect::<Vec<_>>(),
+            ["x86_64", "arm64"]
+        );
+        assert_eq!(slices[1].data, &arm[..]);
+        let signature = MachO::parse(slices[1].data)
+            .unwrap()
+            .code_signature()
+            .unwrap()
+            .unwrap();
+        assert!(signature.hardened_runtime());
+    }
+
+    #[test]
+    fn reads_signature_flags_and_entitlements() {
+        let image = signed_macho(
+            CPU_TYPE_ARM64,
+            CS_RUNTIME | CS_REQUIRE_LV,
+            Some(ENTITLEMENTS),
+        );
+        let signature = MachO::parse(&image)
+            .unwrap()
+            .code_signature()
+            .unwrap()
+            .unwrap();
+        assert!(signature.hardened_runtime());
+        assert!(signature.library_validation());
+        assert!(!signature.restricted());
+        assert!(signature.has_entitlement("com.apple.security.cs.disable-library-validation"));
+        assert!(!signature.has_entitlement("com.apple.security.cs.allow-jit"));
+        assert!(!signature.has_entitlement("com.apple.security.get-task-allow"));
+    }
+
+    #[test]
+    fn unsigned_image_has_no_signature() {
+        let image = build_macho(CPU_TYPE_X86_64, &[command(0x2, &[0; 16])], &[]);
+        let macho = MachO::parse(&image).unwrap();
+        assert_eq!(macho.load_commands().unwrap().len(), 1);
+        assert_eq!(macho.code_signature().unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(!is_macho(b"\x7fELF\x02\x01\x01"));
+        assert_eq!(
+            MachO::parse(b"\x7fELF\x02\x01\x01\x00").unwrap_err(),
+            MachOError::BadMagic(0x464c_457f)
+        );
+        let mut image = signed_macho(CPU_TYPE_ARM64, 0, None);
+        image.truncate(40);
+        assert!(matches!(
+            MachO::parse(&image),
+            Err(MachOError::Truncated { .. })
+        ));
+        let mut bad_size = build_macho(CPU_TYPE_X86_64, &[command(0x2, &[0; 16])], &[]);
+        bad_size[36..40].copy_from_slice(&4u32.to_le_bytes());
+        assert_eq!(
+            MachO::parse(&bad_size).unwrap().load_commands(),
+            Err(MachOError::Malformed("load command size"))
+        );
+        // sizeofcmds must be exactly the commands' sizes added up.
+        let mut padded = build_macho(CPU_TYPE_X86_64, &[command(0x2, &[0; 16])], &[0; 64]);
+        padded[20..24].copy_from_slice(&(24u32 + 32).to_le_bytes());
+        assert_eq!(
+            MachO::parse(&padded).unwrap().load_commands(),
+            Err(MachOError::Malformed("sizeofcmds"))
+        );
+    }
+}
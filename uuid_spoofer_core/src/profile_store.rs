@@ -0,0 +1,418 @@
+//! The profile store: a directory holding one identity profile per file, so identities
+//! can be kept, copied and handed around separately from the config file.
+//!
+//! The store is `UUID_SPOOF_PROFILE_DIR`, or `profiles` next to the default config file
+//! (`~/.config/uuid-spoof/profiles`). Profile `work` lives in `work.toml`, written like a
+//! `[profiles.work]` table of the config file:
+//!
+//! ```toml
+//! uuid = "11111111-2222-3333-4444-555555555555"   # or seed = "..."
+//! [properties]
+//! IOPlatformSerialNumber = "C02WORK00001"
+//! ```
+//!
+//! `UUID_SPOOF_PROFILE` picks one for the library to use (see [`crate::config`]).
+
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::default_config_dir;
+use crate::profiles::{self, Profile, ProfileError, ProfileFile};
+
+/// Environment variable overriding the store's directory.
+pub const PROFILE_DIR_ENV_VAR: &str = "UUID_SPOOF_PROFILE_DIR";
+
+const EXTENSION: &str = "toml";
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug)]
+pub enum StoreError {
+    /// Neither `UUID_SPOOF_PROFILE_DIR` nor a home directory says where the store is.
+    NoDirectory,
+    /// Not usable as a profile name.
+    InvalidName(String),
+    NotFound {
+        name: String,
+        path: PathBuf,
+    },
+    Exists {
+        name: String,
+        path: PathBuf,
+    },
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        /// The line and dotted key the parser stopped at, when it says.
+        location: Option<(usize, String)>,
+        message: String,
+    },
+    Profile {
+        path: PathBuf,
+        error: ProfileError,
+    },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NoDirectory => write!(
+                f,
+                "no profile directory: set ${} or $HOME",
+                PROFILE_DIR_ENV_VAR
+            ),
+            StoreError::InvalidName(name) => write!(
+                f,
+                "invalid profile name {:?}: use up to {} letters, digits, '.', '_' and '-', \
+                 not starting with '.'",
+                name, MAX_NAME_LEN
+            ),
+            StoreError::NotFound { name, path } => {
+                write!(f, "no profile named {:?} ({})", name, path.display())
+            }
+            StoreError::Exists { name, path } => {
+                write!(f, "profile {:?} already exists ({})", name, path.display())
+            }
+            StoreError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            StoreError::Parse {
+                path,
+                location: Some((line, key)),
+                message,
+            } if !key.is_empty() => {
+                write!(f, "{}:{}: {}: {}", path.display(), line, key, message)
+            }
+            StoreError::Parse {
+                path,
+                location: Some((line, _)),
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            StoreError::Parse {
+                path,
+                location: None,
+                message,
+            } => write!(f, "{}: {}", path.display(), message),
+            StoreError::Profile { path, error } => write!(
+                f,
+                "{}:{}: {}: {}",
+                path.display(),
+                error.line,
+                error.key,
+                error.reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// Checks `name` can name a profile file: letters, digits, `.`, `_` and `-`, not
+/// starting with `.`.
+pub fn validate_name(name: &str) -> Result<(), StoreError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(StoreError::InvalidName(name.to_string()))
+    }
+}
+
+/// Reads the profile `name` from `source`, the text of the file at `path`.
+pub fn parse(name: &str, source: &str, path: &Path) -> Result<Profile, StoreError> {
+    let file: ProfileFile = toml::from_str(source).map_err(|e| StoreError::Parse {
+        path: path.to_path_buf(),
+        location: e.span().map(|span| profiles::locate(source, span.start)),
+        message: e.message().to_string(),
+    })?;
+    Profile::from_file(name, file, "", source).map_err(|error| StoreError::Profile {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// A profile directory. Nothing is created until a profile is saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileStore {
+    dir: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        ProfileStore { dir: dir.into() }
+    }
+
+    /// The store `UUID_SPOOF_PROFILE_DIR` names, or the default one.
+    pub fn from_env() -> Result<Self, StoreError> {
+        std::env::var_os(PROFILE_DIR_ENV_VAR)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| default_config_dir().map(|dir| dir.join("profiles")))
+            .map(ProfileStore::new)
+            .ok_or(StoreError::NoDirectory)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The file holding profile `name`, whether or not it exists.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, EXTENSION))
+    }
+
+    /// The names of the stored profiles, sorted. A missing directory is an empty store.
+    pub fn names(&self) -> Result<Vec<String>, StoreError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(self.io_error(&self.dir, error)),
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry.map_err(|e| self.io_error(&self.dir, e))?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+                continue;
+            }
+            let name = path.file_stem().and_then(|stem| stem.to_str());
+            if let Some(name) = name.filter(|name| validate_name(name).is_ok()) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// The text of profile `name`, as written.
+    pub fn read(&self, name: &str) -> Result<String, StoreError> {
+        validate_name(name)?;
+        let path = self.path(name);
+        fs::read_to_string(&path).map_err(|error| match error.kind() {
+            ErrorKind::NotFound => StoreError::NotFound {
+                name: name.to_string(),
+                path: path.clone(),
+            },
+            _ => self.io_error(&path, error),
+        })
+    }
+
+    /// Reads and checks profile `name`.
+    pub fn load(&self, name: &str) -> Result<Profile, StoreError> {
+        let source = self.read(name)?;
+        parse(name, &source, &self.path(name))
+    }
+
+    /// Stores `source` as profile `name` once it parses, replacing an existing profile
+    /// only if `replace` is set. Returns the file written.
+    pub fn save(&self, name: &str, source: &str, replace: bool) -> Result<PathBuf, StoreError> {
+        validate_name(name)?;
+        let path = self.path(name);
+        parse(name, source, &path)?;
+        fs::create_dir_all(&self.dir).map_err(|e| self.io_error(&self.dir, e))?;
+        // Written aside and moved into place, so the library never reads half a file.
+        let temporary = self
+            .dir
+            .join(format!(".{}.{}.tmp", name, std::process::id()));
+        let written = fs::File::create(&temporary)
+            .and_then(|mut file| file.write_all(source.as_bytes()))
+            .map_err(|e| self.io_error(&temporary, e));
+        let moved = written.and_then(|()| {
+            if replace {
+                fs::rename(&temporary, &path).map_err(|e| self.io_error(&path, e))
+            } else {
+                self.link_new(&temporary, name)
+            }
+        });
+        let _ = fs::remove_file(&temporary);
+        moved.map(|()| path)
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), StoreError> {
+        validate_name(name)?;
+        let path = self.path(name);
+        fs::remove_file(&path).map_err(|error| match error.kind() {
+            ErrorKind::NotFound => StoreError::NotFound {
+                name: name.to_string(),
+                path: path.clone(),
+            },
+            _ => self.io_error(&path, error),
+        })
+    }
+
+    /// Renames profile `from` to `to`, which must not exist yet.
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), StoreError> {
+        validate_name(from)?;
+        validate_name(to)?;
+        let source = self.path(from);
+        if !source.exists() {
+            return Err(StoreError::NotFound {
+                name: from.to_string(),
+                path: source,
+            });
+        }
+        self.link_new(&source, to)?;
+        fs::remove_file(&source).map_err(|e| self.io_error(&source, e))
+    }
+
+    // Links `file` in as profile `name`, failing rather than replacing an existing one.
+    fn link_new(&self, file: &Path, name: &str) -> Result<(), StoreError> {
+        let path = self.path(name);
+        fs::hard_link(file, &path).map_err(|error| match error.kind() {
+            ErrorKind::AlreadyExists => StoreError::Exists {
+                name: name.to_string(),
+                path: path.clone(),
+            },
+            _ => self.io_error(&path, error),
+        })
+    }
+
+    fn io_error(&self, path: &Path, error: std::io::Error) -> StoreError {
+        StoreError::Io {
+            path: path.to_path_buf(),
+            error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::PropertyValue;
+
+    const WORK: &str = "uuid = \"11111111-2222-3333-4444-555555555555\"\n\
+                        [properties]\n\
+                        IOPlatformSerialNumber = \"C02WORK00001\"\n";
+
+    fn temp_store(name: &str) -> ProfileStore {
+        let dir = std::env::temp_dir().join(format!(
+            "uuid_spoofer_store_{}_{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        ProfileStore::new(dir)
+    }
+
+    #[test]
+    fn saves_lists_and_loads_profiles() {
+        let store = temp_store("basic");
+        assert!(store.names().unwrap().is_empty());
+        let path = store.save("work", WORK, false).unwrap();
+        assert_eq!(path, store.dir().join("work.toml"));
+        store.save("chat", "seed = \"secret\"\n", false).unwrap();
+        fs::write(store.dir().join("notes.txt"), "not a profile").unwrap();
+        assert_eq!(store.names().unwrap(), ["chat", "work"]);
+
+        let work = store.load("work").unwrap();
+        assert_eq!(work.name, "work");
+        assert_eq!(
+            work.uuid.as_deref(),
+            Some("11111111-2222-3333-4444-555555555555")
+        );
+        assert_eq!(
+            work.identity.get("IOPlatformSerialNumber"),
+            Some(&PropertyValue::String("C02WORK00001".into()))
+        );
+        assert_eq!(store.read("work").unwrap(), WORK);
+        assert_eq!(store.load("chat").unwrap().seed.as_deref(), Some("secret"));
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn existing_profiles_are_only_replaced_when_asked() {
+        let store = temp_store("replace");
+        store.save("work", WORK, false).unwrap();
+        let err = store.save("work", "seed = \"x\"\n", false).unwrap_err();
+        assert!(matches!(err, StoreError::Exists { .. }), "{}", err);
+        assert_eq!(store.read("work").unwrap(), WORK);
+        store.save("work", "seed = \"x\"\n", true).unwrap();
+        assert_eq!(store.load("work").unwrap().seed.as_deref(), Some("x"));
+        // No temporary files are left behind.
+        assert_eq!(fs::read_dir(store.dir()).unwrap().count(), 1);
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn renames_and_removes() {
+        let store = temp_store("rename");
+        store.save("work", WORK, false).unwrap();
+        store.save("chat", "seed = \"secret\"\n", false).unwrap();
+        assert!(matches!(
+            store.rename("work", "chat"),
+            Err(StoreError::Exists { .. })
+        ));
+        store.rename("work", "acme").unwrap();
+        assert_eq!(store.names().unwrap(), ["acme", "chat"]);
+        assert_eq!(
+            store.load("acme").unwrap().uuid.as_deref(),
+            Some("11111111-2222-3333-4444-555555555555")
+        );
+        assert!(matches!(
+            store.rename("work", "other"),
+            Err(StoreError::NotFound { .. })
+        ));
+        store.remove("chat").unwrap();
+        assert!(matches!(
+            store.remove("chat"),
+            Err(StoreError::NotFound { .. })
+        ));
+        assert_eq!(store.names().unwrap(), ["acme"]);
+        fs::remove_dir_all(store.dir()).unwrap();
+    }
+
+    #[test]
+    fn names_must_be_plain_file_names() {
+        for name in ["work", "acme-2024", "v1.2_b", &"x".repeat(64)] {
+            assert!(validate_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", ".hidden", "a/b", "../x", "a b", "é", &"x".repeat(65)] {
+            assert!(validate_name(name).is_err(), "{:?}", name);
+        }
+        let store = temp_store("names");
+        assert!(matches!(
+            store.save("../escape", WORK, false),
+            Err(StoreError::InvalidName(_))
+        ));
+        assert!(!store.dir().exists());
+    }
+
+    #[test]
+    fn invalid_profiles_are_rejected_with_their_location() {
+        let store = temp_store("invalid");
+        let err = store
+            .save("work", "uuid = \"11111111-2222\"\n", false)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "{}:1: uuid: invalid UUID \"11111111-2222\": expected 36 characters, got 13",
+                store.path("work").display()
+            )
+        );
+        assert!(!store.dir().exists());
+
+        let err = parse(
+            "work",
+            "[properties]\nmodel = 3\nexe = \"x\"\n[rules]\n",
+            Path::new("work.toml"),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("work.toml:4: rules: unknown field `rules`"),
+            "{}",
+            err
+        );
+        assert!(matches!(
+            store.load("missing"),
+            Err(StoreError::NotFound { .. })
+        ));
+    }
+}
@@ -0,0 +1,827 @@
+//! Named identity profiles in the config file, and the rules that pick one per process.
+//!
+//! ```toml
+//! [profiles.work]
+//! uuid = "11111111-2222-3333-4444-555555555555"   # or seed = "..."
+//! [profiles.work.properties]
+//! IOPlatformSerialNumber = "C02WORK00001"
+//!
+//! [[rules]]
+//! profile = "work"
+//! bundle_id = "com.tinyspeck.*"
+//! ```
+//!
+//! A profile may also give `serial_format` (`legacy11`, `legacy12` or `random10`, see
+//! [`crate::serial`]). Its `IOPlatformSerialNumber` must then be in that format, and if it
+//! has none, one is generated for its `model` (or the default model). The generated
+//! serial is derived from the profile's name and `uuid` or `seed`, so it stays the same
+//! from launch to launch.
+//!
+//! A `[profiles.<name>.mac_addresses]` table gives network interfaces their MAC
+//! addresses, each either written out or `"generate"`d; the key `"*"` (which only takes
+//! `"generate"`) covers every other interface with an address. Generated addresses are
+//! locally administered unless the profile gives a vendor prefix as `mac_oui`, and are
+//! derived like the serial, so they too stay the same from launch to launch (see
+//! [`crate::mac`]).
+//!
+//! A rule gives any of `exe` (the executable's path), `bundle_id` (the main bundle's
+//! identifier) and `parent` (the parent process's executable), each a [`Glob`], and
+//! matches a process when all of them do. A path pattern without a `/` is matched
+//! against the file name alone. The first matching rule wins; a process no rule matches
+//! gets the file's top-level values, which also fill in whatever a profile leaves out.
+//!
+//! Errors name the line and the dotted key they are about, e.g. `rules[1].profile`.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use toml::Spanned;
+
+use crate::catalog::Catalog;
+use crate::config::validate_uuid;
+use crate::generate::DEFAULT_MODEL;
+use crate::glob::Glob;
+use crate::identity::{
+    value_from_toml, IdentityTable, PropertyValue, IO_PLATFORM_SERIAL_NUMBER_KEY, MODEL_KEY,
+};
+use crate::mac::{self, MacAddress, MacAddresses, Oui, GENERATE, OTHER_INTERFACES};
+use crate::serial::{self, Serial, SerialFormat};
+
+/// What the rules can look at in a process. Anything unknown matches no pattern.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Process {
+    pub exe: Option<PathBuf>,
+    pub bundle_id: Option<String>,
+    pub parent: Option<PathBuf>,
+}
+
+/// One `[profiles.<name>]` table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub name: String,
+    /// A validated, uppercased UUID. Wins over `seed`, as at the top level.
+    pub uuid: Option<String>,
+    pub seed: Option<String>,
+    /// The format `IOPlatformSerialNumber` must have, if given.
+    pub serial_format: Option<SerialFormat>,
+    /// Laid over the top-level `[properties]`, including any generated serial number.
+    pub identity: IdentityTable,
+    /// The network interfaces' addresses, generated ones included.
+    pub mac_addresses: MacAddresses,
+}
+
+impl Profile {
+    /// Builds and checks the profile `name` read from `source`. Error keys start with
+    /// `prefix`, the dotted path of its table.
+    pub(crate) fn from_file(
+        name: &str,
+        file: ProfileFile,
+        prefix: &str,
+        source: &str,
+    ) -> Result<Self, ProfileError> {
+        let error = |span: std::ops::Range<usize>, field: &str, reason: String| ProfileError {
+            line: line_of(source, span.start),
+            key: format!("{}{}", prefix, field),
+            reason,
+        };
+        let uuid = match file.uuid {
+            Some(uuid) => Some(validate_uuid(uuid.get_ref().trim()).map_err(|reason| {
+                error(
+                    uuid.span(),
+                    "uuid",
+                    format!("invalid UUID {:?}: {}", uuid.get_ref(), reason),
+                )
+            })?),
+            None => None,
+        };
+        if let Some(seed) = file.seed.as_ref().filter(|seed| seed.get_ref().is_empty()) {
+            return Err(error(seed.span(), "seed", "empty seed".to_string()));
+        }
+        let mut identity = IdentityTable::new();
+        let mut serial_span = None;
+        for (property, value) in file.properties.unwrap_or_default() {
+            let converted = value_from_toml(&property, value.get_ref())
+                .map_err(|e| error(value.span(), &format!("properties.{}", property), e.reason))?;
+            if property == IO_PLATFORM_SERIAL_NUMBER_KEY {
+                serial_span = Some(value.span());
+            }
+            identity.insert(property, converted);
+        }
+        let serial_format = match &file.serial_format {
+            Some(format) => Some(
+                format
+                    .get_ref()
+                    .parse::<SerialFormat>()
+                    .map_err(|reason| error(format.span(), "serial_format", reason))?,
+            ),
+            None => None,
+        };
+        let seed = file.seed.map(Spanned::into_inner);
+        // What generated values are drawn from, so they stay the same for the profile.
+        let entropy = format!(
+            "{}\0{}",
+            name,
+            uuid.as_deref().or(seed.as_deref()).unwrap_or_default()
+        );
+        if let Some(format) = serial_format {
+            match (serial_span, identity.get(IO_PLATFORM_SERIAL_NUMBER_KEY)) {
+                (Some(span), Some(value)) => {
+                    let checked = match value {
+                        PropertyValue::String(text) => Serial::parse_as(text, format)
+                            .map(|_| ())
+                            .map_err(|e| format!("not a {} serial number: {}", format, e)),
+                        _ => Err("a serial number must be a string".to_string()),
+                    };
+                    checked.map_err(|reason| {
+                        error(
+                            span,
+                            &format!("properties.{}", IO_PLATFORM_SERIAL_NUMBER_KEY),
+                            reason,
+                        )
+                    })?;
+                }
+                _ => {
+                    // A broken user catalog is reported where it is loaded; profiles
+                    // fall back to the built-in one.
+                    let catalog = Catalog::installed().unwrap_or(Catalog::builtin());
+                    let model = identity
+                        .text(MODEL_KEY)
+                        .and_then(|identifier| catalog.model(identifier))
+                        .or_else(|| catalog.model(DEFAULT_MODEL))
+                        .expect("the default model is listed");
+                    if !model.supports(format) {
+                        let span = file.serial_format.as_ref().map_or(0..0, Spanned::span);
+                        return Err(error(
+                            span,
+                            "serial_format",
+                            format!(
+                                "{} was only made with random10 serial numbers",
+                                model.identifier
+                            ),
+                        ));
+                    }
+                    identity.insert(
+                        IO_PLATFORM_SERIAL_NUMBER_KEY,
+                        PropertyValue::String(serial::generate(format, model, entropy.as_bytes())),
+                    );
+                }
+            }
+        }
+        let mac_oui = match &file.mac_oui {
+            Some(oui) => Some(
+                oui.get_ref()
+                    .parse::<Oui>()
+                    .map_err(|reason| error(oui.span(), "mac_oui", reason))?,
+            ),
+            None => None,
+        };
+        let mut mac_addresses = MacAddresses::new();
+        for (interface, value) in file.mac_addresses.unwrap_or_default() {
+            let key = format!("mac_addresses.{}", interface);
+            let generate = value.get_ref() == GENERATE;
+            if interface == OTHER_INTERFACES {
+                if !generate {
+                    return Err(error(
+                        value.span(),
+                        &key,
+                        format!("other interfaces can only be {:?}", GENERATE),
+                    ));
+                }
+                mac_addresses.generate_others(entropy.as_bytes(), mac_oui);
+                continue;
+            }
+            if !mac::is_interface_name(&interface) {
+                return Err(error(
+                    value.span(),
+                    &key,
+                    format!("{:?} is not an interface name", interface),
+                ));
+            }
+            let address = if generate {
+                MacAddresses::generated(entropy.as_bytes(), &interface, mac_oui)
+            } else {
+                let address = value
+                    .get_ref()
+                    .parse::<MacAddress>()
+                    .map_err(|reason| error(value.span(), &key, reason))?;
+                if address.is_multicast() || address.is_zero() {
+                    return Err(error(
+                        value.span(),
+                        &key,
+                        format!("{} cannot be an interface's own address", address),
+                    ));
+                }
+                address
+            };
+            mac_addresses.insert(interface, address);
+        }
+        Ok(Profile {
+            name: name.to_string(),
+            uuid,
+            seed,
+            serial_format,
+            identity,
+            mac_addresses,
+        })
+    }
+}
+
+/// One `[[rules]]` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub profile: String,
+    /// Where the rule starts in the file.
+    pub line: usize,
+    exe: Option<Glob>,
+    bundle_id: Option<Glob>,
+    parent: Option<Glob>,
+}
+
+impl Rule {
+    pub fn matches(&self, process: &Process) -> bool {
+        let criteria = [
+            self.exe
+                .as_ref()
+                .map(|glob| path_matches(glob, process.exe.as_deref())),
+            self.bundle_id.as_ref().map(|glob| {
+                process
+                    .bundle_id
+                    .as_deref()
+                    .is_some_and(|id| glob.matches(id))
+            }),
+            self.parent
+                .as_ref()
+                .map(|glob| path_matches(glob, process.parent.as_deref())),
+        ];
+        criteria.iter().all(|matched| matched.unwrap_or(true))
+    }
+}
+
+fn path_matches(glob: &Glob, path: Option<&Path>) -> bool {
+    let Some(path) = path else {
+        return false;
+    };
+    if glob.has_slash() {
+        glob.matches(&path.to_string_lossy())
+    } else {
+        path.file_name()
+            .is_some_and(|name| glob.matches(&name.to_string_lossy()))
+    }
+}
+
+/// The profiles and rules of one config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profiles {
+    /// The file they came from.
+    pub path: PathBuf,
+    profiles: BTreeMap<String, Profile>,
+    rules: Vec<Rule>,
+}
+
+impl Profiles {
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// The profiles, by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Profile> {
+        self.profiles.values()
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The first rule matching `process`, with the profile it picks.
+    pub fn select(&self, process: &Process) -> Option<(&Rule, &Profile)> {
+        let rule = self.rules.iter().find(|rule| rule.matches(process))?;
+        // Every rule's profile was checked to exist when the file was read.
+        Some((rule, self.profiles.get(&rule.profile)?))
+    }
+
+    /// Builds and checks the profiles and rules read from `source`, the file at `path`.
+    pub(crate) fn from_file(
+        path: &Path,
+        profiles: BTreeMap<String, ProfileFile>,
+        rules: Vec<Spanned<RuleFile>>,
+        source: &str,
+    ) -> Result<Self, ProfileError> {
+        let error = |span: std::ops::Range<usize>, key: String, reason: String| ProfileError {
+            line: line_of(source, span.start),
+            key,
+            reason,
+        };
+
+        let mut built = BTreeMap::new();
+        for (name, file) in profiles {
+            let profile = Profile::from_file(&name, file, &format!("profiles.{}.", name), source)?;
+            built.insert(name, profile);
+        }
+
+        let mut checked = Vec::new();
+        for (index, rule) in rules.into_iter().enumerate() {
+            let key = |field: &str| format!("rules[{}].{}", index, field);
+            let span = rule.span();
+            let rule = rule.into_inner();
+            if !built.contains_key(rule.profile.get_ref()) {
+                return Err(error(
+                    rule.profile.span(),
+                    key("profile"),
+                    format!("no profile named {:?}", rule.profile.get_ref()),
+                ));
+            }
+            let glob = |field: &str, pattern: Option<Spanned<String>>| {
+                pattern
+                    .map(|pattern| {
+                        Glob::new(pattern.get_ref()).map_err(|e| {
+                            error(
+                                pattern.span(),
+                                key(field),
+                                format!("invalid pattern {:?}: {}", pattern.get_ref(), e),
+                            )
+                        })
+                    })
+                    .transpose()
+            };
+            let checked_rule = Rule {
+                profile: rule.profile.into_inner(),
+                line: line_of(source, span.start),
+                exe: glob("exe", rule.exe)?,
+                bundle_id: glob("bundle_id", rule.bundle_id)?,
+                parent: glob("parent", rule.parent)?,
+            };
+            if checked_rule.exe.is_none()
+                && checked_rule.bundle_id.is_none()
+                && checked_rule.parent.is_none()
+            {
+                return Err(error(
+                    span,
+                    format!("rules[{}]", index),
+                    "a rule needs at least one of exe, bundle_id or parent".to_string(),
+                ));
+            }
+            checked.push(checked_rule);
+        }
+
+        Ok(Profiles {
+            path: path.to_path_buf(),
+            profiles: built,
+            rules: checked,
+        })
+    }
+}
+
+/// A `[profiles.<name>]` table, or a profile store file, as written.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProfileFile {
+    uuid: Option<Spanned<String>>,
+    seed: Option<Spanned<String>>,
+    serial_format: Option<Spanned<String>>,
+    mac_oui: Option<Spanned<String>>,
+    properties: Option<BTreeMap<String, Spanned<toml::Value>>>,
+    mac_addresses: Option<BTreeMap<String, Spanned<String>>>,
+}
+
+/// A `[[rules]]` entry as written.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RuleFile {
+    profile: Spanned<String>,
+    exe: Option<Spanned<String>>,
+    bundle_id: Option<Spanned<String>>,
+    parent: Option<Spanned<String>>,
+}
+
+/// What is wrong with a profile or rule, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileError {
+    pub line: usize,
+    /// The dotted path of the offending key.
+    pub key: String,
+    pub reason: String,
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}: {}", self.line, self.key, self.reason)
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source.as_bytes()[..offset.min(source.len())]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count()
+        + 1
+}
+
+/// The line of byte `offset` in `source`, and the dotted path of the key written there
+/// (or of the table, on a header line), for errors that only come with a position.
+pub fn locate(source: &str, offset: usize) -> (usize, String) {
+    let line = line_of(source, offset);
+    let mut table = String::new();
+    let mut arrays: BTreeMap<String, usize> = BTreeMap::new();
+    let mut key = String::new();
+    for text in source.lines().take(line) {
+        let text = text.trim();
+        key.clear();
+        if let Some(name) = text.strip_prefix("[[").and_then(|t| t.split("]]").next()) {
+            let name = name.trim().to_string();
+            let index = arrays.entry(name.clone()).or_insert(0);
+            table = format!("{}[{}]", name, index);
+            *index += 1;
+        } else if let Some(name) = text.strip_prefix('[').and_then(|t| t.split(']').next()) {
+            table = name.trim().to_string();
+        } else if let Some((name, _)) = text.split_once('=') {
+            key = name.trim().to_string();
+        }
+    }
+    let path = match (table.is_empty(), key.is_empty()) {
+        (_, true) => table,
+        (true, false) => key,
+        (false, false) => format!("{}.{}", table, key),
+    };
+    (line, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::PropertyValue;
+
+    #[derive(Deserialize)]
+    struct Document {
+        #[serde(default)]
+        profiles: BTreeMap<String, ProfileFile>,
+        #[serde(default)]
+        rules: Vec<Spanned<RuleFile>>,
+    }
+
+    fn parse(source: &str) -> Result<Profiles, ProfileError> {
+        let document: Document = toml::from_str(source).map_err(|e| {
+            let (line, key) = locate(source, e.span().unwrap().start);
+            ProfileError {
+                line,
+                key,
+                reason: e.message().to_string(),
+            }
+        })?;
+        Profiles::from_file(
+            Path::new("config.toml"),
+            document.profiles,
+            document.rules,
+            source,
+        )
+    }
+
+    const CONFIG: &str = r#"
+[profiles.work]
+uuid = "11111111-2222-3333-4444-555555555555"
+[profiles.work.properties]
+IOPlatformSerialNumber = "C02WORK00001"
+
+[profiles.chat]
+seed = "chat secret"
+
+[profiles.build]
+
+[[rules]]
+profile = "chat"
+bundle_id = "com.tinyspeck.*"
+
+[[rules]]
+profile = "work"
+exe = "/Applications/**"
+
+[[rules]]
+profile = "build"
+exe = "cargo"
+parent = "/usr/bin/*sh"
+"#;
+
+    fn process(exe: &str, bundle_id: Option<&str>, parent: Option<&str>) -> Process {
+        Process {
+            exe: Some(PathBuf::from(exe)),
+            bundle_id: bundle_id.map(str::to_string),
+            parent: parent.map(PathBuf::from),
+        }
+    }
+
+    fn selected(profiles: &Profiles, process: &Process) -> Option<String> {
+        profiles
+            .select(process)
+            .map(|(_, profile)| profile.name.clone())
+    }
+
+    #[test]
+    fn reads_profiles() {
+        let profiles = parse(CONFIG).unwrap();
+        let work = profiles.get("work").unwrap();
+        assert_eq!(
+            work.uuid.as_deref(),
+            Some("11111111-2222-3333-4444-555555555555")
+        );
+        assert_eq!(
+            work.identity.get("IOPlatformSerialNumber"),
+            Some(&PropertyValue::String("C02WORK00001".into()))
+        );
+        assert_eq!(
+            profiles.get("chat").unwrap().seed.as_deref(),
+            Some("chat secret")
+        );
+        let build = profiles.get("build").unwrap();
+        assert!(build.uuid.is_none() && build.seed.is_none() && build.identity.is_empty());
+        assert_eq!(
+            profiles.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(),
+            ["build", "chat", "work"]
+        );
+        assert_eq!(
+            profiles.rules().iter().map(|r| r.line).collect::<Vec<_>>(),
+            [12, 16, 20]
+        );
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins() {
+        let profiles = parse(CONFIG).unwrap();
+        let slack = process(
+            "/Applications/Slack.app/Contents/MacOS/Slack",
+            Some("com.tinyspeck.slackmacgap"),
+            None,
+        );
+        assert_eq!(selected(&profiles, &slack).as_deref(), Some("chat"));
+        let safari = process(
+            "/Applications/Safari.app/Contents/MacOS/Safari",
+            Some("com.apple.Safari"),
+            None,
+        );
+        assert_eq!(selected(&profiles, &safari).as_deref(), Some("work"));
+        assert_eq!(
+            selected(&profiles, &process("/usr/bin/true", None, None)),
+            None
+        );
+    }
+
+    #[test]
+    fn every_criterion_of_a_rule_must_match() {
+        let profiles = parse(CONFIG).unwrap();
+        let cargo = |parent| process("/home/me/.cargo/bin/cargo", None, parent);
+        // `exe = "cargo"` has no slash, so it matches the file name.
+        assert_eq!(
+            selected(&profiles, &cargo(Some("/usr/bin/zsh"))).as_deref(),
+            Some("build")
+        );
+        assert_eq!(selected(&profiles, &cargo(Some("/usr/bin/make"))), None);
+        // An unknown parent matches no pattern.
+        assert_eq!(selected(&profiles, &cargo(None)), None);
+        // Nor does a missing bundle identifier.
+        let profiles =
+            parse("[profiles.p]\n[[rules]]\nprofile = \"p\"\nbundle_id = \"*\"\n").unwrap();
+        assert_eq!(selected(&profiles, &process("/bin/ls", None, None)), None);
+        assert_eq!(
+            selected(&profiles, &process("/bin/ls", Some("x"), None)).as_deref(),
+            Some("p")
+        );
+    }
+
+    fn error(source: &str) -> (usize, String, String) {
+        let error = parse(source).unwrap_err();
+        (error.line, error.key, error.reason)
+    }
+
+    #[test]
+    fn errors_name_the_line_and_key() {
+        assert_eq!(
+            error("[profiles.work]\nuuid = \"1234\"\n"),
+            (
+                2,
+                "profiles.work.uuid".to_string(),
+                "invalid UUID \"1234\": expected 36 characters, got 4".to_string()
+            )
+        );
+        assert_eq!(
+            error("[profiles.work]\nseed = \"\"\n").1,
+            "profiles.work.seed"
+        );
+        let (line, key, reason) =
+            error("[profiles.work.properties]\nmodel = \"x\"\nIOMACAddress = \"nope\"\n");
+        assert_eq!(
+            (line, key.as_str()),
+            (3, "profiles.work.properties.IOMACAddress")
+        );
+        assert!(reason.contains("not a MAC address"), "{}", reason);
+
+        let source = "[profiles.a]\n\n[[rules]]\nprofile = \"a\"\nexe = \"x\"\n\n[[rules]]\nprofile = \"b\"\nexe = \"y\"\n";
+        assert_eq!(
+            error(source),
+            (
+                8,
+                "rules[1].profile".to_string(),
+                "no profile named \"b\"".to_string()
+            )
+        );
+        assert_eq!(
+            error("[profiles.a]\n[[rules]]\nprofile = \"a\"\nexe = \"[x\"\n"),
+            (
+                4,
+                "rules[0].exe".to_string(),
+                "invalid pattern \"[x\": '[' at position 0 is never closed".to_string()
+            )
+        );
+        assert_eq!(
+            error("[profiles.a]\n[[rules]]\nprofile = \"a\"\n"),
+            (
+                2,
+                "rules[0]".to_string(),
+                "a rule needs at least one of exe, bundle_id or parent".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn serial_formats_are_checked_or_generated() {
+        let profiles = parse(
+            "[profiles.given]\nserial_format = \"legacy12\"\n\
+             [profiles.given.properties]\nIOPlatformSerialNumber = \"C02J74RZJYVX\"\n\
+             [profiles.made]\nuuid = \"11111111-2222-3333-4444-555555555555\"\n\
+             serial_format = \"legacy12\"\n\
+             [profiles.made.properties]\nmodel = \"MacBookPro15,1\"\n\
+             [profiles.new]\nserial_format = \"random10\"\n",
+        )
+        .unwrap();
+        let given = profiles.get("given").unwrap();
+        assert_eq!(given.serial_format, Some(SerialFormat::Legacy12));
+        assert_eq!(
+            given.identity.text(IO_PLATFORM_SERIAL_NUMBER_KEY),
+            Some("C02J74RZJYVX")
+        );
+
+        // A generated serial suits the model and is the same every time.
+        let made = profiles.get("made").unwrap();
+        let serial = made.identity.text(IO_PLATFORM_SERIAL_NUMBER_KEY).unwrap();
+        let parsed = Serial::parse_as(serial, SerialFormat::Legacy12).unwrap();
+        assert!(
+            matches!(parsed.model_code(), Some("MD6M" | "MD6N")),
+            "{}",
+            serial
+        );
+        let again = parse(
+            "[profiles.made]\nuuid = \"11111111-2222-3333-4444-555555555555\"\n\
+             serial_format = \"legacy12\"\n\
+             [profiles.made.properties]\nmodel = \"MacBookPro15,1\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            again
+                .get("made")
+                .unwrap()
+                .identity
+                .text(IO_PLATFORM_SERIAL_NUMBER_KEY),
+            Some(serial)
+        );
+        let new = profiles.get("new").unwrap();
+        let serial = new.identity.text(IO_PLATFORM_SERIAL_NUMBER_KEY).unwrap();
+        assert!(
+            Serial::parse_as(serial, SerialFormat::Random10).is_ok(),
+            "{}",
+            serial
+        );
+
+        assert_eq!(
+            error(
+                "[profiles.p]\nserial_format = \"legacy11\"\n\
+                 [profiles.p.properties]\nIOPlatformSerialNumber = \"C02J74RZJYVX\"\n"
+            ),
+            (
+                4,
+                "profiles.p.properties.IOPlatformSerialNumber".to_string(),
+                "not a legacy11 serial number: expected 11 characters for legacy11, got 12"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            error("[profiles.p]\nserial_format = \"legacy\"\n"),
+            (
+                2,
+                "profiles.p.serial_format".to_string(),
+                "unknown serial format \"legacy\"; expected legacy11, legacy12 or random10"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            error(
+                "[profiles.p]\nserial_format = \"legacy12\"\n\
+                 [profiles.p.properties]\nmodel = \"Mac14,2\"\n"
+            ),
+            (
+                2,
+                "profiles.p.serial_format".to_string(),
+                "Mac14,2 was only made with random10 serial numbers".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn mac_addresses_are_read_or_generated() {
+        let source = "[profiles.p]\nseed = \"secret\"\nmac_oui = \"a4:83:e7\"\n\
+                      [profiles.p.mac_addresses]\nen0 = \"0A:00:00:00:00:01\"\n\
+                      en1 = \"generate\"\n\"*\" = \"generate\"\n\
+                      [profiles.q.mac_addresses]\nen1 = \"generate\"\n";
+        let profiles = parse(source).unwrap();
+        let p = &profiles.get("p").unwrap().mac_addresses;
+        assert_eq!(
+            p.for_interface("en0"),
+            Some(MacAddress([0x0a, 0, 0, 0, 0, 1]))
+        );
+        let en1 = p.for_interface("en1").unwrap();
+        assert_eq!(en1.0[..3], [0xa4, 0x83, 0xe7]);
+        assert!(p.for_interface("en7").is_some());
+        // Generated addresses depend on the profile, and are the same every time.
+        let q = &profiles.get("q").unwrap().mac_addresses;
+        let q_en1 = q.for_interface("en1").unwrap();
+        assert!(q_en1.is_locally_administered(), "{}", q_en1);
+        assert_ne!(q_en1, en1);
+        assert_eq!(q.for_interface("en7"), None);
+        assert_eq!(parse(source).unwrap().get("p").unwrap().mac_addresses, *p);
+
+        for (source, expected) in [
+            (
+                "[profiles.p.mac_addresses]\n\"*\" = \"02:00:00:00:00:01\"\n",
+                (
+                    2,
+                    "profiles.p.mac_addresses.*",
+                    "other interfaces can only be \"generate\"",
+                ),
+            ),
+            (
+                "[profiles.p.mac_addresses]\nen0 = \"01:00:5e:00:00:01\"\n",
+                (
+                    2,
+                    "profiles.p.mac_addresses.en0",
+                    "01:00:5e:00:00:01 cannot be an interface's own address",
+                ),
+            ),
+            (
+                "[profiles.p.mac_addresses]\nen0 = \"random\"\n",
+                (
+                    2,
+                    "profiles.p.mac_addresses.en0",
+                    "\"random\" is not a MAC address like aa:bb:cc:dd:ee:ff",
+                ),
+            ),
+            (
+                "[profiles.p.mac_addresses]\n\"en 0\" = \"generate\"\n",
+                (
+                    2,
+                    "profiles.p.mac_addresses.en 0",
+                    "\"en 0\" is not an interface name",
+                ),
+            ),
+            (
+                "[profiles.p]\nmac_oui = \"03:00:00\"\n",
+                (2, "profiles.p.mac_oui", "03:00:00 is a multicast prefix"),
+            ),
+        ] {
+            let (line, key, reason) = error(source);
+            assert_eq!(
+                (line, key.as_str(), reason.as_str()),
+                expected,
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_tables_are_located() {
+        let (line, key, reason) = error("[profiles.work]\nuuid = \"x\"\nexe = \"y\"\n");
+        assert_eq!((line, key.as_str()), (3, "profiles.work.exe"));
+        assert!(reason.starts_with("unknown field `exe`"), "{}", reason);
+
+        let (line, key, reason) = error("[profiles.a]\n[[rules]]\nprofile = \"a\"\nexe = 3\n");
+        assert_eq!((line, key.as_str()), (4, "rules[0].exe"));
+        assert!(
+            reason.starts_with("invalid type: integer `3`"),
+            "{}",
+            reason
+        );
+
+        let (line, key, reason) = error("[[rules]]\nexe = \"a\"\n");
+        assert_eq!((line, key.as_str()), (1, "rules[0]"));
+        assert_eq!(reason, "missing field `profile`");
+    }
+
+    #[test]
+    fn locate_tracks_tables_and_arrays() {
+        let source = "top = 1\n[a.b]\nc = 2\n[[r]]\n[[r]]\nd = 3\n";
+        let offset = |text: &str| source.find(text).unwrap();
+        assert_eq!(locate(source, offset("1")), (1, "top".to_string()));
+        assert_eq!(locate(source, offset("2")), (3, "a.b.c".to_string()));
+        assert_eq!(locate(source, offset("d")), (6, "r[1].d".to_string()));
+        assert_eq!(locate(source, offset("[[r]]")), (4, "r[0]".to_string()));
+    }
+}
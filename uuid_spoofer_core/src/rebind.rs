@@ -0,0 +1,485 @@
+//! Finds the pointer slots through which a Mach-O image calls imported functions, so the
+//! injected library can point them at its hooks (what fishhook does, in Rust).
+//!
+//! Classic binding goes through the symbol pointer sections (`__la_symbol_ptr`,
+//! `__nl_symbol_ptr`, `__got`): each pointer-sized entry has a matching entry in the
+//! indirect symbol table, which names the imported symbol. Everything here works on the
+//! file layout, so it runs on fixtures anywhere; the library maps the results onto a
+//! loaded image with the image's slide.
+
+use std::ops::Range;
+
+use crate::macho::{bytes_at, u32_at, MachO, MachOError};
+
+pub const LC_SEGMENT: u32 = 0x1;
+pub const LC_SYMTAB: u32 = 0x2;
+pub const LC_DYSYMTAB: u32 = 0xb;
+pub const LC_SEGMENT_64: u32 = 0x19;
+pub const LC_DYLD_CHAINED_FIXUPS: u32 = 0x8000_0034;
+
+pub const SECTION_TYPE: u32 = 0xff;
+pub const S_NON_LAZY_SYMBOL_POINTERS: u32 = 0x6;
+pub const S_LAZY_SYMBOL_POINTERS: u32 = 0x7;
+
+/// Indirect symbol table entries for slots that do not name a symbol.
+pub const INDIRECT_SYMBOL_LOCAL: u32 = 0x8000_0000;
+pub const INDIRECT_SYMBOL_ABS: u32 = 0x4000_0000;
+
+/// Segment flag: dyld makes the segment read-only once it has bound it (`__DATA_CONST`).
+pub const SG_READ_ONLY: u32 = 0x10;
+
+pub const VM_PROT_WRITE: u32 = 0x2;
+
+pub const LINKEDIT: &str = "__LINKEDIT";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub name: String,
+    pub vmaddr: u64,
+    pub vmsize: u64,
+    pub fileoff: u64,
+    pub filesize: u64,
+    pub initprot: u32,
+    pub flags: u32,
+}
+
+impl Segment {
+    /// Whether the slots in this segment are read-only by the time the library runs.
+    pub fn read_only_after_binding(&self) -> bool {
+        self.initprot & VM_PROT_WRITE == 0 || self.flags & SG_READ_ONLY != 0
+    }
+}
+
+/// A section of symbol pointers, each backed by an indirect symbol table entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PointerSection {
+    pub segment: usize,
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+    /// Index of the section's first entry in the indirect symbol table (`reserved1`).
+    pub indirect_index: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symtab {
+    pub symoff: u32,
+    pub nsyms: u32,
+    pub stroff: u32,
+    pub strsize: u32,
+}
+
+/// The parts of an image's load commands that rebinding needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageLayout {
+    pub is_64: bool,
+    pub big_endian: bool,
+    pub segments: Vec<Segment>,
+    pub pointer_sections: Vec<PointerSection>,
+    pub symtab: Option<Symtab>,
+    /// `(indirectsymoff, nindirectsyms)` from `LC_DYSYMTAB`.
+    pub indirect_symbols: Option<(u32, u32)>,
+    /// `(dataoff, datasize)` from `LC_DYLD_CHAINED_FIXUPS`; see `crate::chained_fixups`.
+    pub chained_fixups: Option<(u32, u32)>,
+}
+
+/// File ranges of the linkedit tables, all inside `__LINKEDIT` in a linked image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableRanges {
+    pub symbols: Range<u64>,
+    pub strings: Range<u64>,
+    pub indirect: Range<u64>,
+}
+
+/// The linkedit tables' contents, from a file or from a loaded image's memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Tables<'a> {
+    pub symbols: &'a [u8],
+    pub strings: &'a [u8],
+    pub indirect: &'a [u8],
+}
+
+/// A pointer to overwrite: the one at `address` (unslid, in `segments[segment]`) imports
+/// `names[name]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slot {
+    pub name: usize,
+    pub segment: usize,
+    pub address: u64,
+    /// Holds an arm64e signed pointer, which an unsigned replacement would break.
+    pub authenticated: bool,
+}
+
+fn fixed_name(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+impl ImageLayout {
+    pub fn parse(macho: &MachO<'_>) -> Result<Self, MachOError> {
+        let mut layout = ImageLayout {
+            is_64: macho.is_64,
+            big_endian: macho.big_endian,
+            segments: Vec::new(),
+            pointer_sections: Vec::new(),
+            symtab: None,
+            indirect_symbols: None,
+            chained_fixups: None,
+        };
+        for command in macho.load_commands()? {
+            let at = command.offset;
+            match command.cmd {
+                LC_SEGMENT_64 | LC_SEGMENT => layout.parse_segment(macho, at)?,
+                LC_SYMTAB => {
+                    layout.symtab = Some(Symtab {
+                        symoff: macho.u32_at(at + 8)?,
+                        nsyms: macho.u32_at(at + 12)?,
+                        stroff: macho.u32_at(at + 16)?,
+                        strsize: macho.u32_at(at + 20)?,
+                    })
+                }
+                LC_DYSYMTAB => {
+                    layout.indirect_symbols = Some((macho.u32_at(at + 56)?, macho.u32_at(at + 60)?))
+                }
+                LC_DYLD_CHAINED_FIXUPS => {
+                    layout.chained_fixups = Some((macho.u32_at(at + 8)?, macho.u32_at(at + 12)?))
+                }
+                _ => {}
+            }
+        }
+        Ok(layout)
+    }
+
+    // segment_command(_64) followed by nsects section(_64) structs.
+    fn parse_segment(&mut self, macho: &MachO<'_>, at: usize) -> Result<(), MachOError> {
+        let data = macho.data();
+        let name = fixed_name(bytes_at(data, at + 8, 16)?);
+        let (word, words) = if self.is_64 {
+            (8, 16 + 4 * 8)
+        } else {
+            (4, 16 + 4 * 4)
+        };
+        let address = |offset: usize| -> Result<u64, MachOError> {
+            if self.is_64 {
+                macho.u64_at(offset)
+            } else {
+                macho.u32_at(offset).map(u64::from)
+            }
+        };
+        let fields = at + 24;
+        let segment = Segment {
+            name,
+            vmaddr: address(fields)?,
+            vmsize: address(fields + word)?,
+            fileoff: address(fields + 2 * word)?,
+            filesize: address(fields + 3 * word)?,
+            initprot: macho.u32_at(at + 8 + words + 4)?,
+            flags: macho.u32_at(at + 8 + words + 12)?,
+        };
+        let nsects = macho.u32_at(at + 8 + words + 8)? as usize;
+        let header = 8 + words + 16;
+        let section_size = if self.is_64 { 80 } else { 68 };
+        let index = self.segments.len();
+        for i in 0..nsects {
+            let section = at + header + i * section_size;
+            // sectname[16], segname[16], addr, size, offset, align, reloff, nreloc, flags,
+            // reserved1...
+            let flags_at = section + 32 + 2 * word + 4 * 4;
+            let flags = macho.u32_at(flags_at)?;
+            let kind = flags & SECTION_TYPE;
+            if kind != S_LAZY_SYMBOL_POINTERS && kind != S_NON_LAZY_SYMBOL_POINTERS {
+                continue;
+            }
+            self.pointer_sections.push(PointerSection {
+                segment: index,
+                name: fixed_name(bytes_at(data, section, 16)?),
+                addr: address(section + 32)?,
+                size: address(section + 32 + word)?,
+                indirect_index: macho.u32_at(flags_at + 4)?,
+            });
+        }
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    pub fn pointer_size(&self) -> usize {
+        if self.is_64 {
+            8
+        } else {
+            4
+        }
+    }
+
+    fn nlist_size(&self) -> usize {
+        if self.is_64 {
+            16
+        } else {
+            12
+        }
+    }
+
+    pub fn linkedit(&self) -> Option<&Segment> {
+        self.segments
+            .iter()
+            .find(|segment| segment.name == LINKEDIT)
+    }
+
+    /// The unslid address at which linkedit data at file offset `fileoff` is mapped.
+    /// Not bounded by the segment's size: images in the shared cache point into the
+    /// cache's common linkedit region, which their own `__LINKEDIT` does not cover.
+    pub fn linkedit_address(&self, fileoff: u64) -> Option<u64> {
+        let linkedit = self.linkedit()?;
+        Some(linkedit.vmaddr + fileoff.checked_sub(linkedit.fileoff)?)
+    }
+
+    /// Where the symbol, string and indirect symbol tables are, or `None` if the image
+    /// has no symbol tables (and so nothing to rebind).
+    pub fn table_ranges(&self) -> Option<TableRanges> {
+        let symtab = self.symtab?;
+        let (indirectsymoff, nindirectsyms) = self.indirect_symbols?;
+        let range = |offset: u32, len: u64| offset as u64..offset as u64 + len;
+        Some(TableRanges {
+            symbols: range(
+                symtab.symoff,
+                symtab.nsyms as u64 * self.nlist_size() as u64,
+            ),
+            strings: range(symtab.stroff, symtab.strsize as u64),
+            indirect: range(indirectsymoff, nindirectsyms as u64 * 4),
+        })
+    }
+
+    /// Reads the tables out of a whole file (or fat slice).
+    pub fn tables_in_file<'a>(&self, data: &'a [u8]) -> Result<Option<Tables<'a>>, MachOError> {
+        let Some(ranges) = self.table_ranges() else {
+            return Ok(None);
+        };
+        let slice = |range: &Range<u64>| {
+            bytes_at(
+                data,
+                range.start as usize,
+                (range.end - range.start) as usize,
+            )
+        };
+        Ok(Some(Tables {
+            symbols: slice(&ranges.symbols)?,
+            strings: slice(&ranges.strings)?,
+            indirect: slice(&ranges.indirect)?,
+        }))
+    }
+
+    // The symbol's name with the leading underscore the C compiler adds, without the NUL.
+    fn symbol_name<'a>(&self, tables: &Tables<'a>, symbol: u32) -> Option<&'a [u8]> {
+        let strx = u32_at(
+            tables.symbols,
+            symbol as usize * self.nlist_size(),
+            self.big_endian,
+        )
+        .ok()? as usize;
+        let rest = tables.strings.get(strx..)?;
+        let end = rest.iter().position(|&b| b == 0)?;
+        Some(&rest[..end])
+    }
+
+    /// Every slot importing one of `names` (C names, without the leading underscore), in
+    /// section order. Entries that do not resolve to a symbol are skipped.
+    pub fn import_slots(&self, tables: &Tables<'_>, names: &[&str]) -> Vec<Slot> {
+        let pointer_size = self.pointer_size() as u64;
+        let mut slots = Vec::new();
+        for section in &self.pointer_sections {
+            for entry in 0..section.size / pointer_size {
+                let Ok(symbol) = u32_at(
+                    tables.indirect,
+                    (section.indirect_index as usize + entry as usize) * 4,
+                    self.big_endian,
+                ) else {
+                    break;
+                };
+                if symbol & (INDIRECT_SYMBOL_LOCAL | INDIRECT_SYMBOL_ABS) != 0 {
+                    continue;
+                }
+                let Some(symbol_name) = self.symbol_name(tables, symbol) else {
+                    continue;
+                };
+                let Some(name) = names
+                    .iter()
+                    .position(|name| symbol_name.strip_prefix(b"_") == Some(name.as_bytes()))
+                else {
+                    continue;
+                };
+                slots.push(Slot {
+                    name,
+                    segment: section.segment,
+                    address: section.addr + entry * pointer_size,
+                    authenticated: false,
+                });
+            }
+        }
+        slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::{slices, CPU_TYPE_ARM64, CPU_TYPE_X86_64};
+    use crate::testing::fat;
+
+    // See the .yaml files next to them for how they are laid out.
+    const X86_64_DYLIB: &[u8] = include_bytes!("../tests/fixtures/x86_64_dylib");
+    const ARM64_EXECUTABLE: &[u8] = include_bytes!("../tests/fixtures/arm64_executable");
+
+    const NAMES: &[&str] = &[
+        "IORegistryEntryCreateCFProperty",
+        "IORegistryEntrySearchCFProperty",
+        "IORegistryEntryCreateCFProperties",
+        "gethostuuid",
+        "sysctlbyname",
+    ];
+
+    fn layout(data: &[u8]) -> ImageLayout {
+        ImageLayout::parse(&MachO::parse(data).unwrap()).unwrap()
+    }
+
+    fn slots(data: &[u8], names: &[&str]) -> Vec<(String, String, u64)> {
+        let layout = layout(data);
+        let tables = layout.tables_in_file(data).unwrap().unwrap();
+        layout
+            .import_slots(&tables, names)
+            .into_iter()
+            .map(|slot| {
+                (
+                    names[slot.name].to_string(),
+                    layout
+                        .pointer_sections
+                        .iter()
+                        .find(|section| {
+                            (section.addr..section.addr + section.size).contains(&slot.address)
+                        })
+                        .unwrap()
+                        .name
+                        .clone(),
+                    slot.address,
+                )
+            })
+            .collect()
+    }
+
+    fn owned(expected: &[(&str, &str, u64)]) -> Vec<(String, String, u64)> {
+        expected
+            .iter()
+            .map(|&(name, section, address)| (name.to_string(), section.to_string(), address))
+            .collect()
+    }
+
+    #[test]
+    fn parses_segments_and_pointer_sections() {
+        let layout = layout(X86_64_DYLIB);
+        assert_eq!(
+            layout
+                .segments
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>(),
+            ["__TEXT", "__DATA_CONST", "__DATA", "__LINKEDIT"]
+        );
+        assert_eq!(
+            layout.pointer_sections,
+            [
+                PointerSection {
+                    segment: 1,
+                    name: "__got".into(),
+                    addr: 0x1000,
+                    size: 16,
+                    indirect_index: 0,
+                },
+                PointerSection {
+                    segment: 2,
+                    name: "__la_symbol_ptr".into(),
+                    addr: 0x2000,
+                    size: 24,
+                    indirect_index: 2,
+                },
+            ]
+        );
+        assert!(layout.segments[1].read_only_after_binding());
+        assert!(!layout.segments[2].read_only_after_binding());
+        assert_eq!(layout.linkedit_address(0x3000 + 0x50), Some(0x3050));
+        assert_eq!(layout.linkedit_address(0x2000), None);
+        assert_eq!(
+            layout.table_ranges(),
+            Some(TableRanges {
+                symbols: 0x3000..0x3050,
+                strings: 0x3064..0x30d0,
+                indirect: 0x3050..0x3064,
+            })
+        );
+    }
+
+    #[test]
+    fn finds_import_slots_in_a_dylib() {
+        assert_eq!(
+            slots(X86_64_DYLIB, NAMES),
+            owned(&[
+                ("sysctlbyname", "__got", 0x1000),
+                ("gethostuuid", "__la_symbol_ptr", 0x2000),
+                ("IORegistryEntryCreateCFProperty", "__la_symbol_ptr", 0x2008),
+                ("IORegistryEntrySearchCFProperty", "__la_symbol_ptr", 0x2010),
+            ])
+        );
+        // Only the requested names, and never a prefix match.
+        assert_eq!(
+            slots(X86_64_DYLIB, &["gethostuuid", "IORegistryEntryCreate"]),
+            owned(&[("gethostuuid", "__la_symbol_ptr", 0x2000)])
+        );
+    }
+
+    #[test]
+    fn finds_import_slots_in_an_executable() {
+        // The __got's second entry is INDIRECT_SYMBOL_ABS | INDIRECT_SYMBOL_LOCAL.
+        assert_eq!(
+            slots(ARM64_EXECUTABLE, NAMES),
+            owned(&[
+                ("gethostuuid", "__got", 0x1_0000_4000),
+                ("sysctlbyname", "__la_symbol_ptr", 0x1_0000_8000),
+                (
+                    "IORegistryEntryCreateCFProperties",
+                    "__nl_symbol_ptr",
+                    0x1_0000_8008
+                ),
+            ])
+        );
+        let layout = layout(ARM64_EXECUTABLE);
+        assert_eq!(layout.segments[0].name, "__PAGEZERO");
+        assert_eq!(layout.linkedit_address(0xc000), Some(0x1_0000_c000));
+    }
+
+    #[test]
+    fn finds_slots_in_each_fat_slice() {
+        let file = fat(&[
+            (CPU_TYPE_X86_64, X86_64_DYLIB),
+            (CPU_TYPE_ARM64, ARM64_EXECUTABLE),
+        ]);
+        let found: Vec<usize> = slices(&file)
+            .unwrap()
+            .iter()
+            .map(|slice| slots(slice.data, &["gethostuuid"]).len())
+            .collect();
+        assert_eq!(found, [1, 1]);
+    }
+
+    #[test]
+    fn tolerates_truncated_tables() {
+        let layout = layout(X86_64_DYLIB);
+        let tables = layout.tables_in_file(X86_64_DYLIB).unwrap().unwrap();
+        let short = Tables {
+            indirect: &tables.indirect[..8],
+            ..tables
+        };
+        // The indirect table stops after the __got entries.
+        assert_eq!(layout.import_slots(&short, NAMES).len(), 1);
+        assert!(matches!(
+            layout.tables_in_file(&X86_64_DYLIB[..0x3010]),
+            Err(MachOError::Truncated { .. })
+        ));
+    }
+}
@@ -0,0 +1,266 @@
+//! Static answers to "who reads my UUID": which identity APIs each architecture of a
+//! Mach-O file imports, and what its code signature says about injection.
+//!
+//! Imports are taken from the undefined symbols of the symbol table and, for images that
+//! use them, the chained fixups' import table. Everything works on the file's bytes, so
+//! `uuid_spoof scan` runs on any host.
+
+use crate::chained_fixups::ChainedFixups;
+use crate::macho::{self, bytes_at, CodeSignature, MachO, MachOError};
+use crate::rebind::ImageLayout;
+
+pub const MH_EXECUTE: u32 = 0x2;
+pub const MH_DYLIB: u32 = 0x6;
+pub const MH_BUNDLE: u32 = 0x8;
+
+// nlist n_type bits.
+const N_STAB: u8 = 0xe0;
+const N_TYPE: u8 = 0x0e;
+const N_UNDF: u8 = 0x0;
+const N_EXT: u8 = 0x1;
+
+/// A function through which a process can learn something about the machine's identity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdentityApi {
+    /// C name, without the leading underscore.
+    pub name: &'static str,
+    /// Whether the injected library hooks it.
+    pub hooked: bool,
+    pub reads: &'static str,
+}
+
+pub const IDENTITY_APIS: &[IdentityApi] = &[
+    IdentityApi {
+        name: "IORegistryEntryCreateCFProperty",
+        hooked: true,
+        reads: "IOPlatformUUID, serial number and other registry properties",
+    },
+    IdentityApi {
+        name: "IORegistryEntrySearchCFProperty",
+        hooked: true,
+        reads: "registry properties, searching parent entries",
+    },
+    IdentityApi {
+        name: "IORegistryEntryCreateCFProperties",
+        hooked: true,
+        reads: "all properties of a registry entry at once",
+    },
+    IdentityApi {
+        name: "gethostuuid",
+        hooked: true,
+        reads: "the host UUID",
+    },
+    IdentityApi {
+        name: "sysctlbyname",
+        hooked: true,
+        reads: "kern.uuid, hw.model and hw.target",
+    },
+    IdentityApi {
+        name: "sysctl",
+        hooked: false,
+        reads: "kern.uuid, hw.model and hw.target by MIB",
+    },
+    IdentityApi {
+        name: "IOServiceGetMatchingService",
+        hooked: false,
+        reads: "the IOPlatformExpertDevice entry",
+    },
+    IdentityApi {
+        name: "IORegistryEntryFromPath",
+        hooked: false,
+        reads: "registry entries by path",
+    },
+    IdentityApi {
+        name: "gethostid",
+        hooked: false,
+        reads: "the host ID",
+    },
+    IdentityApi {
+        name: "getifaddrs",
+        hooked: true,
+        reads: "network interface MAC addresses",
+    },
+];
+
+/// What one architecture of a file imports and how it is signed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SliceScan {
+    pub cputype: i32,
+    pub filetype: u32,
+    pub identity_imports: Vec<IdentityApi>,
+    pub signature: Option<CodeSignature>,
+}
+
+/// The `MH_*` file type as a word, for reports.
+pub fn filetype_name(filetype: u32) -> String {
+    match filetype {
+        MH_EXECUTE => "executable".to_string(),
+        MH_DYLIB => "dylib".to_string(),
+        MH_BUNDLE => "bundle".to_string(),
+        other => format!("filetype {:#x}", other),
+    }
+}
+
+/// Names of the symbols the image imports, with the leading underscore, in order of first
+/// appearance.
+pub fn imported_symbols(macho: &MachO<'_>) -> Result<Vec<String>, MachOError> {
+    let layout = ImageLayout::parse(macho)?;
+    let data = macho.data();
+    let mut names: Vec<String> = Vec::new();
+    if let Some(symtab) = layout.symtab {
+        let nlist_size = if layout.is_64 { 16 } else { 12 };
+        let symbols = bytes_at(
+            data,
+            symtab.symoff as usize,
+            symtab.nsyms as usize * nlist_size,
+        )?;
+        let strings = bytes_at(data, symtab.stroff as usize, symtab.strsize as usize)?;
+        for entry in symbols.chunks_exact(nlist_size) {
+            let n_type = entry[4];
+            if n_type & N_STAB != 0 || n_type & N_TYPE != N_UNDF || n_type & N_EXT == 0 {
+                continue;
+            }
+            let strx = macho::u32_at(entry, 0, layout.big_endian)? as usize;
+            let Some(rest) = strings.get(strx..) else {
+                continue;
+            };
+            let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+            let name = String::from_utf8_lossy(&rest[..end]).into_owned();
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    if let Some(fixups) = ChainedFixups::parse(&layout, data)? {
+        for import in fixups.imports {
+            if !names.contains(&import.name) {
+                names.push(import.name);
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The identity APIs among `symbols`, in `IDENTITY_APIS` order.
+pub fn identity_imports(symbols: &[String]) -> Vec<IdentityApi> {
+    IDENTITY_APIS
+        .iter()
+        .filter(|api| {
+            symbols
+                .iter()
+                .any(|symbol| symbol.strip_prefix('_') == Some(api.name))
+        })
+        .copied()
+        .collect()
+}
+
+/// Scans every architecture of a thin or fat file.
+pub fn scan(data: &[u8]) -> Result<Vec<SliceScan>, MachOError> {
+    macho::slices(data)?
+        .into_iter()
+        .map(|slice| {
+            let image = MachO::parse(slice.data)?;
+            Ok(SliceScan {
+                cputype: image.cputype,
+                filetype: image.filetype,
+                identity_imports: identity_imports(&imported_symbols(&image)?),
+                signature: image.code_signature()?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::macho::{CPU_TYPE_ARM64, CPU_TYPE_X86_64, CS_RUNTIME};
+    use crate::testing::{fat, signed_macho};
+
+    const X86_64_DYLIB: &[u8] = include_bytes!("../tests/fixtures/x86_64_dylib");
+    const ARM64_CHAINED_64_OFFSET: &[u8] =
+        include_bytes!("../tests/fixtures/arm64_chained_64_offset");
+
+    fn names(apis: &[IdentityApi]) -> Vec<&'static str> {
+        apis.iter().map(|api| api.name).collect()
+    }
+
+    #[test]
+    fn lists_undefined_symbols() {
+        let symbols = imported_symbols(&MachO::parse(X86_64_DYLIB).unwrap()).unwrap();
+        // The defined _fixture is not an import.
+        assert_eq!(
+            symbols,
+            [
+                "_gethostuuid",
+                "_sysctlbyname",
+                "_IORegistryEntryCreateCFProperty",
+                "_IORegistryEntrySearchCFProperty",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_chained_fixup_imports() {
+        let symbols = imported_symbols(&MachO::parse(ARM64_CHAINED_64_OFFSET).unwrap()).unwrap();
+        // The image has no symbol table; data imports are listed too.
+        assert_eq!(
+            symbols,
+            [
+                "_gethostuuid",
+                "_sysctlbyname",
+                "_IORegistryEntrySearchCFProperty",
+                "_kIOMasterPortDefault",
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_each_architecture() {
+        let file = fat(&[
+            (CPU_TYPE_X86_64, X86_64_DYLIB),
+            (CPU_TYPE_ARM64, ARM64_CHAINED_64_OFFSET),
+        ]);
+        let slices = scan(&file).unwrap();
+        assert_eq!(slices.len(), 2);
+        assert_eq!(slices[0].cputype, CPU_TYPE_X86_64);
+        assert_eq!(filetype_name(slices[0].filetype), "dylib");
+        assert_eq!(
+            names(&slices[0].identity_imports),
+            [
+                "IORegistryEntryCreateCFProperty",
+                "IORegistryEntrySearchCFProperty",
+                "gethostuuid",
+                "sysctlbyname",
+            ]
+        );
+        assert_eq!(filetype_name(slices[1].filetype), "executable");
+        assert_eq!(slices[1].signature, None);
+    }
+
+    #[test]
+    fn reports_the_signature() {
+        let slices = scan(&signed_macho(CPU_TYPE_ARM64, CS_RUNTIME, None)).unwrap();
+        assert!(slices[0].identity_imports.is_empty());
+        assert!(slices[0].signature.as_ref().unwrap().hardened_runtime());
+        assert!(matches!(
+            scan(b"\xca\xfe\xba\xbe\x00\x00\x00\x01"),
+            Err(MachOError::Truncated { .. })
+        ));
+    }
+
+    #[test]
+    fn matches_whole_names_only() {
+        let symbols = ["_sysctl".to_string(), "_gethostuuid_np".to_string()];
+        assert_eq!(names(&identity_imports(&symbols)), ["sysctl"]);
+    }
+
+    #[test]
+    fn flags_what_the_hooks_cover() {
+        let symbols = ["_getifaddrs".to_string(), "_sysctl".to_string()];
+        let hooked: Vec<_> = identity_imports(&symbols)
+            .iter()
+            .map(|api| (api.name, api.hooked))
+            .collect();
+        assert_eq!(hooked, [("sysctl", false), ("getifaddrs", true)]);
+    }
+}
@@ -0,0 +1,514 @@
+//! Apple-style serial numbers: generating them and checking their structure.
+//!
+//! Three formats are in use, told apart by length:
+//! - 11 characters, on Macs made until 2010: a 2-character factory code, the year's last
+//!   digit, a two-digit week, a 3-character unit number and a 3-character model code,
+//!   e.g. `W8` `8` `23` `4GH` `0PA`.
+//! - 12 characters, from 2010 to 2020: a 3-character factory code, a letter for the
+//!   half-year, one for the week within it, a 3-character unit number and a 4-character
+//!   model code, e.g. `C02` `J` `7` `4RZ` `JYVX`.
+//! - 10 characters, from 2021: random digits and consonants, with no structure to check.
+//!
+//! Checkers that validate serials look at the length, at each field's alphabet and range,
+//! and at whether the model code and date suit the machine; [`Serial::made_in`] and
+//! [`Serial::model_code`] answer the last two.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::catalog::Model;
+use crate::generate::expand;
+
+/// The layout of a serial number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialFormat {
+    Legacy11,
+    Legacy12,
+    Random10,
+}
+
+impl SerialFormat {
+    pub const ALL: [SerialFormat; 3] = [
+        SerialFormat::Legacy11,
+        SerialFormat::Legacy12,
+        SerialFormat::Random10,
+    ];
+
+    /// The number of characters.
+    pub fn length(self) -> usize {
+        match self {
+            SerialFormat::Legacy11 => 11,
+            SerialFormat::Legacy12 => 12,
+            SerialFormat::Random10 => 10,
+        }
+    }
+
+    /// The name used in profiles and on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            SerialFormat::Legacy11 => "legacy11",
+            SerialFormat::Legacy12 => "legacy12",
+            SerialFormat::Random10 => "random10",
+        }
+    }
+
+    /// The first and last year Apple issued serial numbers in this format.
+    pub fn years(self) -> (u16, u16) {
+        match self {
+            SerialFormat::Legacy11 => (1998, 2010),
+            SerialFormat::Legacy12 => (2010, 2020),
+            SerialFormat::Random10 => (2021, u16::MAX),
+        }
+    }
+
+    fn fields(self) -> &'static [Field] {
+        match self {
+            SerialFormat::Legacy11 => &LEGACY_11,
+            SerialFormat::Legacy12 => &LEGACY_12,
+            SerialFormat::Random10 => &RANDOM_10,
+        }
+    }
+}
+
+impl fmt::Display for SerialFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for SerialFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, String> {
+        SerialFormat::ALL
+            .into_iter()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown serial format {:?}; expected legacy11, legacy12 or random10",
+                    name
+                )
+            })
+    }
+}
+
+/// Why a string is not a serial number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SerialError {
+    /// The wrong number of characters, for the expected format if there was one.
+    Length {
+        expected: Option<SerialFormat>,
+        found: usize,
+    },
+    /// A field holds characters or a value its format does not allow.
+    InvalidField {
+        field: &'static str,
+        index: usize,
+        value: String,
+    },
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerialError::Length {
+                expected: Some(format),
+                found,
+            } => write!(
+                f,
+                "expected {} characters for {}, got {}",
+                format.length(),
+                format,
+                found
+            ),
+            SerialError::Length {
+                expected: None,
+                found,
+            } => write!(f, "expected 10, 11 or 12 characters, got {}", found),
+            SerialError::InvalidField {
+                field,
+                index,
+                value,
+            } => write!(f, "invalid {} {:?} at position {}", field, value, index),
+        }
+    }
+}
+
+impl std::error::Error for SerialError {}
+
+// Factory codes that start serial numbers.
+const LOCATIONS_11: &[&str] = &["4H", "CK", "G8", "QP", "RM", "W8", "YM"];
+const LOCATIONS_12: &[&str] = &["C02", "C07", "C17", "C1M", "D25", "FVF", "W80"];
+// One letter per half-year, from the first half of 2010, repeating every ten years.
+const YEAR_CHARS: &[u8] = b"CDFGHJKLMNPQRSTVWXYZ";
+// The week within the half-year.
+const WEEK_CHARS: &[u8] = b"123456789CDFGHJKLMNPQRTVWXY";
+// Unit numbers, model and factory codes leave out I and O.
+const CODE_CHARS: &[u8] = b"0123456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+// Randomized serials also leave out the other vowels.
+const RANDOM_CHARS: &[u8] = b"0123456789BCDFGHJKLMNPQRSTVWXYZ";
+
+// One field of a layout, and what it may hold.
+struct Field {
+    name: &'static str,
+    len: usize,
+    valid: fn(&str) -> bool,
+}
+
+fn all_in(alphabet: &'static [u8]) -> impl Fn(&str) -> bool {
+    move |text| text.bytes().all(|b| alphabet.contains(&b))
+}
+
+const fn field(name: &'static str, len: usize, valid: fn(&str) -> bool) -> Field {
+    Field { name, len, valid }
+}
+
+fn code(text: &str) -> bool {
+    all_in(CODE_CHARS)(text)
+}
+
+/// Whether `text` can end a 12-character serial number as its model code.
+pub(crate) fn is_model_code(text: &str) -> bool {
+    text.len() == 4 && code(text)
+}
+
+const LEGACY_11: [Field; 5] = [
+    field("factory code", 2, code),
+    field("year", 1, |text| text.bytes().all(|b| b.is_ascii_digit())),
+    field("week", 2, |text| {
+        text.bytes().all(|b| b.is_ascii_digit()) && matches!(text.parse(), Ok(1..=53))
+    }),
+    field("unit number", 3, code),
+    field("model code", 3, code),
+];
+
+const LEGACY_12: [Field; 5] = [
+    field("factory code", 3, code),
+    field("year", 1, |text| all_in(YEAR_CHARS)(text)),
+    field("week", 1, |text| all_in(WEEK_CHARS)(text)),
+    field("unit number", 3, code),
+    field("model code", 4, code),
+];
+
+const RANDOM_10: [Field; 10] = {
+    const CHARACTER: Field = field("character", 1, |text| all_in(RANDOM_CHARS)(text));
+    [CHARACTER; 10]
+};
+
+/// A serial number whose structure has been checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Serial<'a> {
+    text: &'a str,
+    format: SerialFormat,
+}
+
+impl<'a> Serial<'a> {
+    /// Checks `text` against the format its length implies.
+    pub fn parse(text: &'a str) -> Result<Self, SerialError> {
+        let length = text.chars().count();
+        let format = SerialFormat::ALL
+            .into_iter()
+            .find(|format| format.length() == length)
+            .ok_or(SerialError::Length {
+                expected: None,
+                found: length,
+            })?;
+        Serial::parse_as(text, format)
+    }
+
+    /// Checks `text` is a serial number in `format`.
+    pub fn parse_as(text: &'a str, format: SerialFormat) -> Result<Self, SerialError> {
+        let chars: Vec<char> = text.chars().collect();
+        if chars.len() != format.length() {
+            return Err(SerialError::Length {
+                expected: Some(format),
+                found: chars.len(),
+            });
+        }
+        let mut index = 0;
+        for field in format.fields() {
+            let value: String = chars[index..index + field.len].iter().collect();
+            if !(field.valid)(&value) {
+                return Err(SerialError::InvalidField {
+                    field: field.name,
+                    index,
+                    value,
+                });
+            }
+            index += field.len;
+        }
+        Ok(Serial { text, format })
+    }
+
+    pub fn format(&self) -> SerialFormat {
+        self.format
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.text
+    }
+
+    /// The trailing code naming the model; randomized serials have none.
+    pub fn model_code(&self) -> Option<&'a str> {
+        match self.format {
+            SerialFormat::Legacy11 => Some(&self.text[8..]),
+            SerialFormat::Legacy12 => Some(&self.text[8..]),
+            SerialFormat::Random10 => None,
+        }
+    }
+
+    /// Whether the serial's date code can stand for `year`. Dates repeat every ten years,
+    /// and randomized serials carry none, so those fit any year.
+    pub fn made_in(&self, year: u16) -> bool {
+        let code = self.text.as_bytes();
+        match self.format {
+            SerialFormat::Legacy11 => u16::from(code[2] - b'0') == year % 10,
+            SerialFormat::Legacy12 => YEAR_CHARS
+                .iter()
+                .position(|&c| c == code[3])
+                .is_some_and(|index| index as u16 / 2 == year % 10),
+            SerialFormat::Random10 => true,
+        }
+    }
+}
+
+impl fmt::Display for Serial<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.text)
+    }
+}
+
+/// Generates a serial number in `format` for `model`, drawing every random choice from
+/// `entropy`. Legacy serials end in one of the model's codes (the last three characters
+/// of it for 11-character ones) and are dated in a year the model was made.
+///
+/// Panics for a legacy format if the model has no serial codes; see [`Model::supports`].
+pub fn generate(format: SerialFormat, model: &Model, entropy: &[u8]) -> String {
+    assert!(
+        model.supports(format),
+        "{} has no {} serial numbers",
+        model.identifier,
+        format
+    );
+    let random = expand(entropy, "serial");
+    let pick = |chars: &[u8], byte: u8| chars[byte as usize % chars.len()] as char;
+
+    let (first, last) = model.years;
+    let year = first + u16::from(random[1]) % (last - first + 1);
+    let model_code = match format {
+        SerialFormat::Random10 => "",
+        _ => &model.serial_codes[random[7] as usize % model.serial_codes.len()],
+    };
+    let unit = random[4..7].iter().map(|&byte| pick(CODE_CHARS, byte));
+    match format {
+        SerialFormat::Legacy11 => {
+            let mut serial = LOCATIONS_11[random[0] as usize % LOCATIONS_11.len()].to_string();
+            serial.push(char::from(b'0' + (year % 10) as u8));
+            serial += &format!("{:02}", 1 + random[3] % 52);
+            serial.extend(unit);
+            serial.push_str(&model_code[1..]);
+            serial
+        }
+        SerialFormat::Legacy12 => {
+            let half = usize::from(random[2] & 1);
+            let mut serial = LOCATIONS_12[random[0] as usize % LOCATIONS_12.len()].to_string();
+            serial.push(YEAR_CHARS[(usize::from(year % 10) * 2 + half) % YEAR_CHARS.len()] as char);
+            serial.push(pick(WEEK_CHARS, random[3]));
+            serial.extend(unit);
+            serial.push_str(model_code);
+            serial
+        }
+        SerialFormat::Random10 => random[8..18]
+            .iter()
+            .map(|&byte| pick(RANDOM_CHARS, byte))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::catalog::Catalog;
+
+    // A small deterministic source of test cases, so the properties below are checked
+    // over the same few thousand inputs on every run.
+    struct Cases(u64);
+
+    impl Cases {
+        fn next(&mut self) -> u64 {
+            // SplitMix64.
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    const CASES: u64 = 2_000;
+
+    // The catalog models legacy serials can be made for.
+    fn legacy_models() -> Vec<&'static Model> {
+        Catalog::builtin()
+            .models()
+            .iter()
+            .filter(|model| model.supports(SerialFormat::Legacy12))
+            .collect()
+    }
+
+    #[test]
+    fn generated_serials_always_validate() {
+        let models = legacy_models();
+        let mut cases = Cases(1);
+        for _ in 0..CASES {
+            let model = models[cases.below(models.len())];
+            let entropy = cases.next().to_le_bytes();
+            for format in SerialFormat::ALL {
+                let serial = generate(format, model, &entropy);
+                let parsed = Serial::parse(&serial)
+                    .unwrap_or_else(|e| panic!("{} ({}): {}", serial, format, e));
+                assert_eq!(parsed.format(), format, "{}", serial);
+                assert_eq!(Serial::parse_as(&serial, format), Ok(parsed));
+            }
+        }
+    }
+
+    #[test]
+    fn generated_serials_suit_their_model() {
+        let models = legacy_models();
+        let mut cases = Cases(2);
+        for _ in 0..CASES {
+            let model = models[cases.below(models.len())];
+            let entropy = cases.next().to_le_bytes();
+            let made_by_model =
+                |serial: &Serial| (model.years.0..=model.years.1).any(|year| serial.made_in(year));
+
+            let serial = generate(SerialFormat::Legacy12, model, &entropy);
+            let parsed = Serial::parse(&serial).unwrap();
+            let code = parsed.model_code().unwrap();
+            assert!(model.serial_codes.iter().any(|c| c == code));
+            assert!(
+                made_by_model(&parsed),
+                "{} for {}",
+                serial,
+                model.identifier
+            );
+
+            let serial = generate(SerialFormat::Legacy11, model, &entropy);
+            let parsed = Serial::parse(&serial).unwrap();
+            let code = parsed.model_code().unwrap();
+            assert!(
+                model.serial_codes.iter().any(|c| c.ends_with(code)),
+                "{} for {}",
+                serial,
+                model.identifier
+            );
+            assert!(
+                made_by_model(&parsed),
+                "{} for {}",
+                serial,
+                model.identifier
+            );
+        }
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let model = legacy_models()[0];
+        for format in SerialFormat::ALL {
+            assert_eq!(
+                generate(format, model, b"entropy"),
+                generate(format, model, b"entropy")
+            );
+            assert_ne!(generate(format, model, b"a"), generate(format, model, b"b"));
+        }
+    }
+
+    #[test]
+    fn any_single_corruption_of_a_fixed_field_is_caught() {
+        // Replacing one character with one no field allows, or changing the length,
+        // always fails validation.
+        let mut cases = Cases(3);
+        for _ in 0..CASES {
+            let format = SerialFormat::ALL[cases.below(3)];
+            let serial = generate(format, legacy_models()[0], &cases.next().to_le_bytes());
+            let index = cases.below(serial.len());
+            let bad = ['I', 'O', 'a', '-', ' ', 'é'][cases.below(6)];
+            let mut corrupted: Vec<char> = serial.chars().collect();
+            corrupted[index] = bad;
+            let corrupted: String = corrupted.into_iter().collect();
+            assert!(Serial::parse(&corrupted).is_err(), "{}", corrupted);
+
+            let mut longer = serial.clone();
+            longer.insert(index, '0');
+            assert!(Serial::parse_as(&longer, format).is_err(), "{}", longer);
+            let mut shorter = serial.clone();
+            shorter.remove(index);
+            assert!(Serial::parse_as(&shorter, format).is_err(), "{}", shorter);
+        }
+    }
+
+    #[test]
+    fn fields_and_errors() {
+        let serial = Serial::parse("C02J74RZJYVX").unwrap();
+        assert_eq!(serial.format(), SerialFormat::Legacy12);
+        assert_eq!(serial.model_code(), Some("JYVX"));
+        // J is the second half of 2012 (and of 2022).
+        assert!(serial.made_in(2012) && serial.made_in(2022) && !serial.made_in(2013));
+
+        let serial = Serial::parse("W88234GH0PA").unwrap();
+        assert_eq!(serial.format(), SerialFormat::Legacy11);
+        assert_eq!(serial.model_code(), Some("0PA"));
+        assert!(serial.made_in(2008) && !serial.made_in(2009));
+
+        let serial = Serial::parse("H2WXK7F9QL").unwrap();
+        assert_eq!(serial.format(), SerialFormat::Random10);
+        assert_eq!(serial.model_code(), None);
+        assert!(serial.made_in(2023));
+
+        for (text, error) in [
+            ("C02J74RZ", "expected 10, 11 or 12 characters, got 8"),
+            ("C02B74RZJYVX", "invalid year \"B\" at position 3"),
+            ("C02J04RZJYVX", "invalid week \"0\" at position 4"),
+            ("C02J74RZJYVO", "invalid model code \"JYVO\" at position 8"),
+            ("c02J74RZJYVX", "invalid factory code \"c02\" at position 0"),
+            ("W88544GH0PA", "invalid week \"54\" at position 3"),
+            ("W8800", "expected 10, 11 or 12 characters, got 5"),
+            ("H2WXK7F9QA", "invalid character \"A\" at position 9"),
+        ] {
+            assert_eq!(
+                Serial::parse(text).unwrap_err().to_string(),
+                error,
+                "{}",
+                text
+            );
+        }
+        assert_eq!(
+            Serial::parse_as("H2WXK7F9QL", SerialFormat::Legacy12)
+                .unwrap_err()
+                .to_string(),
+            "expected 12 characters for legacy12, got 10"
+        );
+        assert_eq!(
+            Serial::parse("C02J74RZJYVé").unwrap_err(),
+            SerialError::InvalidField {
+                field: "model code",
+                index: 8,
+                value: "JYVé".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn formats_by_name() {
+        for format in SerialFormat::ALL {
+            assert_eq!(format.name().parse(), Ok(format));
+            assert_eq!(format.to_string(), format.name());
+        }
+        assert!("legacy13".parse::<SerialFormat>().is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Substitution of spoofed values into whole property dictionaries, as returned by
+//! `IORegistryEntryCreateCFProperties`.
+//!
+//! The hook wraps a CFMutableDictionary in [`PropertyDictionary`]; tests use a plain map.
+
+use crate::identity::{IdentityTable, PropertyValue};
+
+/// The operations substitution needs from a mutable property dictionary.
+pub trait PropertyDictionary {
+    fn contains_key(&self, key: &str) -> bool;
+
+    /// Replaces the value stored under `key`. Returns false if the value could not be set.
+    fn set(&mut self, key: &str, value: &PropertyValue) -> bool;
+}
+
+/// Overwrites every key of `dict` that `table` has a replacement for and returns how many
+/// were replaced. Keys the dictionary does not already have are left out, so an entry
+/// never gains properties it did not report.
+pub fn substitute_properties<D: PropertyDictionary + ?Sized>(
+    dict: &mut D,
+    table: &IdentityTable,
+) -> usize {
+    table
+        .iter()
+        .filter(|(key, value)| dict.contains_key(key) && dict.set(key, value))
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::{BOARD_ID_KEY, IO_PLATFORM_SERIAL_NUMBER_KEY, IO_PLATFORM_UUID_KEY};
+    use std::collections::BTreeMap;
+
+    #[derive(Default)]
+    struct MockDictionary {
+        entries: BTreeMap<String, PropertyValue>,
+        read_only: Vec<&'static str>,
+    }
+
+    impl PropertyDictionary for MockDictionary {
+        fn contains_key(&self, key: &str) -> bool {
+            self.entries.contains_key(key)
+        }
+
+        fn set(&mut self, key: &str, value: &PropertyValue) -> bool {
+            if self.read_only.contains(&key) {
+                return false;
+            }
+            self.entries.insert(key.to_string(), value.clone());
+            true
+        }
+    }
+
+    fn spoof_table() -> IdentityTable {
+        let mut table = IdentityTable::new();
+        table.insert(
+            IO_PLATFORM_UUID_KEY,
+            PropertyValue::String("DEADBEEF-DEAD-BEEF-DEAD-BEEFDEADBEEF".into()),
+        );
+        table.insert(
+            IO_PLATFORM_SERIAL_NUMBER_KEY,
+            PropertyValue::String("C02SPOOFED".into()),
+        );
+        table.insert(BOARD_ID_KEY, PropertyValue::Data(b"Mac-SPOOF\0".to_vec()));
+        table
+    }
+
+    fn platform_expert_dictionary() -> MockDictionary {
+        let mut dict = MockDictionary::default();
+        for (key, value) in [
+            (
+                IO_PLATFORM_UUID_KEY,
+                PropertyValue::String("11111111-2222-3333-4444-555555555555".into()),
+            ),
+            (
+                IO_PLATFORM_SERIAL_NUMBER_KEY,
+                PropertyValue::String("C02REAL".into()),
+            ),
+            ("IOBusyInterest", PropertyValue::String("busy".into())),
+        ] {
+            dict.entries.insert(key.to_string(), value);
+        }
+        dict
+    }
+
+    #[test]
+    fn replaces_present_keys_only() {
+        let mut dict = platform_expert_dictionary();
+        assert_eq!(substitute_properties(&mut dict, &spoof_table()), 2);
+        assert_eq!(
+            dict.entries[IO_PLATFORM_UUID_KEY],
+            PropertyValue::String("DEADBEEF-DEAD-BEEF-DEAD-BEEFDEADBEEF".into())
+        );
+        assert_eq!(
+            dict.entries[IO_PLATFORM_SERIAL_NUMBER_KEY],
+            PropertyValue::String("C02SPOOFED".into())
+        );
+        assert_eq!(
+            dict.entries["IOBusyInterest"],
+            PropertyValue::String("busy".into())
+        );
+        assert!(!dict.entries.contains_key(BOARD_ID_KEY));
+    }
+
+    #[test]
+    fn unrelated_dictionary_is_untouched() {
+        let mut dict = MockDictionary::default();
+        dict.entries.insert(
+            "IOInterfaceName".into(),
+            PropertyValue::String("en0".into()),
+        );
+        assert_eq!(substitute_properties(&mut dict, &spoof_table()), 0);
+        assert_eq!(dict.entries.len(), 1);
+    }
+
+    #[test]
+    fn failed_sets_are_not_counted() {
+        let mut dict = platform_expert_dictionary();
+        dict.read_only.push(IO_PLATFORM_SERIAL_NUMBER_KEY);
+        assert_eq!(substitute_properties(&mut dict, &spoof_table()), 1);
+        assert_eq!(
+            dict.entries[IO_PLATFORM_SERIAL_NUMBER_KEY],
+            PropertyValue::String("C02REAL".into())
+        );
+    }
+}
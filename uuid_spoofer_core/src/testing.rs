@@ -0,0 +1,100 @@
+//! Builders for synthetic binaries, shared by the unit tests here and by the tools'
+//! tests (through the `test-support` feature).
+
+use crate::macho::*;
+
+/// Builds a little-endian 64-bit image from raw load commands, with `tail`
+/// appended after them (for linkedit-style data referenced by offset).
+pub fn build_macho(cputype: i32, commands: &[Vec<u8>], tail: &[u8]) -> Vec<u8> {
+    let sizeofcmds: usize = commands.iter().map(Vec::len).sum();
+    let mut out = Vec::new();
+    for field in [
+        MH_MAGIC_64,
+        cputype as u32,
+        0,
+        2, // MH_EXECUTE
+        commands.len() as u32,
+        sizeofcmds as u32,
+        0,
+        0,
+    ] {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+    for command in commands {
+        out.extend_from_slice(command);
+    }
+    out.extend_from_slice(tail);
+    out
+}
+
+pub fn command(cmd: u32, body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&cmd.to_le_bytes());
+    out.extend_from_slice(&((8 + body.len()) as u32).to_le_bytes());
+    out.extend_from_slice(body);
+    out
+}
+
+pub fn superblob(flags: u32, entitlements: Option<&str>) -> Vec<u8> {
+    let mut blobs: Vec<(u32, Vec<u8>)> = Vec::new();
+    let mut code_directory = Vec::new();
+    for field in [CSMAGIC_CODEDIRECTORY, 44, 0x20400, flags] {
+        code_directory.extend_from_slice(&field.to_be_bytes());
+    }
+    code_directory.resize(44, 0);
+    blobs.push((CSSLOT_CODEDIRECTORY, code_directory));
+    if let Some(xml) = entitlements {
+        let mut blob = Vec::new();
+        blob.extend_from_slice(&CSMAGIC_EMBEDDED_ENTITLEMENTS.to_be_bytes());
+        blob.extend_from_slice(&((8 + xml.len()) as u32).to_be_bytes());
+        blob.extend_from_slice(xml.as_bytes());
+        blobs.push((CSSLOT_ENTITLEMENTS, blob));
+    }
+
+    let header_len = 12 + blobs.len() * 8;
+    let total = header_len + blobs.iter().map(|(_, b)| b.len()).sum::<usize>();
+    let mut out = Vec::new();
+    for field in [CSMAGIC_EMBEDDED_SIGNATURE, total as u32, blobs.len() as u32] {
+        out.extend_from_slice(&field.to_be_bytes());
+    }
+    let mut offset = header_len;
+    for (slot, blob) in &blobs {
+        out.extend_from_slice(&slot.to_be_bytes());
+        out.extend_from_slice(&(offset as u32).to_be_bytes());
+        offset += blob.len();
+    }
+    for (_, blob) in blobs {
+        out.extend_from_slice(&blob);
+    }
+    out
+}
+
+/// A 64-bit image whose only load command is LC_CODE_SIGNATURE.
+pub fn signed_macho(cputype: i32, flags: u32, entitlements: Option<&str>) -> Vec<u8> {
+    let signature = superblob(flags, entitlements);
+    let dataoff = 32 + 16;
+    let mut body = Vec::new();
+    body.extend_from_slice(&(dataoff as u32).to_le_bytes());
+    body.extend_from_slice(&(signature.len() as u32).to_le_bytes());
+    build_macho(cputype, &[command(LC_CODE_SIGNATURE, &body)], &signature)
+}
+
+pub fn fat(images: &[(i32, &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&FAT_MAGIC.to_be_bytes());
+    out.extend_from_slice(&(images.len() as u32).to_be_bytes());
+    let mut offset = 0x1000;
+    let mut placed = Vec::new();
+    for (cputype, image) in images {
+        for field in [*cputype as u32, 0, offset, image.len() as u32, 12] {
+            out.extend_from_slice(&field.to_be_bytes());
+        }
+        placed.push((offset as usize, *image));
+        offset = (offset + image.len() as u32 + 0xfff) & !0xfff;
+    }
+    for (at, image) in placed {
+        out.resize(at, 0);
+        out.extend_from_slice(image);
+    }
+    out
+}
@@ -0,0 +1,127 @@
+//! Conversions between the textual UUID the IOKit hook returns and the other forms the
+//! same identity is exposed in: the raw `uuid_t` of `gethostuuid(2)`, the
+//! NUL-terminated string of `sysctlbyname("kern.uuid")` and, on Linux, the undashed
+//! lowercase hex of `/etc/machine-id` and the 32-bit host ID of `gethostid(3)`.
+
+use crate::config::{validate_uuid, UuidFormatError};
+
+/// Length of the textual form, excluding any terminator.
+pub const UUID_STRING_LEN: usize = 36;
+
+/// Parses an 8-4-4-4-12 hex UUID into its 16 bytes, in textual order.
+pub fn uuid_to_bytes(uuid: &str) -> Result<[u8; 16], UuidFormatError> {
+    let canonical = validate_uuid(uuid)?;
+    let hex: Vec<u8> = canonical.bytes().filter(|&b| b != b'-').collect();
+    let mut bytes = [0u8; 16];
+    for (byte, pair) in bytes.iter_mut().zip(hex.chunks(2)) {
+        *byte = (hex_value(pair[0]) << 4) | hex_value(pair[1]);
+    }
+    Ok(bytes)
+}
+
+/// Formats 16 bytes as an uppercase 8-4-4-4-12 UUID, as IOKit reports them.
+pub fn uuid_from_bytes(bytes: &[u8; 16]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// The NUL-terminated form sysctl hands out, normalised like [`uuid_to_bytes`].
+pub fn uuid_to_c_string(uuid: &str) -> Result<[u8; UUID_STRING_LEN + 1], UuidFormatError> {
+    let canonical = validate_uuid(uuid)?;
+    let mut c_string = [0u8; UUID_STRING_LEN + 1];
+    c_string[..UUID_STRING_LEN].copy_from_slice(canonical.as_bytes());
+    Ok(c_string)
+}
+
+/// The 32 lowercase hex digits, without dashes, that make up a systemd machine ID.
+pub fn uuid_to_machine_id(uuid: &str) -> Result<String, UuidFormatError> {
+    let canonical = validate_uuid(uuid)?;
+    Ok(canonical
+        .chars()
+        .filter(|&c| c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect())
+}
+
+/// The host ID `gethostid(3)` returns: the UUID's first four bytes, read big-endian so
+/// that `hostid` prints the UUID's first eight hex digits.
+pub fn uuid_to_host_id(uuid: &str) -> Result<u32, UuidFormatError> {
+    let bytes = uuid_to_bytes(uuid)?;
+    Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: &str = "DEADBEEF-0123-4567-89AB-CDEF00112233";
+    const BYTES: [u8; 16] = [
+        0xde, 0xad, 0xbe, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x00, 0x11, 0x22,
+        0x33,
+    ];
+
+    #[test]
+    fn string_to_bytes() {
+        assert_eq!(uuid_to_bytes(UUID).unwrap(), BYTES);
+        assert_eq!(uuid_to_bytes(&UUID.to_lowercase()).unwrap(), BYTES);
+    }
+
+    #[test]
+    fn bytes_to_string() {
+        assert_eq!(uuid_from_bytes(&BYTES), UUID);
+    }
+
+    #[test]
+    fn round_trips() {
+        for bytes in [[0u8; 16], [0xff; 16], BYTES] {
+            assert_eq!(uuid_to_bytes(&uuid_from_bytes(&bytes)).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn c_string_is_terminated_and_uppercase() {
+        let c_string = uuid_to_c_string(&UUID.to_lowercase()).unwrap();
+        assert_eq!(&c_string[..UUID_STRING_LEN], UUID.as_bytes());
+        assert_eq!(c_string[UUID_STRING_LEN], 0);
+    }
+
+    #[test]
+    fn machine_id_is_undashed_lowercase() {
+        assert_eq!(
+            uuid_to_machine_id(UUID).unwrap(),
+            "deadbeef0123456789abcdef00112233"
+        );
+    }
+
+    #[test]
+    fn host_id_is_the_first_eight_digits() {
+        assert_eq!(uuid_to_host_id(UUID).unwrap(), 0xdeadbeef);
+        assert_eq!(
+            uuid_to_host_id("00000001-0000-0000-0000-000000000000").unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn rejects_malformed() {
+        assert!(uuid_to_bytes("DEADBEEF").is_err());
+        assert!(uuid_to_machine_id("DEADBEEF").is_err());
+        assert!(uuid_to_host_id("DEADBEEF").is_err());
+        assert!(uuid_to_c_string("DEADBEEF-0123-4567-89AB-CDEF0011223Z").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! The CoreFoundation objects the IOKit hooks return, created once per property and then
+//! shared: every call hands out one more reference to the same immutable object.
+//!
+//! Hooks run on whatever thread the host app calls from, so the cache takes no lock and
+//! cannot be poisoned. Each property has one atomic slot. The first caller to find it
+//! empty creates the object and publishes it with a compare-and-swap; a caller that loses
+//! the race releases its own object and uses the winner's. Nothing here panics.
+//!
+//! The hook supplies CoreFoundation through [`RetainedObjects`]; tests use a counting mock.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::identity::{IdentityTable, PropertyValue};
+
+/// Reference-counted objects, identified by their (non-zero) address.
+pub trait RetainedObjects {
+    /// A new object holding `value`, with one reference owned by the caller, or `None` if
+    /// it cannot be created.
+    fn create(&self, value: &PropertyValue) -> Option<usize>;
+
+    /// Adds a reference (`CFRetain`).
+    fn retain(&self, object: usize);
+
+    /// Drops a reference (`CFRelease`).
+    fn release(&self, object: usize);
+}
+
+struct Slot {
+    name: String,
+    value: PropertyValue,
+    // The cached object, or 0 until first use. The cache owns one reference to it.
+    object: AtomicUsize,
+}
+
+/// One lazily created object per property of an identity table.
+pub struct ValueCache {
+    slots: Vec<Slot>,
+}
+
+impl ValueCache {
+    pub fn new(table: &IdentityTable) -> Self {
+        ValueCache {
+            slots: table
+                .iter()
+                .map(|(name, value)| Slot {
+                    name: name.to_string(),
+                    value: value.clone(),
+                    object: AtomicUsize::new(0),
+                })
+                .collect(),
+        }
+    }
+
+    /// A new reference to the object for property `name`, which the caller owns (the
+    /// "create" rule), or `None` if there is no such property or its object cannot be
+    /// created.
+    pub fn get<O: RetainedObjects + ?Sized>(&self, objects: &O, name: &str) -> Option<usize> {
+        let slot = self.slots.iter().find(|slot| slot.name == name)?;
+        let cached = slot.object.load(Ordering::Acquire);
+        if cached != 0 {
+            objects.retain(cached);
+            return Some(cached);
+        }
+        let created = objects.create(&slot.value).filter(|&object| object != 0)?;
+        match slot
+            .object
+            .compare_exchange(0, created, Ordering::AcqRel, Ordering::Acquire)
+        {
+            // Our reference now belongs to the cache; the caller gets another.
+            Ok(_) => {
+                objects.retain(created);
+                Some(created)
+            }
+            // Another thread got there first: hand out theirs, so every caller of a
+            // property sees the same object.
+            Err(winner) => {
+                objects.release(created);
+                objects.retain(winner);
+                Some(winner)
+            }
+        }
+    }
+
+    /// Whether property `name`'s object has been created.
+    pub fn is_cached(&self, name: &str) -> bool {
+        self.slots
+            .iter()
+            .any(|slot| slot.name == name && slot.object.load(Ordering::Acquire) != 0)
+    }
+
+    /// Drops the cache's references, for a cache that is not kept for the life of the
+    /// process.
+    pub fn release_all<O: RetainedObjects + ?Sized>(&mut self, objects: &O) {
+        for slot in &mut self.slots {
+            let object = std::mem::take(slot.object.get_mut());
+            if object != 0 {
+                objects.release(object);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identity::{BOARD_ID_KEY, IO_PLATFORM_UUID_KEY, MODEL_KEY};
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Barrier, Mutex};
+
+    // Objects are numbered from 1; tracks every object's reference count.
+    #[derive(Default)]
+    struct CountingObjects {
+        counts: Mutex<BTreeMap<usize, i64>>,
+        fail: bool,
+    }
+
+    impl CountingObjects {
+        fn adjust(&self, object: usize, by: i64) {
+            let mut counts = self.counts.lock().unwrap();
+            let count = counts.get_mut(&object).expect("unknown object");
+            assert!(*count > 0, "object {} used after its last release", object);
+            *count += by;
+        }
+
+        fn live(&self) -> Vec<(usize, i64)> {
+            let counts = self.counts.lock().unwrap();
+            counts
+                .iter()
+                .filter(|(_, &count)| count != 0)
+                .map(|(&object, &count)| (object, count))
+                .collect()
+        }
+
+        fn created(&self) -> usize {
+            self.counts.lock().unwrap().len()
+        }
+    }
+
+    impl RetainedObjects for CountingObjects {
+        fn create(&self, _value: &PropertyValue) -> Option<usize> {
+            if self.fail {
+                return None;
+            }
+            let mut counts = self.counts.lock().unwrap();
+            let object = counts.len() + 1;
+            counts.insert(object, 1);
+            Some(object)
+        }
+
+        fn retain(&self, object: usize) {
+            self.adjust(object, 1);
+        }
+
+        fn release(&self, object: usize) {
+            self.adjust(object, -1);
+        }
+    }
+
+    fn table() -> IdentityTable {
+        let mut table = IdentityTable::new();
+        for (name, value) in [
+            (IO_PLATFORM_UUID_KEY, "DEADBEEF-0123-4567-89AB-CDEF00112233"),
+            (BOARD_ID_KEY, "Mac-7BA5B2DFE22DDD8C"),
+            (MODEL_KEY, "Macmini8,1"),
+        ] {
+            table.insert(name, PropertyValue::String(value.to_string()));
+        }
+        table
+    }
+
+    #[test]
+    fn creates_each_object_once() {
+        let objects = CountingObjects::default();
+        let mut cache = ValueCache::new(&table());
+        assert!(!cache.is_cached(MODEL_KEY));
+        let first = cache.get(&objects, MODEL_KEY).unwrap();
+        let second = cache.get(&objects, MODEL_KEY).unwrap();
+        assert_eq!(first, second);
+        assert!(cache.is_cached(MODEL_KEY));
+        assert_eq!(objects.created(), 1);
+        // The cache's reference and the two callers'.
+        assert_eq!(objects.live(), [(first, 3)]);
+
+        objects.release(first);
+        objects.release(second);
+        cache.release_all(&objects);
+        assert_eq!(objects.live(), []);
+    }
+
+    #[test]
+    fn unknown_and_uncreatable_properties_pass_through() {
+        let objects = CountingObjects {
+            fail: true,
+            ..Default::default()
+        };
+        let cache = ValueCache::new(&table());
+        assert_eq!(cache.get(&objects, "IOPlatformSerialNumber"), None);
+        assert_eq!(cache.get(&objects, MODEL_KEY), None);
+        // A failed creation is not cached; the next call tries again.
+        assert!(!cache.is_cached(MODEL_KEY));
+    }
+
+    #[test]
+    fn concurrent_callers_share_one_object_and_balance_references() {
+        const THREADS: usize = 16;
+        const CALLS: usize = 2_000;
+        let objects = Arc::new(CountingObjects::default());
+        let cache = Arc::new(ValueCache::new(&table()));
+        let names = [IO_PLATFORM_UUID_KEY, BOARD_ID_KEY, MODEL_KEY];
+        // Start together, so the first calls race to create the objects.
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|thread| {
+                let (objects, cache, barrier) = (objects.clone(), cache.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    let mut seen = BTreeMap::new();
+                    for call in 0..CALLS {
+                        let name = names[(thread + call) % names.len()];
+                        let object = cache.get(&*objects, name).unwrap();
+                        assert_eq!(*seen.entry(name).or_insert(object), object);
+                        objects.release(object);
+                    }
+                    seen
+                })
+            })
+            .collect();
+        let seen: Vec<_> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        // Every thread saw the same object for each property.
+        assert!(seen.windows(2).all(|pair| pair[0] == pair[1]));
+
+        // Only the cache's references are left, one per property; objects that lost a
+        // race were released.
+        let live = objects.live();
+        assert_eq!(live.len(), names.len());
+        assert!(live.iter().all(|&(_, count)| count == 1), "{:?}", live);
+        let mut cache = Arc::try_unwrap(cache).ok().unwrap();
+        cache.release_all(&*objects);
+        assert_eq!(objects.live(), []);
+    }
+}